@@ -29,6 +29,7 @@ export const sidebarItems: ISidebarSection[] = [
         href: "/docs/configuration/environment",
       },
       { text: "Procfile", href: "/docs/configuration/procfile" },
+      { text: "Heroku", href: "/docs/configuration/heroku" },
       { text: "Caching", href: "/docs/configuration/caching" },
     ],
   },
@@ -0,0 +1 @@
+console.log(`Started from heroku.yml with ${process.env.GREETING}!`);
@@ -0,0 +1,6 @@
+from PIL import Image
+from pydub import AudioSegment
+
+Image.new("RGB", (64, 64), "teal").save("/tmp/thumbnail.jpg")
+AudioSegment.silent(duration=1000).export("/tmp/silence.mp3", format="mp3")
+print("Hello from Python")
@@ -0,0 +1 @@
+print("Hello from Python-Poetry with a package.json")
@@ -0,0 +1,3 @@
+#!/bin/bash
+
+echo 'Hello from shell.nix' | cowsay
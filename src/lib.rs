@@ -21,18 +21,33 @@
 use crate::nixpacks::{
     app::App,
     builder::{
-        docker::{docker_image_builder::DockerImageBuilder, DockerBuilderOptions},
+        docker::{
+            client::DockerClient, docker_image_builder::DockerImageBuilder,
+            dockerfile_template::read_template, provenance::get_provenance_labels,
+            DockerBuilderOptions,
+        },
         ImageBuilder,
     },
+    deploy::{
+        compose::{self, ComposeOptions},
+        k8s::write_k8s_manifests,
+    },
     environment::Environment,
     logger::Logger,
-    nix::pkg::Pkg,
+    multi::{get_app_image_names, read_apps_manifest},
+    nix::{create_nix_shell_for_phases, pkg::Pkg},
     plan::{
+        diff::PlanChange,
         generator::{GeneratePlanOptions, NixpacksBuildPlanGenerator},
+        phase::Phase,
         BuildPlan, PlanGenerator,
     },
 };
-use anyhow::{bail, Result};
+use anyhow::{anyhow, bail, Context, Result};
+use console::Term;
+use futures::{stream, StreamExt};
+pub use nixpacks::builder::ImageInfo;
+pub use nixpacks::error::NixpacksError;
 use providers::{
     clojure::ClojureProvider, cobol::CobolProvider, crystal::CrystalProvider,
     csharp::CSharpProvider, dart::DartProvider, deno::DenoProvider, elixir::ElixirProvider,
@@ -40,9 +55,11 @@ use providers::{
     haskell::HaskellStackProvider, java::JavaProvider, lunatic::LunaticProvider,
     node::NodeProvider, php::PhpProvider, python::PythonProvider, ruby::RubyProvider,
     rust::RustProvider, scala::ScalaProvider, scheme::HauntProvider,
-    staticfile::StaticfileProvider, swift::SwiftProvider, zig::ZigProvider, Provider,
+    staticfile::StaticfileProvider, swift::SwiftProvider, zig::ZigProvider, Confidence, Provider,
 };
-use std::process::Command;
+use std::path::Path;
+use tokio::runtime::Handle;
+use uuid::Uuid;
 
 mod chain;
 #[macro_use]
@@ -50,7 +67,7 @@ pub mod nixpacks;
 pub mod providers;
 
 /// Supplies all currently-defined providers to build plan generators and image builders.
-pub fn get_providers() -> &'static [&'static (dyn Provider)] {
+pub fn get_providers() -> &'static [&'static dyn Provider] {
     &[
         &CrystalProvider {},
         &CSharpProvider {},
@@ -83,9 +100,9 @@ pub fn generate_build_plan(
     path: &str,
     envs: Vec<&str>,
     options: &GeneratePlanOptions,
-) -> Result<BuildPlan> {
-    let app = App::new(path)?;
-    let environment = Environment::from_envs(envs)?;
+) -> Result<BuildPlan, NixpacksError> {
+    let mut environment = Environment::from_envs(envs)?;
+    let app = get_app(path, options, &mut environment)?;
 
     let mut generator = NixpacksBuildPlanGenerator::new(get_providers(), options.clone());
     let plan = generator.generate_plan(&app, &environment)?;
@@ -98,80 +115,392 @@ pub fn get_plan_providers(
     path: &str,
     envs: Vec<&str>,
     options: &GeneratePlanOptions,
-) -> Result<Vec<String>> {
-    let app = App::new(path)?;
-    let environment = Environment::from_envs(envs)?;
+) -> Result<Vec<String>, NixpacksError> {
+    let mut environment = Environment::from_envs(envs)?;
+    let app = get_app(path, options, &mut environment)?;
+
+    let generator = NixpacksBuildPlanGenerator::new(get_providers(), options.clone());
+
+    Ok(generator.get_plan_providers(&app, &environment)?)
+}
+
+/// Get every provider that detects the project with its confidence, from the most confident to the least.
+///
+/// Only the first is used to build the app, unless all detected providers are combined with `NIXPACKS_DETECT_ALL_PROVIDERS`.
+pub fn get_matching_providers(
+    path: &str,
+    envs: Vec<&str>,
+    options: &GeneratePlanOptions,
+) -> Result<Vec<(String, Confidence)>, NixpacksError> {
+    let mut environment = Environment::from_envs(envs)?;
+    let app = get_app(path, options, &mut environment)?;
 
     let generator = NixpacksBuildPlanGenerator::new(get_providers(), options.clone());
 
-    generator.get_plan_providers(&app, &environment)
+    Ok(generator.get_matching_providers(&app, &environment)?)
+}
+
+/// Generates a `docker-compose.yml` that runs the app's image next to the services it uses.
+pub fn generate_compose_file(
+    path: &str,
+    envs: Vec<&str>,
+    options: &GeneratePlanOptions,
+    compose_options: &ComposeOptions,
+) -> Result<String, NixpacksError> {
+    let mut environment = Environment::from_envs(envs)?;
+    let app = get_app(path, options, &mut environment)?;
+
+    let mut generator = NixpacksBuildPlanGenerator::new(get_providers(), options.clone());
+    let (plan, app) = generator.generate_plan(&app, &environment)?;
+
+    Ok(compose::create_compose_file(&app, &plan, compose_options)?)
+}
+
+/// Compares the plan committed as the app's config file with the plan that is detected for the app without it.
+pub fn get_plan_drift(
+    path: &str,
+    envs: Vec<&str>,
+    options: &GeneratePlanOptions,
+) -> Result<Vec<PlanChange>, NixpacksError> {
+    let mut environment = Environment::from_envs(envs)?;
+    let app = get_app(path, options, &mut environment)?;
+
+    let generator = NixpacksBuildPlanGenerator::new(get_providers(), options.clone());
+    let committed = generator.read_file_plan(&app, &environment)?;
+    if committed == BuildPlan::default() {
+        return Err(NixpacksError::plan_invalid(
+            "There is no nixpacks.toml or nixpacks.json plan to check",
+        ));
+    }
+
+    let mut generator = NixpacksBuildPlanGenerator::new(
+        get_providers(),
+        GeneratePlanOptions {
+            no_config_file: true,
+            ..options.clone()
+        },
+    );
+    let (detected, _) = generator.generate_plan(&app, &environment)?;
+    Ok(committed.drift(&detected)?)
+}
+
+/// Generates a `shell.nix` with the Nix packages of the app's build plan, for developing with the same toolchain as the image.
+pub fn generate_nix_shell(
+    path: &str,
+    envs: Vec<&str>,
+    options: &GeneratePlanOptions,
+) -> Result<String, NixpacksError> {
+    let plan = generate_build_plan(path, envs, options)?;
+    if let Some(nix_file) = &plan.nix_file {
+        return Err(anyhow!(
+            "The app's environment comes from its own {nix_file}, which can be used directly"
+        )
+        .into());
+    }
+
+    let phases = plan.phases.unwrap_or_default();
+    if !phases.values().any(Phase::uses_nix) {
+        return Err(anyhow!("The build plan has no Nix packages to put in a shell").into());
+    }
+    Ok(create_nix_shell_for_phases(&phases))
 }
 
 /// Builds a Docker image based on environment data and build options from config files or existing build plans.
+///
+/// Images without a `name` in the build options are named with a random UUID, which is returned in the [`ImageInfo`].
+/// When `use_dockerfile` is set and the app has a `Dockerfile`, it is built as is instead of generating a plan.
 pub async fn create_docker_image(
     path: &str,
     envs: Vec<&str>,
     plan_options: &GeneratePlanOptions,
     build_options: &DockerBuilderOptions,
-) -> Result<()> {
-    let app = App::new(path)?;
+) -> Result<ImageInfo, NixpacksError> {
+    let mut environment = Environment::from_envs(envs)?;
+    let logger = Logger::with_format(build_options.log_format);
+    logger.start_phase("detect");
+    let app = get_app(path, plan_options, &mut environment)?;
 
-    let environment = Environment::from_envs(envs)?;
-    let orig_path = app.source.clone();
+    let mut build_options = build_options.clone();
+    build_options.use_dockerfile = (build_options.use_dockerfile
+        || environment.is_config_variable_truthy("USE_DOCKERFILE"))
+        && app.includes_file("Dockerfile");
 
-    let mut generator = NixpacksBuildPlanGenerator::new(get_providers(), plan_options.clone());
-    let (plan, app) = generator.generate_plan(&app, &environment)?;
+    let (plan, app, providers) = if build_options.use_dockerfile {
+        logger.log_output("Building the app's Dockerfile");
 
-    if let Ok(subdir) = app.source.strip_prefix(orig_path) {
-        if subdir != std::path::Path::new("") {
-            println!("Using subdirectory \"{}\"", subdir.to_str().unwrap());
+        // The variables are still passed to the Dockerfile as build args
+        let mut plan = BuildPlan::default();
+        let variables = Environment::clone_variables(&environment);
+        if !variables.is_empty() {
+            plan.add_variables(variables);
         }
+        (plan, app, Vec::new())
+    } else {
+        generate_plan_for_build(&app, &environment, plan_options, &build_options, &logger)?
+    };
+    logger.add_secrets(plan.get_secret_values());
+
+    let name = build_options
+        .name
+        .get_or_insert_with(|| Uuid::new_v4().to_string())
+        .clone();
+    if build_options.git_info.is_none() {
+        build_options.git_info = app.git_info();
     }
+    let dockerfile_template = build_options
+        .dockerfile_template
+        .as_deref()
+        .map(read_template)
+        .transpose()?;
+    build_options.labels.extend(get_provenance_labels(
+        &app.source,
+        &plan,
+        &providers,
+        build_options.git_info.as_ref(),
+        dockerfile_template.as_deref(),
+    )?);
 
-    let logger = Logger::new();
     let builder = DockerImageBuilder::new(logger, build_options.clone());
 
+    if build_options.out_dir.is_none() && !build_options.docker_api {
+        ensure_docker_exists(&build_options)?;
+    }
+
+    builder
+        .create_image(&app.source, &plan, &environment)
+        .await?;
+
+    if let (Some(dir), false) = (&build_options.k8s_out, build_options.print_dockerfile) {
+        // Tags usually include the registry the cluster pulls from, while the name is often local
+        let image = build_options.tags.first().unwrap_or(&name);
+        write_k8s_manifests(Path::new(dir), &plan, image)
+            .context("Writing Kubernetes manifests")?;
+        Logger::with_format(build_options.log_format)
+            .log_step(&format!("Saved Kubernetes manifests to {dir}"));
+    }
+
+    Ok(ImageInfo {
+        name,
+        tags: build_options.tags,
+        plan,
+        providers,
+        out_dir: build_options.out_dir,
+    })
+}
+
+/// Builds an image for each app in a subdirectory of `path`, such as the services of a monorepo, with up to `jobs` building at once, or all of them when it's not set.
+///
+/// When no apps are given they are read from the `nixpacks-apps.toml` in `path`. Each image is named after its subdirectory,
+/// prefixed with the `name` from the build options. The apps share the build options' cache key, so cached directories
+/// are reused between them, and Docker reuses the Nix layers of apps that install the same packages.
+pub async fn create_docker_images(
+    path: &str,
+    apps: &[String],
+    jobs: Option<usize>,
+    envs: Vec<&str>,
+    plan_options: &GeneratePlanOptions,
+    build_options: &DockerBuilderOptions,
+) -> Result<Vec<ImageInfo>, NixpacksError> {
+    if plan_options.subdir.is_some() {
+        return Err(anyhow!("A subdirectory can't be set when building several apps, which are each built from their own subdirectory").into());
+    }
+    let apps = if apps.is_empty() {
+        read_apps_manifest(&App::new(path)?.source)?
+    } else {
+        apps.to_vec()
+    };
+    let names = get_app_image_names(build_options.name.as_deref(), &apps)?;
+    let envs = envs
+        .into_iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>();
+
+    let logger = Logger::with_format(build_options.log_format);
+    logger.log_section(&format!(
+        "Building {} apps: {}",
+        apps.len(),
+        apps.join(", ")
+    ));
+
+    let results = stream::iter(apps.iter().cloned().zip(names))
+        .map(|(app, name)| {
+            let path = path.to_string();
+            let envs = envs.clone();
+            let plan_options = GeneratePlanOptions {
+                subdir: Some(app),
+                ..plan_options.clone()
+            };
+            let build_options = DockerBuilderOptions {
+                name: Some(name),
+                ..build_options.clone()
+            };
+
+            // Builds block while the container engine runs, so each one gets its own thread
+            let runtime = Handle::current();
+            tokio::task::spawn_blocking(move || {
+                runtime.block_on(create_docker_image(
+                    &path,
+                    envs.iter().map(String::as_str).collect(),
+                    &plan_options,
+                    &build_options,
+                ))
+            })
+        })
+        .buffered(jobs.unwrap_or(apps.len()).max(1))
+        .collect::<Vec<_>>()
+        .await;
+
+    let mut images = Vec::new();
+    let mut failed = Vec::new();
+    let mut timed_out = None;
+    for (app, result) in apps.iter().zip(results) {
+        match result.context("Waiting for the build to finish")? {
+            Ok(image) => images.push(image),
+            Err(error) => {
+                if let NixpacksError::TimedOut { seconds } = error {
+                    timed_out = Some(seconds);
+                }
+                let error = anyhow::Error::from(error);
+                logger.log_output(&format!("Building {app} failed: {error:#}"));
+                failed.push(app.as_str());
+            }
+        }
+    }
+    if !failed.is_empty() {
+        let summary = format!(
+            "{} of {} apps failed to build: {}",
+            failed.len(),
+            apps.len(),
+            failed.join(", ")
+        );
+        // A timeout is kept as the error, so callers can tell it apart from a failed build
+        if let Some(seconds) = timed_out {
+            logger.log_output(&summary);
+            return Err(NixpacksError::TimedOut { seconds });
+        }
+        return Err(anyhow!(summary).into());
+    }
+
+    for image in &images {
+        logger.log_step(&format!("Built {}", image.name));
+    }
+    Ok(images)
+}
+
+/// The app at `path`, or in its subdirectory when one is set with `subdir` or `NIXPACKS_SUBDIR`.
+///
+/// Workspace members are built from the repository root, with the variable that selects the member added to the environment.
+fn get_app(
+    path: &str,
+    options: &GeneratePlanOptions,
+    environment: &mut Environment,
+) -> Result<App> {
+    match options
+        .subdir
+        .clone()
+        .or_else(|| environment.get_config_variable("SUBDIR"))
+    {
+        Some(subdir) => {
+            let (app, member) = App::new_in_subdir(path, &subdir)?;
+            if let Some((name, value)) = member {
+                // A member chosen by the user takes precedence
+                if environment.get_config_variable(name).is_none() {
+                    environment.set_variable(format!("NIXPACKS_{name}"), value);
+                }
+            }
+            app.with_max_search_depth(environment)
+        }
+        None => App::new(path)?.with_max_search_depth(environment),
+    }
+}
+
+/// Generates the plan to build an app with, failing if it can't be built.
+fn generate_plan_for_build(
+    app: &App,
+    environment: &Environment,
+    plan_options: &GeneratePlanOptions,
+    build_options: &DockerBuilderOptions,
+    logger: &Logger,
+) -> Result<(BuildPlan, App, Vec<String>)> {
+    let orig_path = app.source.clone();
+
+    let mut generator =
+        NixpacksBuildPlanGenerator::new(get_providers(), plan_options.clone()).with_logger(logger);
+
+    logger.start_phase("plan");
+    let (plan, app) = generator.generate_plan(app, environment)?;
+    // The plan can come from a subdirectory when nothing was detected in the app
+    let providers = generator.get_plan_providers(&app, environment)?;
+
+    if let Ok(subdir) = app.source.strip_prefix(&orig_path) {
+        if subdir != std::path::Path::new("") {
+            logger.log_output(&format!("Using subdirectory \"{}\"", subdir.display()));
+        }
+    }
+
     let phase_count = plan.phases.clone().map_or(0, |phases| phases.len());
-    if phase_count > 0 {
-        println!("{}", plan.get_build_string()?);
+    if phase_count > 0 || plan.nix_file.is_some() {
+        plan.validate()
+            .map_err(|e| NixpacksError::plan_invalid(format!("{e:#}")))?;
+
+        logger.log_key_values("Build plan", &plan.get_summary(&providers)?);
 
         let start = plan.start_phase.clone().unwrap_or_default();
         if start.cmd.is_none() && !build_options.no_error_without_start {
-            bail!("No start command could be found")
+            return Err(NixpacksError::plan_invalid("No start command could be found").into());
+        }
+
+        // Only builds need approval, since nothing is baked into an image when saving the output
+        if build_options.confirm
+            && build_options.out_dir.is_none()
+            && !build_options.print_dockerfile
+        {
+            confirm_build()?;
         }
     } else {
-        println!("\nNixpacks was unable to generate a build plan for this app.\nPlease check the documentation for supported languages: https://nixpacks.com");
-        println!("\nThe contents of the app directory are:\n");
+        logger.log_output("\nNixpacks was unable to generate a build plan for this app.\nPlease check the documentation for supported languages: https://nixpacks.com");
+        logger.log_output("\nThe contents of the app directory are:\n");
 
         for file in &app.paths {
             let path = app.strip_source_path(file.as_path())?;
-            println!(
+            logger.log_output(&format!(
                 "  {}{}",
                 path.display(),
                 if file.is_dir() { "/" } else { "" }
-            );
+            ));
         }
 
-        std::process::exit(1);
+        return Err(NixpacksError::DetectionFailed.into());
     }
 
-    if build_options.out_dir.is_none() {
-        ensure_docker_exists()?;
-    }
+    Ok((plan, app, providers))
+}
 
-    builder
-        .create_image(app.source.to_str().unwrap(), &plan, &environment)
-        .await?;
+/// Asks on the terminal whether to build the plan that was printed, failing unless the answer is yes.
+fn confirm_build() -> Result<()> {
+    let term = Term::stdout();
+    if !term.is_term() {
+        bail!("--confirm needs a terminal to ask whether to build the plan");
+    }
 
+    term.write_str("Build this plan? [y/N] ")?;
+    let answer = term.read_line()?;
+    if !matches!(answer.trim().to_lowercase().as_str(), "y" | "yes") {
+        bail!("The build was cancelled");
+    }
     Ok(())
 }
 
-fn ensure_docker_exists() -> Result<()> {
-    let mut docker_build_cmd = Command::new("docker");
+fn ensure_docker_exists(build_options: &DockerBuilderOptions) -> Result<()> {
+    let client = DockerClient::resolve(build_options.docker_client.as_deref())?;
 
-    if docker_build_cmd.output().is_err() {
-        bail!("Please install Docker to build the app https://docs.docker.com/engine/install/");
+    if !client.is_installed() {
+        return Err(client.not_installed_error());
     }
 
-    Ok(())
+    // Remote builders only need the local daemon to load the image into
+    if build_options.builder.is_some() && build_options.push {
+        return Ok(());
+    }
+    client.ensure_daemon_running(build_options.docker_host.as_deref())
 }
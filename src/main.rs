@@ -1,24 +1,41 @@
-use anyhow::Result;
-use clap::{arg, Parser, Subcommand, ValueEnum};
+use anyhow::{bail, Context, Result};
+use clap::{Parser, Subcommand, ValueEnum};
+use indicatif::ProgressDrawTarget;
 use nixpacks::{
-    create_docker_image, generate_build_plan, get_plan_providers,
+    create_docker_image, create_docker_images, generate_build_plan, generate_compose_file,
+    generate_nix_shell, get_matching_providers, get_plan_drift, get_plan_providers,
     nixpacks::{
-        builder::docker::DockerBuilderOptions,
-        nix::pkg::Pkg,
+        builder::docker::{DockerBuilderOptions, OutputFormat},
+        deploy::{
+            compose::{ComposeOptions, Service},
+            default_image_name,
+        },
+        environment::{Environment, REDACTED},
+        git::GitSource,
+        logger::{self, LogFormat},
+        nix::{nixpkgs_archive_for_channel, pkg::Pkg},
         plan::{
-            generator::GeneratePlanOptions,
+            generator::{get_provider_tie_warning, GeneratePlanOptions},
+            locale::{DEFAULT_LOCALE, DEFAULT_TIMEZONE},
             phase::{Phase, StartPhase},
             BuildPlan,
         },
     },
+    NixpacksError,
 };
 use std::{
     collections::hash_map::DefaultHasher,
-    env,
+    env, fs,
     hash::{Hash, Hasher},
+    io::ErrorKind,
     ops::Deref,
+    process::Command,
     string::ToString,
 };
+use tempdir::TempDir;
+
+/// Exit code of builds stopped by `--timeout`, the same as the `timeout` command's.
+const TIMEOUT_EXIT_CODE: i32 = 124;
 
 /// The build plan config file format to use.
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
@@ -27,6 +44,20 @@ enum PlanFormat {
     Toml,
 }
 
+/// The format used when saving build output with `--out`.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+enum OutFormat {
+    Dir,
+    Oci,
+}
+
+/// How build progress is written with `--log-format`.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+enum LogFormatArg {
+    Text,
+    Json,
+}
+
 /// Arguments passed to `nixpacks`.
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
@@ -39,18 +70,30 @@ struct Args {
     #[arg(long, global = true)]
     json_plan: Option<String>,
 
-    /// Specify the install command to use
+    /// Specify the install command to use. Repeat to run several commands, each in its own layer
     #[arg(long, short, global = true)]
-    install_cmd: Option<String>,
+    install_cmd: Vec<String>,
 
-    /// Specify the build command to use
+    /// Specify the build command to use. Repeat to run several commands, each in its own layer
     #[arg(long, short, global = true)]
-    build_cmd: Option<String>,
+    build_cmd: Vec<String>,
+
+    /// Providers to build the app with, such as `node,python`. Include `...` to keep the detected providers
+    #[arg(long, global = true, value_delimiter = ',')]
+    providers: Vec<String>,
 
     /// Specify the start command to use
     #[arg(long, short, global = true)]
     start_cmd: Option<String>,
 
+    /// Specify the command to run before each deploy, such as database migrations
+    #[arg(long, global = true)]
+    release_cmd: Option<String>,
+
+    /// Specify the port the app listens on
+    #[arg(long, global = true)]
+    port: Option<u16>,
+
     /// Provide additional nix packages to install in the environment
     #[arg(long, short, global = true)]
     pkgs: Vec<String>,
@@ -63,13 +106,90 @@ struct Args {
     #[arg(long, global = true)]
     libs: Vec<String>,
 
+    /// Install the timezone database and set TZ, which defaults to Etc/UTC
+    #[arg(long, global = true, num_args = 0..=1, require_equals = true, default_missing_value = DEFAULT_TIMEZONE, value_name = "TIMEZONE")]
+    tzdata: Option<String>,
+
+    /// Install glibc locales and set LANG, which defaults to en_US.UTF-8
+    #[arg(long, global = true, num_args = 0..=1, require_equals = true, default_missing_value = DEFAULT_LOCALE, value_name = "LOCALE")]
+    locales: Option<String>,
+
+    /// Don't install CA certificates or set SSL_CERT_FILE and NIX_SSL_CERT_FILE in the image
+    #[arg(long, global = true)]
+    no_cacert: bool,
+
+    /// Run every process from the Procfile in one container with the overmind process manager
+    #[arg(long, global = true)]
+    process_manager: bool,
+
+    /// Build Rust apps as statically linked musl binaries that run in an empty image
+    #[arg(long = "static", global = true)]
+    static_binary: bool,
+
+    /// Bundle Node apps into one file with esbuild that runs without node_modules
+    #[arg(long, global = true)]
+    bundle: bool,
+
     /// Provide environment variables to your build
     #[arg(long, short, global = true)]
     env: Vec<String>,
 
+    /// Copy a variable from the current environment into the build. Repeat for each variable
+    #[arg(long, global = true)]
+    env_passthrough: Vec<String>,
+
+    /// Copy every variable in the current environment that starts with this prefix into the build
+    #[arg(long, global = true)]
+    env_passthrough_prefix: Vec<String>,
+
+    /// Provide environment variables that are only available while building and are not set in the image
+    #[arg(long, global = true)]
+    build_env: Vec<String>,
+
     /// Path to config file
     #[arg(long, short, global = true)]
     config: Option<String>,
+
+    /// Image to build on, such as `debian:bookworm-slim`. Nix is installed on it if needed
+    #[arg(long, global = true)]
+    base_image: Option<String>,
+
+    /// Use every provider that detects the app, such as both Python and Node for an API with a JS frontend
+    #[arg(long, global = true)]
+    detect_all_providers: bool,
+
+    /// Use the app's flake.nix, default.nix, or shell.nix as the environment instead of generating one
+    #[arg(long, global = true)]
+    use_app_nix: bool,
+
+    /// Pin every Nix package to this nixpkgs commit
+    #[arg(long, global = true)]
+    nixpkgs_archive: Option<String>,
+
+    /// Install Nix packages from a nixpkgs channel, such as `unstable` or `23.05`
+    #[arg(long, global = true, conflicts_with = "nixpkgs_archive")]
+    channel: Option<String>,
+
+    /// Directory inside the app source to build, such as `apps/api` in a monorepo
+    #[arg(long, global = true)]
+    subdir: Option<String>,
+
+    /// Branch, tag, or commit to check out when the app source is a git URL
+    #[arg(long = "ref", global = true)]
+    git_ref: Option<String>,
+}
+
+/// Subcommands of `nixpacks plan`.
+#[derive(Subcommand)]
+enum PlanCommands {
+    /// Compare a saved plan with the plan generated for an app. Exits with 1 if they differ
+    Diff {
+        /// Plan file saved with `nixpacks plan`, in json or toml format
+        plan_file: String,
+
+        /// App source
+        path: String,
+    },
 }
 
 /// The valid subcommands passed to `nixpacks`, and their arguments.
@@ -78,19 +198,60 @@ struct Args {
 enum Commands {
     /// Generate a build plan for an app.
     /// Generated plan will be outputted to stdout, while warnings might be outputted to stderr.
+    #[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
     Plan {
         /// App source
-        path: String,
+        #[arg(required = true)]
+        path: Option<String>,
 
         /// Specify the output format of the build plan.
         #[arg(short, long, value_enum, default_value = "json")]
         format: PlanFormat,
+
+        /// Check that the plan is valid and its Nix packages exist, instead of printing it. Exits with 1 if it isn't
+        #[arg(long)]
+        check: bool,
+
+        #[command(subcommand)]
+        command: Option<PlanCommands>,
     },
 
     /// List all of the providers that will be used to build the app
     Detect {
         /// App source
         path: String,
+
+        /// List every provider that matches the app in priority order, including ones that aren't used
+        #[arg(long)]
+        all: bool,
+    },
+
+    /// Generate a docker-compose.yml that runs the app's image with the services it uses
+    Compose {
+        /// App source
+        path: String,
+
+        /// Name of the app's image. Defaults to the name of the app's directory
+        #[arg(short, long)]
+        name: Option<String>,
+
+        /// Service to run next to the app, `postgres` or `redis`, even if it isn't detected
+        #[arg(long)]
+        service: Vec<String>,
+
+        /// Don't add the services detected from the app's dependencies
+        #[arg(long)]
+        no_services: bool,
+    },
+
+    /// Open a nix-shell with the same Nix packages as the app's image, for developing with the same toolchain
+    Shell {
+        /// App source
+        path: String,
+
+        /// Print the shell.nix instead of opening the shell
+        #[arg(long)]
+        print: bool,
     },
 
     /// Build an app
@@ -106,6 +267,14 @@ enum Commands {
         #[arg(short, long)]
         out: Option<String>,
 
+        /// Format of the saved output. `oci` writes a context tarball for kaniko or buildah
+        #[arg(long, value_enum, default_value = "dir", requires = "out")]
+        out_format: OutFormat,
+
+        /// Format of the build output. `json` writes one event per line
+        #[arg(long, value_enum, default_value = "text")]
+        log_format: LogFormatArg,
+
         /// Print the generated Dockerfile to stdout
         #[arg(short, long, hide = true)]
         dockerfile: bool,
@@ -130,6 +299,70 @@ enum Commands {
         #[arg(long)]
         current_dir: bool,
 
+        /// Copy files matched by .gitignore into the image and don't generate a .dockerignore
+        #[arg(long)]
+        include_ignored: bool,
+
+        /// Install Nix packages from a generated flake.nix with pinned nixpkgs inputs
+        #[arg(long)]
+        flake: bool,
+
+        /// Environment variable to pass to install and build commands as a BuildKit secret, which keeps it out of the image
+        #[arg(long)]
+        secret: Vec<String>,
+
+        /// Registry config, such as `.npmrc` or `pip.conf`, that is only available while dependencies are installed
+        #[arg(long)]
+        registry_config: Vec<String>,
+
+        /// Run the pre- and post-build hooks from the app's config on this machine
+        #[arg(long)]
+        allow_hooks: bool,
+
+        /// Build the app's Dockerfile as is when it has one, instead of generating a Dockerfile
+        #[arg(long)]
+        use_dockerfile: bool,
+
+        /// Build an image for each of these subdirectories at once, or for the apps listed in nixpacks-apps.toml when none are given
+        #[arg(long, num_args = 0.., value_name = "SUBDIR", conflicts_with_all = ["tag", "out", "save", "artifacts", "k8s_out", "docker_output", "gha", "check_plan"])]
+        multi: Option<Vec<String>>,
+
+        /// How many apps to build at once with --multi. Defaults to all of them
+        #[arg(long, requires = "multi")]
+        jobs: Option<usize>,
+
+        /// Dockerfile template to place the generated packages, phases, variables, and start command in
+        #[arg(long, conflicts_with = "use_dockerfile")]
+        dockerfile_template: Option<String>,
+
+        /// Forward the SSH agent to install and build commands so private git dependencies can be fetched
+        #[arg(long)]
+        ssh: bool,
+
+        /// Run the plan's release command in the built image once the build succeeds
+        #[arg(long, conflicts_with = "docker_api")]
+        run_release: bool,
+
+        /// Name of the Procfile process to start the image with. Defaults to `web`
+        #[arg(long)]
+        process: Option<String>,
+
+        /// Run the app under the tini init process so signals reach it and zombie processes are reaped
+        #[arg(long)]
+        init: bool,
+
+        /// Run the app as an unprivileged user instead of root
+        #[arg(long)]
+        non_root: bool,
+
+        /// Nix binary cache to substitute packages from, such as a Cachix cache
+        #[arg(long)]
+        nix_cache: Vec<String>,
+
+        /// Public key to trust for packages from the Nix binary caches
+        #[arg(long)]
+        nix_cache_key: Vec<String>,
+
         /// Disable building with the cache
         #[arg(long)]
         no_cache: bool,
@@ -159,10 +392,58 @@ enum Commands {
         #[arg(long)]
         docker_output: Option<String>,
 
+        /// Save the built image to a tar file, which `docker load` imports on machines without registry access
+        #[arg(long, conflicts_with_all = ["out", "docker_output"])]
+        save: Option<String>,
+
+        /// Copy a path out of the built image, as `<image path>=<local path>`. Relative image paths are in the app directory
+        #[arg(long, conflicts_with_all = ["out", "docker_output", "docker_api"])]
+        artifacts: Vec<String>,
+
+        /// Scan the built image for vulnerabilities with trivy and fail the build when any are found
+        #[arg(long, conflicts_with_all = ["out", "docker_output"])]
+        scan: bool,
+
+        /// Lowest severity that fails the scan (low, medium, high, or critical). Defaults to high
+        #[arg(long, requires = "scan", value_name = "SEVERITY")]
+        scan_severity: Option<String>,
+
+        /// Scan with this command instead of trivy. `{image}` is replaced by the image name, and a non-zero exit fails the build
+        #[arg(long, requires = "scan")]
+        scan_cmd: Option<String>,
+
+        /// Write a Kubernetes Deployment and Service for the image to this directory. The image is the first --tag, or the --name
+        #[arg(long)]
+        k8s_out: Option<String>,
+
+        /// Running in GitHub Actions. Writes the image, its digest, and the plan to GITHUB_OUTPUT and caches layers in the GitHub Actions cache
+        #[arg(long, conflicts_with_all = ["out", "docker_api"])]
+        gha: bool,
+
+        /// Fail if the plan committed as nixpacks.toml or nixpacks.json pins values that detection now generates differently
+        #[arg(long)]
+        check_plan: bool,
+
         /// Specify the path to the Docker client certificates
         #[arg(long)]
         docker_cert_path: Option<String>,
 
+        /// Container engine CLI to build with (docker or podman). Detected when not set
+        #[arg(long)]
+        docker_client: Option<String>,
+
+        /// Build through the Docker Engine API instead of the docker CLI
+        #[arg(long)]
+        docker_api: bool,
+
+        /// Build on a remote BuildKit daemon (remote://host[:port]) or buildx builder, and load the image locally
+        #[arg(long, conflicts_with = "docker_api")]
+        builder: Option<String>,
+
+        /// Push the image and its tags to their registry from BuildKit, instead of loading the image into the local daemon
+        #[arg(long, conflicts_with_all = ["out", "docker_output", "docker_api", "save", "artifacts", "scan", "gha", "run_release", "incremental_cache_image"])]
+        push: bool,
+
         /// Enable writing cache metadata into the output image
         #[arg(long)]
         inline_cache: bool,
@@ -172,15 +453,35 @@ enum Commands {
         no_error_without_start: bool,
 
         /// Limit the CPU CFS (Completely Fair Scheduler) quota.
-        /// Passed directly to the docker build command
+        /// Applied by Podman and --docker-api builds, since BuildKit ignores it
         #[arg(long)]
         cpu_quota: Option<String>,
 
+        /// Number of CPUs the build can use, like 2 or 0.5
+        #[arg(long, conflicts_with = "cpu_quota")]
+        cpus: Option<String>,
+
         /// Memory limit.
         /// Passed directly to the docker build command
         #[arg(long)]
         memory: Option<String>,
 
+        /// Network for RUN instructions during the build (default, none, or host)
+        #[arg(long)]
+        network: Option<String>,
+
+        /// Print the build plan and ask whether to build it before starting the build
+        #[arg(long, conflicts_with = "multi")]
+        confirm: bool,
+
+        /// Stop the image build when it runs for longer than this many seconds, and exit with code 124
+        #[arg(long, value_name = "SECONDS")]
+        timeout: Option<u64>,
+
+        /// Retry Nix package downloads, and builds that push to a registry with --docker-output, this many times with exponential backoff
+        #[arg(long, default_value_t = 0)]
+        retries: u32,
+
         /// Display more info during build
         #[arg(long, short)]
         verbose: bool,
@@ -190,6 +491,7 @@ enum Commands {
 #[tokio::main]
 async fn main() -> Result<()> {
     let args = Args::parse();
+    logger::set_colors(logger::colors_enabled());
 
     let pkgs = args
         .pkgs
@@ -206,20 +508,46 @@ async fn main() -> Result<()> {
         setup.nix_libs = Some([args.libs, ["...".to_string()].to_vec()].to_vec().concat());
         cli_plan.add_phase(setup);
     }
-    if let Some(install_cmds) = args.install_cmd {
+    if !args.install_cmd.is_empty() {
         let mut install = Phase::install(None);
-        install.cmds = Some(vec![install_cmds]);
+        install.cmds = Some(args.install_cmd);
         cli_plan.add_phase(install);
     }
-    if let Some(build_cmds) = args.build_cmd {
+    if !args.build_cmd.is_empty() {
         let mut build = Phase::build(None);
-        build.cmds = Some(vec![build_cmds]);
+        build.cmds = Some(args.build_cmd);
         cli_plan.add_phase(build);
     }
     if let Some(start_cmd) = args.start_cmd {
         let start = StartPhase::new(start_cmd);
         cli_plan.set_start_phase(start);
     }
+    if let Some(release_cmd) = args.release_cmd {
+        let mut start = cli_plan.start_phase.clone().unwrap_or_default();
+        start.release_cmd = Some(release_cmd);
+        cli_plan.set_start_phase(start);
+    }
+    if !args.build_env.is_empty() {
+        let build_env = Environment::from_envs(args.build_env.iter().map(Deref::deref).collect())?;
+        cli_plan.add_build_variables(Environment::clone_variables(&build_env));
+    }
+    if !args.providers.is_empty() {
+        cli_plan.providers = Some(args.providers);
+    }
+    if let Some(base_image) = args.base_image {
+        cli_plan.build_image = Some(base_image);
+    }
+    if let Some(port) = args.port {
+        let mut start = cli_plan.start_phase.clone().unwrap_or_default();
+        start.port = Some(port);
+        cli_plan.set_start_phase(start);
+    }
+    if let Some(timezone) = args.tzdata {
+        cli_plan.add_timezone(&timezone);
+    }
+    if let Some(locale) = args.locales {
+        cli_plan.add_locale(&locale);
+    }
 
     let json_plan = args.json_plan.map(BuildPlan::from_json).transpose()?;
 
@@ -230,17 +558,104 @@ async fn main() -> Result<()> {
         cli_plan
     };
 
-    let env: Vec<&str> = args.env.iter().map(|e| e.deref()).collect();
+    let nixpkgs_archive = match args.channel {
+        Some(channel) => Some(nixpkgs_archive_for_channel(&channel)?),
+        None => args.nixpkgs_archive,
+    };
+
+    // Variables passed with `--env` take precedence over the ones passed through from the host
+    let mut passthrough = args.env_passthrough;
+    passthrough.extend(Environment::get_host_variable_names(
+        &args.env_passthrough_prefix,
+    ));
+    // Providers read `--static` and `--bundle` as the NIXPACKS_STATIC and NIXPACKS_BUNDLE variables
+    let flag_env = [
+        (args.static_binary, "NIXPACKS_STATIC=1"),
+        (args.bundle, "NIXPACKS_BUNDLE=1"),
+    ]
+    .into_iter()
+    .filter(|(enabled, _)| *enabled)
+    .map(|(_, var)| var.to_string())
+    .collect::<Vec<_>>();
+    let env: Vec<&str> = passthrough
+        .iter()
+        .chain(&args.env)
+        .chain(&flag_env)
+        .map(|e| e.deref())
+        .collect();
     let options = GeneratePlanOptions {
         plan: Some(cli_plan),
         config_file: args.config,
+        nixpkgs_archive,
+        detect_all_providers: args.detect_all_providers,
+        use_app_nix: args.use_app_nix,
+        subdir: args.subdir,
+        no_cacert: args.no_cacert,
+        process_manager: args.process_manager,
+        no_config_file: false,
     };
+    let git_ref = args.git_ref;
 
     match args.command {
         // Produce a build plan for a project and print it to stdout.
-        Commands::Plan { path, format } => {
+        Commands::Plan {
+            command: Some(PlanCommands::Diff { plan_file, path }),
+            ..
+        } => {
+            let contents = fs::read_to_string(&plan_file)
+                .with_context(|| format!("Reading plan file {plan_file}"))?;
+            let saved_plan = if plan_file.ends_with(".toml") {
+                BuildPlan::from_toml(contents)?
+            } else {
+                BuildPlan::from_json(contents)?
+            };
+            let (path, _checkout) = checkout_source(&path, git_ref.as_ref())?;
+            let plan = generate_build_plan(&path, env, &options)?;
+
+            let changes = saved_plan.diff(&plan)?;
+            if changes.is_empty() {
+                println!("No changes");
+            } else {
+                // Changed secrets are still reported, just without their values
+                let secrets = [saved_plan.get_secret_values(), plan.get_secret_values()].concat();
+                for change in changes {
+                    let change = secrets.iter().fold(change.to_string(), |change, secret| {
+                        change.replace(secret.as_str(), REDACTED)
+                    });
+                    print!("{change}");
+                }
+                std::process::exit(1);
+            }
+        }
+        Commands::Plan {
+            path, check: true, ..
+        } => {
+            let (path, _checkout) = checkout_source(&path.unwrap_or_default(), git_ref.as_ref())?;
             let plan = generate_build_plan(&path, env, &options)?;
 
+            let mut errors = plan.get_validation_errors();
+            // Evaluating nixpkgs downloads the archive the first time, which takes a while
+            let spinner = logger::spinner("Checking Nix packages", ProgressDrawTarget::stderr());
+            let package_errors = plan.get_package_errors();
+            spinner.finish_and_clear();
+            match package_errors? {
+                Some(package_errors) => errors.extend(package_errors),
+                None => eprintln!("Nix is not installed, so Nix packages were not checked"),
+            }
+
+            if errors.is_empty() {
+                println!("The plan is valid");
+            } else {
+                for error in errors {
+                    println!("{error}");
+                }
+                std::process::exit(1);
+            }
+        }
+        Commands::Plan { path, format, .. } => {
+            let (path, _checkout) = checkout_source(&path.unwrap_or_default(), git_ref.as_ref())?;
+            let plan = generate_build_plan(&path, env, &options)?.redacted();
+
             let plan_s = match format {
                 PlanFormat::Json => plan.to_json()?,
                 PlanFormat::Toml => plan.to_toml()?,
@@ -249,33 +664,136 @@ async fn main() -> Result<()> {
             println!("{plan_s}");
         }
         // Detect which providers should be used to build a project and print them to stdout.
-        Commands::Detect { path } => {
-            let providers = get_plan_providers(&path, env, &options)?;
-            println!("{}", providers.join(", "));
+        Commands::Detect { path, all } => {
+            let (path, _checkout) = checkout_source(&path, git_ref.as_ref())?;
+            let used = get_plan_providers(&path, env.clone(), &options)?;
+            let providers = get_matching_providers(&path, env, &options)?;
+            if all {
+                for (priority, (provider, confidence)) in providers.iter().enumerate() {
+                    let status = if used.contains(provider) {
+                        "used"
+                    } else {
+                        "not used"
+                    };
+                    println!(
+                        "{}. {provider} ({status}, {confidence} confidence)",
+                        priority + 1
+                    );
+                }
+            } else {
+                println!("{}", used.join(", "));
+            }
+
+            if let Some(warning) = get_provider_tie_warning(&providers, &used) {
+                eprintln!("Warning: {warning}");
+            }
+        }
+        Commands::Compose {
+            path,
+            name,
+            service,
+            no_services,
+        } => {
+            let image = name.unwrap_or_else(|| match &options.subdir {
+                Some(subdir) => default_image_name(&format!("{path}/{subdir}")),
+                None => default_image_name(&path),
+            });
+            let compose_options = ComposeOptions {
+                image,
+                services: service
+                    .iter()
+                    .map(|name| Service::from_name(name))
+                    .collect::<Result<_>>()?,
+                detect_services: !no_services,
+            };
+
+            let (path, _checkout) = checkout_source(&path, git_ref.as_ref())?;
+            let compose = generate_compose_file(&path, env, &options, &compose_options)?;
+            print!("{compose}");
+        }
+        Commands::Shell { path, print } => {
+            let (path, _checkout) = checkout_source(&path, git_ref.as_ref())?;
+            let shell = generate_nix_shell(&path, env, &options)?;
+            if print {
+                print!("{shell}");
+                return Ok(());
+            }
+
+            let dir = TempDir::new("nixpacks-shell").context("Creating temporary directory")?;
+            let shell_file = dir.path().join("shell.nix");
+            fs::write(&shell_file, shell).context("Writing shell.nix")?;
+
+            let status = match Command::new("nix-shell")
+                .arg(&shell_file)
+                .current_dir(&path)
+                .status()
+            {
+                Err(e) if e.kind() == ErrorKind::NotFound => {
+                    bail!("nix-shell is not installed. Use --print to write the shell.nix instead")
+                }
+                status => status.context("Running nix-shell")?,
+            };
+            drop(dir);
+            std::process::exit(status.code().unwrap_or(1));
         }
         // Generate a Dockerfile and builds a container, using any specified build options.
         Commands::Build {
             path,
             name,
             out,
+            out_format,
+            log_format,
             dockerfile,
             tag,
             label,
             platform,
             cache_key,
             current_dir,
+            include_ignored,
+            flake,
+            run_release,
+            secret,
+            ssh,
+            registry_config,
+            allow_hooks,
+            use_dockerfile,
+            dockerfile_template,
+            multi,
+            jobs,
+            process,
+            init,
+            non_root,
+            nix_cache,
+            nix_cache_key,
             no_cache,
             incremental_cache_image,
             cache_from,
             docker_host,
             docker_tls_verify,
             docker_output,
+            save,
+            artifacts,
+            scan,
+            scan_severity,
+            scan_cmd,
+            k8s_out,
+            gha,
+            check_plan,
             add_host,
             docker_cert_path,
+            docker_client,
+            docker_api,
+            builder,
+            push,
             inline_cache,
             no_error_without_start,
             cpu_quota,
+            cpus,
             memory,
+            network,
+            confirm,
+            timeout,
+            retries,
             verbose,
         } => {
             let verbose = verbose || args.env.contains(&"NIXPACKS_VERBOSE=1".to_string());
@@ -286,42 +804,142 @@ async fn main() -> Result<()> {
             } else {
                 cache_key
             };
+            let (path, _checkout) = checkout_source(&path, git_ref.as_ref())?;
+
+            if check_plan {
+                let changes = get_plan_drift(&path, env.clone(), &options)?;
+                if !changes.is_empty() {
+                    eprintln!("The committed plan differs from the plan detected for the app:\n");
+                    for change in changes {
+                        eprint!("{change}");
+                    }
+                    std::process::exit(1);
+                }
+            }
 
             let build_options = &DockerBuilderOptions {
                 name,
                 tags: tag,
                 labels: label,
                 out_dir: out,
+                out_format: match out_format {
+                    OutFormat::Dir => OutputFormat::Dir,
+                    OutFormat::Oci => OutputFormat::Oci,
+                },
                 quiet: false,
                 cache_key,
                 no_cache,
                 platform,
                 print_dockerfile: dockerfile,
                 current_dir,
+                include_ignored,
+                nix_flake: flake,
+                non_root,
+                init,
+                process,
+                run_release,
+                secrets: secret,
+                ssh,
+                registry_configs: registry_config,
+                allow_hooks,
+                use_dockerfile,
+                dockerfile_template,
+                git_info: None,
+                log_format: match log_format {
+                    LogFormatArg::Text => LogFormat::Text,
+                    LogFormatArg::Json => LogFormat::Json,
+                },
+                nix_caches: nix_cache,
+                nix_cache_keys: nix_cache_key,
                 inline_cache,
                 cache_from,
                 docker_host,
                 docker_tls_verify,
                 docker_output,
+                save,
+                artifacts,
+                scan,
+                scan_severity,
+                scan_cmd,
+                k8s_out,
+                gha,
                 docker_cert_path,
+                docker_client,
+                docker_api,
+                builder,
+                push,
                 no_error_without_start,
                 incremental_cache_image,
                 cpu_quota,
+                cpus,
                 add_host,
                 memory,
+                network,
+                confirm,
+                timeout,
+                retries,
                 verbose,
             };
-            create_docker_image(&path, env, &options, build_options).await?;
+            let result = match multi {
+                Some(apps) => {
+                    create_docker_images(&path, &apps, jobs, env, &options, build_options)
+                        .await
+                        .map(|_| ())
+                }
+                None => create_docker_image(&path, env, &options, build_options)
+                    .await
+                    .map(|_| ()),
+            };
+            match result {
+                Err(error @ NixpacksError::TimedOut { .. }) => {
+                    eprintln!("Error: {error}");
+                    std::process::exit(TIMEOUT_EXIT_CODE);
+                }
+                result => result?,
+            }
         }
     }
 
     Ok(())
 }
 
+/// Clones git URLs into a temporary directory, which is removed when the returned `TempDir` is dropped.
+fn checkout_source(path: &str, git_ref: Option<&String>) -> Result<(String, Option<TempDir>)> {
+    let mut source = match GitSource::parse(path) {
+        Some(source) => source,
+        None if git_ref.is_some() => {
+            anyhow::bail!("--ref can only be used when the app source is a git URL")
+        }
+        None => return Ok((path.to_string(), None)),
+    };
+    if let Some(git_ref) = git_ref {
+        source.git_ref = Some(git_ref.clone());
+    }
+
+    let dir = TempDir::new("nixpacks-git")?;
+    let app_dir = source
+        .checkout(dir.path())
+        .with_context(|| format!("Cloning {}", source.url))?;
+
+    let app_dir = app_dir
+        .to_str()
+        .with_context(|| {
+            format!(
+                "The clone directory {} is not valid UTF-8",
+                app_dir.display()
+            )
+        })?
+        .to_string();
+    Ok((app_dir, Some(dir)))
+}
+
 /// Creates a key for storing image layers in the Docker cache.
 fn get_default_cache_key(path: &str) -> Result<Option<String>> {
-    let current_dir = env::current_dir()?;
-    let source = current_dir.join(path).canonicalize();
+    // Git URLs are cloned into a new directory each time, so the URL identifies the app instead
+    let source = match GitSource::parse(path) {
+        Some(_) => Ok(path.into()),
+        None => env::current_dir()?.join(path).canonicalize(),
+    };
     if let Ok(source) = source {
         let source_str = source.to_string_lossy().to_string();
         let mut hasher = DefaultHasher::new();
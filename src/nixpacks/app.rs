@@ -1,7 +1,9 @@
 use path_slash::PathBufExt;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashMap};
 use std::ffi::OsStr;
 use std::path::Path;
+use std::process::Command;
+use std::sync::{Arc, Mutex};
 use std::{env, fs, path::PathBuf};
 
 use anyhow::{bail, Context, Result};
@@ -10,20 +12,59 @@ use ignore::{DirEntry, WalkBuilder};
 use regex::Regex;
 use serde::de::DeserializeOwned;
 
+use super::environment::Environment;
+
 pub type StaticAssets = BTreeMap<String, String>;
 
 pub const ASSETS_DIR: &str = "/assets/";
 
+/// Directories that never hold the app's own source files, so glob searches don't descend into them.
+const SKIPPED_DIRECTORIES: &[&str] = &["node_modules", ".git"];
+
+/// How many directories deep glob searches look by default, so detection stays fast in huge trees.
+const DEFAULT_MAX_SEARCH_DEPTH: usize = 16;
+
+/// Lockfiles that monorepos keep in the repository root rather than next to each app, with the manifest they lock.
+const ROOT_LOCKFILES: &[(&str, &str)] = &[
+    ("package-lock.json", "package.json"),
+    ("yarn.lock", "package.json"),
+    ("pnpm-lock.yaml", "package.json"),
+    ("bun.lockb", "package.json"),
+    ("Cargo.lock", "Cargo.toml"),
+    ("poetry.lock", "pyproject.toml"),
+    ("uv.lock", "pyproject.toml"),
+];
+
 /// Represents a project's file and directory paths.
 #[derive(Debug, Clone)]
 pub struct App {
     pub source: PathBuf,
     pub paths: Vec<PathBuf>,
+    manifests: ManifestCache,
+    max_search_depth: usize,
+}
+
+/// The commit an app is checked out at, which images are labelled with.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GitInfo {
+    pub sha: String,
+    /// The checked out branch, which is `None` when the HEAD is detached, as in many CI checkouts.
+    pub branch: Option<String>,
+    /// Whether the working tree has uncommitted changes.
+    pub dirty: bool,
+}
+
+/// Parsed manifest files by name, shared between clones of an app so each file is only parsed once.
+#[derive(Debug, Clone, Default)]
+struct ManifestCache {
+    json: Arc<Mutex<HashMap<String, serde_json::Value>>>,
+    toml: Arc<Mutex<HashMap<String, toml::Value>>>,
+    yaml: Arc<Mutex<HashMap<String, serde_yaml::Value>>>,
 }
 
 impl App {
     /// Generate a path representation of a project.
-    pub fn new(path: &str) -> Result<App> {
+    pub fn new<P: AsRef<Path>>(path: P) -> Result<App> {
         let current_dir = env::current_dir()?;
         let source = current_dir
             .join(path)
@@ -33,7 +74,80 @@ impl App {
         let dir = fs::read_dir(source.clone()).context("Failed to read app source directory")?;
         let paths: Vec<PathBuf> = dir.map(|path| path.unwrap().path()).collect();
 
-        Ok(App { source, paths })
+        Ok(App {
+            source,
+            paths,
+            manifests: ManifestCache::default(),
+            max_search_depth: DEFAULT_MAX_SEARCH_DEPTH,
+        })
+    }
+
+    /// Limits how many directories deep glob searches look to `NIXPACKS_MAX_SEARCH_DEPTH`, when it is set.
+    pub fn with_max_search_depth(mut self, env: &Environment) -> Result<App> {
+        if let Some(depth) = env
+            .get_config_value::<usize>("MAX_SEARCH_DEPTH")
+            .context("NIXPACKS_MAX_SEARCH_DEPTH must be a number of directories")?
+        {
+            self.max_search_depth = depth;
+        }
+        Ok(self)
+    }
+
+    /// The app in a subdirectory of `path`, such as one service of a monorepo.
+    ///
+    /// When the subdirectory is a member of a Cargo or Turborepo workspace in the repository root, the root is
+    /// returned instead, along with the config variable and value that build only that member. This way the
+    /// install phase uses the root lockfile and can see the sibling packages of the workspace.
+    pub fn new_in_subdir<P: AsRef<Path>>(
+        path: P,
+        subdir: &str,
+    ) -> Result<(App, Option<(&'static str, String)>)> {
+        let root = App::new(path)?;
+        let app = App::new(root.source.join(subdir))
+            .with_context(|| format!("Failed to read subdirectory `{subdir}`"))?;
+        if !app.source.starts_with(&root.source) {
+            bail!("The subdirectory `{subdir}` is outside of the app source");
+        }
+
+        // Only the root lockfiles for manifests that the subdirectory has but doesn't lock itself are needed
+        let is_locked = |manifest: &str| {
+            ROOT_LOCKFILES
+                .iter()
+                .any(|(lockfile, other)| *other == manifest && app.includes_file(lockfile))
+        };
+        let lockfiles: Vec<&str> = ROOT_LOCKFILES
+            .iter()
+            .filter(|(lockfile, manifest)| {
+                root.includes_file(lockfile) && app.includes_file(manifest) && !is_locked(manifest)
+            })
+            .map(|(lockfile, _)| *lockfile)
+            .collect();
+        if lockfiles.is_empty() {
+            return Ok((app, None));
+        }
+
+        if root.includes_file("Cargo.lock") && app.includes_file("Cargo.toml") {
+            let manifest: toml::Value = app.read_toml("Cargo.toml")?;
+            if let Some(name) = manifest
+                .get("package")
+                .and_then(|package| package.get("name"))
+                .and_then(toml::Value::as_str)
+            {
+                return Ok((root, Some(("CARGO_WORKSPACE", name.to_string()))));
+            }
+        }
+        if root.includes_file("turbo.json") && app.includes_file("package.json") {
+            // Turborepo apps are found by the name of their directory
+            if let Some(name) = app.source.file_name() {
+                let name = name.to_string_lossy().into_owned();
+                return Ok((root, Some(("TURBO_APP_NAME", name))));
+            }
+        }
+
+        bail!(
+            "The subdirectory `{subdir}` uses the {} in the repository root, but only members of Cargo and Turborepo workspaces can be built from a subdirectory. Build the repository root instead",
+            lockfiles.join(" and ")
+        )
     }
 
     /// Check if a file exists
@@ -71,38 +185,56 @@ impl App {
 
     /// Check whether a shell-style pattern matches any paths in the app.
     fn find_glob(&self, pattern: &str) -> Result<Vec<PathBuf>> {
+        Ok(self.glob_paths(pattern)?.collect())
+    }
+
+    /// Lazily walks the app for paths matching a shell-style pattern, in file name order.
+    fn glob_paths(&self, pattern: &str) -> Result<Box<dyn Iterator<Item = PathBuf>>> {
         let full_pattern = self.source.join(pattern);
 
         let pattern_str = match full_pattern.to_str() {
             Some(s) => s,
-            None => return Ok(Vec::new()),
+            None => return Ok(Box::new(std::iter::empty())),
         };
 
         let walker = WalkBuilder::new(&self.source)
             // this includes hidden directories & files
             .hidden(false)
+            .max_depth(Some(self.max_search_depth))
+            .filter_entry(|entry| {
+                !(entry.file_type().map_or(false, |t| t.is_dir())
+                    && SKIPPED_DIRECTORIES
+                        .iter()
+                        .any(|dir| entry.file_name() == OsStr::new(dir)))
+            })
             .sort_by_file_name(OsStr::cmp)
             .build();
         let glob = Glob::new(pattern_str)?.compile_matcher();
 
-        let relative_paths = walker
+        let paths = walker
             .into_iter()
             .filter_map(Result::ok) // remove bad ones
             .map(DirEntry::into_path) // convert to paths
-            .filter(|path| glob.is_match(path)) // find matches
-            .collect();
+            .filter(move |path| glob.is_match(path)); // find matches
 
-        Ok(relative_paths)
+        Ok(Box::new(paths))
     }
 
-    /// Check if a path matching a glob exists
-    pub fn has_match(&self, pattern: &str) -> bool {
-        match self.find_files(pattern) {
-            Ok(v) => !v.is_empty(),
+    /// Check if a file matching a glob exists anywhere in the app, such as `**/*.csproj`.
+    ///
+    /// Unlike `includes_file`, this finds manifests that aren't at the root, and it stops at the first match.
+    pub fn includes_glob(&self, pattern: &str) -> bool {
+        match self.glob_paths(pattern) {
+            Ok(mut paths) => paths.any(|path| path.is_file()),
             Err(_e) => false,
         }
     }
 
+    /// Check if a path matching a glob exists
+    pub fn has_match(&self, pattern: &str) -> bool {
+        self.includes_glob(pattern)
+    }
+
     /// Read the contents of a file
     ///
     /// # Errors
@@ -111,10 +243,7 @@ impl App {
         let data = fs::read_to_string(PathBuf::from_slash_lossy(
             self.source.join(name).as_os_str(),
         ))
-        .with_context(|| {
-            let relative_path = self.strip_source_path(Path::new(name)).unwrap();
-            format!("Error reading {}", relative_path.to_str().unwrap())
-        })?;
+        .with_context(|| format!("Error reading {}", self.relative_display(name)))?;
 
         Ok(data.replace("\r\n", "\n"))
     }
@@ -147,7 +276,8 @@ impl App {
 
     #[cfg(target_os = "windows")]
     pub fn is_file_executable(&self, name: &str) -> bool {
-        true
+        // Windows has no executable bit, and files in the build context are executable in the image
+        self.source.join(name).is_file()
     }
 
     /// Check if a path is an executable file
@@ -165,29 +295,31 @@ impl App {
     }
 
     /// Try to json-parse a file.
+    ///
+    /// The file is only parsed the first time it is read, and later reads deserialize the cached value.
     pub fn read_json<T>(&self, name: &str) -> Result<T>
     where
         T: DeserializeOwned,
     {
-        let contents = self.read_file(name)?;
-        let value: T = serde_json::from_str(contents.as_str()).with_context(|| {
-            let relative_path = self.strip_source_path(Path::new(name)).unwrap();
-            format!("Error reading {} as JSON", relative_path.to_str().unwrap())
+        let value = self.read_manifest(name, "JSON", &self.manifests.json, |contents| {
+            Ok(serde_json::from_str(contents)?)
         })?;
-        Ok(value)
+        serde_json::from_value(value).with_context(|| self.manifest_error(name, "JSON"))
     }
 
     /// Try to toml-parse a file.
+    ///
+    /// The file is only parsed the first time it is read, and later reads deserialize the cached value.
     pub fn read_toml<T>(&self, name: &str) -> Result<T>
     where
         T: DeserializeOwned,
     {
-        let contents = self.read_file(name)?;
-        let toml_file = toml::from_str(contents.as_str()).with_context(|| {
-            let relative_path = self.strip_source_path(Path::new(name)).unwrap();
-            format!("Error reading {} as TOML", relative_path.to_str().unwrap())
+        let value = self.read_manifest(name, "TOML", &self.manifests.toml, |contents| {
+            Ok(toml::from_str(contents)?)
         })?;
-        Ok(toml_file)
+        value
+            .try_into()
+            .with_context(|| self.manifest_error(name, "TOML"))
     }
 
     /// Parse jsonc files as json by ignoring all kinds of comments
@@ -217,45 +349,95 @@ impl App {
                                 break;
                             }
                             None => break,
-                            _ => continue,
+                            _ => {}
                         }
                     }
                 }
                 _ => cleaned_jsonc.push(current_char),
             }
         }
-        let value: T = serde_json::from_str(cleaned_jsonc.as_str()).with_context(|| {
-            let relative_path = self.strip_source_path(Path::new(name)).unwrap();
-            format!("Error reading {} as JSONC", relative_path.to_str().unwrap())
-        })?;
+        let value: T = serde_json::from_str(cleaned_jsonc.as_str())
+            .with_context(|| format!("Error reading {} as JSONC", self.relative_display(name)))?;
         Ok(value)
     }
 
     /// Try to yaml-parse a file.
+    ///
+    /// The file is only parsed the first time it is read, and later reads deserialize the cached value.
     pub fn read_yaml<T>(&self, name: &str) -> Result<T>
     where
         T: DeserializeOwned,
     {
+        let value = self.read_manifest(name, "YAML", &self.manifests.yaml, |contents| {
+            Ok(serde_yaml::from_str(contents)?)
+        })?;
+        serde_yaml::from_value(value).with_context(|| self.manifest_error(name, "YAML"))
+    }
+
+    /// Parse a file into a generic value, or return the value cached when it was last parsed.
+    fn read_manifest<V: Clone>(
+        &self,
+        name: &str,
+        format: &str,
+        cache: &Mutex<HashMap<String, V>>,
+        parse: impl FnOnce(&str) -> Result<V>,
+    ) -> Result<V> {
+        if let Some(value) = cache.lock().unwrap().get(name) {
+            return Ok(value.clone());
+        }
+
         let contents = self.read_file(name)?;
-        let yaml_file = serde_yaml::from_str(contents.as_str())?;
-        Ok(yaml_file)
+        let value = parse(&contents).with_context(|| self.manifest_error(name, format))?;
+        cache
+            .lock()
+            .unwrap()
+            .insert(name.to_string(), value.clone());
+        Ok(value)
+    }
+
+    fn manifest_error(&self, name: &str, format: &str) -> String {
+        format!("Error reading {} as {format}", self.relative_display(name))
+    }
+
+    /// A file's path relative to the app source directory, for showing in errors.
+    fn relative_display(&self, name: &str) -> String {
+        Path::new(name)
+            .strip_prefix(&self.source)
+            .unwrap_or_else(|_| Path::new(name))
+            .display()
+            .to_string()
     }
 
     /// Convert an absolute path to a path relative to the app source directory
     pub fn strip_source_path(&self, abs_path: &Path) -> Result<PathBuf> {
-        let source_str = match self.source.to_str() {
-            Some(s) => s,
-            None => bail!("Failed to parse source path"),
-        };
+        // Strip source path from absolute path. Paths outside of the source are kept as they are
+        let stripped = abs_path.strip_prefix(&self.source).unwrap_or(abs_path);
+        Ok(stripped.to_owned())
+    }
 
-        // Strip source path from absolute path
-        let stripped = match abs_path.strip_prefix(source_str) {
-            Ok(p) => p,
-            Err(_e) => abs_path,
+    /// The commit, branch, and dirtiness of the app's git checkout.
+    ///
+    /// Returns `None` if the app isn't in a git repository, or git isn't installed.
+    pub fn git_info(&self) -> Option<GitInfo> {
+        let git = |args: &[&str]| {
+            let output = Command::new("git")
+                .arg("-C")
+                .arg(&self.source)
+                .args(args)
+                .output()
+                .ok()?;
+            if output.status.success() {
+                Some(String::from_utf8_lossy(&output.stdout).trim().to_string())
+            } else {
+                None
+            }
         };
 
-        // Convert path to PathBuf
-        Ok(stripped.to_owned())
+        let sha = git(&["rev-parse", "HEAD"])?;
+        let branch = git(&["rev-parse", "--abbrev-ref", "HEAD"]).filter(|branch| branch != "HEAD");
+        let dirty = git(&["status", "--porcelain"]).map_or(false, |status| !status.is_empty());
+
+        Some(GitInfo { sha, branch, dirty })
     }
 
     /// Get the path in the container to an asset defined in `static_assets`.
@@ -271,8 +453,9 @@ mod tests {
     use serde::{Deserialize, Serialize};
     use serde_json::{Map, Value};
     use std::collections::HashMap;
+    use tempdir::TempDir;
 
-    #[derive(Serialize, Deserialize)]
+    #[derive(Debug, Serialize, Deserialize)]
     struct TestPackageJson {
         name: String,
         scripts: HashMap<String, String>,
@@ -285,6 +468,35 @@ mod tests {
         Ok(())
     }
 
+    #[cfg(unix)]
+    #[test]
+    fn test_app_in_non_utf8_directory() -> Result<()> {
+        use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+        let dir = TempDir::new("nixpacks-app")?;
+        let source = dir.path().join(OsStr::from_bytes(b"app \xff"));
+        fs::create_dir_all(source.join("web"))?;
+        fs::write(source.join("package.json"), r#"{"name": "app"}"#)?;
+        fs::write(source.join("web/package.json"), "{")?;
+
+        let app = App::new(&source)?;
+        assert!(app.includes_file("package.json"));
+        let json: Value = app.read_json("package.json")?;
+        assert_eq!(json["name"], "app");
+        assert_eq!(
+            app.strip_source_path(&app.source.join("web/package.json"))?,
+            PathBuf::from("web/package.json")
+        );
+
+        let error = app.read_json::<Value>("web/package.json").unwrap_err();
+        assert!(error.to_string().contains("web/package.json"));
+        assert!(app.read_file("missing.txt").is_err());
+
+        let (subdir, _) = App::new_in_subdir(&source, "web")?;
+        assert_eq!(subdir.source, app.source.join("web"));
+        Ok(())
+    }
+
     #[test]
     fn test_read_file() -> Result<()> {
         let app = App::new("./examples/node-npm")?;
@@ -348,6 +560,166 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_read_yaml_file() -> Result<()> {
+        let app = App::new("./examples/node-pnpm-v8")?;
+        let value: serde_yaml::Mapping = app.read_yaml("pnpm-lock.yaml")?;
+        assert!(value.get("lockfileVersion").is_some());
+        Ok(())
+    }
+
+    #[test]
+    fn test_manifests_are_cached() -> Result<()> {
+        let dir = tempdir::TempDir::new("nixpacks-app")?;
+        fs::write(dir.path().join("package.json"), r#"{"name": "first"}"#)?;
+        let app = App::new(dir.path().to_str().unwrap())?;
+
+        let value: Map<String, Value> = app.read_json("package.json")?;
+        assert_eq!(value.get("name").unwrap(), "first");
+
+        // Clones share the cache, so the changed file is not parsed again
+        fs::write(dir.path().join("package.json"), r#"{"name": "second"}"#)?;
+        let value: Map<String, Value> = app.clone().read_json("package.json")?;
+        assert_eq!(value.get("name").unwrap(), "first");
+        Ok(())
+    }
+
+    #[test]
+    fn test_manifest_errors() -> Result<()> {
+        let dir = tempdir::TempDir::new("nixpacks-app")?;
+        fs::write(dir.path().join("app.yaml"), "name: [")?;
+        fs::write(dir.path().join("package.json"), r#"{"name": 1}"#)?;
+        let app = App::new(dir.path().to_str().unwrap())?;
+
+        let error = app.read_yaml::<Value>("app.yaml").unwrap_err();
+        assert_eq!(error.to_string(), "Error reading app.yaml as YAML");
+
+        let error = app
+            .read_json::<TestPackageJson>("package.json")
+            .unwrap_err();
+        assert_eq!(error.to_string(), "Error reading package.json as JSON");
+        Ok(())
+    }
+
+    #[test]
+    fn test_includes_glob() -> Result<()> {
+        let app = App::new("./examples/node-monorepo")?;
+        assert!(app.includes_glob("**/*.tsx"));
+        assert!(app.includes_glob("**/package.json"));
+        assert!(!app.includes_glob("**/*.csproj"));
+        assert!(!app.includes_glob("[invalid"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_glob_skips_dependency_directories() -> Result<()> {
+        let dir = tempdir::TempDir::new("nixpacks-app")?;
+        fs::create_dir_all(dir.path().join("node_modules/left-pad"))?;
+        fs::write(dir.path().join("node_modules/left-pad/package.json"), "{}")?;
+        fs::write(dir.path().join("package.json"), "{}")?;
+        let app = App::new(dir.path().to_str().unwrap())?;
+
+        assert_eq!(
+            app.find_files("**/package.json")?,
+            vec![app.source.join("package.json")]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_max_search_depth() -> Result<()> {
+        let dir = tempdir::TempDir::new("nixpacks-app")?;
+        fs::create_dir_all(dir.path().join("a/b"))?;
+        fs::write(dir.path().join("a/package.json"), "{}")?;
+        fs::write(dir.path().join("a/b/package.json"), "{}")?;
+
+        let env = Environment::from_envs(vec!["NIXPACKS_MAX_SEARCH_DEPTH=2"])?;
+        let app = App::new(dir.path().to_str().unwrap())?.with_max_search_depth(&env)?;
+        assert_eq!(
+            app.find_files("**/package.json")?,
+            vec![app.source.join("a/package.json")]
+        );
+
+        let env = Environment::from_envs(vec!["NIXPACKS_MAX_SEARCH_DEPTH=deep"])?;
+        assert!(App::new(dir.path().to_str().unwrap())?
+            .with_max_search_depth(&env)
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_new_in_subdir() -> Result<()> {
+        let dir = tempdir::TempDir::new("nixpacks-app")?;
+        let root = dir.path().to_str().unwrap();
+        fs::create_dir_all(dir.path().join("apps/api"))?;
+        fs::create_dir_all(dir.path().join("apps/web"))?;
+        fs::create_dir_all(dir.path().join("crates/server"))?;
+        fs::write(dir.path().join("yarn.lock"), "")?;
+        fs::write(dir.path().join("Cargo.lock"), "")?;
+        fs::write(dir.path().join("apps/api/package.json"), "{}")?;
+        fs::write(dir.path().join("apps/web/package.json"), "{}")?;
+        fs::write(dir.path().join("apps/web/yarn.lock"), "# web")?;
+        fs::write(
+            dir.path().join("crates/server/Cargo.toml"),
+            "[package]\nname = \"server\"",
+        )?;
+
+        // Subdirectories with their own lockfiles are used in place
+        let (app, member) = App::new_in_subdir(root, "apps/web")?;
+        assert!(member.is_none());
+        assert_eq!(app.source, dir.path().join("apps/web").canonicalize()?);
+        assert_eq!(app.read_file("yarn.lock")?, "# web");
+
+        // Workspace members are built from the root
+        let (app, member) = App::new_in_subdir(root, "crates/server")?;
+        assert_eq!(app.source, dir.path().canonicalize()?);
+        assert_eq!(member, Some(("CARGO_WORKSPACE", "server".to_string())));
+
+        // The root lockfile can't be used without a workspace that selects the app
+        assert!(App::new_in_subdir(root, "apps/api").is_err());
+        fs::write(dir.path().join("turbo.json"), "{}")?;
+        let (app, member) = App::new_in_subdir(root, "apps/api")?;
+        assert_eq!(app.source, dir.path().canonicalize()?);
+        assert_eq!(member, Some(("TURBO_APP_NAME", "api".to_string())));
+
+        assert!(App::new_in_subdir(root, "apps/worker").is_err());
+        assert!(App::new_in_subdir(format!("{root}/apps/api"), "../web").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_git_info() -> Result<()> {
+        let dir = tempdir::TempDir::new("nixpacks-app")?;
+        let app = App::new(dir.path().to_str().unwrap())?;
+        assert_eq!(app.git_info(), None);
+
+        let git = |args: &[&str]| {
+            Command::new("git")
+                .arg("-C")
+                .arg(dir.path())
+                .args(["-c", "user.name=Test", "-c", "user.email=test@example.com"])
+                .args(args)
+                .output()
+                .unwrap()
+        };
+        git(&["init", "-q", "-b", "main"]);
+        fs::write(dir.path().join("index.js"), "")?;
+        git(&["add", "."]);
+        git(&["commit", "-q", "-m", "Initial commit"]);
+
+        let info = app.git_info().unwrap();
+        assert_eq!(info.sha.len(), 40);
+        assert_eq!(info.branch, Some("main".to_string()));
+        assert!(!info.dirty);
+
+        fs::write(dir.path().join("index.js"), "console.log(1)")?;
+        assert!(app.git_info().unwrap().dirty);
+
+        git(&["checkout", "-q", "--detach"]);
+        assert_eq!(app.git_info().unwrap().branch, None);
+        Ok(())
+    }
+
     #[test]
     fn test_find_files() -> Result<()> {
         let app = App::new("./examples/node-monorepo")?;
@@ -0,0 +1,106 @@
+use super::{client::DockerClient, dockerfile_generation::APP_DIR};
+use anyhow::{bail, Context, Result};
+use std::{fs, path::Path};
+
+/// A path in the built image to copy to the host, given as `<image path>=<local path>`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Artifact {
+    /// Absolute path in the image. Relative paths are resolved against the app directory.
+    pub image_path: String,
+    pub local_path: String,
+}
+
+impl Artifact {
+    pub fn parse(value: &str) -> Result<Artifact> {
+        let (image_path, local_path) = match value.split_once('=') {
+            Some((image_path, local_path)) => (image_path.trim(), local_path.trim()),
+            None => bail!("Artifact `{value}` must be given as <image path>=<local path>"),
+        };
+        if image_path.is_empty() || local_path.is_empty() {
+            bail!("Artifact `{value}` must be given as <image path>=<local path>");
+        }
+
+        let image_path = if image_path.starts_with('/') {
+            image_path.to_string()
+        } else {
+            format!("{APP_DIR}{}", image_path.trim_start_matches("./"))
+        };
+        Ok(Artifact {
+            image_path,
+            local_path: local_path.to_string(),
+        })
+    }
+}
+
+/// Copies the artifacts out of a container created from the image, which is removed afterwards.
+pub fn extract(
+    client: DockerClient,
+    host: Option<&str>,
+    image: &str,
+    artifacts: &[Artifact],
+) -> Result<()> {
+    let container = client
+        .output(host, &["create", image])
+        .context("Creating a container to copy artifacts from")?;
+    let container = container.trim();
+
+    let result = artifacts.iter().try_for_each(|artifact| {
+        if let Some(parent) = Path::new(&artifact.local_path).parent() {
+            if !parent.as_os_str().is_empty() {
+                fs::create_dir_all(parent)
+                    .with_context(|| format!("Creating {}", parent.display()))?;
+            }
+        }
+        client
+            .output(
+                host,
+                &[
+                    "cp",
+                    &format!("{container}:{}", artifact.image_path),
+                    &artifact.local_path,
+                ],
+            )
+            .with_context(|| {
+                format!(
+                    "Copying {} from the image to {}",
+                    artifact.image_path, artifact.local_path
+                )
+            })
+            .map(|_| ())
+    });
+
+    // Remove the container even when a copy failed, so it doesn't pile up on the daemon
+    client
+        .output(host, &["rm", container])
+        .context("Removing the container artifacts were copied from")?;
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_artifact() {
+        assert_eq!(
+            Artifact::parse("dist=/tmp/out").unwrap(),
+            Artifact {
+                image_path: "/app/dist".to_string(),
+                local_path: "/tmp/out".to_string(),
+            }
+        );
+        assert_eq!(
+            Artifact::parse("./target/release/app=bin/app")
+                .unwrap()
+                .image_path,
+            "/app/target/release/app"
+        );
+        assert_eq!(
+            Artifact::parse("/usr/share/doc=docs").unwrap().image_path,
+            "/usr/share/doc"
+        );
+        assert!(Artifact::parse("dist").is_err());
+        assert!(Artifact::parse("=out").is_err());
+        assert!(Artifact::parse("dist=").is_err());
+    }
+}
@@ -0,0 +1,208 @@
+use anyhow::{anyhow, bail, Context, Error, Result};
+use std::process::{Command, Stdio};
+
+/// The container engine CLI used to build, inspect, and run images.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DockerClient {
+    #[default]
+    Docker,
+    Podman,
+}
+
+impl DockerClient {
+    /// Parse a client from its CLI name (`docker` or `podman`).
+    pub fn from_name(name: &str) -> Result<DockerClient> {
+        match name.trim().to_lowercase().as_str() {
+            "docker" => Ok(DockerClient::Docker),
+            "podman" => Ok(DockerClient::Podman),
+            other => bail!("Unknown docker client `{other}`. Expected `docker` or `podman`"),
+        }
+    }
+
+    /// Use the client that was asked for, or detect one that is installed on the host.
+    ///
+    /// Docker is preferred when both are available, since that is what Nixpacks has always used.
+    pub fn resolve(name: Option<&str>) -> Result<DockerClient> {
+        match name {
+            Some(name) => DockerClient::from_name(name),
+            None => Ok(DockerClient::detect()),
+        }
+    }
+
+    /// Find the first installed client, falling back to Docker if neither is found.
+    pub fn detect() -> DockerClient {
+        if DockerClient::Docker.is_installed() {
+            DockerClient::Docker
+        } else if DockerClient::Podman.is_installed() {
+            DockerClient::Podman
+        } else {
+            DockerClient::Docker
+        }
+    }
+
+    /// The name of the executable for this client.
+    pub fn binary(self) -> &'static str {
+        match self {
+            DockerClient::Docker => "docker",
+            DockerClient::Podman => "podman",
+        }
+    }
+
+    /// Create a new command that invokes this client.
+    pub fn command(self) -> Command {
+        Command::new(self.binary())
+    }
+
+    pub fn is_podman(self) -> bool {
+        self == DockerClient::Podman
+    }
+
+    /// The variable this client reads to find a remote daemon/service.
+    pub fn host_env_var(self) -> &'static str {
+        match self {
+            DockerClient::Docker => "DOCKER_HOST",
+            DockerClient::Podman => "CONTAINER_HOST",
+        }
+    }
+
+    /// Runs the client against the daemon at `host` and returns its output, failing with its error output.
+    pub fn output(self, host: Option<&str>, args: &[&str]) -> Result<String> {
+        let mut command = self.command();
+        match host {
+            Some(value) => command.env(self.host_env_var(), value),
+            None => command.env_remove(self.host_env_var()),
+        };
+
+        let output = command
+            .args(args)
+            .output()
+            .with_context(|| format!("Running {} {}", self.binary(), args.join(" ")))?;
+        if !output.status.success() {
+            bail!("{}", String::from_utf8_lossy(&output.stderr).trim());
+        }
+
+        Ok(String::from_utf8_lossy(&output.stdout).to_string())
+    }
+
+    /// Check whether the client executable can be spawned.
+    pub fn is_installed(self) -> bool {
+        self.command()
+            .arg("--version")
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .is_ok()
+    }
+
+    /// The error for when the client isn't installed, with a link to install it.
+    pub fn not_installed_error(self) -> Error {
+        match self {
+            DockerClient::Docker => anyhow!(
+                "Please install Docker to build the app https://docs.docker.com/engine/install/"
+            ),
+            DockerClient::Podman => {
+                anyhow!(
+                    "Please install Podman to build the app https://podman.io/docs/installation"
+                )
+            }
+        }
+    }
+
+    /// Check that the client can reach its daemon, so a stopped daemon is reported before anything is built.
+    pub fn ensure_daemon_running(self, host: Option<&str>) -> Result<()> {
+        let mut info_cmd = self.command();
+        match host {
+            Some(value) => info_cmd.env(self.host_env_var(), value),
+            None => info_cmd.env_remove(self.host_env_var()),
+        };
+        let output = info_cmd
+            .arg("info")
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .output()
+            .map_err(|_| self.not_installed_error())?;
+
+        if !output.status.success() {
+            return Err(self.daemon_error(host, &String::from_utf8_lossy(&output.stderr)));
+        }
+
+        Ok(())
+    }
+
+    /// The error for when the daemon can't be reached, with a hint for the usual causes.
+    pub fn daemon_error(self, host: Option<&str>, cause: &str) -> Error {
+        let (daemon, hint) = match self {
+            DockerClient::Docker if cause.to_lowercase().contains("permission denied") => (
+                "Docker daemon",
+                "Add your user to the docker group with `sudo usermod -aG docker $USER` and log in again, or run Nixpacks with sudo",
+            ),
+            DockerClient::Docker => (
+                "Docker daemon",
+                "Start Docker, e.g. with `sudo systemctl start docker` or by opening Docker Desktop, or set DOCKER_HOST to a running daemon",
+            ),
+            DockerClient::Podman => (
+                "Podman service",
+                "Start Podman, e.g. with `podman machine start` or `systemctl --user start podman.socket`, or set CONTAINER_HOST to a running service",
+            ),
+        };
+        let target = host.map(|host| format!(" at {host}")).unwrap_or_default();
+
+        anyhow!(
+            "Cannot connect to the {daemon}{target}. {hint}\n\n{}",
+            cause.trim()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_docker_client_from_name() {
+        assert_eq!(
+            DockerClient::from_name("docker").unwrap(),
+            DockerClient::Docker
+        );
+        assert_eq!(
+            DockerClient::from_name("Podman").unwrap(),
+            DockerClient::Podman
+        );
+        assert!(DockerClient::from_name("nerdctl").is_err());
+    }
+
+    #[test]
+    fn test_docker_client_resolve_explicit() {
+        assert_eq!(
+            DockerClient::resolve(Some("podman")).unwrap(),
+            DockerClient::Podman
+        );
+        assert_eq!(DockerClient::Podman.binary(), "podman");
+        assert_eq!(DockerClient::Podman.host_env_var(), "CONTAINER_HOST");
+    }
+
+    #[test]
+    fn test_daemon_error() {
+        let error = DockerClient::Docker.daemon_error(
+            None,
+            "Cannot connect to the Docker daemon at unix:///var/run/docker.sock. Is the docker daemon running?\n",
+        );
+        assert!(error.to_string().starts_with(
+            "Cannot connect to the Docker daemon. Start Docker, e.g. with `sudo systemctl start docker`"
+        ));
+        assert!(error.to_string().ends_with("Is the docker daemon running?"));
+
+        let error = DockerClient::Docker.daemon_error(
+            Some("unix:///var/run/docker.sock"),
+            "permission denied while trying to connect to the Docker daemon socket",
+        );
+        assert!(error.to_string().starts_with(
+            "Cannot connect to the Docker daemon at unix:///var/run/docker.sock. Add your user to the docker group"
+        ));
+
+        let error = DockerClient::Podman.daemon_error(None, "unable to connect to Podman socket");
+        assert!(error
+            .to_string()
+            .starts_with("Cannot connect to the Podman service. Start Podman"));
+    }
+}
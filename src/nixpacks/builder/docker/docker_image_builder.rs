@@ -1,23 +1,53 @@
-use super::{dockerfile_generation::DockerfileGenerator, DockerBuilderOptions, ImageBuilder};
+use super::{
+    dockerfile_generation::DockerfileGenerator, DockerBuilderOptions, ImageBuilder, OutputFormat,
+};
 use crate::nixpacks::{
     builder::docker::{
-        dockerfile_generation::OutputDir,
+        artifacts::{self, Artifact},
+        client::DockerClient,
+        dockerfile_generation::{get_build_args, OutputDir},
+        engine::DockerEngine,
         file_server::FileServer,
+        gha,
+        image_size::{ImageSize, DEFAULT_IMAGE_SIZE_WARNING},
         incremental_cache::{IncrementalCache, IncrementalCacheDirs},
+        limits,
+        provenance::{self, BUILD_HASH_LABEL},
+        remote::RemoteBuilder,
+        retry,
+        scan::{self, Severity, DEFAULT_SCAN_SEVERITY},
+        utils,
     },
-    environment::Environment,
-    files,
-    logger::Logger,
+    environment::{is_shell_variable_name, Environment},
+    error::NixpacksError,
+    files::{self, CopyOptions},
+    logger::{LogFormat, Logger},
     plan::BuildPlan,
 };
 use anyhow::{bail, Context, Ok, Result};
+use flate2::{write::GzEncoder, Compression};
+use path_slash::PathBufExt;
 use std::{
+    env,
     fs::{self, remove_dir_all, File},
-    process::Command,
+    io::{self, BufRead, BufReader, Read, Write},
+    path::{Path, PathBuf},
+    process::{Child, Command, ExitStatus, Stdio},
+    sync::mpsc,
+    thread,
+    time::{Duration, Instant},
 };
 use tempdir::TempDir;
 use uuid::Uuid;
 
+const CONTEXT_ARCHIVE_NAME: &str = "context.tar.gz";
+
+/// Paths left out of the Docker build context when the app doesn't provide a `.dockerignore`.
+const DEFAULT_DOCKERIGNORE: &[&str] = &[".git", "node_modules", ".env"];
+
+/// Apps larger than this are copied with a warning, since they make every build slow.
+const LARGE_APP_SIZE: u64 = 500_000_000;
+
 /// Builds Docker images from options, logging to stdout if the build is successful.
 pub struct DockerImageBuilder {
     logger: Logger,
@@ -25,8 +55,12 @@ pub struct DockerImageBuilder {
 }
 
 /// Determine where to write project files and generated assets like Dockerfiles.
-fn get_output_dir(app_src: &str, options: &DockerBuilderOptions) -> Result<OutputDir> {
-    if let Some(value) = &options.out_dir {
+fn get_output_dir(app_src: &Path, options: &DockerBuilderOptions) -> Result<OutputDir> {
+    if options.out_format == OutputFormat::Oci {
+        // The context is assembled in a temp dir and then archived into the out dir
+        let tmp = TempDir::new("nixpacks").context("Creating a temp directory")?;
+        OutputDir::new(tmp.into_path(), true)
+    } else if let Some(value) = &options.out_dir {
         OutputDir::new(value.into(), false)
     } else if options.current_dir {
         OutputDir::new(app_src.into(), false)
@@ -36,10 +70,75 @@ fn get_output_dir(app_src: &str, options: &DockerBuilderOptions) -> Result<Outpu
     }
 }
 
+/// The size in megabytes that apps are allowed to be, from `NIXPACKS_MAX_APP_SIZE`.
+fn get_max_app_size(env: &Environment) -> Result<Option<u64>> {
+    let megabytes = env
+        .get_config_value::<u64>("MAX_APP_SIZE")
+        .context("NIXPACKS_MAX_APP_SIZE must be a number of megabytes")?;
+    Ok(megabytes.map(|megabytes| megabytes * 1_000_000))
+}
+
+/// The size in megabytes above which the image size is reported with a warning, from `NIXPACKS_IMAGE_SIZE_WARNING`.
+///
+/// Setting it to 0 turns the warning off.
+fn get_image_size_warning(env: &Environment) -> Result<Option<u64>> {
+    let megabytes = env
+        .get_config_value::<u64>("IMAGE_SIZE_WARNING")
+        .context("NIXPACKS_IMAGE_SIZE_WARNING must be a number of megabytes")?
+        .unwrap_or(DEFAULT_IMAGE_SIZE_WARNING);
+    Ok(if megabytes == 0 {
+        None
+    } else {
+        Some(megabytes * 1_000_000)
+    })
+}
+
+/// Creates a command that runs the given line with the host's shell.
+fn shell_command(line: &str) -> Command {
+    let mut command = if cfg!(windows) {
+        let mut command = Command::new("cmd");
+        command.arg("/C");
+        command
+    } else {
+        let mut command = Command::new("sh");
+        command.arg("-c");
+        command
+    };
+    command.arg(line);
+    command
+}
+
+/// Waits for a child process, killing it when it is still running after the timeout.
+fn wait_with_timeout(
+    child: &mut Child,
+    started: Instant,
+    timeout: Option<Duration>,
+) -> Result<ExitStatus> {
+    let timeout = match timeout {
+        Some(timeout) => timeout,
+        None => return Ok(child.wait()?),
+    };
+
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(status);
+        }
+        if started.elapsed() >= timeout {
+            child.kill()?;
+            child.wait()?;
+            return Err(NixpacksError::TimedOut {
+                seconds: timeout.as_secs(),
+            }
+            .into());
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+}
+
 fn command_to_string(command: &Command) -> String {
     let args = command
         .get_args()
-        .map(|arg| arg.to_string_lossy())
+        .map(|arg| utils::shell_quote(&arg.to_string_lossy()))
         .collect::<Vec<_>>();
     format!(
         "{} {}",
@@ -48,13 +147,28 @@ fn command_to_string(command: &Command) -> String {
     )
 }
 
+/// Writes the contents of `root` as a gzipped tarball into `writer`.
+fn write_context_archive<W: Write>(root: &Path, writer: W) -> Result<W> {
+    let mut builder = tar::Builder::new(GzEncoder::new(writer, Compression::default()));
+    builder.follow_symlinks(false);
+    builder.append_dir_all(".", root)?;
+
+    Ok(builder.into_inner()?.finish()?)
+}
+
 use async_trait::async_trait;
 
 #[async_trait]
 impl ImageBuilder for DockerImageBuilder {
     /// Build a Docker image from a given BuildPlan and data from environment variables.
-    async fn create_image(&self, app_src: &str, plan: &BuildPlan, env: &Environment) -> Result<()> {
+    async fn create_image(
+        &self,
+        app_src: &Path,
+        plan: &BuildPlan,
+        env: &Environment,
+    ) -> Result<()> {
         let id = Uuid::new_v4();
+        let client = DockerClient::resolve(self.options.docker_client.as_deref())?;
 
         let output = get_output_dir(app_src, &self.options)?;
         let name = self.options.name.clone().unwrap_or_else(|| id.to_string());
@@ -67,15 +181,81 @@ impl ImageBuilder for DockerImageBuilder {
             incremental_cache_dirs.create()?;
 
             let file_server = FileServer {};
-            let config = file_server.start(&incremental_cache_dirs);
+            let config = file_server.start(&incremental_cache_dirs, self.logger.format());
             Some(config)
         } else {
             None
         };
 
-        let dockerfile = plan
-            .generate_dockerfile(&self.options, env, &output, file_server_config)
-            .context("Generating Dockerfile for plan")?;
+        for secret in &self.options.secrets {
+            if !is_shell_variable_name(secret) {
+                bail!("Secret `{secret}` must be a valid environment variable name");
+            }
+            if self.options.out_format == OutputFormat::Oci || self.options.docker_api {
+                bail!("Secrets require BuildKit and cannot be used with --out-format oci or --docker-api");
+            }
+            if self.options.out_dir.is_none() && env::var_os(secret).is_none() {
+                bail!("Secret `{secret}` is not set in the environment");
+            }
+        }
+
+        let artifacts = self
+            .options
+            .artifacts
+            .iter()
+            .map(|value| Artifact::parse(value))
+            .collect::<Result<Vec<_>>>()?;
+
+        for path in &self.options.registry_configs {
+            utils::get_registry_config_secret(path)?;
+            if self.options.out_format == OutputFormat::Oci || self.options.docker_api {
+                bail!("Registry configs require BuildKit and cannot be used with --out-format oci or --docker-api");
+            }
+            if !Path::new(path).is_file() {
+                bail!("Registry config `{path}` does not exist");
+            }
+        }
+
+        let scan_severity = self
+            .options
+            .scan_severity
+            .as_deref()
+            .map(Severity::from_name)
+            .transpose()?
+            .unwrap_or(DEFAULT_SCAN_SEVERITY);
+
+        if self.options.builder.is_some() && self.options.docker_api {
+            bail!("Remote builders use docker buildx and cannot be used with --docker-api");
+        }
+        if self.options.push && self.options.docker_api {
+            bail!("--push is done by BuildKit and cannot be used with --docker-api");
+        }
+        if self.options.run_release && self.options.docker_api {
+            bail!("The release command is run with the docker CLI and cannot be used with --docker-api");
+        }
+
+        if self.options.ssh {
+            if self.options.out_format == OutputFormat::Oci || self.options.docker_api {
+                bail!("SSH forwarding requires BuildKit and cannot be used with --out-format oci or --docker-api");
+            }
+            if self.options.out_dir.is_none() && env::var_os("SSH_AUTH_SOCK").is_none() {
+                bail!("--ssh needs a running SSH agent, but SSH_AUTH_SOCK is not set");
+            }
+        }
+
+        // Daemonless builders and the Engine API's classic builder don't support BuildKit cache mounts
+        let mut dockerfile_options = self.options.clone();
+        if self.options.out_format == OutputFormat::Oci || self.options.docker_api {
+            dockerfile_options.cache_key = None;
+        }
+
+        let dockerfile = if self.options.use_dockerfile {
+            fs::read_to_string(app_src.join("Dockerfile"))
+                .context("Reading the app's Dockerfile")?
+        } else {
+            plan.generate_dockerfile(&dockerfile_options, env, &output, file_server_config)
+                .context("Generating Dockerfile for plan")?
+        };
 
         // If printing the Dockerfile, don't write anything to disk
         if self.options.print_dockerfile {
@@ -83,13 +263,39 @@ impl ImageBuilder for DockerImageBuilder {
             return Ok(());
         }
 
-        self.write_app(app_src, &output).context("Writing app")?;
+        let hooks = plan.hooks.clone().unwrap_or_default();
+        self.run_hooks("pre-build", hooks.pre_build, plan, &name, app_src)?;
+
+        self.logger.start_phase("copy");
+        self.write_app(app_src, &output, env)
+            .context("Writing app")?;
         self.write_dockerfile(dockerfile, &output)
             .context("Writing Dockerfile")?;
-        plan.write_supporting_files(&self.options, env, &output)
-            .context("Writing supporting files")?;
+        if !self.options.use_dockerfile {
+            plan.write_supporting_files(&self.options, env, &output)
+                .context("Writing supporting files")?;
+        }
 
-        let mut docker_build_cmd = self.get_docker_build_cmd(plan, name.as_str(), &output)?;
+        if self.options.out_format == OutputFormat::Oci {
+            self.save_context_archive(plan, name.as_str(), &output)?;
+            self.logger.finish();
+            return Ok(());
+        }
+
+        self.logger.start_phase("build");
+        if let Some((text, fields)) = limits::get_limits_summary(&self.options) {
+            self.logger.log_summary("Resource limits", &text, fields);
+        }
+        if self.options.docker_api && self.options.out_dir.is_none() {
+            self.build_with_engine_api(plan, name.as_str(), &output, env, scan_severity)
+                .await?;
+            self.run_hooks("post-build", hooks.post_build, plan, &name, app_src)?;
+            self.logger.finish();
+            return Ok(());
+        }
+
+        let mut docker_build_cmd =
+            self.get_docker_build_cmd(client, plan, name.as_str(), &output)?;
 
         if self.options.out_dir.is_some() {
             let command_path = output.get_absolute_path("build.sh");
@@ -100,20 +306,84 @@ impl ImageBuilder for DockerImageBuilder {
 
         // Only build if the --out flag was not specified
         if self.options.out_dir.is_none() {
-            // Execute docker build
-            let build_result = docker_build_cmd.spawn()?.wait().context("Building image")?;
-            if !build_result.success() {
-                bail!("Docker build failed")
+            if let Some(builder) = &self.options.builder {
+                RemoteBuilder::parse(builder)?
+                    .ensure_builder(client, self.options.docker_host.as_deref())?;
             }
 
-            self.logger.log_section("Successfully Built!");
-            println!("\nRun:");
-            println!("  docker run -it {name}");
+            // Phases that are always rebuilt would be skipped by reusing an image, and pushed images aren't kept locally
+            let cached_image =
+                if self.options.no_cache || plan.has_always_rebuilt_phase() || self.options.push {
+                    None
+                } else {
+                    self.find_cached_image(client)?
+                };
+
+            if let Some(image) = cached_image {
+                self.tag_image(client, &image, &name)?;
+                self.logger
+                    .log_section("Reusing image built from the same plan and source");
+            } else {
+                // Execute docker build
+                let build_result = self.run_build(&mut docker_build_cmd);
+                // A build that was stopped leaves its context behind, unlike one that finished
+                if build_result.is_err() && output.is_temp {
+                    remove_dir_all(&output.root).ok();
+                }
+                let build_result = build_result?;
+                if !build_result.success() {
+                    return Err(NixpacksError::docker_failed(
+                        build_result.code(),
+                        "Docker build failed",
+                    )
+                    .into());
+                }
+
+                self.logger.log_section("Successfully Built!");
+            }
+
+            // Images written with --output or pushed aren't loaded into the daemon, so there is nothing to inspect
+            if self.options.docker_output.is_none() && !self.options.push {
+                let size = ImageSize::inspect(client, self.options.docker_host.as_deref(), &name);
+                self.report_image_size(size, env)?;
+            }
+            if self.options.scan {
+                self.scan_image(&name, scan_severity)?;
+            }
+            if let Some(path) = &self.options.save {
+                self.save_image(client, &name, path)?;
+            }
+            if !artifacts.is_empty() {
+                self.logger.log_step("Copying artifacts out of the image");
+                artifacts::extract(
+                    client,
+                    self.options.docker_host.as_deref(),
+                    &name,
+                    &artifacts,
+                )?;
+            }
+            if self.options.gha {
+                self.write_gha_outputs(client, plan, &name)?;
+            }
+            if self.options.push {
+                self.logger.log_output(&format!("\nPushed:\n  {name}"));
+            } else {
+                self.logger
+                    .log_output(&format!("\nRun:\n  {} run -it {name}", client.binary()));
+            }
+
+            self.run_hooks("post-build", hooks.post_build, plan, &name, app_src)?;
+
+            if self.options.run_release {
+                self.run_release_cmd(client, plan, &name)?;
+            }
 
             if self.options.incremental_cache_image.is_some() {
                 incremental_cache.create_image(
+                    client,
                     &incremental_cache_dirs,
                     &self.options.incremental_cache_image.clone().unwrap(),
+                    &self.logger,
                 )?;
             }
 
@@ -121,10 +391,11 @@ impl ImageBuilder for DockerImageBuilder {
                 remove_dir_all(output.root)?;
             }
         } else {
-            println!("\nSaved output to:");
-            println!("  {}", output.root.to_str().unwrap());
+            self.logger
+                .log_output(&format!("\nSaved output to:\n  {}", output.root.display()));
         }
 
+        self.logger.finish();
         Ok(())
     }
 }
@@ -137,33 +408,55 @@ impl DockerImageBuilder {
     /// Generates the Docker command and arguments for building the project.
     fn get_docker_build_cmd(
         &self,
+        client: DockerClient,
         plan: &BuildPlan,
         name: &str,
         output: &OutputDir,
     ) -> Result<Command> {
-        let mut docker_build_cmd = Command::new("docker");
+        let mut docker_build_cmd = client.command();
 
         // Enable BuildKit for all builds
-        docker_build_cmd.env("DOCKER_BUILDKIT", "1");
+        if !client.is_podman() {
+            docker_build_cmd.env("DOCKER_BUILDKIT", "1");
+        }
+
+        // Remote builders receive the context over BuildKit's gRPC API and stream the build logs back
+        let remote = self
+            .options
+            .builder
+            .as_deref()
+            .map(RemoteBuilder::parse)
+            .transpose()?;
+        match &remote {
+            Some(remote) => docker_build_cmd
+                .arg("buildx")
+                .arg("build")
+                .arg("--builder")
+                .arg(remote.builder_name()),
+            None => docker_build_cmd.arg("build"),
+        };
 
         docker_build_cmd
-            .arg("build")
             .arg(&output.root)
             .arg("-f")
             .arg(output.get_absolute_path("Dockerfile"))
             .arg("-t")
             .arg(name);
 
-        if self.options.verbose {
-            docker_build_cmd.arg("--progress=plain");
+        // Podman defaults to the OCI image format, which drops Docker-only metadata like HEALTHCHECK
+        if client.is_podman() {
+            docker_build_cmd.arg("--format").arg("docker");
         }
 
-        if !self.options.add_host.is_empty() {
-            for host in &self.options.add_host {
-                docker_build_cmd.arg("--add-host").arg(host);
-            }
+        if self.options.verbose && !client.is_podman() {
+            docker_build_cmd.arg("--progress=plain");
+        }
 
-            docker_build_cmd.arg("--network").arg("host");
+        for host in &self.options.add_host {
+            docker_build_cmd.arg("--add-host").arg(host);
+        }
+        if let Some(network) = limits::get_network(&self.options) {
+            docker_build_cmd.arg("--network").arg(network);
         }
 
         if self.options.quiet {
@@ -174,6 +467,26 @@ impl DockerImageBuilder {
             docker_build_cmd.arg("--no-cache");
         }
 
+        // Secrets are read from the environment by BuildKit and never written to the image
+        for secret in &self.options.secrets {
+            docker_build_cmd
+                .arg("--secret")
+                .arg(format!("id={secret},env={secret}"));
+        }
+
+        if self.options.ssh {
+            docker_build_cmd.arg("--ssh").arg("default");
+        }
+
+        for path in &self.options.registry_configs {
+            let (id, _) = utils::get_registry_config_secret(path)?;
+            let src = fs::canonicalize(path)
+                .with_context(|| format!("Reading registry config `{path}`"))?;
+            docker_build_cmd
+                .arg("--secret")
+                .arg(format!("id={id},src={}", src.display()));
+        }
+
         if let Some(value) = &self.options.cache_from {
             docker_build_cmd.arg("--cache-from").arg(value);
         }
@@ -182,9 +495,31 @@ impl DockerImageBuilder {
             docker_build_cmd.arg("--output").arg(value);
         }
 
+        // Builders that can export caches, and remote builders, keep the image to themselves unless it is loaded
+        let mut load = remote.is_some();
+        if self.options.gha && !self.options.no_cache && !client.is_podman() {
+            match gha::cache_args(|name| env::var(name).ok(), self.options.cache_key.as_deref()) {
+                Some(args) => {
+                    docker_build_cmd.args(args);
+                    load = true;
+                }
+                None => self.logger.log_step(
+                    "The GitHub Actions cache isn't available to this step, so layers aren't cached between runs",
+                ),
+            }
+        }
+        if self.options.push {
+            if client.is_podman() {
+                bail!("--push is done by BuildKit and cannot be used with Podman");
+            }
+            docker_build_cmd.arg("--push");
+        } else if load && self.options.docker_output.is_none() {
+            docker_build_cmd.arg("--load");
+        }
+
         match &self.options.docker_host {
-            Some(value) => docker_build_cmd.env("DOCKER_HOST", value),
-            None => docker_build_cmd.env_remove("DOCKER_HOST"),
+            Some(value) => docker_build_cmd.env(client.host_env_var(), value),
+            None => docker_build_cmd.env_remove(client.host_env_var()),
         };
 
         match &self.options.docker_tls_verify {
@@ -204,7 +539,7 @@ impl DockerImageBuilder {
         }
 
         // Add build environment variables
-        for (name, value) in &plan.variables.clone().unwrap_or_default() {
+        for (name, value) in &get_build_args(plan) {
             docker_build_cmd
                 .arg("--build-arg")
                 .arg(format!("{name}={value}"));
@@ -221,23 +556,533 @@ impl DockerImageBuilder {
             docker_build_cmd.arg("--platform").arg(l);
         }
 
-        if let Some(cpu_quota) = self.options.cpu_quota.clone() {
-            docker_build_cmd.arg("--cpu-quota").arg(cpu_quota);
-        }
-        if let Some(memory) = self.options.memory.clone() {
-            docker_build_cmd.arg("--memory").arg(memory);
+        // BuildKit accepts the resource limit flags but doesn't apply them
+        if client.is_podman() {
+            if let Some(cpus) = &self.options.cpus {
+                docker_build_cmd
+                    .arg("--cpu-period")
+                    .arg(limits::CPU_PERIOD.to_string())
+                    .arg("--cpu-quota")
+                    .arg(limits::get_cpu_quota(cpus)?.to_string());
+            } else if let Some(cpu_quota) = &self.options.cpu_quota {
+                docker_build_cmd.arg("--cpu-quota").arg(cpu_quota);
+            }
+            if let Some(memory) = &self.options.memory {
+                docker_build_cmd.arg("--memory").arg(memory);
+            }
+        } else if let Some(warning) = limits::get_buildkit_warning(&self.options) {
+            self.logger.log_warning(&warning);
         }
 
         Ok(docker_build_cmd)
     }
 
+    /// Runs a command to completion. When logging JSON, its output is logged line by line instead of written to the terminal.
+    fn run_logged(&self, command: &mut Command) -> Result<ExitStatus> {
+        self.run_logged_with_timeout(command, None)
+    }
+
+    /// Runs a command like [`Self::run_logged`], killing it when it is still running after the timeout.
+    fn run_logged_with_timeout(
+        &self,
+        command: &mut Command,
+        timeout: Option<Duration>,
+    ) -> Result<ExitStatus> {
+        let started = Instant::now();
+        // Output is only passed through when there is nothing to redact from it
+        if self.logger.format() == LogFormat::Text && !self.logger.has_secrets() {
+            return wait_with_timeout(&mut command.spawn()?, started, timeout);
+        }
+
+        let mut child = command
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        let pipes: Vec<Box<dyn Read + Send>> = vec![
+            Box::new(child.stdout.take().unwrap()),
+            Box::new(child.stderr.take().unwrap()),
+        ];
+
+        let (sender, receiver) = mpsc::channel();
+        for pipe in pipes {
+            let sender = sender.clone();
+            thread::spawn(move || {
+                for line in BufReader::new(pipe)
+                    .lines()
+                    .map_while(std::result::Result::ok)
+                {
+                    if sender.send(line).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(sender);
+
+        // Stop reading at the timeout, since a hung command may not print anything else
+        while let Some(line) = match timeout {
+            Some(timeout) => receiver
+                .recv_timeout(timeout.saturating_sub(started.elapsed()))
+                .ok(),
+            None => receiver.recv().ok(),
+        } {
+            self.logger.log_output(&line);
+        }
+
+        wait_with_timeout(&mut child, started, timeout)
+    }
+
+    /// Runs the build, retrying it when it pushes to a registry and failed, since a failed push is often a flaky registry.
+    fn run_build(&self, docker_build_cmd: &mut Command) -> Result<ExitStatus> {
+        let retries = if retry::pushes_to_registry(self.options.docker_output.as_deref()) {
+            self.options.retries
+        } else {
+            0
+        };
+
+        let mut attempt = 0;
+        loop {
+            let status = self
+                .run_logged_with_timeout(docker_build_cmd, self.get_timeout())
+                .context("Building image")?;
+            if status.success() || attempt >= retries {
+                return Ok(status);
+            }
+
+            let delay = retry::get_retry_delay(attempt);
+            self.logger.log_step(&format!(
+                "Build failed, retrying in {}s ({} of {retries})",
+                delay.as_secs(),
+                attempt + 1
+            ));
+            thread::sleep(delay);
+            attempt += 1;
+        }
+    }
+
+    fn get_timeout(&self) -> Option<Duration> {
+        self.options.timeout.map(Duration::from_secs)
+    }
+
+    /// Runs hook commands on the host from the app directory, with the plan and image name in their environment.
+    fn run_hooks(
+        &self,
+        stage: &str,
+        cmds: Option<Vec<String>>,
+        plan: &BuildPlan,
+        name: &str,
+        app_src: &Path,
+    ) -> Result<()> {
+        let cmds = cmds.unwrap_or_default();
+        if cmds.is_empty() {
+            return Ok(());
+        }
+
+        // Hooks come from the app's config, so they only run on this machine when asked to
+        if !self.options.allow_hooks {
+            self.logger.log_step(&format!(
+                "Skipping {stage} hooks. Pass --allow-hooks to run them"
+            ));
+            return Ok(());
+        }
+
+        self.logger.start_phase(stage);
+        self.logger.log_section(&format!("Running {stage} hooks"));
+        let plan_json = plan.to_json()?;
+        for cmd in cmds {
+            self.logger.log_step(&cmd);
+            let mut hook_cmd = shell_command(&cmd);
+            hook_cmd
+                .current_dir(app_src)
+                .env("NIXPACKS_PLAN", &plan_json)
+                .env("NIXPACKS_IMAGE_NAME", name);
+            let status = self
+                .run_logged(&mut hook_cmd)
+                .with_context(|| format!("Running {stage} hook `{cmd}`"))?;
+            if !status.success() {
+                bail!("The {stage} hook `{cmd}` failed");
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Finds a local image with the same build hash label, which has the same contents as the image being built.
+    fn find_cached_image(&self, client: DockerClient) -> Result<Option<String>> {
+        let build_hash = match provenance::get_label(&self.options.labels, BUILD_HASH_LABEL) {
+            Some(build_hash) => build_hash,
+            None => return Ok(None),
+        };
+
+        let mut images_cmd = client.command();
+        match &self.options.docker_host {
+            Some(value) => images_cmd.env(client.host_env_var(), value),
+            None => images_cmd.env_remove(client.host_env_var()),
+        };
+        let output = images_cmd
+            .arg("images")
+            .arg("-q")
+            .arg("--filter")
+            .arg(format!("label={BUILD_HASH_LABEL}={build_hash}"))
+            .output()
+            .context("Listing cached images")?;
+
+        // Fall back to building if the images can't be listed
+        if !output.status.success() {
+            return Ok(None);
+        }
+
+        Ok(String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .next()
+            .map(str::to_string))
+    }
+
+    /// Gives an existing image the name and tags of the image being built.
+    fn tag_image(&self, client: DockerClient, image: &str, name: &str) -> Result<()> {
+        for tag in std::iter::once(name).chain(self.options.tags.iter().map(String::as_str)) {
+            let mut tag_cmd = client.command();
+            match &self.options.docker_host {
+                Some(value) => tag_cmd.env(client.host_env_var(), value),
+                None => tag_cmd.env_remove(client.host_env_var()),
+            };
+            let result = tag_cmd
+                .arg("tag")
+                .arg(image)
+                .arg(tag)
+                .status()
+                .context("Tagging cached image")?;
+            if !result.success() {
+                bail!("Failed to tag image {image} as {tag}")
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Runs the plan's release command in a throwaway container of the built image, with the CLI of the given client.
+    fn run_release_cmd(&self, client: DockerClient, plan: &BuildPlan, name: &str) -> Result<()> {
+        let release_cmd = match plan.start_phase.clone().unwrap_or_default().release_cmd {
+            Some(release_cmd) => release_cmd,
+            None => return Ok(()),
+        };
+
+        self.logger.start_phase("release");
+        self.logger.log_section("Running release command");
+        let mut run_cmd = client.command();
+        run_cmd.arg("run").arg("--rm");
+        match &self.options.docker_host {
+            Some(value) => run_cmd.env(client.host_env_var(), value),
+            None => run_cmd.env_remove(client.host_env_var()),
+        };
+        run_cmd.arg(name).arg(&release_cmd);
+
+        let result = self
+            .run_logged(&mut run_cmd)
+            .context("Running release command")?;
+        if !result.success() {
+            bail!("Release command `{release_cmd}` failed")
+        }
+
+        Ok(())
+    }
+
+    /// Builds the image by streaming the build context to the Docker Engine API.
+    async fn build_with_engine_api(
+        &self,
+        plan: &BuildPlan,
+        name: &str,
+        output: &OutputDir,
+        env: &Environment,
+        scan_severity: Severity,
+    ) -> Result<()> {
+        let engine = DockerEngine::connect(&self.options)?;
+        engine.ping(&self.options).await?;
+        let context =
+            write_context_archive(&output.root, Vec::new()).context("Archiving build context")?;
+        let dockerfile_path = output
+            .get_relative_path("Dockerfile")
+            .to_slash()
+            .context("Failed to convert Dockerfile path to slash path.")?
+            .to_string();
+
+        let build = engine.build_image(
+            context,
+            &dockerfile_path,
+            name,
+            plan,
+            &self.options,
+            &self.logger,
+        );
+        // Dropping the build closes its connection, which makes the daemon cancel it
+        let build_result = match self.get_timeout() {
+            Some(timeout) => tokio::time::timeout(timeout, build)
+                .await
+                .unwrap_or_else(|_| {
+                    Err(NixpacksError::TimedOut {
+                        seconds: timeout.as_secs(),
+                    }
+                    .into())
+                }),
+            None => build.await,
+        };
+        if build_result.is_err() && output.is_temp {
+            remove_dir_all(&output.root).ok();
+        }
+        build_result?;
+
+        self.logger.log_section("Successfully Built!");
+        self.report_image_size(engine.image_size(name).await, env)?;
+        if self.options.scan {
+            self.scan_image(name, scan_severity)?;
+        }
+        if let Some(path) = &self.options.save {
+            self.logger.log_step(&format!("Saving image to {path}"));
+            let names = std::iter::once(name)
+                .chain(self.options.tags.iter().map(String::as_str))
+                .collect::<Vec<_>>();
+            engine.save_image(&names, Path::new(path)).await?;
+        }
+        self.logger
+            .log_output(&format!("\nRun:\n  docker run -it {name}"));
+
+        if output.is_temp {
+            remove_dir_all(&output.root)?;
+        }
+
+        Ok(())
+    }
+
+    /// Logs the size of the built image, warning when it is larger than `NIXPACKS_IMAGE_SIZE_WARNING`.
+    ///
+    /// The image was built either way, so failing to read its size doesn't fail the build.
+    fn report_image_size(&self, size: Result<ImageSize>, env: &Environment) -> Result<()> {
+        let size = match size {
+            std::result::Result::Ok(size) => size,
+            Err(e) => {
+                self.logger
+                    .log_step(&format!("Could not read the image size: {e:#}"));
+                return Ok(());
+            }
+        };
+
+        self.logger
+            .log_summary("Image size", &size.to_text(), size.to_json_fields());
+        if let Some(warning) = get_image_size_warning(env)? {
+            if size.total > warning {
+                self.logger.log_warning(&format!(
+                    "The image is {}, which is larger than {}. The largest layers above show what to trim, and NIXPACKS_IMAGE_SIZE_WARNING changes this limit",
+                    files::format_size(size.total),
+                    files::format_size(warning)
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Scans the built image with trivy or the `--scan-cmd`, failing the build when vulnerabilities are found.
+    fn scan_image(&self, name: &str, severity: Severity) -> Result<()> {
+        self.logger.log_section(&format!(
+            "Scanning the image for vulnerabilities with {} or higher severity",
+            severity.name()
+        ));
+
+        let mut scan_cmd = match &self.options.scan_cmd {
+            Some(cmd) => shell_command(&scan::custom_scan_command(cmd, name)),
+            None => scan::trivy_command(name, severity),
+        };
+        scan_cmd
+            .env("NIXPACKS_IMAGE_NAME", name)
+            .env("NIXPACKS_SCAN_SEVERITY", severity.name());
+        // Scanners read the image from the daemon it was built on
+        if let Some(host) = &self.options.docker_host {
+            scan_cmd.env("DOCKER_HOST", host);
+        }
+
+        let status = self.run_logged(&mut scan_cmd).map_err(|e| {
+            if self.options.scan_cmd.is_none()
+                && e.downcast_ref::<io::Error>().map(io::Error::kind)
+                    == Some(io::ErrorKind::NotFound)
+            {
+                anyhow::anyhow!("trivy is not installed. Install it from https://trivy.dev or pass another scanner with --scan-cmd")
+            } else {
+                e.context("Running the vulnerability scanner")
+            }
+        })?;
+        if !status.success() {
+            match &self.options.scan_cmd {
+                Some(cmd) => bail!("The vulnerability scan `{cmd}` failed"),
+                None => bail!(
+                    "The image has vulnerabilities with {} or higher severity",
+                    severity.name()
+                ),
+            }
+        }
+
+        self.logger
+            .log_step("No vulnerabilities found above the threshold");
+        Ok(())
+    }
+
+    /// Writes the image name, its digest, and the plan as outputs for later steps of a GitHub Actions workflow.
+    fn write_gha_outputs(&self, client: DockerClient, plan: &BuildPlan, name: &str) -> Result<()> {
+        let plan_dir = env::var_os("RUNNER_TEMP").map_or_else(env::temp_dir, PathBuf::from);
+        let plan_path = plan_dir.join(sanitize_filename::sanitize(format!(
+            "nixpacks-plan-{name}.json"
+        )));
+        fs::write(&plan_path, plan.to_json()?).context("Writing plan")?;
+
+        let mut outputs = vec![
+            ("image", name.to_string()),
+            ("plan", plan_path.display().to_string()),
+        ];
+        // Images written with --output aren't in the daemon to inspect
+        if self.options.docker_output.is_none() {
+            let digest = client.output(
+                self.options.docker_host.as_deref(),
+                &["image", "inspect", "--format", "{{.Id}}", name],
+            )?;
+            outputs.push(("digest", digest.trim().to_string()));
+        }
+
+        if !gha::write_outputs(&outputs)? {
+            self.logger
+                .log_step("GITHUB_OUTPUT is not set, so no step outputs were written");
+        }
+
+        Ok(())
+    }
+
+    /// Saves the image with its name and tags to a tar file, which `docker load` imports.
+    fn save_image(&self, client: DockerClient, name: &str, path: &str) -> Result<()> {
+        self.logger.log_step(&format!("Saving image to {path}"));
+
+        let mut save_cmd = client.command();
+        match &self.options.docker_host {
+            Some(value) => save_cmd.env(client.host_env_var(), value),
+            None => save_cmd.env_remove(client.host_env_var()),
+        };
+        save_cmd.arg("save").arg("-o").arg(path);
+        // Podman only saves several names to one archive when asked to
+        if client.is_podman() && !self.options.tags.is_empty() {
+            save_cmd.arg("--multi-image-archive");
+        }
+        let status = save_cmd
+            .arg(name)
+            .args(&self.options.tags)
+            .status()
+            .context("Saving image")?;
+        if !status.success() {
+            bail!("Saving the image to {path} failed");
+        }
+
+        Ok(())
+    }
+
+    /// Archives the build context into `context.tar.gz` in the out dir, alongside a kaniko build script.
+    fn save_context_archive(&self, plan: &BuildPlan, name: &str, output: &OutputDir) -> Result<()> {
+        let out_dir = PathBuf::from(
+            self.options
+                .out_dir
+                .clone()
+                .context("An output directory must be provided to save an oci build context")?,
+        );
+        fs::create_dir_all(&out_dir).context("Creating output directory")?;
+
+        let archive_path = out_dir.join(CONTEXT_ARCHIVE_NAME);
+        write_context_archive(&output.root, File::create(&archive_path)?)
+            .context("Writing context archive")?;
+
+        // Kaniko runs in a Linux container, so the path in the context can't use Windows separators
+        let dockerfile_path = output
+            .get_relative_path("Dockerfile")
+            .to_slash()
+            .context("Failed to convert Dockerfile path to slash path.")?
+            .to_string();
+        let mut kaniko_cmd = Command::new("/kaniko/executor");
+        kaniko_cmd
+            .arg("--context")
+            .arg(format!("tar://{}", archive_path.display()))
+            .arg("--dockerfile")
+            .arg(dockerfile_path)
+            .arg("--destination")
+            .arg(name);
+
+        for (name, value) in &get_build_args(plan) {
+            kaniko_cmd.arg("--build-arg").arg(format!("{name}={value}"));
+        }
+        for label in &self.options.labels {
+            kaniko_cmd.arg("--label").arg(label);
+        }
+
+        fs::write(out_dir.join("build.sh"), command_to_string(&kaniko_cmd))
+            .context("Write command")?;
+
+        if output.is_temp {
+            remove_dir_all(&output.root)?;
+        }
+
+        self.logger.log_output(&format!(
+            "\nSaved build context to:\n  {}",
+            archive_path.display()
+        ));
+
+        Ok(())
+    }
+
     /// Copies project files to temporary output dir, if that option was used.
-    fn write_app(&self, app_src: &str, output: &OutputDir) -> Result<()> {
+    fn write_app(&self, app_src: &Path, output: &OutputDir, env: &Environment) -> Result<()> {
         if output.is_temp {
-            files::recursive_copy_dir(app_src, &output.root)
-        } else {
-            Ok(())
+            let max_size = get_max_app_size(env)?;
+            let progress = self.logger.start_progress("Copying app");
+            let copy_options = if self.options.include_ignored {
+                CopyOptions {
+                    max_size,
+                    progress: Some(progress.clone()),
+                    ..Default::default()
+                }
+            } else {
+                let mut exclude = vec![".git".to_string()];
+                // These would be left out of the build context by the default .dockerignore anyway
+                if !app_src.join(".dockerignore").exists() {
+                    exclude.extend(DEFAULT_DOCKERIGNORE.iter().map(ToString::to_string));
+                }
+
+                CopyOptions {
+                    exclude,
+                    git_ignore: true,
+                    max_size,
+                    progress: Some(progress.clone()),
+                }
+            };
+
+            let size = files::recursive_copy_dir(app_src, &output.root, &copy_options);
+            progress.finish_and_clear();
+            let size = size?;
+            if size > LARGE_APP_SIZE {
+                self.logger.log_warning(&format!(
+                    "The app is {}, which makes the build slow. Add large files and directories that aren't needed to .nixpacksignore",
+                    files::format_size(size)
+                ));
+            }
         }
+
+        self.write_dockerignore(output)
+    }
+
+    /// Writes a `.dockerignore` to a temporary build context, unless the app already has one.
+    ///
+    /// Contexts in the app or `--out` dir are left alone so nothing is added to the user's tree.
+    /// `.nixpacksignore` patterns aren't included since they were already applied when copying the app.
+    fn write_dockerignore(&self, output: &OutputDir) -> Result<()> {
+        let dockerignore_path = output.root.join(".dockerignore");
+        if !output.is_temp || self.options.include_ignored || dockerignore_path.exists() {
+            return Ok(());
+        }
+
+        fs::write(dockerignore_path, DEFAULT_DOCKERIGNORE.join("\n") + "\n")
+            .context("Writing .dockerignore")
     }
 
     /// Writes the generated Dockerfile to the output dir.
@@ -249,3 +1094,239 @@ impl DockerImageBuilder {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::GzDecoder;
+
+    #[cfg(unix)]
+    #[test]
+    fn test_run_logged_with_timeout() -> Result<()> {
+        for format in [LogFormat::Text, LogFormat::Json] {
+            let builder = DockerImageBuilder::new(
+                Logger::with_format(format),
+                DockerBuilderOptions::default(),
+            );
+            let timeout = Some(Duration::from_millis(200));
+
+            let status =
+                builder.run_logged_with_timeout(&mut shell_command("echo done"), timeout)?;
+            assert!(status.success());
+
+            let started = Instant::now();
+            let error = builder
+                .run_logged_with_timeout(&mut shell_command("echo waiting; sleep 10"), timeout)
+                .unwrap_err();
+            assert!(matches!(
+                NixpacksError::from(error),
+                NixpacksError::TimedOut { .. }
+            ));
+            assert!(started.elapsed() < Duration::from_secs(5));
+        }
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_run_hooks() -> Result<()> {
+        let app = TempDir::new("nixpacks-app")?;
+        let app_src = app.path();
+        let hooks = Some(vec!["echo $NIXPACKS_IMAGE_NAME > hook.txt".to_string()]);
+        let plan = BuildPlan::default();
+
+        let builder = DockerImageBuilder::new(Logger::new(), DockerBuilderOptions::default());
+        builder.run_hooks("pre-build", hooks.clone(), &plan, "my-image", app_src)?;
+        assert!(!app.path().join("hook.txt").exists());
+
+        let builder = DockerImageBuilder::new(
+            Logger::new(),
+            DockerBuilderOptions {
+                allow_hooks: true,
+                ..Default::default()
+            },
+        );
+        builder.run_hooks("pre-build", hooks, &plan, "my-image", app_src)?;
+        assert_eq!(
+            fs::read_to_string(app.path().join("hook.txt"))?,
+            "my-image\n"
+        );
+
+        assert!(builder
+            .run_hooks(
+                "post-build",
+                Some(vec!["exit 1".to_string()]),
+                &plan,
+                "my-image",
+                app_src
+            )
+            .is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_command_to_string_quotes_args() {
+        let mut command = Command::new("docker");
+        command
+            .arg("build")
+            .arg("--label")
+            .arg("com.nixpacks.plan={\"phases\": {}}")
+            .arg("--build-arg")
+            .arg("MESSAGE=it's");
+
+        assert_eq!(
+            command_to_string(&command),
+            "docker build --label 'com.nixpacks.plan={\"phases\": {}}' --build-arg 'MESSAGE=it'\\''s'"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_write_app_with_unusual_paths() -> Result<()> {
+        use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+        let root = TempDir::new("nixpacks-app")?;
+        let app_src = root.path().join(OsStr::from_bytes(b"my app \xff"));
+        let non_utf8_file = OsStr::from_bytes(b"caf\xe9.txt");
+        fs::create_dir_all(app_src.join("src dir"))?;
+        fs::write(app_src.join("src dir/index.js"), "console.log('hi')")?;
+        fs::write(app_src.join(non_utf8_file), "")?;
+
+        let out = TempDir::new("nixpacks-out")?;
+        let output = OutputDir::new(out.path().to_path_buf(), true)?;
+        let builder = DockerImageBuilder::new(Logger::new(), DockerBuilderOptions::default());
+        builder.write_app(&app_src, &output, &Environment::default())?;
+
+        assert!(out.path().join("src dir/index.js").is_file());
+        assert!(out.path().join(non_utf8_file).is_file());
+
+        let builder = DockerImageBuilder::new(
+            Logger::new(),
+            DockerBuilderOptions {
+                allow_hooks: true,
+                ..Default::default()
+            },
+        );
+        let hooks = Some(vec!["touch hook.txt".to_string()]);
+        builder.run_hooks(
+            "pre-build",
+            hooks,
+            &BuildPlan::default(),
+            "my-image",
+            &app_src,
+        )?;
+        assert!(app_src.join("hook.txt").is_file());
+        Ok(())
+    }
+
+    #[test]
+    fn test_docker_build_cmd_with_remote_builder() -> Result<()> {
+        let get_args = |builder: Option<&str>| -> Result<Vec<String>> {
+            let builder = DockerImageBuilder::new(
+                Logger::new(),
+                DockerBuilderOptions {
+                    builder: builder.map(ToString::to_string),
+                    ..Default::default()
+                },
+            );
+            let command = builder.get_docker_build_cmd(
+                DockerClient::Docker,
+                &BuildPlan::default(),
+                "my-image",
+                &OutputDir::default(),
+            )?;
+            Ok(command
+                .get_args()
+                .map(|arg| arg.to_string_lossy().to_string())
+                .collect())
+        };
+
+        let args = get_args(None)?;
+        assert_eq!(args[0], "build");
+        assert!(!args.contains(&"--load".to_string()));
+
+        let args = get_args(Some("remote://builder.internal"))?;
+        assert_eq!(args[..3], ["buildx", "build", "--builder"]);
+        assert_eq!(
+            args[3],
+            RemoteBuilder::parse("tcp://builder.internal:1234")?.builder_name()
+        );
+        assert!(args.contains(&"--load".to_string()));
+
+        assert!(get_args(Some("ssh://builder.internal")).is_err());
+
+        // Pushed images aren't loaded
+        let builder = DockerImageBuilder::new(
+            Logger::new(),
+            DockerBuilderOptions {
+                builder: Some("remote://builder.internal".to_string()),
+                push: true,
+                ..Default::default()
+            },
+        );
+        let args = command_to_string(&builder.get_docker_build_cmd(
+            DockerClient::Docker,
+            &BuildPlan::default(),
+            "my-image",
+            &OutputDir::default(),
+        )?);
+        assert!(args.contains("--push"));
+        assert!(!args.contains("--load"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_docker_build_cmd_with_limits() -> Result<()> {
+        let builder = DockerImageBuilder::new(
+            Logger::new(),
+            DockerBuilderOptions {
+                cpus: Some("1.5".to_string()),
+                memory: Some("2g".to_string()),
+                network: Some("none".to_string()),
+                add_host: vec!["db:10.0.0.2".to_string()],
+                ..Default::default()
+            },
+        );
+        let get_args = |client| -> Result<String> {
+            let command = builder.get_docker_build_cmd(
+                client,
+                &BuildPlan::default(),
+                "my-image",
+                &OutputDir::default(),
+            )?;
+            Ok(command_to_string(&command))
+        };
+
+        let args = get_args(DockerClient::Docker)?;
+        assert!(args.contains("--add-host db:10.0.0.2 --network none"));
+        assert!(!args.contains("--cpu-quota"));
+        assert!(!args.contains("--memory"));
+
+        let args = get_args(DockerClient::Podman)?;
+        assert!(args.contains("--cpu-period 100000 --cpu-quota 150000 --memory 2g"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_context_archive() -> Result<()> {
+        let context = TempDir::new("nixpacks-context")?;
+        fs::create_dir_all(context.path().join(".nixpacks"))?;
+        fs::write(context.path().join(".nixpacks/Dockerfile"), "FROM scratch")?;
+        fs::write(context.path().join("index.js"), "console.log('hi')")?;
+
+        let out = TempDir::new("nixpacks-out")?;
+        let archive_path = out.path().join(CONTEXT_ARCHIVE_NAME);
+        write_context_archive(context.path(), File::create(&archive_path)?)?;
+
+        let mut archive = tar::Archive::new(GzDecoder::new(File::open(archive_path)?));
+        let entries = archive
+            .entries()?
+            .map(|entry| Ok(entry?.path()?.to_path_buf()))
+            .collect::<Result<Vec<_>>>()?;
+
+        assert!(entries.contains(&PathBuf::from(".nixpacks/Dockerfile")));
+        assert!(entries.contains(&PathBuf::from("index.js")));
+        Ok(())
+    }
+}
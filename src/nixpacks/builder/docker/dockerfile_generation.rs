@@ -1,27 +1,65 @@
 use super::{
-    file_server::FileServerConfig, incremental_cache::IncrementalCache, utils, DockerBuilderOptions,
+    client::DockerClient, dockerfile_template, file_server::FileServerConfig,
+    incremental_cache::IncrementalCache, retry, utils, DockerBuilderOptions,
 };
 use crate::nixpacks::{
     app,
-    environment::Environment,
-    images::DEFAULT_BASE_IMAGE,
-    nix::{create_nix_expressions_for_phases, nix_file_names_for_phases, setup_files_for_phases},
+    environment::{is_valid_env_name, Environment, EnvironmentVariables},
+    images::{
+        is_distroless_image, is_nixpacks_base_image, is_shell_less_image, DEFAULT_BASE_IMAGE,
+        STANDALONE_IMAGE,
+    },
+    nix::{
+        app_nix_install_cmds, create_nix_expressions_for_phases, create_nix_flake_for_phases,
+        flake_package_names_for_phases, flake_requires_impure, nix_file_names_for_phases,
+        setup_files_for_phases, NIX_FLAKE_FILE_NAME,
+    },
     plan::{
-        phase::{Phase, StartPhase},
+        cron::{CRONTAB_PATH, CRON_PROCESS, SUPERCRONIC_PKG},
+        phase::{Phase, Rebuild, StartPhase},
         BuildPlan,
     },
 };
-use anyhow::{Context, Ok, Result};
+use anyhow::{bail, Context, Ok, Result};
 use indoc::formatdoc;
 use path_slash::PathBufExt;
 use std::{
+    collections::BTreeMap,
     fs::{self, File},
     io::Write,
     path::{Path, PathBuf},
 };
+use uuid::Uuid;
 
 const NIXPACKS_OUTPUT_DIR: &str = ".nixpacks";
 pub const APP_DIR: &str = "/app/";
+/// Build argument that is given a new value on every build to rebuild phases with `rebuild = "always"`.
+pub const CACHE_BUST_ARG: &str = "NIXPACKS_CACHE_BUST";
+
+/// The build arguments of the plan, with a new `NIXPACKS_CACHE_BUST` value when a phase is always rebuilt.
+pub fn get_build_args(plan: &BuildPlan) -> EnvironmentVariables {
+    let mut args = plan.get_build_args();
+    if plan.has_always_rebuilt_phase() {
+        args.insert(CACHE_BUST_ARG.to_string(), Uuid::new_v4().to_string());
+    }
+    args
+}
+/// Unprivileged user that the app is run as by default.
+pub const DEFAULT_USER: &str = "nixpacks";
+/// The `nobody` user, which the app is run as in images without a shell to create a user with.
+const NOBODY_USER: &str = "65534:65534";
+/// Shell that runs the start command with the profile that makes Nix packages available.
+const SHELL_ENTRYPOINT: &str = "\"/bin/bash\", \"-l\", \"-c\"";
+/// Output folder for the scripts that start each of the plan's processes.
+const PROCESSES_DIR: &str = "processes";
+/// Where process scripts are copied to in the image.
+const PROCESSES_IMAGE_DIR: &str = "/nixpacks/processes";
+/// Output file for the crontab of the plan's cron jobs.
+const CRONTAB_FILE_NAME: &str = "crontab";
+/// Where the `tini` init process is installed when building with `--init`.
+const TINI_PATH: &str = "/usr/bin/tini";
+/// SSH command used by git when dependencies are fetched through the forwarded SSH agent.
+const SSH_COMMAND: &str = "ssh -o StrictHostKeyChecking=accept-new";
 
 /// Represents a directory into which project files and generated assets like Dockerfiles are written.
 #[derive(Debug, Clone)]
@@ -113,23 +151,45 @@ impl DockerfileGenerator for BuildPlan {
         let setup_files = setup_files_for_phases(&plan.phases.clone().unwrap_or_default());
         let setup_copy_cmds = utils::get_copy_commands(&setup_files, APP_DIR).join("\n");
 
-        let nix_file_names = nix_file_names_for_phases(&plan.phases.clone().unwrap_or_default());
+        let nix_install_cmds = if let Some(nix_file) = &plan.nix_file {
+            let archive = plan
+                .get_phase("setup")
+                .and_then(|setup| setup.nixpkgs_archive.as_ref());
+            app_nix_install_cmds(nix_file, archive)
+        } else if use_nix_flake(options, env) {
+            self.get_flake_install_cmds(output)?
+        } else {
+            let nix_file_names =
+                nix_file_names_for_phases(&plan.phases.clone().unwrap_or_default());
 
-        let mut nix_install_cmds: Vec<String> = Vec::new();
-        for name in nix_file_names {
-            let nix_file = output.get_relative_path(name);
+            let mut nix_install_cmds: Vec<String> = Vec::new();
+            for name in nix_file_names {
+                let nix_file = output.get_relative_path(name);
 
-            let nix_file_path = nix_file
-                .to_slash()
-                .context("Failed to convert nix file path to slash path.")?;
+                let nix_file_path = nix_file
+                    .to_slash()
+                    .context("Failed to convert nix file path to slash path.")?;
 
-            nix_install_cmds.push(format!(
-                "COPY {nix_file_path} {nix_file_path}\nRUN nix-env -if {nix_file_path} && nix-collect-garbage -d"
-            ));
-        }
-        let nix_install_cmds = nix_install_cmds.join("\n");
+                nix_install_cmds.push(format!(
+                    "COPY {nix_file_path} {nix_file_path}\nRUN nix-env -if {nix_file_path} && nix-collect-garbage -d"
+                ));
+            }
+            nix_install_cmds.join("\n")
+        };
+        // Nix downloads from mirrors and binary caches that fail now and then
+        let nix_install_cmds = retry::with_retries(&nix_install_cmds, options.retries);
+
+        let nix_cache_cmd = get_nix_cache_command(options);
 
-        let apt_pkgs = self.all_apt_packages();
+        let mut apt_pkgs = self.all_apt_packages();
+        if options.init {
+            apt_pkgs.push("tini".to_string());
+        }
+        if options.ssh {
+            apt_pkgs.push("openssh-client".to_string());
+        }
+        apt_pkgs.sort();
+        apt_pkgs.dedup();
         let apt_pkgs_str = if apt_pkgs.is_empty() {
             String::new()
         } else {
@@ -140,24 +200,33 @@ impl DockerfileGenerator for BuildPlan {
         };
 
         let variables = plan.variables.clone().unwrap_or_default();
-        let args_string = if variables.is_empty() {
+        let build_args = plan.get_build_args();
+        // Names are written into the Dockerfile as-is, so they can't contain anything that splits them
+        if let Some(name) = build_args.keys().find(|name| !is_valid_env_name(name)) {
+            bail!("Variable name `{name}` cannot be empty or contain whitespace or `=`");
+        }
+        let args_string = if build_args.is_empty() {
             String::new()
         } else {
-            format!(
-                "ARG {}\nENV {}",
-                // Pull the variables in from docker `--build-arg`
-                variables
-                    .iter()
-                    .map(|var| var.0.to_string())
-                    .collect::<Vec<_>>()
-                    .join(" "),
-                // Make the variables available at runtime
-                variables
-                    .iter()
-                    .map(|var| format!("{}=${}", var.0.trim(), var.0.trim()))
-                    .collect::<Vec<_>>()
-                    .join(" ")
-            )
+            // Pull the variables in from docker `--build-arg`
+            let args = format!(
+                "ARG {}",
+                build_args.keys().cloned().collect::<Vec<_>>().join(" ")
+            );
+
+            if variables.is_empty() {
+                args
+            } else {
+                // Make the runtime variables available in the image. Build variables are left out
+                format!(
+                    "{args}\nENV {}",
+                    variables
+                        .iter()
+                        .map(|var| format!("{}=${}", var.0.trim(), var.0.trim()))
+                        .collect::<Vec<_>>()
+                        .join(" ")
+                )
+            }
         };
 
         let static_assets = plan.static_assets.clone().unwrap_or_default();
@@ -175,7 +244,8 @@ impl DockerfileGenerator for BuildPlan {
 
         let mut dockerfile_phases = phases
             .into_iter()
-            .map(|phase| {
+            .map(|mut phase| {
+                phase.cache_directories = plan.get_phase_cache_directories(&phase);
                 let phase_dockerfile = phase
                     .generate_dockerfile(options, env, output, file_server_config.clone())
                     .context(format!(
@@ -193,8 +263,8 @@ impl DockerfileGenerator for BuildPlan {
             .map(|phase| {
                 // Ensure paths are available in the environment
                 let profile_dockerfile = if let Some(paths) = &phase.paths {
-                    let joined_paths = paths.join(":");
-                    format!("RUN printf '\\nPATH={joined_paths}:$PATH' >> /root/.profile")
+                    let path = utils::shell_quote(&format!("PATH={}:$PATH", paths.join(":")));
+                    format!("RUN printf '\\n%s' {path} >> /root/.profile")
                 } else {
                     String::new()
                 };
@@ -205,11 +275,19 @@ impl DockerfileGenerator for BuildPlan {
 
         dockerfile_phases.extend(profile_dockerfile.iter().cloned());
 
+        if plan.has_cron_jobs() {
+            let crontab_path = output
+                .get_relative_path(CRONTAB_FILE_NAME)
+                .to_slash()
+                .context("Failed to convert crontab path to slash path.")?
+                .to_string();
+            dockerfile_phases.push(format!("COPY {crontab_path} {CRONTAB_PATH}"));
+        }
+
         let dockerfile_phases_str = dockerfile_phases.join("\n");
 
         let start_phase_str = plan
-            .start_phase
-            .clone()
+            .get_start_phase()
             .unwrap_or_default()
             .generate_dockerfile(options, env, output, file_server_config)?;
 
@@ -217,14 +295,41 @@ impl DockerfileGenerator for BuildPlan {
             .build_image
             .clone()
             .unwrap_or_else(|| DEFAULT_BASE_IMAGE.to_string());
+        let nix_installer_layer = get_nix_installer_layer(&base_image)?;
+
+        if let Some(path) = &options.dockerfile_template {
+            let template = dockerfile_template::read_template(path)?;
+            let packages = [
+                setup_copy_cmds,
+                nix_cache_cmd,
+                nix_install_cmds,
+                apt_pkgs_str,
+            ]
+            .into_iter()
+            .filter(|cmds| !cmds.is_empty())
+            .collect::<Vec<_>>()
+            .join("\n");
+            let sections = BTreeMap::from([
+                ("base_image", base_image),
+                ("nix_installer", nix_installer_layer),
+                ("packages", packages),
+                ("assets", assets_copy_cmd),
+                ("env", args_string),
+                ("phases", dockerfile_phases_str),
+                ("start", start_phase_str),
+            ]);
+            return Ok(dockerfile_template::render_template(&template, &sections));
+        }
 
         let dockerfile = formatdoc! {"
             FROM {base_image}
+            {nix_installer_layer}
 
-            ENTRYPOINT [\"/bin/bash\", \"-l\", \"-c\"]
+            ENTRYPOINT [{SHELL_ENTRYPOINT}]
             WORKDIR {APP_DIR}
 
             {setup_copy_cmds}
+            {nix_cache_cmd}
             {nix_install_cmds}
             {apt_pkgs_str}
             {assets_copy_cmd}
@@ -235,8 +340,11 @@ impl DockerfileGenerator for BuildPlan {
             {start_phase_str}
         ", 
         base_image=base_image,
+        nix_installer_layer=nix_installer_layer,
+        SHELL_ENTRYPOINT=SHELL_ENTRYPOINT,
         APP_DIR=APP_DIR,
         setup_copy_cmds=setup_copy_cmds,
+        nix_cache_cmd=nix_cache_cmd,
         nix_install_cmds=nix_install_cmds,
         apt_pkgs_str=apt_pkgs_str,
         assets_copy_cmd=assets_copy_cmd,
@@ -256,8 +364,18 @@ impl DockerfileGenerator for BuildPlan {
     ) -> Result<()> {
         self.write_assets(self, output).context("Writing assets")?;
 
-        let nix_expressions =
-            create_nix_expressions_for_phases(&self.phases.clone().unwrap_or_default());
+        let phases = self.phases.clone().unwrap_or_default();
+        let nix_expressions = if self.nix_file.is_some() {
+            // The environment comes from the app's own Nix file
+            BTreeMap::new()
+        } else if use_nix_flake(options, env) {
+            BTreeMap::from([(
+                NIX_FLAKE_FILE_NAME.to_string(),
+                create_nix_flake_for_phases(&phases),
+            )])
+        } else {
+            create_nix_expressions_for_phases(&phases)
+        };
 
         for (name, nix_expression) in nix_expressions {
             let nix_path = output.get_absolute_path(name);
@@ -273,11 +391,288 @@ impl DockerfileGenerator for BuildPlan {
                 .context(format!("Writing files for phase {}", phase.get_name()))?;
         }
 
+        if let Some(crontab) = self.get_crontab() {
+            fs::write(output.get_absolute_path(CRONTAB_FILE_NAME), crontab)
+                .context("Writing crontab")?;
+        }
+
+        if let Some(start_phase) = &self.get_start_phase() {
+            start_phase
+                .write_supporting_files(options, env, output)
+                .context("Writing files for start phase")?;
+        }
+
         Ok(())
     }
 }
 
+/// Whether Nix packages should be installed from a generated flake instead of standalone expressions.
+fn use_nix_flake(options: &DockerBuilderOptions, env: &Environment) -> bool {
+    options.nix_flake || env.is_config_variable_truthy("FLAKE")
+}
+
+/// Adds the binary caches to the image's Nix config so packages are substituted instead of built.
+fn get_nix_cache_command(options: &DockerBuilderOptions) -> String {
+    let mut settings = Vec::new();
+    if !options.nix_caches.is_empty() {
+        settings.push(format!(
+            "extra-substituters = {}",
+            options.nix_caches.join(" ")
+        ));
+    }
+    if !options.nix_cache_keys.is_empty() {
+        settings.push(format!(
+            "extra-trusted-public-keys = {}",
+            options.nix_cache_keys.join(" ")
+        ));
+    }
+
+    if settings.is_empty() {
+        String::new()
+    } else {
+        // The caches and keys are user input, so they are quoted instead of being part of the format string
+        format!(
+            "RUN printf '%s\\n' {} >> /etc/nix/nix.conf",
+            settings
+                .iter()
+                .map(|setting| utils::shell_quote(setting))
+                .collect::<Vec<_>>()
+                .join(" ")
+        )
+    }
+}
+
+/// BuildKit mounts that give a phase's commands the secrets and SSH agent they were built with.
+/// Registry configs are only mounted for install phases, since that is when dependencies are fetched.
+fn get_credential_mounts(options: &DockerBuilderOptions, phase: &Phase) -> Result<String> {
+    let mut mounts = vec![utils::get_secret_mounts(&options.secrets)];
+    if options.ssh {
+        mounts.push("--mount=type=ssh".to_string());
+    }
+
+    let name = phase.get_name();
+    if name == "install" || name.ends_with(":install") {
+        for path in &options.registry_configs {
+            let (id, target) = utils::get_registry_config_secret(path)?;
+            mounts.push(format!(
+                "--mount=type=secret,id={id},target={target},required=true"
+            ));
+        }
+    }
+
+    Ok(mounts
+        .into_iter()
+        .filter(|mount| !mount.is_empty())
+        .collect::<Vec<_>>()
+        .join(" "))
+}
+
+/// Prefix a phase command so it can use the credentials from `get_credential_mounts`.
+fn with_credential_env(cmd: &str, options: &DockerBuilderOptions) -> String {
+    let cmd = utils::with_secret_env(cmd, &options.secrets);
+    if options.ssh {
+        // The build container has never connected to the git host, so trust its key on first use
+        format!("export GIT_SSH_COMMAND=\"{SSH_COMMAND}\" && {cmd}")
+    } else {
+        cmd
+    }
+}
+
+/// Installs Nix into a Debian or Ubuntu image the same way the images in `base/` do.
+const NIX_INSTALLER_LAYER: &str = r#"ARG DEBIAN_FRONTEND=noninteractive
+RUN apt-get update \
+  && apt-get install --no-install-recommends -y sudo locales curl xz-utils ca-certificates openssl make git pkg-config \
+  && apt-get clean && rm -rf /var/lib/apt/lists/* \
+  && mkdir -m 0755 /nix && mkdir -m 0755 -p /etc/nix && groupadd -r nixbld && chown root /nix \
+  && printf 'sandbox = false \nfilter-syscalls = false\n' > /etc/nix/nix.conf \
+  && printf 'experimental-features = nix-command\n' >> /etc/nix/nix.conf \
+  && for n in $(seq 1 10); do useradd -c "Nix build user $n" -d /var/empty -g nixbld -G nixbld -M -N -r -s "$(command -v nologin)" "nixbld$n"; done
+
+SHELL ["/bin/bash", "-ol", "pipefail", "-c"]
+RUN set -o pipefail && curl -L https://nixos.org/nix/install | bash \
+  && /nix/var/nix/profiles/default/bin/nix-channel --remove nixpkgs \
+  && /nix/var/nix/profiles/default/bin/nix-collect-garbage --delete-old \
+  && printf 'if [ -d $HOME/.nix-profile/etc/profile.d ]; then\n for i in $HOME/.nix-profile/etc/profile.d/*.sh; do\n if [ -r $i ]; then\n . $i\n fi\n done\n fi\n' >> /root/.profile \
+  && printf 'PATH=$NIXPACKS_PATH:$PATH' >> /root/.profile
+
+ENV \
+  ENV=/etc/profile \
+  USER=root \
+  PATH=/nix/var/nix/profiles/default/bin:/nix/var/nix/profiles/default/sbin:/bin:/sbin:/usr/bin:/usr/sbin \
+  GIT_SSL_CAINFO=/etc/ssl/certs/ca-certificates.crt \
+  NIX_SSL_CERT_FILE=/etc/ssl/certs/ca-certificates.crt \
+  NIX_PATH=/nix/var/nix/profiles/per-user/root/channels \
+  NIXPKGS_ALLOW_BROKEN=1 \
+  NIXPKGS_ALLOW_UNFREE=1 \
+  NIXPKGS_ALLOW_INSECURE=1"#;
+
+/// Commands that install Nix when building on an image other than the Nixpacks base images.
+fn get_nix_installer_layer(base_image: &str) -> Result<String> {
+    if is_nixpacks_base_image(base_image) {
+        Ok(String::new())
+    } else if is_distroless_image(base_image) {
+        bail!(
+            "Cannot build on {base_image} because distroless images have no shell to install Nix with. Use it as the start phase's runImage instead"
+        )
+    } else {
+        Ok(NIX_INSTALLER_LAYER.to_string())
+    }
+}
+
+impl StartPhase {
+    /// Runs the scheduler for the cron jobs in the background of the start command, and adds a process that only runs it.
+    fn with_cron(mut self) -> StartPhase {
+        let scheduler = format!("{SUPERCRONIC_PKG} {CRONTAB_PATH}");
+        self.cmd = Some(match &self.cmd {
+            Some(cmd) => format!("{scheduler} & {}", utils::exec_start_command(cmd)),
+            None => scheduler.clone(),
+        });
+        self.processes
+            .get_or_insert_with(BTreeMap::new)
+            .entry(CRON_PROCESS.to_string())
+            .or_insert(scheduler);
+        self
+    }
+
+    /// Whether the start command or the healthcheck can only be run by a shell.
+    fn needs_shell(&self, options: &DockerBuilderOptions, env: &Environment) -> Result<bool> {
+        let cmd_needs_shell = self
+            .get_process_cmd(options, env)?
+            .map_or(false, |cmd| utils::get_exec_form_command(&cmd).is_none());
+        let has_healthcheck = self.healthcheck.as_ref().map_or(false, |healthcheck| {
+            healthcheck.cmd.is_some() || healthcheck.path.is_some()
+        });
+        Ok(cmd_needs_shell || has_healthcheck)
+    }
+
+    /// The start stage for a run image without a shell, which starts the program directly as `nobody`.
+    ///
+    /// There is no shell to run process scripts or healthchecks with, or to create a user with.
+    fn generate_shell_less_start(
+        &self,
+        run_image: &str,
+        options: &DockerBuilderOptions,
+        env: &Environment,
+        instructions: &[&str],
+    ) -> Result<String> {
+        let start_cmd = match self.get_process_cmd(options, env)? {
+            Some(cmd) => utils::get_exec_form_command(&cmd).with_context(|| {
+                format!("Cannot start the app with `{cmd}` because {run_image} has no shell. Start the program directly, or set a start.runImage with a shell")
+            })?,
+            None => String::new(),
+        };
+        if self.healthcheck.as_ref().map_or(false, |healthcheck| {
+            healthcheck.cmd.is_some() || healthcheck.path.is_some()
+        }) {
+            bail!("Cannot run the healthcheck because {run_image} has no shell. Set a start.runImage with a shell to use one");
+        }
+
+        let mut copy_cmds = utils::get_copy_from_commands(
+            "0",
+            &self.only_include_files.clone().unwrap_or_default(),
+            APP_DIR,
+        );
+        let entrypoint_str = if options.init {
+            copy_cmds.push(format!("COPY --from=0 /usr/bin/tini-static {TINI_PATH}"));
+            format!("ENTRYPOINT [\"{TINI_PATH}\", \"-g\", \"--\"]")
+        } else {
+            String::new()
+        };
+        let user_str = match &self.user {
+            Some(user) if user != "root" => format!("USER {user}"),
+            None if options.non_root => format!("USER {NOBODY_USER}"),
+            _ => String::new(),
+        };
+
+        Ok(formatdoc! {"
+              # start
+              FROM {run_image}
+              {entrypoint_str}
+              WORKDIR {APP_DIR}
+              COPY --from=0 /etc/ssl/certs /etc/ssl/certs
+              {copy_cmds}
+              {instructions}
+              {user_str}
+              {start_cmd}
+            ",
+            copy_cmds = copy_cmds.join("\n"),
+            instructions = instructions.join("\n"),
+        })
+    }
+
+    /// The command to start the image with, which is the process picked at build time or the default start command.
+    fn get_process_cmd(
+        &self,
+        options: &DockerBuilderOptions,
+        env: &Environment,
+    ) -> Result<Option<String>> {
+        match options
+            .process
+            .clone()
+            .or_else(|| env.get_config_variable("PROCESS"))
+        {
+            Some(name) => {
+                let processes = self.processes.clone().unwrap_or_default();
+                match processes.get(&name) {
+                    Some(cmd) => Ok(Some(cmd.clone())),
+                    None => bail!(
+                        "Process `{name}` not found. Available processes: {}",
+                        processes.keys().cloned().collect::<Vec<_>>().join(", ")
+                    ),
+                }
+            }
+            None => Ok(self.cmd.clone()),
+        }
+    }
+}
+
 impl BuildPlan {
+    /// The start phase, which also runs the cron jobs when there are any.
+    fn get_start_phase(&self) -> Option<StartPhase> {
+        if self.has_cron_jobs() {
+            Some(self.start_phase.clone().unwrap_or_default().with_cron())
+        } else {
+            self.start_phase.clone()
+        }
+    }
+
+    /// Builds every package in the generated flake and installs the results into the user profile.
+    fn get_flake_install_cmds(&self, output: &OutputDir) -> Result<String> {
+        let phases = self.phases.clone().unwrap_or_default();
+        let package_names = flake_package_names_for_phases(&phases);
+        if package_names.is_empty() {
+            return Ok(String::new());
+        }
+
+        let flake_file_path = output
+            .get_relative_path(NIX_FLAKE_FILE_NAME)
+            .to_slash()
+            .context("Failed to convert nix file path to slash path.")?
+            .to_string();
+        let flake_dir = output
+            .get_relative_path("")
+            .to_slash()
+            .context("Failed to convert nix file path to slash path.")?
+            .trim_end_matches('/')
+            .to_string();
+
+        let installables = package_names
+            .iter()
+            .map(|name| format!("'path:./{flake_dir}#{name}'"))
+            .collect::<Vec<_>>()
+            .join(" ");
+        // Overlays are fetched with `builtins.fetchTarball`, which is not allowed in pure evaluation
+        let impure = if flake_requires_impure(&phases) {
+            " --impure"
+        } else {
+            ""
+        };
+
+        Ok(format!(
+            "COPY {flake_file_path} {flake_file_path}\nRUN nix --extra-experimental-features 'nix-command flakes' build{impure} --no-link --print-out-paths {installables} | xargs nix-env -i && nix-collect-garbage -d"
+        ))
+    }
+
     /// Copies the plan's static assets to the output directory.
     fn write_assets(&self, plan: &BuildPlan, output: &OutputDir) -> Result<()> {
         if let Some(assets) = &plan.static_assets {
@@ -291,9 +686,17 @@ impl BuildPlan {
                     fs::create_dir_all(parent)
                         .context(format!("Creating parent directory for {name}"))?;
                     let mut file =
-                        File::create(path).context(format!("Creating asset file for {name}"))?;
+                        File::create(&path).context(format!("Creating asset file for {name}"))?;
                     file.write_all(content.as_bytes())
                         .context(format!("Writing asset {name}"))?;
+
+                    // Wrapper scripts can be run directly from the assets directory
+                    #[cfg(unix)]
+                    if content.starts_with("#!") {
+                        use std::os::unix::fs::PermissionsExt;
+                        fs::set_permissions(&path, fs::Permissions::from_mode(0o755))
+                            .context(format!("Making asset {name} executable"))?;
+                    }
                 }
             }
         }
@@ -316,64 +719,234 @@ impl DockerfileGenerator for StartPhase {
     /// Write the StartPhase data to the Dockerfile.
     fn generate_dockerfile(
         &self,
-        _options: &DockerBuilderOptions,
-        _env: &Environment,
-        _output: &OutputDir,
+        options: &DockerBuilderOptions,
+        env: &Environment,
+        output: &OutputDir,
         _file_server_config: Option<FileServerConfig>,
     ) -> Result<String> {
-        let start_cmd = match &self.cmd {
-            Some(cmd) => utils::get_exec_command(cmd),
+        let start_cmd = match self.get_process_cmd(options, env)? {
+            Some(cmd) => utils::get_exec_command(&utils::exec_start_command(&cmd)),
             None => String::new(),
         };
 
-        let user_str = match &self.user {
-            Some(user) => formatdoc! {"
-                RUN useradd -m -s /bin/bash {user}
-                USER {user}
+        // Every process can also be started by running its script, e.g. `docker run <image> /nixpacks/processes/worker`
+        let processes_copy_cmd = if self.processes.clone().unwrap_or_default().is_empty() {
+            String::new()
+        } else {
+            let processes_path = output
+                .get_relative_path(PROCESSES_DIR)
+                .to_slash()
+                .context("Failed to convert processes path to slash path.")?
+                .to_string();
+            format!("COPY {processes_path} {PROCESSES_IMAGE_DIR}")
+        };
+
+        // Containers run as root unless the plan picks a user or the build asks for an unprivileged one
+        let user = match &self.user {
+            Some(user) => Some(user.clone()),
+            None if options.non_root => Some(DEFAULT_USER.to_string()),
+            None => None,
+        }
+        .filter(|user| user != "root");
+
+        // The user is created before the app is copied, so that the files are copied with it as their owner
+        let create_user_str = match &user {
+            // Nix packages and PATH additions live in root's profile, so share them with the new user.
+            // Toolchains such as rbenv are installed in root's home, which the user needs to be able to read.
+            Some(user) if self.run_image.is_none() => formatdoc! {"
+                RUN useradd -m -s /bin/bash {user} \\
+                  && cp /root/.profile /home/{user}/.profile \\
+                  && ln -s \"$(readlink -f /root/.nix-profile)\" /home/{user}/.nix-profile \\
+                  && chown {user}:{user} {APP_DIR} /home/{user}/.profile \\
+                  && chmod 0755 /root
             "},
+            Some(user) => {
+                format!("RUN useradd -m -s /bin/bash {user} && chown {user}:{user} {APP_DIR}")
+            }
+            None => String::new(),
+        };
+        let chown_str = user
+            .as_ref()
+            .map(|user| format!(" --chown={user}:{user}"))
+            .unwrap_or_default();
+        let user_str = user
+            .as_ref()
+            .map(|user| format!("USER {user}\nENV USER={user}"))
+            .unwrap_or_default();
+
+        // Keep a PORT passed in as a build variable, otherwise default it to the detected port
+        let port_str = match self.port {
+            Some(port) => format!("ENV PORT=${{PORT:-{port}}}\nEXPOSE {port}"),
+            None => String::new(),
+        };
+
+        // tini forwards signals to the whole process group and reaps zombies when the start command is not a simple one
+        let entrypoint_str = if options.init {
+            format!("ENTRYPOINT [\"{TINI_PATH}\", \"-g\", \"--\", {SHELL_ENTRYPOINT}]")
+        } else {
+            format!("ENTRYPOINT [{SHELL_ENTRYPOINT}]")
+        };
+
+        // Set in the last layers so a new commit doesn't invalidate the cached phases
+        let git_str = match &options.git_info {
+            Some(git_info) => {
+                let mut variables = vec![format!("GIT_SHA=${{GIT_SHA:-{}}}", git_info.sha)];
+                if let Some(branch) = &git_info.branch {
+                    variables.push(format!(
+                        "GIT_BRANCH=\"${{GIT_BRANCH:-{}}}\"",
+                        branch.replace('"', "\\\"").replace('$', "\\$")
+                    ));
+                }
+                format!("ENV {}", variables.join(" "))
+            }
+            None => String::new(),
+        };
+
+        let healthcheck_str = self
+            .healthcheck
+            .as_ref()
+            .map(utils::get_healthcheck_command)
+            .unwrap_or_default();
+
+        // Relative directories are resolved by Docker from the app directory, the previous WORKDIR
+        let workdir_str = match &self.workdir {
+            Some(workdir) => format!("WORKDIR {}", workdir.trim()),
             None => String::new(),
         };
 
-        let dockerfile: String = match &self.run_image {
-            Some(run_image) => {
-                let copy_cmds = utils::get_copy_from_commands(
-                    "0",
-                    &self.only_include_files.clone().unwrap_or_default(),
-                    APP_DIR,
-                );
+        // A start command or healthcheck that needs a shell runs in the slim image instead of one without a shell
+        let run_image = match self.run_image.as_deref() {
+            Some(image) if is_shell_less_image(image) && self.needs_shell(options, env)? => {
+                Some(STANDALONE_IMAGE)
+            }
+            image => image,
+        };
+
+        let dockerfile: String = if let Some(run_image) =
+            run_image.filter(|image| is_shell_less_image(image))
+        {
+            self.generate_shell_less_start(
+                run_image,
+                options,
+                env,
+                &[&port_str, &git_str, &workdir_str],
+            )?
+        } else if let Some(run_image) = run_image {
+            // Unlike the build image, run images may not come with curl to request the path with
+            let healthcheck_str = match &self.healthcheck {
+                Some(healthcheck) if healthcheck.cmd.is_none() && healthcheck.path.is_some() => {
+                    format!("{}\n{healthcheck_str}", utils::HEALTHCHECK_CURL_INSTALL)
+                }
+                _ => healthcheck_str.clone(),
+            };
+            let mut copy_cmds = utils::get_copy_from_commands(
+                "0",
+                &self.only_include_files.clone().unwrap_or_default(),
+                APP_DIR,
+            )
+            .into_iter()
+            .map(|cmd| cmd.replacen("COPY --from=0", &format!("COPY --from=0{chown_str}"), 1))
+            .collect::<Vec<_>>();
+            if options.init {
+                // The statically linked build works in run images with a different libc
+                copy_cmds.push(format!("COPY --from=0 /usr/bin/tini-static {TINI_PATH}"));
+            }
 
-                // RUN true to prevent a Docker bug https://github.com/moby/moby/issues/37965#issuecomment-426853382
-                formatdoc! {"
+            // RUN true to prevent a Docker bug https://github.com/moby/moby/issues/37965#issuecomment-426853382
+            formatdoc! {"
                   # start
                   FROM {run_image}
-                  ENTRYPOINT [\"/bin/bash\", \"-l\", \"-c\"]
+                  {entrypoint_str}
                   WORKDIR {APP_DIR}
                   COPY --from=0 /etc/ssl/certs /etc/ssl/certs
                   RUN true
+                  {create_user_str}
                   {copy_cmds}
+                  {processes_copy_cmd}
+                  {port_str}
+                  {git_str}
+                  {healthcheck_str}
                   {user_str}
+                  {workdir_str}
                   {start_cmd}
                 ",
-                run_image=run_image,
-                APP_DIR=APP_DIR,
-                copy_cmds=copy_cmds.join("\n"),
-                user_str=user_str,
-                start_cmd=start_cmd,}
-            }
-            None => {
-                formatdoc! {"
+            run_image=run_image,
+            entrypoint_str=entrypoint_str,
+            APP_DIR=APP_DIR,
+            copy_cmds=copy_cmds.join("\n"),
+            processes_copy_cmd=processes_copy_cmd,
+            port_str=port_str,
+            git_str=git_str,
+            healthcheck_str=healthcheck_str,
+            user_str=user_str,
+            workdir_str=workdir_str,
+            start_cmd=start_cmd,}
+        } else {
+            let entrypoint_str = if options.init {
+                entrypoint_str
+            } else {
+                String::new()
+            };
+
+            formatdoc! {"
                   # start
-                  COPY . /app
+                  {create_user_str}
+                  COPY{chown_str} . /app
+                  {entrypoint_str}
+                  {processes_copy_cmd}
+                  {port_str}
+                  {git_str}
+                  {healthcheck_str}
                   {user_str}
+                  {workdir_str}
                   {start_cmd}
                 ",
-                start_cmd=start_cmd,
-                user_str=user_str}
-            }
+            entrypoint_str=entrypoint_str,
+            processes_copy_cmd=processes_copy_cmd,
+            port_str=port_str,
+            git_str=git_str,
+            healthcheck_str=healthcheck_str,
+            workdir_str=workdir_str,
+            start_cmd=start_cmd,
+            user_str=user_str}
         };
 
         Ok(dockerfile)
     }
+
+    /// Writes a script for each process that the image can be started with.
+    fn write_supporting_files(
+        &self,
+        _options: &DockerBuilderOptions,
+        _env: &Environment,
+        output: &OutputDir,
+    ) -> Result<()> {
+        let processes = self.processes.clone().unwrap_or_default();
+        if processes.is_empty() {
+            return Ok(());
+        }
+
+        let processes_dir = output.get_absolute_path(PROCESSES_DIR);
+        fs::create_dir_all(&processes_dir).context("Creating processes folder")?;
+
+        for (name, cmd) in processes {
+            let path = processes_dir.join(sanitize_filename::sanitize(&name));
+            fs::write(
+                &path,
+                format!("#!/bin/bash -l\n{}\n", utils::exec_start_command(&cmd)),
+            )
+            .context(format!("Writing script for process {name}"))?;
+
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt;
+                fs::set_permissions(&path, fs::Permissions::from_mode(0o755))
+                    .context(format!("Making script for process {name} executable"))?;
+            }
+        }
+
+        Ok(())
+    }
 }
 
 impl DockerfileGenerator for Phase {
@@ -399,23 +972,37 @@ impl DockerfileGenerator for Phase {
 
         // Ensure paths are available in the environment
         let build_path = if let Some(paths) = &phase.paths {
-            let joined_paths = paths.join(":");
+            let joined_paths = utils::quote_dockerfile_value(&paths.join(":"));
             format!("ENV NIXPACKS_PATH={joined_paths}:$NIXPACKS_PATH")
         } else {
             String::new()
         };
 
         // Copy over app files
-        let phase_files = match (phase.get_name().as_str(), &phase.only_include_files) {
-            (_, Some(files)) => files.clone(),
-            _ => vec![".".to_string()],
+        let phase_files = match &phase.only_include_files {
+            Some(files) => files.clone(),
+            None => vec![".".to_string()],
         };
         let phase_copy_cmds = utils::get_copy_commands(&phase_files, APP_DIR);
 
+        // Commands after the argument see its value, so a new value misses the cache
+        let cache_bust = if phase.rebuild == Some(Rebuild::Always) {
+            format!("ARG {CACHE_BUST_ARG}")
+        } else {
+            String::new()
+        };
+
         let cache_mount = utils::get_cache_mount(&cache_key, &phase.cache_directories);
+        let credential_mounts = get_credential_mounts(options, phase)?;
+        let mounts = [cache_mount, credential_mounts.clone()]
+            .into_iter()
+            .filter(|mount| !mount.is_empty())
+            .collect::<Vec<_>>()
+            .join(" ");
         let cmds_str = if options.incremental_cache_image.is_some() {
             let image = &options.incremental_cache_image.clone().unwrap();
-            let cache_copy_in_command = if IncrementalCache::is_image_exists(image)? {
+            let client = DockerClient::resolve(options.docker_client.as_deref())?;
+            let cache_copy_in_command = if IncrementalCache::is_image_exists(client, image)? {
                 IncrementalCache::get_copy_to_image_command(&phase.cache_directories, image)
                     .join("\n")
             } else {
@@ -427,15 +1014,22 @@ impl DockerfileGenerator for Phase {
                 file_server_config,
             );
 
-            let run_commands = [
-                phase.cmds.clone().unwrap_or_default(),
-                cache_copy_out_command,
-            ]
-            .concat()
-            .iter()
-            .map(|s| format!("RUN {s}"))
-            .collect::<Vec<_>>()
-            .join("\n");
+            let run_commands = phase
+                .cmds
+                .clone()
+                .unwrap_or_default()
+                .iter()
+                .map(|s| {
+                    let cmd = utils::get_run_command(&with_credential_env(s, options));
+                    if credential_mounts.is_empty() {
+                        format!("RUN {cmd}")
+                    } else {
+                        format!("RUN {credential_mounts} {cmd}")
+                    }
+                })
+                .chain(cache_copy_out_command.iter().map(|s| format!("RUN {s}")))
+                .collect::<Vec<_>>()
+                .join("\n");
 
             format!("{cache_copy_in_command}\n{run_commands}")
         } else {
@@ -444,12 +1038,17 @@ impl DockerfileGenerator for Phase {
                 .clone()
                 .unwrap_or_default()
                 .iter()
-                .map(|s| format!("RUN {cache_mount} {s}"))
+                .map(|s| {
+                    format!(
+                        "RUN {mounts} {}",
+                        utils::get_run_command(&with_credential_env(s, options))
+                    )
+                })
                 .collect::<Vec<_>>()
                 .join("\n")
         };
 
-        let dockerfile_stmts = vec![build_path, phase_copy_cmds.join("\n"), cmds_str]
+        let dockerfile_stmts = vec![build_path, phase_copy_cmds.join("\n"), cache_bust, cmds_str]
             .into_iter()
             .filter(|stmt| !stmt.is_empty())
             .collect::<Vec<_>>()
@@ -478,9 +1077,12 @@ impl DockerfileGenerator for Phase {
 
 #[cfg(test)]
 mod tests {
-    use std::collections::BTreeMap;
-
     use super::*;
+    use crate::nixpacks::{
+        app::GitInfo,
+        nix::pkg::Pkg,
+        plan::{cron::CronJob, phase::Healthcheck},
+    };
 
     #[test]
     fn test_phase_generation() {
@@ -501,6 +1103,188 @@ mod tests {
         assert!(dockerfile.contains("ENV NIXPACKS_PATH=/test:$NIXPACKS_PATH"));
     }
 
+    #[test]
+    fn test_phase_generation_with_rebuild() {
+        let generate = |rebuild| {
+            let mut phase = Phase::new("build");
+            phase.add_cmd("date > built-at");
+            phase.only_include_files = Some(vec!["package.json".to_string()]);
+            phase.rebuild = rebuild;
+            phase
+                .generate_dockerfile(
+                    &DockerBuilderOptions::default(),
+                    &Environment::default(),
+                    &OutputDir::default(),
+                    None,
+                )
+                .unwrap()
+        };
+
+        let dockerfile = generate(None);
+        assert!(dockerfile.contains("COPY package.json /app/package.json"));
+        assert!(!dockerfile.contains(CACHE_BUST_ARG));
+
+        let dockerfile = generate(Some(Rebuild::Always));
+        let cache_bust = dockerfile.find("ARG NIXPACKS_CACHE_BUST").unwrap();
+        assert!(dockerfile.find("COPY package.json").unwrap() < cache_bust);
+        assert!(cache_bust < dockerfile.find("RUN").unwrap());
+
+        // The files the phase lists are still copied
+        let dockerfile = generate(Some(Rebuild::Never));
+        assert!(dockerfile.contains("COPY package.json /app/package.json"));
+        assert!(!dockerfile.contains(CACHE_BUST_ARG));
+        assert!(dockerfile.contains("date > built-at"));
+    }
+
+    #[test]
+    fn test_phase_generation_with_secrets() {
+        let mut phase = Phase::new("install");
+        phase.add_cmd("npm ci");
+
+        let dockerfile = phase
+            .generate_dockerfile(
+                &DockerBuilderOptions {
+                    secrets: vec!["NPM_TOKEN".to_string()],
+                    ..Default::default()
+                },
+                &Environment::default(),
+                &OutputDir::default(),
+                None,
+            )
+            .unwrap();
+
+        assert!(dockerfile.contains(
+            "RUN --mount=type=secret,id=NPM_TOKEN,required=true export NPM_TOKEN=\"$(cat /run/secrets/NPM_TOKEN)\" && npm ci"
+        ));
+        assert!(!dockerfile.contains("ENV NPM_TOKEN"));
+    }
+
+    #[test]
+    fn test_phase_generation_with_ssh() {
+        let mut phase = Phase::new("install");
+        phase.add_cmd("go mod download");
+
+        let dockerfile = phase
+            .generate_dockerfile(
+                &DockerBuilderOptions {
+                    ssh: true,
+                    ..Default::default()
+                },
+                &Environment::default(),
+                &OutputDir::default(),
+                None,
+            )
+            .unwrap();
+
+        assert!(dockerfile.contains(&format!(
+            "RUN --mount=type=ssh export GIT_SSH_COMMAND=\"{SSH_COMMAND}\" && go mod download"
+        )));
+    }
+
+    #[test]
+    fn test_phase_generation_with_registry_config() {
+        let options = DockerBuilderOptions {
+            registry_configs: vec![".npmrc".to_string()],
+            ..Default::default()
+        };
+
+        let install = Phase::install(Some("npm ci".to_string()));
+        let dockerfile = install
+            .generate_dockerfile(
+                &options,
+                &Environment::default(),
+                &OutputDir::default(),
+                None,
+            )
+            .unwrap();
+        assert!(dockerfile.contains(
+            "RUN --mount=type=secret,id=registry-npmrc,target=/root/.npmrc,required=true npm ci"
+        ));
+
+        let build = Phase::build(Some("npm run build".to_string()));
+        let dockerfile = build
+            .generate_dockerfile(
+                &options,
+                &Environment::default(),
+                &OutputDir::default(),
+                None,
+            )
+            .unwrap();
+        assert!(!dockerfile.contains("registry-npmrc"));
+    }
+
+    #[test]
+    fn test_plan_generation_escaping() {
+        let mut plan = BuildPlan::default();
+        plan.add_variables(BTreeMap::from([(
+            "GREETING".to_string(),
+            "it's \"$HOME\"\nbye".to_string(),
+        )]));
+
+        let mut build = Phase::build(Some("echo \"done\"".to_string()));
+        build.add_path("/app/my \"bin\"".to_string());
+        plan.add_phase(build);
+        plan.set_start_phase(StartPhase::new("node -e \"console.log('\\\\n')\""));
+
+        let dockerfile = plan
+            .generate_dockerfile(
+                &DockerBuilderOptions::default(),
+                &Environment::default(),
+                &OutputDir::default(),
+                None,
+            )
+            .unwrap();
+
+        // Values are passed in as build args rather than written into the Dockerfile
+        assert!(dockerfile.contains("ARG GREETING\nENV GREETING=$GREETING"));
+        assert!(!dockerfile.contains("bye"));
+        assert!(dockerfile.contains(r#"ENV NIXPACKS_PATH="/app/my \"bin\"":$NIXPACKS_PATH"#));
+        assert!(dockerfile
+            .contains(r#"RUN printf '\n%s' 'PATH=/app/my "bin":$PATH' >> /root/.profile"#));
+        assert!(dockerfile.contains(r#"CMD ["node -e \"console.log('\\\\n')\""]"#));
+
+        plan.add_variables(BTreeMap::from([(
+            "MY VAR".to_string(),
+            "value".to_string(),
+        )]));
+        assert!(plan
+            .generate_dockerfile(
+                &DockerBuilderOptions::default(),
+                &Environment::default(),
+                &OutputDir::default(),
+                None,
+            )
+            .is_err());
+    }
+
+    #[test]
+    fn test_build_variables_are_not_set_in_image() {
+        let mut plan = BuildPlan::default();
+        plan.add_variables(BTreeMap::from([(
+            "NODE_ENV".to_string(),
+            "production".to_string(),
+        )]));
+        plan.add_build_variables(BTreeMap::from([(
+            "SENTRY_RELEASE".to_string(),
+            "abc123".to_string(),
+        )]));
+
+        let dockerfile = plan
+            .generate_dockerfile(
+                &DockerBuilderOptions::default(),
+                &Environment::default(),
+                &OutputDir::default(),
+                None,
+            )
+            .unwrap();
+
+        assert!(dockerfile.contains("ARG NODE_ENV SENTRY_RELEASE\nENV NODE_ENV=$NODE_ENV\n"));
+        assert_eq!(
+            plan.get_build_args().keys().collect::<Vec<_>>(),
+            vec!["NODE_ENV", "SENTRY_RELEASE"]
+        );
+    }
+
     #[test]
     fn test_plan_generation() {
         let mut plan = BuildPlan::default();
@@ -532,6 +1316,713 @@ mod tests {
         assert!(dockerfile.contains("echo test2"));
         assert!(dockerfile.contains("apt-get update"));
         assert!(dockerfile.contains("wget"));
-        assert!(dockerfile.contains("ENV VAR1=$VAR1"));
+        assert!(dockerfile.contains("ARG VAR1\nENV VAR1=$VAR1"));
+    }
+
+    #[test]
+    fn test_plan_generation_with_flake() {
+        let mut plan = BuildPlan::default();
+        plan.add_phase(Phase::setup(Some(vec![Pkg::new("nodejs")])));
+
+        let dockerfile = plan
+            .generate_dockerfile(
+                &DockerBuilderOptions {
+                    nix_flake: true,
+                    ..Default::default()
+                },
+                &Environment::default(),
+                &OutputDir::default(),
+                Some(FileServerConfig::default()),
+            )
+            .unwrap();
+
+        assert!(dockerfile.contains("COPY .nixpacks/flake.nix .nixpacks/flake.nix"));
+        assert!(
+            dockerfile.contains("build --no-link --print-out-paths 'path:./.nixpacks#env-nixpkgs-")
+        );
+        assert!(!dockerfile.contains("nix-env -if"));
+    }
+
+    #[test]
+    fn test_plan_generation_with_app_nix_file() {
+        let mut plan = BuildPlan::default();
+        plan.add_phase(Phase::setup(Some(vec![Pkg::new("nodejs")])));
+        plan.nix_file = Some("shell.nix".to_string());
+
+        let dockerfile = plan
+            .generate_dockerfile(
+                &DockerBuilderOptions::default(),
+                &Environment::default(),
+                &OutputDir::default(),
+                Some(FileServerConfig::default()),
+            )
+            .unwrap();
+
+        assert!(dockerfile.contains("COPY shell.nix shell.nix"));
+        assert!(dockerfile.contains("let shell = import ./shell.nix;"));
+        assert!(!dockerfile.contains(".nixpacks/nixpkgs"));
+    }
+
+    #[test]
+    fn test_nix_cache_command() {
+        assert_eq!(get_nix_cache_command(&DockerBuilderOptions::default()), "");
+
+        let options = DockerBuilderOptions {
+            nix_caches: vec!["https://my-org.cachix.org".to_string()],
+            nix_cache_keys: vec!["my-org.cachix.org-1:abc=".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(
+            get_nix_cache_command(&options),
+            "RUN printf '%s\\n' 'extra-substituters = https://my-org.cachix.org' 'extra-trusted-public-keys = my-org.cachix.org-1:abc=' >> /etc/nix/nix.conf"
+        );
+
+        let options = DockerBuilderOptions {
+            nix_caches: vec!["https://cache.example.com/'%s".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(
+            get_nix_cache_command(&options),
+            "RUN printf '%s\\n' 'extra-substituters = https://cache.example.com/'\\''%s' >> /etc/nix/nix.conf"
+        );
+    }
+
+    #[test]
+    fn test_start_phase_user() {
+        let start = StartPhase::new("npm start".to_string());
+        let generate = |start: &StartPhase, options: &DockerBuilderOptions| {
+            start
+                .generate_dockerfile(
+                    options,
+                    &Environment::default(),
+                    &OutputDir::default(),
+                    None,
+                )
+                .unwrap()
+        };
+
+        let dockerfile = generate(
+            &start,
+            &DockerBuilderOptions {
+                non_root: true,
+                ..Default::default()
+            },
+        );
+        assert!(dockerfile.contains("useradd -m -s /bin/bash nixpacks"));
+        assert!(dockerfile.contains("chmod 0755 /root\n\nCOPY --chown=nixpacks:nixpacks . /app"));
+        assert!(!dockerfile.contains("chown -R"));
+        assert!(dockerfile.contains("USER nixpacks\nENV USER=nixpacks"));
+
+        let dockerfile = generate(&start, &DockerBuilderOptions::default());
+        assert!(!dockerfile.contains("USER"));
+        assert!(!dockerfile.contains("useradd"));
+
+        assert!(dockerfile.contains("CMD [\"exec npm start\"]"));
+
+        let dockerfile = generate(
+            &start,
+            &DockerBuilderOptions {
+                init: true,
+                ..Default::default()
+            },
+        );
+        assert!(dockerfile.contains(
+            "ENTRYPOINT [\"/usr/bin/tini\", \"-g\", \"--\", \"/bin/bash\", \"-l\", \"-c\"]"
+        ));
+
+        let mut port_start = start.clone();
+        port_start.port = Some(8080);
+        let dockerfile = generate(&port_start, &DockerBuilderOptions::default());
+        assert!(dockerfile.contains("ENV PORT=${PORT:-8080}\nEXPOSE 8080"));
+
+        let mut root_start = start.clone();
+        root_start.user = Some("root".to_string());
+        assert!(!generate(
+            &root_start,
+            &DockerBuilderOptions {
+                non_root: true,
+                ..Default::default()
+            }
+        )
+        .contains("USER"));
+
+        let mut run_image_start = start.clone();
+        run_image_start.run_image = Some("debian:bookworm-slim".to_string());
+        run_image_start.add_file_dependency("dist");
+        let dockerfile = generate(
+            &run_image_start,
+            &DockerBuilderOptions {
+                non_root: true,
+                ..Default::default()
+            },
+        );
+        assert!(dockerfile.contains("RUN useradd -m -s /bin/bash nixpacks && chown nixpacks:nixpacks /app/\nCOPY --from=0 --chown=nixpacks:nixpacks /app/dist /app/dist"));
+    }
+
+    #[test]
+    fn test_start_phase_healthcheck_curl() {
+        let generate = |start: &StartPhase| {
+            start
+                .generate_dockerfile(
+                    &DockerBuilderOptions::default(),
+                    &Environment::default(),
+                    &OutputDir::default(),
+                    None,
+                )
+                .unwrap()
+        };
+
+        let mut start = StartPhase::new("./server".to_string());
+        start.healthcheck = Some(Healthcheck::http("/health"));
+        // The build image comes with curl
+        assert!(!generate(&start).contains("apt-get install -y --no-install-recommends curl"));
+
+        start.run_in_slim_image();
+        let dockerfile = generate(&start);
+        assert!(dockerfile.contains(&format!(
+            "{}\nHEALTHCHECK CMD curl -fsS \"http://localhost:${{PORT}}/health\" || exit 1",
+            utils::HEALTHCHECK_CURL_INSTALL
+        )));
+
+        start.healthcheck = Some(Healthcheck {
+            cmd: Some("./server --check".to_string()),
+            ..Default::default()
+        });
+        assert!(!generate(&start).contains("apt-get install -y --no-install-recommends curl"));
+    }
+
+    #[test]
+    fn test_start_phase_workdir() {
+        let generate = |start: &StartPhase| {
+            start
+                .generate_dockerfile(
+                    &DockerBuilderOptions::default(),
+                    &Environment::default(),
+                    &OutputDir::default(),
+                    None,
+                )
+                .unwrap()
+        };
+
+        let mut start = StartPhase::new("npm start".to_string());
+        assert!(!generate(&start).contains("WORKDIR"));
+
+        start.workdir = Some("dist/apps/web".to_string());
+        assert!(generate(&start).contains("\nWORKDIR dist/apps/web\nCMD"));
+
+        start.run_image = Some("debian:bookworm-slim".to_string());
+        let dockerfile = generate(&start);
+        assert!(dockerfile.contains("WORKDIR /app/\n"));
+        assert!(dockerfile.contains("WORKDIR dist/apps/web\nCMD"));
+    }
+
+    #[test]
+    fn test_start_phase_scratch_image() {
+        let generate = |start: &StartPhase, options: &DockerBuilderOptions| {
+            start.generate_dockerfile(
+                options,
+                &Environment::default(),
+                &OutputDir::default(),
+                None,
+            )
+        };
+
+        let mut start = StartPhase::new("./out");
+        start.run_in_scratch_image();
+        start.add_file_dependency("out");
+        start.port = Some(8080);
+        let dockerfile = generate(&start, &DockerBuilderOptions::default()).unwrap();
+        assert!(dockerfile.contains("FROM scratch\n\nWORKDIR /app/\n"));
+        assert!(dockerfile.contains("COPY --from=0 /app/out /app/out\n"));
+        assert!(dockerfile.contains("EXPOSE 8080"));
+        assert!(!dockerfile.contains("USER"));
+        assert!(!dockerfile.contains("RUN "));
+        assert!(!dockerfile.contains("/bin/bash"));
+        assert!(generate(
+            &start,
+            &DockerBuilderOptions {
+                non_root: true,
+                ..Default::default()
+            }
+        )
+        .unwrap()
+        .contains("USER 65534:65534\nCMD [\"./out\"]"));
+
+        let dockerfile = generate(
+            &start,
+            &DockerBuilderOptions {
+                init: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert!(dockerfile.contains("FROM scratch\nENTRYPOINT [\"/usr/bin/tini\", \"-g\", \"--\"]"));
+        assert!(!dockerfile.contains("USER"));
+
+        start.cmd = Some("./out --port $PORT".to_string());
+        let dockerfile = generate(&start, &DockerBuilderOptions::default()).unwrap();
+        assert!(dockerfile.contains("FROM ubuntu:jammy\n"));
+        assert!(dockerfile.contains("COPY --from=0 /app/out /app/out\n"));
+
+        // Distroless images have no shell either, except for their debug tags
+        start.run_image = Some("gcr.io/distroless/static-debian12:debug".to_string());
+        assert!(generate(&start, &DockerBuilderOptions::default()).is_ok());
+    }
+
+    #[test]
+    fn test_start_phase_git_variables() {
+        let start = StartPhase::new("npm start".to_string());
+        let generate = |git_info: Option<GitInfo>| {
+            start
+                .generate_dockerfile(
+                    &DockerBuilderOptions {
+                        git_info,
+                        ..Default::default()
+                    },
+                    &Environment::default(),
+                    &OutputDir::default(),
+                    None,
+                )
+                .unwrap()
+        };
+
+        assert!(!generate(None).contains("GIT_SHA"));
+        assert!(generate(Some(GitInfo {
+            sha: "abc123".to_string(),
+            branch: Some("feature/$x".to_string()),
+            dirty: false,
+        }))
+        .contains(r#"ENV GIT_SHA=${GIT_SHA:-abc123} GIT_BRANCH="${GIT_BRANCH:-feature/\$x}""#));
+        assert!(generate(Some(GitInfo {
+            sha: "abc123".to_string(),
+            ..Default::default()
+        }))
+        .contains("ENV GIT_SHA=${GIT_SHA:-abc123}\n"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_write_executable_assets() -> Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempdir::TempDir::new("nixpacks-output")?;
+        let output = OutputDir::new(dir.path().to_path_buf(), false)?;
+        output.ensure_output_exists()?;
+
+        let mut plan = BuildPlan::default();
+        plan.add_static_assets(BTreeMap::from([
+            ("start.sh".to_string(), "#!/bin/bash\nnginx\n".to_string()),
+            ("nginx.conf".to_string(), "daemon off;\n".to_string()),
+        ]));
+        plan.write_assets(&plan, &output)?;
+
+        let mode = |name: &str| {
+            fs::metadata(output.get_absolute_path("assets").join(name))
+                .unwrap()
+                .permissions()
+                .mode()
+        };
+        assert_eq!(mode("start.sh") & 0o111, 0o111);
+        assert_eq!(mode("nginx.conf") & 0o111, 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_cron_jobs() -> Result<()> {
+        let mut plan = BuildPlan::new(&[Phase::setup(None)], Some(StartPhase::new("npm start")));
+        plan.cron = Some(BTreeMap::from([(
+            "cleanup".to_string(),
+            CronJob::new("0 * * * *", "node cleanup.js"),
+        )]));
+
+        let dockerfile = plan
+            .generate_dockerfile(
+                &DockerBuilderOptions::default(),
+                &Environment::default(),
+                &OutputDir::default(),
+                None,
+            )
+            .unwrap();
+        assert!(dockerfile.contains("COPY .nixpacks/crontab /nixpacks/crontab"));
+        assert!(dockerfile.contains("CMD [\"supercronic /nixpacks/crontab & exec npm start\"]"));
+
+        let dir = tempdir::TempDir::new("nixpacks-output")?;
+        let output = OutputDir::new(dir.path().to_path_buf(), false)?;
+        output.ensure_output_exists()?;
+        plan.write_supporting_files(
+            &DockerBuilderOptions::default(),
+            &Environment::default(),
+            &output,
+        )?;
+        assert_eq!(
+            fs::read_to_string(output.get_absolute_path("crontab"))?,
+            "# cleanup\n0 * * * * node cleanup.js\n"
+        );
+        assert_eq!(
+            fs::read_to_string(output.get_absolute_path("processes/cron"))?,
+            "#!/bin/bash -l\nexec supercronic /nixpacks/crontab\n"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_start_phase_processes() {
+        let mut start = StartPhase::new("npm start");
+        start.processes = Some(BTreeMap::from([
+            ("web".to_string(), "npm start".to_string()),
+            ("worker".to_string(), "node worker.js".to_string()),
+        ]));
+
+        let dockerfile = start
+            .generate_dockerfile(
+                &DockerBuilderOptions::default(),
+                &Environment::default(),
+                &OutputDir::default(),
+                None,
+            )
+            .unwrap();
+        assert!(dockerfile.contains("COPY .nixpacks/processes /nixpacks/processes"));
+        assert!(dockerfile.contains("CMD [\"exec npm start\"]"));
+
+        let dockerfile = start
+            .generate_dockerfile(
+                &DockerBuilderOptions {
+                    process: Some("worker".to_string()),
+                    ..Default::default()
+                },
+                &Environment::default(),
+                &OutputDir::default(),
+                None,
+            )
+            .unwrap();
+        assert!(dockerfile.contains("CMD [\"exec node worker.js\"]"));
+
+        assert!(start
+            .generate_dockerfile(
+                &DockerBuilderOptions {
+                    process: Some("clock".to_string()),
+                    ..Default::default()
+                },
+                &Environment::default(),
+                &OutputDir::default(),
+                None,
+            )
+            .is_err());
+    }
+
+    #[test]
+    fn test_custom_base_image() {
+        let generate = |build_image: Option<&str>| {
+            let plan = BuildPlan {
+                build_image: build_image.map(ToString::to_string),
+                ..Default::default()
+            };
+            plan.generate_dockerfile(
+                &DockerBuilderOptions::default(),
+                &Environment::default(),
+                &OutputDir::default(),
+                None,
+            )
+        };
+
+        let dockerfile = generate(None).unwrap();
+        assert!(dockerfile.contains(&format!("FROM {DEFAULT_BASE_IMAGE}")));
+        assert!(!dockerfile.contains("https://nixos.org/nix/install"));
+
+        let dockerfile = generate(Some("debian:bookworm-slim")).unwrap();
+        assert!(dockerfile.contains("FROM debian:bookworm-slim"));
+        assert!(dockerfile.contains("https://nixos.org/nix/install"));
+
+        assert!(generate(Some("gcr.io/distroless/cc-debian12")).is_err());
+    }
+
+    #[test]
+    fn test_apt_pkgs_are_sorted() {
+        let mut setup = Phase::setup(None);
+        setup.add_apt_pkgs(vec!["wget".to_string(), "curl".to_string()]);
+        let mut install = Phase::install(None);
+        install.add_apt_pkgs(vec!["curl".to_string(), "git".to_string()]);
+        let plan = BuildPlan::new(&[setup, install], None);
+
+        let dockerfile = snapshot_dockerfile(
+            &plan,
+            &DockerBuilderOptions {
+                init: true,
+                ..Default::default()
+            },
+        );
+        assert!(
+            dockerfile.contains("apt-get install -y --no-install-recommends curl git tini wget")
+        );
+    }
+
+    /// Splits a Dockerfile into instructions like Docker does, joining lines that end in a backslash and skipping comments.
+    fn parse_dockerfile(dockerfile: &str) -> Vec<String> {
+        let mut instructions = Vec::new();
+        let mut current: Option<String> = None;
+        for line in dockerfile.lines() {
+            let trimmed = line.trim();
+            if trimmed.is_empty() || trimmed.starts_with('#') {
+                continue;
+            }
+
+            let instruction = current.get_or_insert_with(String::new);
+            match trimmed.strip_suffix('\\') {
+                Some(continued) => instruction.push_str(continued),
+                None => instructions.push(format!("{instruction}{trimmed}")),
+            }
+            if !trimmed.ends_with('\\') {
+                current = None;
+            }
+        }
+        assert!(
+            current.is_none(),
+            "The Dockerfile ends in a line continuation"
+        );
+
+        instructions
+    }
+
+    fn assert_valid_dockerfile(dockerfile: &str) -> Vec<String> {
+        const INSTRUCTIONS: &[&str] = &[
+            "FROM",
+            "ARG",
+            "ENV",
+            "RUN",
+            "COPY",
+            "WORKDIR",
+            "USER",
+            "CMD",
+            "ENTRYPOINT",
+            "EXPOSE",
+            "HEALTHCHECK",
+            "LABEL",
+        ];
+
+        let instructions = parse_dockerfile(dockerfile);
+        for instruction in &instructions {
+            let keyword = instruction.split_whitespace().next().unwrap_or_default();
+            assert!(
+                INSTRUCTIONS.contains(&keyword),
+                "`{instruction}` is not a Dockerfile instruction in:\n{dockerfile}"
+            );
+        }
+
+        instructions
+    }
+
+    /// The command that a `RUN` instruction runs, with its mounts removed.
+    fn get_run_instruction_cmd(instruction: &str) -> String {
+        let mut rest = instruction.strip_prefix("RUN").unwrap().trim_start();
+        while rest.starts_with("--mount=") {
+            rest = rest
+                .split_once(' ')
+                .map_or("", |(_, rest)| rest)
+                .trim_start();
+        }
+
+        match rest
+            .strip_prefix("eval $'")
+            .and_then(|quoted| quoted.strip_suffix('\''))
+        {
+            Some(quoted) => unquote_ansi_c(quoted),
+            None => rest.to_string(),
+        }
+    }
+
+    /// Reverses the escapes of a Bash `$'...'` string that `RUN` commands are quoted with.
+    fn unquote_ansi_c(quoted: &str) -> String {
+        let mut value = String::new();
+        let mut chars = quoted.chars();
+        while let Some(c) = chars.next() {
+            if c == '\\' {
+                match chars.next() {
+                    Some('n') => value.push('\n'),
+                    Some('r') => value.push('\r'),
+                    Some(c) => value.push(c),
+                    None => {}
+                }
+            } else {
+                value.push(c);
+            }
+        }
+        value
+    }
+
+    /// Any string, including the newlines, backslashes, and quotes that the Dockerfile parser treats specially.
+    const ANY_STRING: &str = "(\\PC|[\n\r\t\\\\\"'$\\[\\]#])*";
+
+    proptest::proptest! {
+        #[test]
+        fn test_any_cmd_round_trips(cmd in ANY_STRING) {
+            let mut phase = Phase::build(Some(cmd.clone()));
+            phase.add_cache_directory("/root/.cache");
+            let dockerfile = phase
+                .generate_dockerfile(
+                    &DockerBuilderOptions {
+                        cache_key: Some("app".to_string()),
+                        ..Default::default()
+                    },
+                    &Environment::default(),
+                    &OutputDir::default(),
+                    None,
+                )
+                .unwrap();
+
+            let instructions = assert_valid_dockerfile(&dockerfile);
+            let run = instructions.iter().find(|i| i.starts_with("RUN")).unwrap();
+            let parsed = get_run_instruction_cmd(run);
+            if run.contains("eval $'") {
+                proptest::prop_assert_eq!(parsed, cmd);
+            } else {
+                proptest::prop_assert_eq!(parsed, cmd.trim());
+            }
+        }
+
+        #[test]
+        fn test_any_start_cmd_round_trips(cmd in ANY_STRING) {
+            let plan = BuildPlan::new(&[], Some(StartPhase::new(cmd.clone())));
+            let dockerfile = snapshot_dockerfile(&plan, &DockerBuilderOptions::default());
+
+            let instructions = assert_valid_dockerfile(&dockerfile);
+            let start = instructions.iter().find(|i| i.starts_with("CMD ")).unwrap();
+            let args: Vec<String> = serde_json::from_str(&start["CMD ".len()..]).unwrap();
+            proptest::prop_assert_eq!(args, vec![utils::exec_start_command(&cmd)]);
+        }
+
+        #[test]
+        fn test_any_variable_value_is_a_build_arg(
+            name in "[A-Za-z_][A-Za-z0-9_]{0,20}",
+            value in ANY_STRING,
+        ) {
+            let mut plan = node_plan();
+            plan.add_variables(BTreeMap::from([(name.clone(), value.clone())]));
+            let dockerfile = snapshot_dockerfile(&plan, &DockerBuilderOptions::default());
+
+            let instructions = assert_valid_dockerfile(&dockerfile);
+            let (arg, env) = (format!("ARG {name}"), format!("ENV {name}=${name}"));
+            proptest::prop_assert!(instructions.contains(&arg));
+            proptest::prop_assert!(instructions.contains(&env));
+            proptest::prop_assert_eq!(plan.get_build_args().remove(&name), Some(value));
+        }
+    }
+
+    /// Generates the Dockerfile for a plan, with the default image and archive redacted so they can be bumped.
+    fn snapshot_dockerfile(plan: &BuildPlan, options: &DockerBuilderOptions) -> String {
+        plan.generate_dockerfile(
+            options,
+            &Environment::default(),
+            &OutputDir::default(),
+            None,
+        )
+        .unwrap()
+        .replace(DEFAULT_BASE_IMAGE, "[build_image]")
+        .replace(crate::nixpacks::nix::NIXPKGS_ARCHIVE, "[archive]")
+    }
+
+    fn node_plan() -> BuildPlan {
+        let setup = Phase::setup(Some(vec![Pkg::new("nodejs_18"), Pkg::new("npm-9_x")]));
+        let mut install = Phase::install(Some("npm ci".to_string()));
+        install.add_cache_directory("/root/.npm");
+        install.add_path("/app/node_modules/.bin".to_string());
+        let build = Phase::build(Some("npm run build".to_string()));
+
+        BuildPlan::new(
+            &[setup, install, build],
+            Some(StartPhase::new("npm run start")),
+        )
+    }
+
+    #[test]
+    fn test_dockerfile_snapshot() {
+        insta::assert_snapshot!(snapshot_dockerfile(
+            &node_plan(),
+            &DockerBuilderOptions::default()
+        ));
+    }
+
+    #[test]
+    fn test_dockerfile_snapshot_with_variables() {
+        let mut plan = node_plan();
+        plan.add_variables(BTreeMap::from([
+            ("NODE_ENV".to_string(), "production".to_string()),
+            ("GREETING".to_string(), "it's \"$HOME\"\nbye".to_string()),
+        ]));
+        plan.add_build_variables(BTreeMap::from([(
+            "NPM_CONFIG_PRODUCTION".to_string(),
+            "false".to_string(),
+        )]));
+
+        insta::assert_snapshot!(snapshot_dockerfile(
+            &plan,
+            &DockerBuilderOptions {
+                cache_key: Some("my-app".to_string()),
+                ..Default::default()
+            }
+        ));
+    }
+
+    #[test]
+    fn test_dockerfile_snapshot_with_template() -> Result<()> {
+        let dir = tempdir::TempDir::new("nixpacks-template")?;
+        let template_path = dir.path().join("Dockerfile.template");
+        fs::write(
+            &template_path,
+            indoc::indoc! {r#"
+                FROM {{base_image}}
+                {{nix_installer}}
+                COPY --from=registry.example.com/security/agent:1 /agent /opt/agent
+                ENTRYPOINT ["/bin/bash", "-l", "-c"]
+                WORKDIR /app/
+                {{packages}}
+                {{env}}
+                {{phases}}
+                LABEL com.example.team=platform
+                {{start}}
+            "#},
+        )?;
+
+        insta::assert_snapshot!(snapshot_dockerfile(
+            &node_plan(),
+            &DockerBuilderOptions {
+                dockerfile_template: Some(template_path.display().to_string()),
+                ..Default::default()
+            }
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_dockerfile_with_retries() {
+        let dockerfile = snapshot_dockerfile(
+            &node_plan(),
+            &DockerBuilderOptions {
+                retries: 2,
+                ..Default::default()
+            },
+        );
+        assert!(dockerfile.contains("RUN for attempt in $(seq 0 2); do (nix-env -if"));
+        assert!(!dockerfile.contains("RUN for attempt in $(seq 0 2); do (npm"));
+    }
+
+    #[test]
+    fn test_dockerfile_snapshot_with_pinned_pkgs() {
+        let mut setup = Phase::setup(Some(vec![Pkg::new("python311"), Pkg::new("gcc")]));
+        setup.add_pkgs_libs(vec!["zlib".to_string(), "stdenv.cc.cc.lib".to_string()]);
+        setup.add_apt_pkgs(vec!["libpq-dev".to_string()]);
+        setup.nixpkgs_archive = Some("bf744fe90419885eefced41b3e5ae442d732712d".to_string());
+        setup.nix_overlays = Some(vec!["self: super: { }".to_string()]);
+
+        let mut custom = Phase::setup(Some(vec![Pkg::new("cowsay")]));
+        custom.name = Some("custom".to_string());
+        let install = Phase::install(Some("pip install -r requirements.txt".to_string()));
+
+        let mut start = StartPhase::new("echo 'Hello World' | cowsay");
+        start.user = Some("app".to_string());
+        let plan = BuildPlan::new(&[setup, custom, install], Some(start));
+
+        insta::assert_snapshot!(snapshot_dockerfile(&plan, &DockerBuilderOptions::default()));
     }
 }
@@ -0,0 +1,103 @@
+use anyhow::{bail, Context, Result};
+use regex::{Captures, Regex};
+use std::{collections::BTreeMap, fs};
+
+/// Sections of the generated Dockerfile that a template can place, written as `{{name}}`.
+pub const PLACEHOLDERS: [&str; 7] = [
+    "base_image",
+    "nix_installer",
+    "packages",
+    "assets",
+    "env",
+    "phases",
+    "start",
+];
+
+/// Without the phases the app would never be installed or built.
+const REQUIRED_PLACEHOLDER: &str = "phases";
+
+fn placeholder_regex() -> Regex {
+    Regex::new(r"\{\{\s*([A-Za-z0-9_]+)\s*\}\}").unwrap()
+}
+
+/// Reads a Dockerfile template and checks that it only uses known placeholders.
+pub fn read_template(path: &str) -> Result<String> {
+    let template =
+        fs::read_to_string(path).with_context(|| format!("Reading Dockerfile template {path}"))?;
+    validate_template(&template).with_context(|| format!("Invalid Dockerfile template {path}"))?;
+    Ok(template)
+}
+
+fn validate_template(template: &str) -> Result<()> {
+    let names = placeholder_regex()
+        .captures_iter(template)
+        .map(|captures| captures[1].to_string())
+        .collect::<Vec<_>>();
+
+    if let Some(name) = names
+        .iter()
+        .find(|name| !PLACEHOLDERS.contains(&name.as_str()))
+    {
+        bail!(
+            "Unknown placeholder `{{{{{name}}}}}`. The available placeholders are {}",
+            PLACEHOLDERS
+                .iter()
+                .map(|name| format!("{{{{{name}}}}}"))
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+    }
+    if !names.iter().any(|name| name == REQUIRED_PLACEHOLDER) {
+        bail!("The template must contain `{{{{{REQUIRED_PLACEHOLDER}}}}}` to build the app");
+    }
+
+    Ok(())
+}
+
+/// Replaces each placeholder in a validated template with its generated section.
+pub fn render_template(template: &str, sections: &BTreeMap<&str, String>) -> String {
+    placeholder_regex()
+        .replace_all(template, |captures: &Captures| {
+            sections.get(&captures[1]).cloned().unwrap_or_default()
+        })
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_template() {
+        assert!(validate_template("FROM {{ base_image }}\n{{phases}}\n{{start}}").is_ok());
+        assert!(validate_template("{{phases}}").is_ok());
+
+        let error = validate_template("{{phases}}\n{{cmd}}").unwrap_err();
+        assert!(error.to_string().contains("Unknown placeholder `{{cmd}}`"));
+        assert!(error
+            .to_string()
+            .contains("{{base_image}}, {{nix_installer}}"));
+
+        let error = validate_template("FROM {{base_image}}").unwrap_err();
+        assert!(error.to_string().contains("must contain `{{phases}}`"));
+    }
+
+    #[test]
+    fn test_render_template() {
+        let sections = BTreeMap::from([
+            (
+                "base_image",
+                "ghcr.io/railwayapp/nixpacks:ubuntu".to_string(),
+            ),
+            ("phases", "RUN npm ci\nRUN npm run build".to_string()),
+        ]);
+
+        assert_eq!(
+            render_template(
+                "FROM {{base_image}}\nRUN /opt/scanner/install.sh\n{{ phases }}\n{{start}}\n",
+                &sections
+            ),
+            "FROM ghcr.io/railwayapp/nixpacks:ubuntu\nRUN /opt/scanner/install.sh\nRUN npm ci\nRUN npm run build\n\n"
+        );
+    }
+}
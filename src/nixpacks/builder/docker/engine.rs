@@ -0,0 +1,321 @@
+use super::{
+    client::DockerClient,
+    dockerfile_generation::get_build_args,
+    image_size::{ImageLayer, ImageSize},
+    limits, DockerBuilderOptions,
+};
+use crate::nixpacks::{
+    error::NixpacksError,
+    logger::{layer_progress_bar, Logger},
+    plan::BuildPlan,
+};
+use anyhow::{Context, Result};
+use bollard::{
+    image::{BuildImageOptions, TagImageOptions},
+    models::ProgressDetail,
+    Docker, API_DEFAULT_VERSION,
+};
+use futures_util::StreamExt;
+use indicatif::ProgressBar;
+use std::{collections::HashMap, env, fs::File, io::Write, path::Path};
+
+/// Builds can stream for a long time, so don't time out requests to the daemon early.
+const REQUEST_TIMEOUT_SECS: u64 = 60 * 60;
+
+/// Talks to the Docker Engine API directly, without needing the docker CLI.
+pub struct DockerEngine {
+    docker: Docker,
+}
+
+impl DockerEngine {
+    /// Connect to the daemon described by the builder options, falling back to `DOCKER_HOST` and the local socket.
+    pub fn connect(options: &DockerBuilderOptions) -> Result<DockerEngine> {
+        let host = options
+            .docker_host
+            .clone()
+            .or_else(|| env::var("DOCKER_HOST").ok());
+        let tls_verify = options.docker_tls_verify.as_deref() == Some("1");
+
+        let docker = match host {
+            Some(host) if tls_verify || host.starts_with("https://") => {
+                let cert_path = options
+                    .docker_cert_path
+                    .clone()
+                    .or_else(|| env::var("DOCKER_CERT_PATH").ok())
+                    .context("A Docker cert path is required to connect to the daemon over TLS")?;
+                let cert_path = Path::new(&cert_path);
+
+                Docker::connect_with_ssl(
+                    &host,
+                    &cert_path.join("key.pem"),
+                    &cert_path.join("cert.pem"),
+                    &cert_path.join("ca.pem"),
+                    REQUEST_TIMEOUT_SECS,
+                    API_DEFAULT_VERSION,
+                )
+            }
+            Some(host) if host.starts_with("tcp://") || host.starts_with("http://") => {
+                Docker::connect_with_http(&host, REQUEST_TIMEOUT_SECS, API_DEFAULT_VERSION)
+            }
+            #[cfg(unix)]
+            Some(host) if host.starts_with("unix://") => {
+                Docker::connect_with_unix(&host, REQUEST_TIMEOUT_SECS, API_DEFAULT_VERSION)
+            }
+            // Docker Desktop on Windows listens on a named pipe, e.g. npipe:////./pipe/docker_engine
+            #[cfg(windows)]
+            Some(host) if host.starts_with("npipe://") => {
+                Docker::connect_with_named_pipe(&host, REQUEST_TIMEOUT_SECS, API_DEFAULT_VERSION)
+            }
+            _ => Docker::connect_with_local_defaults(),
+        }
+        .context("Connecting to the Docker daemon")?;
+
+        Ok(DockerEngine { docker })
+    }
+
+    /// Check that the daemon is reachable, since connecting doesn't send any requests.
+    pub async fn ping(&self, options: &DockerBuilderOptions) -> Result<()> {
+        match self.docker.ping().await {
+            Ok(_) => Ok(()),
+            Err(e) => Err(DockerClient::Docker.daemon_error(
+                options
+                    .docker_host
+                    .clone()
+                    .or_else(|| env::var("DOCKER_HOST").ok())
+                    .as_deref(),
+                &e.to_string(),
+            )),
+        }
+    }
+
+    /// Build an image from a tarred build context, streaming the daemon's progress to the logger.
+    pub async fn build_image(
+        &self,
+        context: Vec<u8>,
+        dockerfile: &str,
+        name: &str,
+        plan: &BuildPlan,
+        options: &DockerBuilderOptions,
+        logger: &Logger,
+    ) -> Result<()> {
+        let build_options = BuildImageOptions {
+            dockerfile: dockerfile.to_string(),
+            t: name.to_string(),
+            extrahosts: if options.add_host.is_empty() {
+                None
+            } else {
+                Some(options.add_host.join(","))
+            },
+            networkmode: limits::get_network(options).unwrap_or_default(),
+            q: options.quiet,
+            nocache: options.no_cache,
+            cachefrom: options.cache_from.clone().into_iter().collect(),
+            rm: true,
+            forcerm: true,
+            memory: options
+                .memory
+                .as_deref()
+                .map(parse_memory)
+                .transpose()
+                .context("Parsing memory limit")?,
+            cpuperiod: options.cpus.as_ref().map(|_| limits::CPU_PERIOD),
+            cpuquota: match &options.cpus {
+                Some(cpus) => Some(limits::get_cpu_quota(cpus)?),
+                None => options
+                    .cpu_quota
+                    .as_deref()
+                    .map(str::parse)
+                    .transpose()
+                    .context("Parsing CPU quota")?,
+            },
+            buildargs: get_build_args(plan).into_iter().collect(),
+            labels: parse_labels(&options.labels),
+            platform: options.platform.join(","),
+            ..Default::default()
+        };
+
+        let mut stream = self
+            .docker
+            .build_image(build_options, None, Some(context.into()));
+
+        // On a terminal, layers being pulled get a bar each instead of a line per update
+        let layers = logger.start_layer_progress();
+        let mut bars: HashMap<String, ProgressBar> = HashMap::new();
+
+        while let Some(info) = stream.next().await {
+            let info = info.context("Reading build output from the Docker daemon")?;
+
+            if let Some(detail) = info.error_detail {
+                let message = detail
+                    .message
+                    .or(info.error)
+                    .unwrap_or_else(|| "unknown error".to_string());
+                return Err(match detail.code {
+                    Some(code) => NixpacksError::docker_failed(
+                        i32::try_from(code).ok(),
+                        format!("Docker build failed with code {code}: {message}"),
+                    ),
+                    None => NixpacksError::docker_failed(
+                        None,
+                        format!("Docker build failed: {message}"),
+                    ),
+                }
+                .into());
+            } else if let Some(error) = info.error {
+                return Err(NixpacksError::docker_failed(
+                    None,
+                    format!("Docker build failed: {error}"),
+                )
+                .into());
+            }
+
+            if options.quiet {
+                continue;
+            }
+
+            if let (Some(layers), Some(id)) = (&layers, &info.id) {
+                let progress = match info.progress_detail {
+                    Some(ProgressDetail {
+                        current: Some(current),
+                        total: Some(total),
+                    }) if total > 0 => Some((current as u64, total as u64)),
+                    _ => None,
+                };
+                if progress.is_some() || bars.contains_key(id) {
+                    let bar = bars.entry(id.clone()).or_insert_with(|| {
+                        let bar = layers.add(layer_progress_bar(0));
+                        bar.set_prefix(id.clone());
+                        bar
+                    });
+                    if let Some((current, total)) = progress {
+                        bar.set_length(total);
+                        bar.set_position(current);
+                    }
+                    bar.set_message(info.status.unwrap_or_default());
+                    continue;
+                }
+            }
+
+            let output = match (info.stream, info.status) {
+                (Some(output), _) => output.trim_end_matches('\n').to_string(),
+                (None, Some(status)) => status,
+                (None, None) => continue,
+            };
+            match &layers {
+                Some(layers) => layers.suspend(|| logger.log_output(&output)),
+                None => logger.log_output(&output),
+            }
+            std::io::stdout().flush()?;
+        }
+        for bar in bars.values() {
+            bar.finish();
+        }
+
+        for tag in &options.tags {
+            let (repo, tag) = split_image_tag(tag);
+            self.docker
+                .tag_image(name, Some(TagImageOptions { repo, tag }))
+                .await
+                .with_context(|| format!("Tagging image as {repo}:{tag}"))?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes images to a tar file, like `docker save`.
+    pub async fn save_image(&self, names: &[&str], path: &Path) -> Result<()> {
+        let mut file =
+            File::create(path).with_context(|| format!("Creating {}", path.display()))?;
+        let mut stream = self.docker.export_images(names);
+        while let Some(chunk) = stream.next().await {
+            file.write_all(&chunk.context("Exporting image from the Docker daemon")?)
+                .with_context(|| format!("Writing {}", path.display()))?;
+        }
+
+        Ok(())
+    }
+
+    /// Reads the size of a built image and its layers.
+    pub async fn image_size(&self, name: &str) -> Result<ImageSize> {
+        let image = self
+            .docker
+            .inspect_image(name)
+            .await
+            .context("Inspecting image")?;
+        let history = self
+            .docker
+            .image_history(name)
+            .await
+            .context("Reading image history")?;
+
+        Ok(ImageSize {
+            total: image.size.unwrap_or_default().max(0) as u64,
+            // The history starts from the top of the image
+            layers: history
+                .into_iter()
+                .rev()
+                .map(|item| ImageLayer {
+                    size: item.size.max(0) as u64,
+                    created_by: item.created_by,
+                })
+                .collect(),
+        })
+    }
+}
+
+/// Convert docker-style memory limits (`512m`, `2g`) to bytes.
+fn parse_memory(value: &str) -> Result<u64> {
+    let value = value.trim().to_lowercase();
+    let (number, multiplier) = match value.chars().last() {
+        Some('b') => (&value[..value.len() - 1], 1),
+        Some('k') => (&value[..value.len() - 1], 1024),
+        Some('m') => (&value[..value.len() - 1], 1024 * 1024),
+        Some('g') => (&value[..value.len() - 1], 1024 * 1024 * 1024),
+        _ => (value.as_str(), 1),
+    };
+
+    Ok(number.parse::<u64>()? * multiplier)
+}
+
+/// Turn `key=value` label arguments into a map.
+fn parse_labels(labels: &[String]) -> HashMap<String, String> {
+    labels
+        .iter()
+        .map(|label| match label.split_once('=') {
+            Some((key, value)) => (key.to_string(), value.to_string()),
+            None => (label.clone(), String::new()),
+        })
+        .collect()
+}
+
+/// Split an image reference into its repository and tag, defaulting to `latest`.
+fn split_image_tag(image: &str) -> (&str, &str) {
+    match image.rsplit_once(':') {
+        // A colon before the last slash belongs to a registry port, not a tag
+        Some((repo, tag)) if !tag.contains('/') => (repo, tag),
+        _ => (image, "latest"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_memory() {
+        assert_eq!(parse_memory("1024").unwrap(), 1024);
+        assert_eq!(parse_memory("512m").unwrap(), 512 * 1024 * 1024);
+        assert_eq!(parse_memory("2G").unwrap(), 2 * 1024 * 1024 * 1024);
+        assert!(parse_memory("lots").is_err());
+    }
+
+    #[test]
+    fn test_split_image_tag() {
+        assert_eq!(split_image_tag("my-app"), ("my-app", "latest"));
+        assert_eq!(split_image_tag("my-app:v1"), ("my-app", "v1"));
+        assert_eq!(
+            split_image_tag("localhost:5000/my-app"),
+            ("localhost:5000/my-app", "latest")
+        );
+    }
+}
@@ -13,6 +13,7 @@ use std::path::PathBuf;
 use std::thread;
 
 use super::incremental_cache::IncrementalCacheDirs;
+use crate::nixpacks::logger::{LogFormat, Logger};
 use uuid::Uuid;
 
 const NIXPACKS_SERVER_HOST: &str = "host.docker.internal";
@@ -33,7 +34,13 @@ pub struct FileServerConfig {
 
 impl FileServer {
     /// Launch the file server using the default settings and the incremental cache storage directories.
-    pub fn start(self, incremental_cache_dirs: &IncrementalCacheDirs) -> FileServerConfig {
+    ///
+    /// The server runs on its own thread, so its errors are logged with a logger of the given format.
+    pub fn start(
+        self,
+        incremental_cache_dirs: &IncrementalCacheDirs,
+        log_format: LogFormat,
+    ) -> FileServerConfig {
         let port = self.get_free_port();
 
         let config = FileServerConfig {
@@ -48,7 +55,7 @@ impl FileServer {
         thread::spawn(move || {
             let server_future = FileServer::run_app(server_config);
             if let Err(e) = rt::System::new().block_on(server_future) {
-                println!("File server error: {e}");
+                Logger::with_format(log_format).log_output(&format!("File server error: {e}"));
             }
         });
 
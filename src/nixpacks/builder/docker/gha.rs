@@ -0,0 +1,88 @@
+use anyhow::{Context, Result};
+use std::{env, fs::OpenOptions, io::Write};
+
+/// Variables a workflow exposes to actions for the GitHub Actions cache, which BuildKit reads for `type=gha` caches.
+const CACHE_VARIABLES: [&str; 2] = ["ACTIONS_CACHE_URL", "ACTIONS_RUNTIME_TOKEN"];
+
+/// BuildKit arguments that import and export the build cache with the GitHub Actions cache, scoped to the cache key.
+///
+/// Returns `None` when the cache variables aren't available, as they are only exposed to actions, not `run` steps.
+pub fn cache_args<F>(get_var: F, scope: Option<&str>) -> Option<Vec<String>>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    if !CACHE_VARIABLES.iter().all(|name| get_var(name).is_some()) {
+        return None;
+    }
+
+    let scope = scope
+        .map(|scope| format!(",scope={scope}"))
+        .unwrap_or_default();
+    Some(vec![
+        "--cache-from".to_string(),
+        format!("type=gha{scope}"),
+        "--cache-to".to_string(),
+        format!("type=gha,mode=max{scope}"),
+    ])
+}
+
+/// Formats step outputs as `name=value` lines for the `GITHUB_OUTPUT` file.
+pub fn format_outputs(outputs: &[(&str, String)]) -> String {
+    outputs
+        .iter()
+        .map(|(name, value)| format!("{name}={value}\n"))
+        .collect::<Vec<_>>()
+        .concat()
+}
+
+/// Appends step outputs to the `GITHUB_OUTPUT` file, returning `false` when it isn't run in a workflow.
+pub fn write_outputs(outputs: &[(&str, String)]) -> Result<bool> {
+    let path = match env::var_os("GITHUB_OUTPUT") {
+        Some(path) => path,
+        None => return Ok(false),
+    };
+
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .context("Opening GITHUB_OUTPUT")?;
+    file.write_all(format_outputs(outputs).as_bytes())
+        .context("Writing GITHUB_OUTPUT")?;
+
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cache_args() {
+        assert_eq!(cache_args(|_| None, Some("abc")), None);
+        assert_eq!(
+            cache_args(|name| Some(format!("{name}-value")), Some("abc")),
+            Some(vec![
+                "--cache-from".to_string(),
+                "type=gha,scope=abc".to_string(),
+                "--cache-to".to_string(),
+                "type=gha,mode=max,scope=abc".to_string(),
+            ])
+        );
+        assert_eq!(
+            cache_args(|name| Some(name.to_string()), None).unwrap()[3],
+            "type=gha,mode=max"
+        );
+    }
+
+    #[test]
+    fn test_format_outputs() {
+        assert_eq!(
+            format_outputs(&[
+                ("image", "my-app".to_string()),
+                ("digest", "sha256:abc".to_string())
+            ]),
+            "image=my-app\ndigest=sha256:abc\n"
+        );
+    }
+}
@@ -0,0 +1,200 @@
+use super::client::DockerClient;
+use crate::nixpacks::files::format_size;
+use anyhow::{Context, Result};
+use serde_json::{json, Map, Value};
+use std::cmp::Reverse;
+
+/// Images larger than this many megabytes are reported with a warning, unless `NIXPACKS_IMAGE_SIZE_WARNING` is set.
+pub const DEFAULT_IMAGE_SIZE_WARNING: u64 = 2000;
+
+/// How many of the largest layers are listed in the report.
+const REPORTED_LAYERS: usize = 5;
+
+/// Layer commands are shortened to this many characters in the report.
+const MAX_LAYER_DESCRIPTION: usize = 80;
+
+/// One layer of an image and the instruction that created it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImageLayer {
+    pub size: u64,
+    pub created_by: String,
+}
+
+/// The size of a built image and the layers that make it up, from the bottom of the image to the top.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImageSize {
+    pub total: u64,
+    pub layers: Vec<ImageLayer>,
+}
+
+impl ImageSize {
+    /// Reads the size of an image and its layers with the container engine's CLI.
+    pub fn inspect(client: DockerClient, host: Option<&str>, name: &str) -> Result<ImageSize> {
+        let total = client.output(host, &["image", "inspect", "--format", "{{.Size}}", name])?;
+        let total = total
+            .trim()
+            .parse()
+            .with_context(|| format!("Parsing image size `{}`", total.trim()))?;
+
+        let history = client.output(
+            host,
+            &[
+                "history",
+                "--no-trunc",
+                "--human=false",
+                "--format",
+                "{{.Size}}\t{{.CreatedBy}}",
+                name,
+            ],
+        )?;
+
+        Ok(ImageSize {
+            total,
+            layers: parse_history(&history)?,
+        })
+    }
+
+    /// The layers that add the most to the image, largest first.
+    pub fn largest_layers(&self) -> Vec<&ImageLayer> {
+        let mut layers = self
+            .layers
+            .iter()
+            .filter(|layer| layer.size > 0)
+            .collect::<Vec<_>>();
+        // Stable, so equally sized layers stay in build order
+        layers.sort_by_key(|layer| Reverse(layer.size));
+        layers.truncate(REPORTED_LAYERS);
+        layers
+    }
+
+    /// The report printed after a build, with the total size and the largest layers.
+    pub fn to_text(&self) -> String {
+        let layers = self.largest_layers();
+        let sizes = layers
+            .iter()
+            .map(|layer| format_size(layer.size))
+            .collect::<Vec<_>>();
+        let width = sizes.iter().map(String::len).max().unwrap_or(0);
+
+        let mut lines = vec![format!("  Total: {}", format_size(self.total))];
+        if !layers.is_empty() {
+            lines.push("  Largest layers:".to_string());
+            lines.extend(layers.iter().zip(sizes).map(|(layer, size)| {
+                format!("    {size:>width$}  {}", describe_layer(&layer.created_by))
+            }));
+        }
+        lines.join("\n")
+    }
+
+    /// The sizes in bytes for JSON logs, with every layer rather than only the largest ones.
+    pub fn to_json_fields(&self) -> Map<String, Value> {
+        let mut fields = Map::new();
+        fields.insert("image_size".to_string(), json!(self.total));
+        fields.insert(
+            "layers".to_string(),
+            self.layers
+                .iter()
+                .map(|layer| json!({ "size": layer.size, "created_by": layer.created_by }))
+                .collect(),
+        );
+        fields
+    }
+}
+
+/// Parses `history` output with a `<size>\t<created by>` line per layer, which is listed from the top of the image.
+fn parse_history(history: &str) -> Result<Vec<ImageLayer>> {
+    let mut layers = history
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            let (size, created_by) = line.split_once('\t').unwrap_or((line, ""));
+            Ok(ImageLayer {
+                size: size
+                    .trim()
+                    .parse()
+                    .with_context(|| format!("Parsing layer size `{}`", size.trim()))?,
+                created_by: created_by.trim().to_string(),
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    layers.reverse();
+
+    Ok(layers)
+}
+
+/// Shortens the instruction that created a layer, dropping the shell and BuildKit noise Docker adds to it.
+fn describe_layer(created_by: &str) -> String {
+    let description = created_by
+        .trim()
+        .trim_end_matches("# buildkit")
+        .replace("/bin/sh -c #(nop) ", "")
+        .replace("/bin/sh -c ", "")
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    if description.chars().count() > MAX_LAYER_DESCRIPTION {
+        let shortened = description
+            .chars()
+            .take(MAX_LAYER_DESCRIPTION - 3)
+            .collect::<String>();
+        format!("{shortened}...")
+    } else {
+        description
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image_size() -> ImageSize {
+        ImageSize {
+            total: 1_250_000_000,
+            layers: parse_history(
+                "120\tCMD [\"/bin/bash\" \"-l\" \"-c\" \"npm run start\"]\n\
+                 250000000\tRUN /bin/sh -c npm ci # buildkit\n\
+                 0\tWORKDIR /app/\n\
+                 900000000\tRUN /bin/sh -c nix-env -if .nixpacks/nixpkgs.nix && nix-collect-garbage -d # buildkit\n\
+                 100000000\t/bin/sh -c #(nop) ADD file:abc in / \n",
+            )
+            .unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_parse_history() {
+        let size = image_size();
+        assert_eq!(size.layers.len(), 5);
+        assert_eq!(
+            size.layers[0],
+            ImageLayer {
+                size: 100_000_000,
+                created_by: "/bin/sh -c #(nop) ADD file:abc in /".to_string()
+            }
+        );
+        assert!(parse_history("lots\tRUN npm ci").is_err());
+    }
+
+    #[test]
+    fn test_image_size_report() {
+        let size = image_size();
+        assert_eq!(
+            size.to_text(),
+            "  Total: 1.2 GB\n  Largest layers:\n    900.0 MB  RUN nix-env -if .nixpacks/nixpkgs.nix && nix-collect-garbage -d\n    250.0 MB  RUN npm ci\n    100.0 MB  ADD file:abc in /\n       120 B  CMD [\"/bin/bash\" \"-l\" \"-c\" \"npm run start\"]"
+        );
+
+        let fields = size.to_json_fields();
+        assert_eq!(fields["image_size"], json!(1_250_000_000_u64));
+        assert_eq!(fields["layers"].as_array().unwrap().len(), 5);
+    }
+
+    #[test]
+    fn test_describe_layer() {
+        let long = format!("RUN /bin/sh -c echo {}", "a".repeat(100));
+        let description = describe_layer(&long);
+        assert_eq!(description.len(), MAX_LAYER_DESCRIPTION);
+        assert!(description.starts_with("RUN echo aaa"));
+        assert!(description.ends_with("..."));
+    }
+}
@@ -1,10 +1,12 @@
 use std::{
     fs::{self},
     path::PathBuf,
-    process::Command,
 };
 
-use super::{dockerfile_generation::OutputDir, file_server::FileServerConfig};
+use super::{
+    client::DockerClient, dockerfile_generation::OutputDir, file_server::FileServerConfig,
+};
+use crate::nixpacks::logger::Logger;
 use anyhow::{bail, Context, Result};
 use std::process::Stdio;
 
@@ -73,8 +75,10 @@ impl IncrementalCache {
     /// Create a filesystem image for each of the files in the incremental cache uploads directory, then upload these to the Docker cache.
     pub fn create_image(
         &self,
+        client: DockerClient,
         incremental_cache_dirs: &IncrementalCacheDirs,
         tag: &str,
+        logger: &Logger,
     ) -> Result<()> {
         let files = fs::read_dir(&incremental_cache_dirs.uploads_dir)?;
 
@@ -83,8 +87,13 @@ impl IncrementalCache {
         // #2 Create minimal Dockerfile: 6 seconds in a sample test
         // #3 Use Docker import: Provide 3 seconds in a sample test
         for f in files {
-            let mut docker_import_cmd = Command::new("docker");
-            docker_import_cmd.arg("import").arg(f?.path()).arg(tag);
+            let mut docker_import_cmd = client.command();
+            // The id of each imported image would be printed between the log lines
+            docker_import_cmd
+                .arg("import")
+                .arg(f?.path())
+                .arg(tag)
+                .stdout(Stdio::null());
 
             let result = docker_import_cmd
                 .spawn()?
@@ -96,13 +105,13 @@ impl IncrementalCache {
             }
         }
 
-        println!("Incremental cache image created: {}", &tag);
+        logger.log_step(&format!("Incremental cache image created: {tag}"));
         Ok(())
     }
 
     /// Check if the provided image_tag matches a tag in the incremental Docker image cache.
-    pub fn is_image_exists(image_tag: &str) -> Result<bool> {
-        let mut docker_inspect_cmd = Command::new("docker");
+    pub fn is_image_exists(client: DockerClient, image_tag: &str) -> Result<bool> {
+        let mut docker_inspect_cmd = client.command();
         docker_inspect_cmd
             .arg("manifest")
             .arg("inspect")
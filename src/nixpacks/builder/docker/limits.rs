@@ -0,0 +1,142 @@
+use super::DockerBuilderOptions;
+use anyhow::{bail, Context, Result};
+use serde_json::{json, Map, Value};
+
+/// CFS period that `--cpus` is converted to a quota of, matching `docker run --cpus`.
+pub const CPU_PERIOD: u64 = 100_000;
+
+/// Docker rejects CPU quotas below 1ms.
+const MIN_CPU_QUOTA: u64 = 1_000;
+
+/// The CFS quota for a number of CPUs, which can be fractional like `1.5`.
+pub fn get_cpu_quota(cpus: &str) -> Result<u64> {
+    let value: f64 = cpus
+        .trim()
+        .parse()
+        .with_context(|| format!("Parsing --cpus `{cpus}`"))?;
+    if !value.is_finite() || value <= 0.0 {
+        bail!("--cpus must be a positive number of CPUs, like 2 or 0.5");
+    }
+
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    let quota = (value * CPU_PERIOD as f64).round() as u64;
+    if quota < MIN_CPU_QUOTA {
+        bail!("--cpus must be at least 0.01");
+    }
+    Ok(quota)
+}
+
+/// The network that `RUN` instructions use. Extra hosts are only reachable on the host network.
+pub fn get_network(options: &DockerBuilderOptions) -> Option<String> {
+    match &options.network {
+        Some(network) => Some(network.clone()),
+        None if !options.add_host.is_empty() => Some("host".to_string()),
+        None => None,
+    }
+}
+
+/// The limits that BuildKit ignores, when any are set. Only the classic builder of `--docker-api` and Podman apply them.
+pub fn get_buildkit_warning(options: &DockerBuilderOptions) -> Option<String> {
+    let ignored = [
+        ("--memory", options.memory.is_some()),
+        ("--cpus", options.cpus.is_some()),
+        ("--cpu-quota", options.cpu_quota.is_some()),
+    ]
+    .into_iter()
+    .filter(|(_, is_set)| *is_set)
+    .map(|(flag, _)| flag)
+    .collect::<Vec<_>>();
+    if ignored.is_empty() {
+        return None;
+    }
+
+    Some(format!(
+        "BuildKit ignores {}, so the build runs without {}. Build with --docker-api or Podman to apply {}",
+        ignored.join(" and "),
+        if ignored.len() == 1 { "that limit" } else { "those limits" },
+        if ignored.len() == 1 { "it" } else { "them" },
+    ))
+}
+
+/// The limits the build runs with, as text and as fields for JSON logs, when any are set.
+pub fn get_limits_summary(options: &DockerBuilderOptions) -> Option<(String, Map<String, Value>)> {
+    let limits = [
+        ("memory", options.memory.clone()),
+        ("cpus", options.cpus.clone()),
+        ("cpu_quota", options.cpu_quota.clone()),
+        ("network", get_network(options)),
+    ]
+    .into_iter()
+    .filter_map(|(name, value)| value.map(|value| (name, value)))
+    .collect::<Vec<_>>();
+    if limits.is_empty() {
+        return None;
+    }
+
+    let text = limits
+        .iter()
+        .map(|(name, value)| format!("{name}: {value}"))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let fields = Map::from_iter([(
+        "limits".to_string(),
+        limits
+            .into_iter()
+            .map(|(name, value)| (name.to_string(), json!(value)))
+            .collect::<Map<_, _>>()
+            .into(),
+    )]);
+    Some((text, fields))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_cpu_quota() -> Result<()> {
+        assert_eq!(get_cpu_quota("2")?, 200_000);
+        assert_eq!(get_cpu_quota("0.5")?, 50_000);
+        assert!(get_cpu_quota("0").is_err());
+        assert!(get_cpu_quota("0.001").is_err());
+        assert!(get_cpu_quota("many").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_buildkit_warning() {
+        assert!(get_buildkit_warning(&DockerBuilderOptions {
+            network: Some("host".to_string()),
+            ..Default::default()
+        })
+        .is_none());
+
+        let options = DockerBuilderOptions {
+            memory: Some("2g".to_string()),
+            cpus: Some("1.5".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            get_buildkit_warning(&options).unwrap(),
+            "BuildKit ignores --memory and --cpus, so the build runs without those limits. Build with --docker-api or Podman to apply them"
+        );
+    }
+
+    #[test]
+    fn test_get_limits_summary() {
+        assert!(get_limits_summary(&DockerBuilderOptions::default()).is_none());
+
+        let options = DockerBuilderOptions {
+            memory: Some("2g".to_string()),
+            cpus: Some("1.5".to_string()),
+            add_host: vec!["db:10.0.0.2".to_string()],
+            ..Default::default()
+        };
+        let (text, fields) = get_limits_summary(&options).unwrap();
+        assert_eq!(text, "memory: 2g\ncpus: 1.5\nnetwork: host");
+        assert_eq!(
+            Value::from(fields),
+            json!({ "limits": { "memory": "2g", "cpus": "1.5", "network": "host" } })
+        );
+    }
+}
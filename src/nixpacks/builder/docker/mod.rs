@@ -1,4 +1,15 @@
 use super::ImageBuilder;
+use crate::nixpacks::{app::GitInfo, logger::LogFormat};
+
+/// How the build context is written when saving output instead of building with Docker.
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// A plain directory containing the app, Dockerfile, and a `docker build` script.
+    #[default]
+    Dir,
+    /// A gzipped context tarball that daemonless builders like kaniko and buildah can consume.
+    Oci,
+}
 
 /// Holds options for generating a Docker image.
 #[derive(Clone, Default, Debug)]
@@ -6,6 +17,7 @@ use super::ImageBuilder;
 pub struct DockerBuilderOptions {
     pub name: Option<String>,
     pub out_dir: Option<String>,
+    pub out_format: OutputFormat,
     pub print_dockerfile: bool,
     pub tags: Vec<String>,
     pub labels: Vec<String>,
@@ -19,19 +31,77 @@ pub struct DockerBuilderOptions {
     pub no_error_without_start: bool,
     pub incremental_cache_image: Option<String>,
     pub cpu_quota: Option<String>,
+    /// Number of CPUs the build can use, converted to a CFS quota.
+    pub cpus: Option<String>,
     pub memory: Option<String>,
+    /// Network for `RUN` instructions, like `none` or `host`.
+    pub network: Option<String>,
+    /// Ask on the terminal before building the generated plan.
+    pub confirm: bool,
+    /// Seconds the image build can run for before it is stopped.
+    pub timeout: Option<u64>,
+    /// Times to retry Nix package downloads and builds that push to a registry.
+    pub retries: u32,
     pub verbose: bool,
     pub docker_host: Option<String>,
     pub docker_tls_verify: Option<String>,
     pub docker_output: Option<String>,
+    /// Tar file to save the built image to, like `docker save`.
+    pub save: Option<String>,
+    /// Paths to copy out of the built image, as `<image path>=<local path>`.
+    pub artifacts: Vec<String>,
+    /// Scan the built image for vulnerabilities, failing the build on any at or above `scan_severity`.
+    pub scan: bool,
+    pub scan_severity: Option<String>,
+    /// Scanner to run instead of trivy, with `{image}` replaced by the image name.
+    pub scan_cmd: Option<String>,
+    /// Directory to write Kubernetes manifests for the image to.
+    pub k8s_out: Option<String>,
+    /// Write step outputs for GitHub Actions and cache layers in the GitHub Actions cache.
+    pub gha: bool,
     pub add_host: Vec<String>,
     pub docker_cert_path: Option<String>,
+    pub docker_client: Option<String>,
+    pub docker_api: bool,
+    /// BuildKit daemon or buildx builder to build on instead of the local daemon, given as `remote://host[:port]`.
+    pub builder: Option<String>,
+    /// Push the image from BuildKit instead of loading it into the local daemon.
+    pub push: bool,
+    pub include_ignored: bool,
+    pub nix_flake: bool,
+    pub nix_caches: Vec<String>,
+    pub nix_cache_keys: Vec<String>,
+    pub non_root: bool,
+    pub init: bool,
+    pub process: Option<String>,
+    pub run_release: bool,
+    pub secrets: Vec<String>,
+    pub ssh: bool,
+    pub registry_configs: Vec<String>,
+    pub allow_hooks: bool,
+    pub use_dockerfile: bool,
+    /// Dockerfile to place the generated sections in, instead of the default layout.
+    pub dockerfile_template: Option<String>,
+    /// The commit being built, which is set as `GIT_SHA` and `GIT_BRANCH` in the image.
+    pub git_info: Option<GitInfo>,
+    pub log_format: LogFormat,
 }
 
+pub mod artifacts;
 mod cache;
+pub mod client;
 pub mod docker_helper;
 pub mod docker_image_builder;
 mod dockerfile_generation;
+pub mod dockerfile_template;
+pub mod engine;
 pub mod file_server;
+pub mod gha;
+pub mod image_size;
 pub mod incremental_cache;
+pub mod limits;
+pub mod provenance;
+pub mod remote;
+pub mod retry;
+pub mod scan;
 pub mod utils;
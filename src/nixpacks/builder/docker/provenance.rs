@@ -0,0 +1,233 @@
+use crate::nixpacks::{app::GitInfo, files::NIXPACKS_IGNORE_FILE, plan::BuildPlan};
+use anyhow::{Context, Result};
+use ignore::WalkBuilder;
+use sha2::{Digest, Sha256};
+use std::{fs, path::Path};
+
+pub const VERSION_LABEL: &str = "com.nixpacks.version";
+pub const PROVIDERS_LABEL: &str = "com.nixpacks.providers";
+pub const SOURCE_HASH_LABEL: &str = "com.nixpacks.source-hash";
+pub const PLAN_LABEL: &str = "com.nixpacks.plan";
+pub const BUILD_HASH_LABEL: &str = "com.nixpacks.build-hash";
+pub const REVISION_LABEL: &str = "org.opencontainers.image.revision";
+pub const GIT_BRANCH_LABEL: &str = "com.nixpacks.git-branch";
+pub const GIT_DIRTY_LABEL: &str = "com.nixpacks.git-dirty";
+
+/// Labels recording how an image was produced, so the build can be reconstructed later.
+pub fn get_provenance_labels(
+    app_src: &Path,
+    plan: &BuildPlan,
+    providers: &[String],
+    git_info: Option<&GitInfo>,
+    dockerfile_template: Option<&str>,
+) -> Result<Vec<String>> {
+    let plan_json = serde_json::to_string(plan).context("Serializing build plan")?;
+    let source_hash = hash_source(app_src)?;
+
+    // The template is part of the Dockerfile, so images built with another one can't be reused
+    let build_source = match dockerfile_template {
+        Some(template) => format!("{source_hash}\0{template}"),
+        None => source_hash.clone(),
+    };
+
+    // The commit is set in the image as GIT_SHA and GIT_BRANCH, so images from other commits can't be reused
+    let build_hash = match git_info {
+        Some(git_info) => hash_build(
+            &plan_json,
+            &format!(
+                "{build_source}\0{}\0{}",
+                git_info.sha,
+                git_info.branch.clone().unwrap_or_default()
+            ),
+        ),
+        None => hash_build(&plan_json, &build_source),
+    };
+
+    let mut labels = vec![
+        format!("{VERSION_LABEL}={}", env!("CARGO_PKG_VERSION")),
+        format!("{PROVIDERS_LABEL}={}", providers.join(",")),
+        format!("{SOURCE_HASH_LABEL}=sha256:{source_hash}"),
+        format!("{BUILD_HASH_LABEL}=sha256:{build_hash}"),
+        // Labels can be read by anyone who can pull the image, so secrets are only part of the hash
+        format!(
+            "{PLAN_LABEL}={}",
+            serde_json::to_string(&plan.redacted()).context("Serializing build plan")?
+        ),
+    ];
+    labels.extend(git_info.map(get_git_labels).unwrap_or_default());
+
+    Ok(labels)
+}
+
+/// Labels recording the commit an image was built from.
+fn get_git_labels(git_info: &GitInfo) -> Vec<String> {
+    let mut labels = vec![
+        format!("{REVISION_LABEL}={}", git_info.sha),
+        format!("{GIT_DIRTY_LABEL}={}", git_info.dirty),
+    ];
+    if let Some(branch) = &git_info.branch {
+        labels.push(format!("{GIT_BRANCH_LABEL}={branch}"));
+    }
+
+    labels
+}
+
+/// Hashes everything that determines the contents of the image, so identical builds can be reused.
+pub fn hash_build(plan_json: &str, source_hash: &str) -> String {
+    let mut hasher = Sha256::new();
+    for part in [env!("CARGO_PKG_VERSION"), plan_json, source_hash] {
+        hasher.update(part.as_bytes());
+        hasher.update([0]);
+    }
+
+    hex::encode(hasher.finalize())
+}
+
+/// The value of the label with the given key, from a list of `key=value` labels.
+pub fn get_label<'a>(labels: &'a [String], key: &str) -> Option<&'a str> {
+    labels.iter().find_map(|label| {
+        label
+            .strip_prefix(key)
+            .and_then(|rest| rest.strip_prefix('='))
+    })
+}
+
+/// Hashes the paths and contents of the files that are copied into the image.
+///
+/// Files are visited in sorted order so the hash only changes when the source does.
+pub fn hash_source(app_src: &Path) -> Result<String> {
+    let mut paths = WalkBuilder::new(app_src)
+        .standard_filters(false)
+        .hidden(false)
+        .parents(false)
+        .require_git(false)
+        .git_ignore(true)
+        .add_custom_ignore_filename(NIXPACKS_IGNORE_FILE)
+        .filter_entry(|entry| entry.file_name() != ".git")
+        .build()
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_type().map_or(false, |t| t.is_file()))
+        .map(ignore::DirEntry::into_path)
+        .collect::<Vec<_>>();
+    paths.sort();
+
+    let mut hasher = Sha256::new();
+    for path in paths {
+        let relative = path.strip_prefix(app_src)?;
+        hasher.update(relative.to_string_lossy().as_bytes());
+        hasher.update([0]);
+        hasher.update(fs::read(&path).with_context(|| format!("Reading {}", path.display()))?);
+        hasher.update([0]);
+    }
+
+    Ok(hex::encode(hasher.finalize()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempdir::TempDir;
+
+    #[test]
+    fn test_hash_source() -> Result<()> {
+        let app = TempDir::new("nixpacks-app")?;
+        fs::write(app.path().join("index.js"), "console.log('hi')")?;
+        let hash = hash_source(app.path())?;
+        assert_eq!(hash.len(), 64);
+
+        // Ignored files don't change the hash
+        fs::write(app.path().join(".gitignore"), "dist\n")?;
+        let with_gitignore = hash_source(app.path())?;
+        fs::create_dir(app.path().join("dist"))?;
+        fs::write(app.path().join("dist/out.js"), "")?;
+        assert_eq!(hash_source(app.path())?, with_gitignore);
+
+        fs::write(app.path().join("index.js"), "console.log('bye')")?;
+        assert_ne!(hash_source(app.path())?, with_gitignore);
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_provenance_labels() -> Result<()> {
+        let app = TempDir::new("nixpacks-app")?;
+        let labels = get_provenance_labels(
+            app.path(),
+            &BuildPlan::default(),
+            &["node".to_string()],
+            None,
+            None,
+        )?;
+
+        assert!(labels.contains(&"com.nixpacks.providers=node".to_string()));
+        assert!(labels.contains(&"com.nixpacks.plan={}".to_string()));
+        assert_eq!(
+            get_label(&labels, BUILD_HASH_LABEL),
+            Some(format!("sha256:{}", hash_build("{}", &hash_source(app.path())?)).as_str())
+        );
+        assert_eq!(get_label(&labels, "com.nixpacks"), None);
+
+        let with_template = get_provenance_labels(
+            app.path(),
+            &BuildPlan::default(),
+            &["node".to_string()],
+            None,
+            Some("FROM {{base_image}}\n{{phases}}"),
+        )?;
+        assert_eq!(
+            get_label(&with_template, SOURCE_HASH_LABEL),
+            get_label(&labels, SOURCE_HASH_LABEL)
+        );
+        assert_ne!(
+            get_label(&with_template, BUILD_HASH_LABEL),
+            get_label(&labels, BUILD_HASH_LABEL)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_plan_label_is_redacted() -> Result<()> {
+        let app = TempDir::new("nixpacks-app")?;
+        let plan = BuildPlan::from_toml("[variables]\nDATABASE_PASSWORD = \"hunter22\"")?;
+        let labels = get_provenance_labels(app.path(), &plan, &[], None, None)?;
+
+        let plan_label = get_label(&labels, PLAN_LABEL).unwrap();
+        assert!(!plan_label.contains("hunter22"));
+        assert!(plan_label.contains("DATABASE_PASSWORD"));
+        assert_eq!(
+            get_label(&labels, BUILD_HASH_LABEL),
+            Some(
+                format!(
+                    "sha256:{}",
+                    hash_build(&serde_json::to_string(&plan)?, &hash_source(app.path())?)
+                )
+                .as_str()
+            )
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_git_labels() {
+        let labels = get_git_labels(&GitInfo {
+            sha: "abc123".to_string(),
+            branch: Some("main".to_string()),
+            dirty: false,
+        });
+        assert_eq!(
+            labels,
+            vec![
+                "org.opencontainers.image.revision=abc123",
+                "com.nixpacks.git-dirty=false",
+                "com.nixpacks.git-branch=main",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_hash_build() {
+        let hash = hash_build("{}", "abc");
+        assert_eq!(hash, hash_build("{}", "abc"));
+        assert_ne!(hash, hash_build(r#"{"providers":[]}"#, "abc"));
+        assert_ne!(hash, hash_build("{}", "abd"));
+    }
+}
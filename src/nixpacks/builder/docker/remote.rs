@@ -0,0 +1,120 @@
+use super::client::DockerClient;
+use anyhow::{bail, Context, Result};
+use sha2::{Digest, Sha256};
+
+/// Port that `buildkitd` listens on when it is started with `--addr tcp://0.0.0.0:1234`.
+const DEFAULT_BUILDKIT_PORT: u16 = 1234;
+
+/// Where images are built instead of the local Docker daemon, given with `--builder`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RemoteBuilder {
+    /// A BuildKit daemon, which a buildx builder with the `remote` driver is created for.
+    Endpoint(String),
+    /// A buildx builder that was already created, e.g. one that connects over TLS or SSH.
+    Named(String),
+}
+
+impl RemoteBuilder {
+    /// Parses `remote://host[:port]`, a `tcp://` or `unix://` BuildKit address, or the name of a buildx builder.
+    pub fn parse(value: &str) -> Result<RemoteBuilder> {
+        let value = value.trim();
+        if let Some(address) = value.strip_prefix("remote://") {
+            let address = address.trim_end_matches('/');
+            if address.is_empty() {
+                bail!("The remote builder `{value}` must include a host, like remote://builder.internal");
+            }
+            return Ok(if address.contains(':') {
+                RemoteBuilder::Endpoint(format!("tcp://{address}"))
+            } else {
+                RemoteBuilder::Endpoint(format!("tcp://{address}:{DEFAULT_BUILDKIT_PORT}"))
+            });
+        }
+        if value.starts_with("tcp://") || value.starts_with("unix://") {
+            return Ok(RemoteBuilder::Endpoint(value.to_string()));
+        }
+        if value.is_empty() || value.contains("://") {
+            bail!("Unknown builder `{value}`. Expected remote://host[:port], a tcp:// or unix:// BuildKit address, or a buildx builder name");
+        }
+        Ok(RemoteBuilder::Named(value.to_string()))
+    }
+
+    /// The buildx builder that builds run with, which is named after the endpoint so it is only created once.
+    pub fn builder_name(&self) -> String {
+        match self {
+            RemoteBuilder::Endpoint(endpoint) => {
+                let hash = hex::encode(Sha256::digest(endpoint.as_bytes()));
+                format!("nixpacks-remote-{}", &hash[..12])
+            }
+            RemoteBuilder::Named(name) => name.clone(),
+        }
+    }
+
+    /// Creates the buildx builder for an endpoint unless it already exists.
+    pub fn ensure_builder(&self, client: DockerClient, host: Option<&str>) -> Result<()> {
+        if client.is_podman() {
+            bail!("Remote builders use docker buildx, which Podman doesn't support");
+        }
+
+        let name = self.builder_name();
+        if client.output(host, &["buildx", "inspect", &name]).is_ok() {
+            return Ok(());
+        }
+        match self {
+            RemoteBuilder::Endpoint(endpoint) => client
+                .output(
+                    host,
+                    &[
+                        "buildx", "create", "--name", &name, "--driver", "remote", endpoint,
+                    ],
+                )
+                .with_context(|| format!("Creating a buildx builder for {endpoint}"))
+                .map(|_| ()),
+            RemoteBuilder::Named(_) => {
+                bail!("There is no buildx builder named `{name}`. Create it with `docker buildx create --name {name}`")
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_remote_builder() -> Result<()> {
+        assert_eq!(
+            RemoteBuilder::parse("remote://builder.internal")?,
+            RemoteBuilder::Endpoint("tcp://builder.internal:1234".to_string())
+        );
+        assert_eq!(
+            RemoteBuilder::parse("remote://10.0.0.5:9999/")?,
+            RemoteBuilder::Endpoint("tcp://10.0.0.5:9999".to_string())
+        );
+        assert_eq!(
+            RemoteBuilder::parse("unix:///run/buildkit/buildkitd.sock")?,
+            RemoteBuilder::Endpoint("unix:///run/buildkit/buildkitd.sock".to_string())
+        );
+        assert_eq!(
+            RemoteBuilder::parse("x86-builder")?,
+            RemoteBuilder::Named("x86-builder".to_string())
+        );
+        assert!(RemoteBuilder::parse("remote://").is_err());
+        assert!(RemoteBuilder::parse("ssh://me@builder").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_builder_name() -> Result<()> {
+        let builder = RemoteBuilder::parse("remote://builder.internal")?;
+        assert!(builder.builder_name().starts_with("nixpacks-remote-"));
+        assert_eq!(
+            builder.builder_name(),
+            RemoteBuilder::parse("tcp://builder.internal:1234")?.builder_name()
+        );
+        assert_eq!(
+            RemoteBuilder::parse("x86-builder")?.builder_name(),
+            "x86-builder"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,97 @@
+use std::time::Duration;
+
+/// Retries wait at most this long, so a high `--retries` doesn't stall a build for hours.
+const MAX_RETRY_DELAY_SECS: u64 = 60;
+
+/// How long to wait before retrying after a failed attempt, doubling from one second.
+pub fn get_retry_delay(attempt: u32) -> Duration {
+    Duration::from_secs(
+        1_u64
+            .checked_shl(attempt)
+            .unwrap_or(MAX_RETRY_DELAY_SECS)
+            .min(MAX_RETRY_DELAY_SECS),
+    )
+}
+
+/// Wraps the `RUN` instructions in Dockerfile lines in a loop that retries them with the same backoff.
+///
+/// The last failure's exit code is kept, so the step fails the same way it would without retries.
+pub fn with_retries(lines: &str, retries: u32) -> String {
+    if retries == 0 {
+        return lines.to_string();
+    }
+
+    lines
+        .lines()
+        .map(|line| match line.strip_prefix("RUN ") {
+            Some(cmd) => format!(
+                "RUN for attempt in $(seq 0 {retries}); do ({cmd}) && break || status=$?; \
+                 if [ $attempt -eq {retries} ]; then exit $status; fi; \
+                 delay=$((1 << attempt)); [ $delay -le {MAX_RETRY_DELAY_SECS} ] || delay={MAX_RETRY_DELAY_SECS}; \
+                 echo \"Retrying in ${{delay}}s\"; sleep $delay; done"
+            ),
+            None => line.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Whether a `--docker-output` pushes the image, which makes the build worth retrying when it fails.
+pub fn pushes_to_registry(docker_output: Option<&str>) -> bool {
+    docker_output.map_or(false, |output| {
+        output
+            .split(',')
+            .map(str::trim)
+            .any(|part| part == "type=registry" || part == "push=true")
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_retry_delay() {
+        assert_eq!(get_retry_delay(0), Duration::from_secs(1));
+        assert_eq!(get_retry_delay(3), Duration::from_secs(8));
+        assert_eq!(get_retry_delay(10), Duration::from_secs(60));
+        assert_eq!(get_retry_delay(100), Duration::from_secs(60));
+    }
+
+    #[test]
+    fn test_with_retries() {
+        let lines = "COPY .nixpacks/nixpkgs.nix .nixpacks/nixpkgs.nix\nRUN nix-env -if .nixpacks/nixpkgs.nix && nix-collect-garbage -d";
+        assert_eq!(with_retries(lines, 0), lines);
+
+        let retried = with_retries(lines, 3);
+        assert!(retried.starts_with("COPY .nixpacks/nixpkgs.nix .nixpacks/nixpkgs.nix\nRUN for attempt in $(seq 0 3); do (nix-env -if .nixpacks/nixpkgs.nix && nix-collect-garbage -d) && break"));
+        assert!(retried.contains("if [ $attempt -eq 3 ]; then exit $status; fi"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_retried_command_keeps_exit_code() {
+        let run = with_retries("RUN exit 3", 1);
+        let status = std::process::Command::new("sh")
+            .arg("-c")
+            .arg(
+                run.strip_prefix("RUN ")
+                    .unwrap()
+                    .replace("sleep $delay", "true"),
+            )
+            .output()
+            .unwrap()
+            .status;
+        assert_eq!(status.code(), Some(3));
+    }
+
+    #[test]
+    fn test_pushes_to_registry() {
+        assert!(pushes_to_registry(Some("type=registry")));
+        assert!(pushes_to_registry(Some(
+            "type=image,name=ghcr.io/me/app, push=true"
+        )));
+        assert!(!pushes_to_registry(Some("type=local,dest=out")));
+        assert!(!pushes_to_registry(None));
+    }
+}
@@ -0,0 +1,113 @@
+use anyhow::{bail, Result};
+use std::process::Command;
+
+/// Vulnerabilities at or above this severity fail the build unless `--scan-severity` is given.
+pub const DEFAULT_SCAN_SEVERITY: Severity = Severity::High;
+
+/// How severe a vulnerability is, using the levels that trivy and grype report.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Low,
+    Medium,
+    High,
+    Critical,
+}
+
+impl Severity {
+    pub fn from_name(name: &str) -> Result<Severity> {
+        match name.trim().to_lowercase().as_str() {
+            "low" => Ok(Severity::Low),
+            "medium" => Ok(Severity::Medium),
+            "high" => Ok(Severity::High),
+            "critical" => Ok(Severity::Critical),
+            _ => {
+                bail!("Unknown severity `{name}`. Expected `low`, `medium`, `high`, or `critical`")
+            }
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Severity::Low => "low",
+            Severity::Medium => "medium",
+            Severity::High => "high",
+            Severity::Critical => "critical",
+        }
+    }
+
+    /// This severity and the ones above it, as trivy's `--severity` list.
+    fn trivy_severities(self) -> String {
+        [
+            Severity::Low,
+            Severity::Medium,
+            Severity::High,
+            Severity::Critical,
+        ]
+        .into_iter()
+        .filter(|severity| *severity >= self)
+        .map(|severity| severity.name().to_uppercase())
+        .collect::<Vec<_>>()
+        .join(",")
+    }
+}
+
+/// The trivy command that fails when the image has vulnerabilities at or above the severity.
+pub fn trivy_command(image: &str, severity: Severity) -> Command {
+    let mut command = Command::new("trivy");
+    command
+        .arg("image")
+        .arg("--exit-code")
+        .arg("1")
+        .arg("--severity")
+        .arg(severity.trivy_severities())
+        .arg("--no-progress")
+        .arg(image);
+    command
+}
+
+/// A custom scanner command, with `{image}` replaced by the image name.
+pub fn custom_scan_command(cmd: &str, image: &str) -> String {
+    cmd.replace("{image}", image)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_severity() -> Result<()> {
+        assert_eq!(Severity::from_name(" HIGH ")?, Severity::High);
+        assert!(Severity::from_name("severe").is_err());
+        assert!(Severity::Critical > Severity::High);
+        Ok(())
+    }
+
+    #[test]
+    fn test_trivy_command() {
+        let command = trivy_command("my-app", Severity::Medium);
+        assert_eq!(command.get_program(), "trivy");
+        assert_eq!(
+            command
+                .get_args()
+                .map(|arg| arg.to_string_lossy().to_string())
+                .collect::<Vec<_>>(),
+            vec![
+                "image",
+                "--exit-code",
+                "1",
+                "--severity",
+                "MEDIUM,HIGH,CRITICAL",
+                "--no-progress",
+                "my-app"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_custom_scan_command() {
+        assert_eq!(
+            custom_scan_command("grype {image} --fail-on high", "my-app:latest"),
+            "grype my-app:latest --fail-on high"
+        );
+    }
+}
@@ -1,6 +1,16 @@
-use std::path::Path;
+use std::{ffi::OsStr, path::Path};
 
 use super::cache::sanitize_cache_key;
+use crate::nixpacks::plan::phase::Healthcheck;
+use anyhow::{bail, Result};
+
+/// Registry config files that can be mounted during the install phase, and where their package manager reads them from.
+const REGISTRY_CONFIG_TARGETS: &[(&str, &str)] = &[
+    (".npmrc", "/root/.npmrc"),
+    (".yarnrc.yml", "/root/.yarnrc.yml"),
+    ("pip.conf", "/etc/pip.conf"),
+    (".netrc", "/root/.netrc"),
+];
 
 /// Using the provided cache_key and cache_directories, produce a string of Docker command flags mounting the cache.
 pub fn get_cache_mount(
@@ -24,6 +34,71 @@ pub fn get_cache_mount(
     }
 }
 
+/// Produce the BuildKit flags that mount each secret into a RUN command without storing it in a layer.
+pub fn get_secret_mounts(secrets: &[String]) -> String {
+    secrets
+        .iter()
+        .map(|name| format!("--mount=type=secret,id={name},required=true"))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Prefix a command so it can read each mounted secret from an environment variable of the same name.
+pub fn with_secret_env(cmd: &str, secrets: &[String]) -> String {
+    if secrets.is_empty() {
+        return cmd.to_string();
+    }
+
+    let exports = secrets
+        .iter()
+        .map(|name| format!("export {name}=\"$(cat /run/secrets/{name})\""))
+        .collect::<Vec<_>>()
+        .join(" && ");
+    format!("{exports} && {cmd}")
+}
+
+/// Find the id of the secret holding a registry config file, and where the file is mounted in the build container.
+pub fn get_registry_config_secret(path: &str) -> Result<(String, &'static str)> {
+    let file_name = Path::new(path)
+        .file_name()
+        .and_then(OsStr::to_str)
+        .unwrap_or_default();
+
+    match REGISTRY_CONFIG_TARGETS
+        .iter()
+        .find(|(name, _)| *name == file_name)
+    {
+        Some((name, target)) => Ok((
+            format!(
+                "registry-{}",
+                name.trim_start_matches('.').replace('.', "-")
+            ),
+            target,
+        )),
+        None => bail!(
+            "Unsupported registry config `{path}`. Expected one of {}",
+            REGISTRY_CONFIG_TARGETS
+                .iter()
+                .map(|(name, _)| *name)
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    }
+}
+
+/// The path of a file in the image, which always uses `/` whatever the host's path separator is.
+fn get_image_path(app_dir: &str, file: &str) -> String {
+    if file.starts_with('/') {
+        file.to_string()
+    } else {
+        format!(
+            "{}/{}",
+            app_dir.trim_end_matches('/'),
+            file.trim_start_matches("./")
+        )
+    }
+}
+
 /// Produce Dockerfile line(s) copying files into the build image.
 pub fn get_copy_commands(files: &[String], app_dir: &str) -> Vec<String> {
     if files.is_empty() {
@@ -31,14 +106,7 @@ pub fn get_copy_commands(files: &[String], app_dir: &str) -> Vec<String> {
     } else {
         files
             .iter()
-            .map(|file| {
-                let file_in_app_dir = Path::new(app_dir)
-                    .join(file.trim_start_matches("./"))
-                    .display()
-                    .to_string();
-
-                format!("COPY {file} {file_in_app_dir}")
-            })
+            .map(|file| format!("COPY {file} {}", get_image_path(app_dir, file)))
             .collect()
     }
 }
@@ -51,11 +119,7 @@ pub fn get_copy_from_commands(from: &str, files: &[String], app_dir: &str) -> Ve
         files
             .iter()
             .map(|file| {
-                let file_in_app_dir = Path::new(app_dir)
-                    .join(file.trim_start_matches("./"))
-                    .display()
-                    .to_string();
-
+                let file_in_app_dir = get_image_path(app_dir, file);
                 format!("COPY --from={from} {file_in_app_dir} {file_in_app_dir}")
             })
             .collect()
@@ -63,10 +127,144 @@ pub fn get_copy_from_commands(from: &str, files: &[String], app_dir: &str) -> Ve
 }
 
 /// Produce the Dockerfile line containing the CMD instruction which executes the application.
+///
+/// The command is JSON encoded, so quotes, backslashes, and newlines survive as written.
 pub fn get_exec_command(command: &str) -> String {
-    let params = command.replace('\"', "\\\"");
+    let params = serde_json::to_string(command).unwrap_or_default();
+
+    format!("CMD [{params}]")
+}
+
+/// The CMD instruction for an image without a shell, which runs the program directly.
+///
+/// Commands that need a shell, such as ones with variables, quotes, or pipes, have no such form.
+pub fn get_exec_form_command(command: &str) -> Option<String> {
+    let command = command.trim();
+    let command = command.strip_prefix("exec ").unwrap_or(command);
+    let words = command.split_whitespace().collect::<Vec<_>>();
+    let needs_shell = command.contains(|c: char| "$\"'\\`;&|()<>*?~{}#\n".contains(c))
+        || words.first().map_or(true, |word| word.contains('='));
+    if needs_shell {
+        return None;
+    }
+
+    Some(format!("CMD {}", serde_json::to_string(&words).ok()?))
+}
+
+/// The command of a `RUN` instruction, in shell form so it runs in the login shell that has the Nix packages.
+///
+/// Commands that the Dockerfile parser would split or read as exec form, such as ones with newlines, a trailing backslash, or a leading `[`, are quoted on one line and run with `eval`, so they run as written.
+pub fn get_run_command(command: &str) -> String {
+    let trimmed = command.trim();
+    if command.contains(['\n', '\r'])
+        || trimmed.is_empty()
+        || trimmed.ends_with('\\')
+        || trimmed.starts_with('[')
+    {
+        format!("eval {}", ansi_c_quote(command))
+    } else {
+        command.to_string()
+    }
+}
+
+/// Quote a string as a Bash `$'...'` string, which keeps newlines on a single line.
+fn ansi_c_quote(value: &str) -> String {
+    let escaped = value
+        .replace('\\', "\\\\")
+        .replace('\'', "\\'")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r");
+    format!("$'{escaped}'")
+}
+
+/// Quote a value for a Dockerfile `ENV` instruction if it contains whitespace, quotes, or backslashes.
+///
+/// `$` is left as is, so values like `$HOME/.rbenv/bin` still expand when the instruction runs.
+pub fn quote_dockerfile_value(value: &str) -> String {
+    if value.is_empty() || value.contains(|c: char| c.is_whitespace() || "\"'\\".contains(c)) {
+        let escaped = value.replace('\\', "\\\\").replace('"', "\\\"");
+        format!("\"{escaped}\"")
+    } else {
+        value.to_string()
+    }
+}
 
-    format!("CMD [\"{params}\"]")
+/// Quote an argument for a POSIX shell if it contains anything other than plain word characters.
+pub fn shell_quote(arg: &str) -> String {
+    let is_plain = !arg.is_empty()
+        && arg
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./:=@,+%".contains(c));
+    if is_plain {
+        arg.to_string()
+    } else {
+        format!("'{}'", arg.replace('\'', "'\\''"))
+    }
+}
+
+/// Shell builtins and keywords, which `exec` can't run since they aren't programs.
+const SHELL_BUILTINS: &[&str] = &[
+    ".", ":", "alias", "case", "cd", "declare", "eval", "export", "for", "if", "local", "read",
+    "readonly", "set", "shift", "source", "trap", "ulimit", "umask", "unset", "until", "wait",
+    "while", "{", "!",
+];
+
+/// Prefix a simple start command with `exec` so the app replaces the shell and receives signals like SIGTERM directly.
+///
+/// Commands that use shell operators, start with variable assignments, or start with a shell builtin are left for the
+/// shell to run.
+pub fn exec_start_command(command: &str) -> String {
+    let command = command.trim();
+    let first_word = command.split_whitespace().next().unwrap_or_default();
+    let is_simple = !command.is_empty()
+        && !command.contains([';', '&', '|', '(', ')', '`', '\n'])
+        && !first_word.contains('=')
+        && first_word != "exec"
+        && !SHELL_BUILTINS.contains(&first_word);
+
+    if is_simple {
+        format!("exec {command}")
+    } else {
+        command.to_string()
+    }
+}
+
+/// Installs curl for a `path` healthcheck in a run image that doesn't have it, such as `ubuntu:jammy` or the slim
+/// Node images. Images without apt fail the build here, rather than reporting the container as unhealthy forever.
+pub const HEALTHCHECK_CURL_INSTALL: &str = "RUN command -v curl > /dev/null \\
+  || (command -v apt-get > /dev/null && apt-get update && apt-get install -y --no-install-recommends curl && rm -rf /var/lib/apt/lists/*) \\
+  || (echo 'The path healthcheck needs curl, which the run image does not have and cannot install with apt-get' >&2 && exit 1)";
+
+/// Produce the Dockerfile line containing the HEALTHCHECK instruction, if the healthcheck has a path or command.
+pub fn get_healthcheck_command(healthcheck: &Healthcheck) -> String {
+    let check = match (&healthcheck.cmd, &healthcheck.path) {
+        (Some(cmd), _) => cmd.clone(),
+        (None, Some(path)) => format!(
+            "curl -fsS \"http://localhost:${{PORT}}/{}\" || exit 1",
+            path.trim_start_matches('/')
+        ),
+        (None, None) => return String::new(),
+    };
+
+    let mut flags = Vec::new();
+    if let Some(interval) = &healthcheck.interval {
+        flags.push(format!("--interval={interval}"));
+    }
+    if let Some(timeout) = &healthcheck.timeout {
+        flags.push(format!("--timeout={timeout}"));
+    }
+    if let Some(start_period) = &healthcheck.start_period {
+        flags.push(format!("--start-period={start_period}"));
+    }
+    if let Some(retries) = healthcheck.retries {
+        flags.push(format!("--retries={retries}"));
+    }
+
+    if flags.is_empty() {
+        format!("HEALTHCHECK CMD {check}")
+    } else {
+        format!("HEALTHCHECK {} CMD {check}", flags.join(" "))
+    }
 }
 
 #[cfg(test)]
@@ -95,7 +293,36 @@ mod tests {
         assert_eq!(expected, actual);
     }
 
-    #[cfg(not(target_os = "windows"))]
+    #[test]
+    fn test_secret_mounts() {
+        let secrets = vec!["NPM_TOKEN".to_string(), "PIP_INDEX_URL".to_string()];
+
+        assert_eq!(get_secret_mounts(&[]), "");
+        assert_eq!(
+            get_secret_mounts(&secrets),
+            "--mount=type=secret,id=NPM_TOKEN,required=true --mount=type=secret,id=PIP_INDEX_URL,required=true"
+        );
+
+        assert_eq!(with_secret_env("npm ci", &[]), "npm ci");
+        assert_eq!(
+            with_secret_env("npm ci", &secrets[..1]),
+            "export NPM_TOKEN=\"$(cat /run/secrets/NPM_TOKEN)\" && npm ci"
+        );
+    }
+
+    #[test]
+    fn test_get_registry_config_secret() {
+        assert_eq!(
+            get_registry_config_secret(".npmrc").unwrap(),
+            ("registry-npmrc".to_string(), "/root/.npmrc")
+        );
+        assert_eq!(
+            get_registry_config_secret("config/pip.conf").unwrap(),
+            ("registry-pip-conf".to_string(), "/etc/pip.conf")
+        );
+        assert!(get_registry_config_secret("settings.xml").is_err());
+    }
+
     #[test]
     fn test_get_copy_commands() {
         let app_dir = "app";
@@ -116,9 +343,12 @@ mod tests {
                 app_dir
             ),
         );
+        assert_eq!(
+            vec!["COPY . /app/.", "COPY src/main.rs /app/src/main.rs"],
+            get_copy_commands(&[".".to_string(), "src/main.rs".to_string()], "/app/"),
+        );
     }
 
-    #[cfg(not(target_os = "windows"))]
     #[test]
     fn test_get_copy_from_command() {
         let from = "0";
@@ -162,5 +392,97 @@ mod tests {
             "CMD [\"command1 command2 -l \\\"asdf\\\"\"]".to_string(),
             get_exec_command("command1 command2 -l \"asdf\"")
         );
+
+        assert_eq!(
+            r#"CMD ["printf 'a\\tb\\n' && echo \"$HOME\"\nnode index.js"]"#.to_string(),
+            get_exec_command("printf 'a\\tb\\n' && echo \"$HOME\"\nnode index.js")
+        );
+    }
+
+    #[test]
+    fn test_get_exec_form_command() {
+        assert_eq!(
+            get_exec_form_command("./out"),
+            Some(r#"CMD ["./out"]"#.to_string())
+        );
+        assert_eq!(
+            get_exec_form_command("exec bin/server  --port 8080"),
+            Some(r#"CMD ["bin/server","--port","8080"]"#.to_string())
+        );
+        assert_eq!(get_exec_form_command("./out --port $PORT"), None);
+        assert_eq!(get_exec_form_command("./migrate && ./out"), None);
+        assert_eq!(get_exec_form_command("PORT=80 ./out"), None);
+        assert_eq!(get_exec_form_command(""), None);
+    }
+
+    #[test]
+    fn test_get_run_command() {
+        assert_eq!(get_run_command("npm ci"), "npm ci");
+        assert_eq!(
+            get_run_command("echo \"$HOME\" \\\n  && ls"),
+            r#"eval $'echo "$HOME" \\\n  && ls'"#
+        );
+        assert_eq!(get_run_command("echo done \\"), r"eval $'echo done \\'");
+        assert_eq!(
+            get_run_command("[ -f .env ] && echo 'found'"),
+            r"eval $'[ -f .env ] && echo \'found\''"
+        );
+    }
+
+    #[test]
+    fn test_escape_values() {
+        assert_eq!(quote_dockerfile_value("/app/bin"), "/app/bin");
+        assert_eq!(quote_dockerfile_value(""), "\"\"");
+        assert_eq!(
+            quote_dockerfile_value(r#"/my "dir"/$HOME\bin"#),
+            r#""/my \"dir\"/$HOME\\bin""#
+        );
+        assert_eq!(
+            quote_dockerfile_value("$HOME/.rbenv/bin"),
+            "$HOME/.rbenv/bin"
+        );
+
+        assert_eq!(shell_quote("/app/bin"), "/app/bin");
+        assert_eq!(shell_quote("it's"), "'it'\\''s'");
+    }
+
+    #[test]
+    fn test_exec_start_command() {
+        assert_eq!(exec_start_command("node index.js"), "exec node index.js");
+        assert_eq!(
+            exec_start_command("exec node index.js"),
+            "exec node index.js"
+        );
+        assert_eq!(
+            exec_start_command("npm run migrate && npm start"),
+            "npm run migrate && npm start"
+        );
+        assert_eq!(
+            exec_start_command("NODE_ENV=production node index.js"),
+            "NODE_ENV=production node index.js"
+        );
+        assert_eq!(
+            exec_start_command("source .venv/bin/activate"),
+            "source .venv/bin/activate"
+        );
+        assert_eq!(exec_start_command("cd app"), "cd app");
+    }
+
+    #[test]
+    fn test_get_healthcheck_command() {
+        assert_eq!(get_healthcheck_command(&Healthcheck::default()), "");
+        assert_eq!(
+            get_healthcheck_command(&Healthcheck::http("/health")),
+            "HEALTHCHECK CMD curl -fsS \"http://localhost:${PORT}/health\" || exit 1"
+        );
+        assert_eq!(
+            get_healthcheck_command(&Healthcheck {
+                cmd: Some("pg_isready".to_string()),
+                interval: Some("10s".to_string()),
+                retries: Some(3),
+                ..Default::default()
+            }),
+            "HEALTHCHECK --interval=10s --retries=3 CMD pg_isready"
+        );
     }
 }
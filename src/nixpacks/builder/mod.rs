@@ -1,15 +1,28 @@
 use super::{environment::Environment, plan::BuildPlan};
 use anyhow::Result;
 use async_trait::async_trait;
+use std::path::Path;
 
 pub mod docker;
 
+/// Details of an image created from a build plan.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImageInfo {
+    /// Name the image was built with, which is a random UUID unless a name was given.
+    pub name: String,
+    pub tags: Vec<String>,
+    pub plan: BuildPlan,
+    pub providers: Vec<String>,
+    /// Directory the build context was written to instead of building, if any.
+    pub out_dir: Option<String>,
+}
+
 /// Types that impl this trait can produce Docker images.
 #[async_trait]
 pub trait ImageBuilder {
     async fn create_image(
         &self,
-        app_source: &str,
+        app_source: &Path,
         plan: &BuildPlan,
         env: &Environment,
     ) -> Result<()>;
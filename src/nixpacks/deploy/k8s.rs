@@ -0,0 +1,246 @@
+use super::app_variables;
+use crate::nixpacks::plan::{phase::Healthcheck, BuildPlan};
+use anyhow::{bail, Context, Result};
+use serde_json::{json, Map, Value};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Port the Service exposes the app on inside the cluster.
+const SERVICE_PORT: u16 = 80;
+
+/// Kubernetes limits object names to DNS labels.
+const MAX_NAME_LENGTH: usize = 63;
+
+/// Writes a Deployment for the image, and a Service when the app listens on a port, to `dir`.
+///
+/// Returns the paths of the written manifests.
+pub fn write_k8s_manifests(dir: &Path, plan: &BuildPlan, image: &str) -> Result<Vec<PathBuf>> {
+    fs::create_dir_all(dir).with_context(|| format!("Creating {}", dir.display()))?;
+
+    let mut manifests = vec![("deployment.yaml", create_deployment(plan, image)?)];
+    if let Some(service) = create_service(plan, image) {
+        manifests.push(("service.yaml", service));
+    }
+
+    manifests
+        .into_iter()
+        .map(|(file_name, manifest)| {
+            let path = dir.join(file_name);
+            fs::write(&path, serde_yaml::to_string(&manifest)?)
+                .with_context(|| format!("Writing {}", path.display()))?;
+            Ok(path)
+        })
+        .collect()
+}
+
+/// The object name for an image, e.g. `my-app` for `ghcr.io/org/my_app:v1`.
+fn resource_name(image: &str) -> String {
+    let without_tag = match image.rsplit_once(':') {
+        Some((repo, tag)) if !tag.contains('/') => repo,
+        _ => image,
+    };
+    let last = without_tag.rsplit('/').next().unwrap_or(without_tag);
+
+    let name = last
+        .to_lowercase()
+        .replace(|c: char| !c.is_ascii_alphanumeric(), "-")
+        .chars()
+        .take(MAX_NAME_LENGTH)
+        .collect::<String>()
+        .trim_matches('-')
+        .to_string();
+    if name.is_empty() {
+        "app".to_string()
+    } else {
+        name
+    }
+}
+
+fn create_deployment(plan: &BuildPlan, image: &str) -> Result<Value> {
+    let name = resource_name(image);
+    let start = plan.start_phase.clone().unwrap_or_default();
+
+    let mut container = Map::new();
+    container.insert("name".to_string(), json!(name));
+    container.insert("image".to_string(), json!(image));
+    if let Some(port) = start.port {
+        container.insert("ports".to_string(), json!([{ "containerPort": port }]));
+    }
+    let env = app_variables(plan)
+        .into_iter()
+        .map(|(name, value)| json!({ "name": name, "value": value }))
+        .collect::<Vec<_>>();
+    if !env.is_empty() {
+        container.insert("env".to_string(), Value::Array(env));
+    }
+    if let Some(healthcheck) = &start.healthcheck {
+        let probe = create_probe(healthcheck, start.port)?;
+        container.insert("readinessProbe".to_string(), probe.clone());
+        container.insert("livenessProbe".to_string(), probe);
+    }
+
+    Ok(json!({
+        "apiVersion": "apps/v1",
+        "kind": "Deployment",
+        "metadata": { "name": name, "labels": { "app": name } },
+        "spec": {
+            "replicas": 1,
+            "selector": { "matchLabels": { "app": name } },
+            "template": {
+                "metadata": { "labels": { "app": name } },
+                "spec": { "containers": [container] },
+            },
+        },
+    }))
+}
+
+fn create_service(plan: &BuildPlan, image: &str) -> Option<Value> {
+    let port = plan.start_phase.as_ref().and_then(|start| start.port)?;
+    let name = resource_name(image);
+
+    Some(json!({
+        "apiVersion": "v1",
+        "kind": "Service",
+        "metadata": { "name": name, "labels": { "app": name } },
+        "spec": {
+            "selector": { "app": name },
+            "ports": [{ "port": SERVICE_PORT, "targetPort": port }],
+        },
+    }))
+}
+
+/// A probe that checks the container the same way as the image's `HEALTHCHECK`.
+fn create_probe(healthcheck: &Healthcheck, port: Option<u16>) -> Result<Value> {
+    let mut probe = match (&healthcheck.cmd, &healthcheck.path, port) {
+        (Some(cmd), _, _) => json!({ "exec": { "command": ["/bin/sh", "-c", cmd] } }),
+        (None, Some(path), Some(port)) => json!({
+            "httpGet": { "path": format!("/{}", path.trim_start_matches('/')), "port": port }
+        }),
+        // Without a known port, the path is requested on whatever `PORT` is set to in the container
+        (None, Some(path), None) => json!({
+            "exec": {
+                "command": [
+                    "/bin/sh",
+                    "-c",
+                    format!("curl -fsS \"http://localhost:${{PORT}}/{}\"", path.trim_start_matches('/')),
+                ]
+            }
+        }),
+        (None, None, _) => bail!("The healthcheck needs a path or a command"),
+    };
+
+    let timings = [
+        ("periodSeconds", &healthcheck.interval),
+        ("timeoutSeconds", &healthcheck.timeout),
+        ("initialDelaySeconds", &healthcheck.start_period),
+    ];
+    for (field, duration) in timings {
+        if let Some(duration) = duration {
+            probe[field] = json!(parse_duration_seconds(duration)?);
+        }
+    }
+    if let Some(retries) = healthcheck.retries {
+        probe["failureThreshold"] = json!(retries);
+    }
+
+    Ok(probe)
+}
+
+/// Converts a Docker healthcheck duration such as `30s` or `1m30s` to whole seconds, rounding up.
+fn parse_duration_seconds(duration: &str) -> Result<u64> {
+    let mut millis = 0;
+    let mut rest = duration.trim();
+    if rest.is_empty() {
+        bail!("Empty duration");
+    }
+
+    while !rest.is_empty() {
+        let digits = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .with_context(|| format!("Duration `{duration}` is missing a unit, such as `30s`"))?;
+        let value: u64 = rest[..digits]
+            .parse()
+            .with_context(|| format!("Invalid duration `{duration}`"))?;
+        rest = &rest[digits..];
+
+        let unit_len = rest
+            .find(|c: char| c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        let multiplier = match &rest[..unit_len] {
+            "ms" => 1,
+            "s" => 1000,
+            "m" => 60 * 1000,
+            "h" => 60 * 60 * 1000,
+            unit => bail!("Unknown unit `{unit}` in duration `{duration}`"),
+        };
+        millis += value * multiplier;
+        rest = &rest[unit_len..];
+    }
+
+    Ok((millis + 999) / 1000)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nixpacks::plan::phase::StartPhase;
+    use std::collections::BTreeMap;
+    use tempdir::TempDir;
+
+    #[test]
+    fn test_resource_name() {
+        assert_eq!(resource_name("my-app"), "my-app");
+        assert_eq!(resource_name("ghcr.io/org/My_App:v1"), "my-app");
+        assert_eq!(resource_name("localhost:5000/api"), "api");
+        assert_eq!(resource_name("___"), "app");
+    }
+
+    #[test]
+    fn test_parse_duration_seconds() {
+        assert_eq!(parse_duration_seconds("30s").unwrap(), 30);
+        assert_eq!(parse_duration_seconds("1m30s").unwrap(), 90);
+        assert_eq!(parse_duration_seconds("1h").unwrap(), 3600);
+        assert_eq!(parse_duration_seconds("500ms").unwrap(), 1);
+        assert!(parse_duration_seconds("30").is_err());
+        assert!(parse_duration_seconds("5d").is_err());
+    }
+
+    #[test]
+    fn test_write_k8s_manifests() -> Result<()> {
+        let mut plan = BuildPlan::default();
+        plan.add_variables(BTreeMap::from([
+            ("NODE_ENV".to_string(), "production".to_string()),
+            ("NIXPACKS_METADATA".to_string(), "node".to_string()),
+        ]));
+        plan.set_start_phase(StartPhase {
+            cmd: Some("node index.js".to_string()),
+            port: Some(8080),
+            healthcheck: Some(Healthcheck {
+                interval: Some("10s".to_string()),
+                retries: Some(3),
+                ..Healthcheck::http("/health")
+            }),
+            ..Default::default()
+        });
+
+        let dir = TempDir::new("nixpacks-k8s")?;
+        let paths = write_k8s_manifests(dir.path(), &plan, "ghcr.io/org/my-app:v1")?;
+        assert_eq!(
+            paths,
+            vec![
+                dir.path().join("deployment.yaml"),
+                dir.path().join("service.yaml")
+            ]
+        );
+        insta::assert_snapshot!("k8s_deployment", fs::read_to_string(&paths[0])?);
+        insta::assert_snapshot!("k8s_service", fs::read_to_string(&paths[1])?);
+
+        // Apps that don't listen on a port only get a Deployment
+        plan.start_phase = None;
+        let dir = TempDir::new("nixpacks-k8s")?;
+        assert_eq!(write_k8s_manifests(dir.path(), &plan, "worker")?.len(), 1);
+        Ok(())
+    }
+}
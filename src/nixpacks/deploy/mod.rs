@@ -0,0 +1,59 @@
+use crate::nixpacks::{environment::EnvironmentVariables, plan::BuildPlan};
+use std::path::Path;
+
+pub mod compose;
+pub mod k8s;
+
+/// Name for the app's image when none is given, from the name of its directory, e.g. `my-app` for `~/code/My App`.
+pub fn default_image_name(path: &str) -> String {
+    let path = Path::new(path);
+    let dir_name = path
+        .canonicalize()
+        .ok()
+        .and_then(|path| {
+            path.file_name()
+                .map(|name| name.to_string_lossy().to_string())
+        })
+        .or_else(|| {
+            path.file_name()
+                .map(|name| name.to_string_lossy().to_string())
+        })
+        .unwrap_or_default();
+
+    let name = dir_name
+        .to_lowercase()
+        .replace(
+            |c: char| !c.is_ascii_alphanumeric() && c != '_' && c != '.',
+            "-",
+        )
+        .trim_matches(|c: char| !c.is_ascii_alphanumeric())
+        .to_string();
+    if name.is_empty() {
+        "app".to_string()
+    } else {
+        name
+    }
+}
+
+/// Variables the app runs with, leaving out the ones Nixpacks sets for itself.
+fn app_variables(plan: &BuildPlan) -> EnvironmentVariables {
+    plan.variables
+        .clone()
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|(name, _)| !name.starts_with("NIXPACKS_"))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_image_name() {
+        assert_eq!(default_image_name("examples/node"), "node");
+        assert_eq!(default_image_name("/code/My App"), "my-app");
+        assert_eq!(default_image_name("/code/_api_"), "api");
+        assert_eq!(default_image_name("/"), "app");
+    }
+}
@@ -1,6 +1,6 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
 use regex::Regex;
-use std::{collections::BTreeMap, env};
+use std::{collections::BTreeMap, env, error::Error, str::FromStr};
 
 pub type EnvironmentVariables = BTreeMap<String, String>;
 
@@ -39,6 +39,20 @@ impl Environment {
         Ok(environment)
     }
 
+    /// Returns the names of the variables in the calling environment that start with any of the prefixes, in sorted order.
+    pub fn get_host_variable_names(prefixes: &[String]) -> Vec<String> {
+        let mut names: Vec<String> = env::vars_os()
+            .filter_map(|(name, _)| name.into_string().ok())
+            .filter(|name| {
+                prefixes
+                    .iter()
+                    .any(|prefix| name.starts_with(prefix.as_str()))
+            })
+            .collect();
+        names.sort();
+        names
+    }
+
     /// Returns the value of the given variable name, if it exists.
     pub fn get_variable(&self, name: &str) -> Option<&str> {
         self.variables.get(name).map(String::as_str)
@@ -50,6 +64,36 @@ impl Environment {
             .map(|var| var.replace('\n', ""))
     }
 
+    /// Returns `NIXPACKS_{name}`, falling back to `{name}` itself.
+    ///
+    /// This is for settings that the language's own tooling also reads, like `NODE_VERSION` or `CGO_ENABLED`.
+    pub fn get_config_or_variable(&self, name: &str) -> Option<String> {
+        self.get_config_variable(name)
+            .or_else(|| self.get_variable(name).map(ToString::to_string))
+    }
+
+    /// Parses the "NIXPACKS_" variable into a number or other type, failing if it is set to an invalid value.
+    pub fn get_config_value<T>(&self, name: &str) -> Result<Option<T>>
+    where
+        T: FromStr,
+        T::Err: Error + Send + Sync + 'static,
+    {
+        self.get_config_variable(name)
+            .map(|value| {
+                value
+                    .trim()
+                    .parse::<T>()
+                    .with_context(|| format!("Invalid value `{value}` for NIXPACKS_{name}"))
+            })
+            .transpose()
+    }
+
+    /// Splits the "NIXPACKS_" variable into a list of packages, libraries, or directories, separated by spaces or commas.
+    pub fn get_config_list(&self, name: &str) -> Option<Vec<String>> {
+        self.get_config_variable(name)
+            .map(|value| split_env_string(&value))
+    }
+
     /// Checks if the given variable is 1 or true.
     pub fn is_config_variable_truthy(&self, name: &str) -> bool {
         if let Some(var) = self.get_config_variable(name) {
@@ -82,9 +126,116 @@ impl Environment {
     }
 }
 
+/// Shown in place of the values of variables that look like secrets.
+pub const REDACTED: &str = "[redacted]";
+
+/// Words in variable names, like the `TOKEN` in `NPM_TOKEN`, that mark their values as secrets.
+const SECRET_NAME_WORDS: &[&str] = &[
+    "AUTH",
+    "CREDENTIAL",
+    "CREDENTIALS",
+    "KEY",
+    "PASS",
+    "PASSWD",
+    "PASSWORD",
+    "PRIVATE",
+    "SECRET",
+    "TOKEN",
+];
+
+/// Lists more variables whose values are secrets, for names that don't look like one.
+pub const SENSITIVE_VARIABLES: &str = "NIXPACKS_SENSITIVE_VARIABLES";
+
+/// Whether a variable's name suggests it holds a secret, which shouldn't be printed.
+pub fn is_secret_name(name: &str) -> bool {
+    name.to_uppercase()
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .any(|word| SECRET_NAME_WORDS.contains(&word))
+}
+
+/// The variables that hold secrets, either by their name or because `NIXPACKS_SENSITIVE_VARIABLES` lists them.
+pub fn get_secret_variable_names(variables: &EnvironmentVariables) -> Vec<String> {
+    let listed = variables
+        .get(SENSITIVE_VARIABLES)
+        .map(|value| split_env_string(value))
+        .unwrap_or_default();
+    variables
+        .keys()
+        .filter(|name| is_secret_name(name) || listed.contains(name))
+        .cloned()
+        .collect()
+}
+
+/// Whether a name can be written into a Dockerfile `ENV` or `ARG`, which only rules out names that would be split.
+///
+/// Names such as `spring.profiles.active` are allowed, since the app reads them from its environment.
+pub fn is_valid_env_name(name: &str) -> bool {
+    !name.is_empty() && !name.contains(|c: char| c.is_whitespace() || c == '=')
+}
+
+/// Whether a name is a shell identifier, for variables that are set with `export` in a `RUN` command, such as secrets.
+///
+/// Every shell variable name is also a valid [`is_valid_env_name`], but not the other way around.
+pub fn is_shell_variable_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Splits a string taken from an environment variable into a vector of packages, libraries, or directories.
+fn split_env_string(s: &str) -> Vec<String> {
+    s.split([' ', ','])
+        .map(std::string::ToString::to_string)
+        .filter(|s| !s.is_empty())
+        .collect::<Vec<_>>()
+}
+
 #[cfg(test)]
 mod tests {
-    use super::Environment;
+    use super::{
+        get_secret_variable_names, is_secret_name, is_shell_variable_name, is_valid_env_name,
+        split_env_string, Environment, EnvironmentVariables, SENSITIVE_VARIABLES,
+    };
+
+    #[test]
+    fn test_variable_names() {
+        assert!(is_valid_env_name("NODE_ENV"));
+        assert!(is_valid_env_name("spring.profiles.active"));
+        assert!(!is_valid_env_name(""));
+        assert!(!is_valid_env_name("MY VAR"));
+        assert!(!is_valid_env_name("A=B"));
+
+        assert!(is_shell_variable_name("NPM_TOKEN"));
+        assert!(is_shell_variable_name("_token2"));
+        assert!(!is_shell_variable_name("2TOKEN"));
+        assert!(!is_shell_variable_name("NPM-TOKEN"));
+        assert!(!is_shell_variable_name("spring.profiles.active"));
+        assert!(!is_shell_variable_name("MY VAR"));
+        assert!(!is_shell_variable_name(""));
+    }
+
+    #[test]
+    fn test_is_secret_name() {
+        assert!(is_secret_name("NPM_TOKEN"));
+        assert!(is_secret_name("aws_secret_access_key"));
+        assert!(is_secret_name("DB_PASSWORD"));
+        assert!(!is_secret_name("NODE_ENV"));
+        assert!(!is_secret_name("MONKEY_PATCH"));
+    }
+
+    #[test]
+    fn test_get_secret_variable_names() {
+        let variables = EnvironmentVariables::from([
+            ("NODE_ENV".to_string(), "production".to_string()),
+            ("NPM_TOKEN".to_string(), "npm_abc123".to_string()),
+            ("STRIPE".to_string(), "sk_live_123".to_string()),
+            (SENSITIVE_VARIABLES.to_string(), "STRIPE, OTHER".to_string()),
+        ]);
+        assert_eq!(
+            get_secret_variable_names(&variables),
+            vec!["NPM_TOKEN", "STRIPE"]
+        );
+    }
 
     #[test]
     fn set_and_get_variables() {
@@ -103,6 +254,14 @@ mod tests {
         assert!(environment.get_variable("NON_EXISTANT").is_none());
     }
 
+    #[test]
+    fn test_get_host_variable_names() {
+        let names = Environment::get_host_variable_names(&["CARGO_PKG_".to_string()]);
+        assert!(names.contains(&"CARGO_PKG_NAME".to_string()));
+        assert!(names.iter().all(|name| name.starts_with("CARGO_PKG_")));
+        assert!(Environment::get_host_variable_names(&[]).is_empty());
+    }
+
     #[test]
     fn test_create_equals_sign_parsing() {
         let environment = Environment::from_envs(vec!["INVALID=ENV=CONFIG"]).unwrap();
@@ -130,6 +289,69 @@ mod tests {
         assert!(!environment.is_config_variable_truthy("NO"));
     }
 
+    #[test]
+    fn test_get_config_or_variable() {
+        let mut environment = Environment::default();
+        environment.set_variable("NODE_VERSION".to_string(), "16".to_string());
+        assert_eq!(
+            environment.get_config_or_variable("NODE_VERSION"),
+            Some("16".to_string())
+        );
+
+        environment.set_variable("NIXPACKS_NODE_VERSION".to_string(), "18".to_string());
+        assert_eq!(
+            environment.get_config_or_variable("NODE_VERSION"),
+            Some("18".to_string())
+        );
+        assert_eq!(environment.get_config_or_variable("PYTHON_VERSION"), None);
+    }
+
+    #[test]
+    fn test_get_config_value() {
+        let mut environment = Environment::default();
+        environment.set_variable("NIXPACKS_MAX_APP_SIZE".to_string(), " 500 ".to_string());
+        environment.set_variable("NIXPACKS_PORT".to_string(), "eighty".to_string());
+
+        assert_eq!(
+            environment.get_config_value::<u64>("MAX_APP_SIZE").unwrap(),
+            Some(500)
+        );
+        assert_eq!(
+            environment.get_config_value::<u16>("TIMEOUT").unwrap(),
+            None
+        );
+        assert_eq!(
+            environment
+                .get_config_value::<u16>("PORT")
+                .unwrap_err()
+                .to_string(),
+            "Invalid value `eighty` for NIXPACKS_PORT"
+        );
+    }
+
+    #[test]
+    fn test_get_config_list() {
+        let mut environment = Environment::default();
+        environment.set_variable("NIXPACKS_PKGS".to_string(), "ffmpeg, cowsay".to_string());
+        assert_eq!(
+            environment.get_config_list("PKGS"),
+            Some(vec!["ffmpeg".to_string(), "cowsay".to_string()])
+        );
+        assert_eq!(environment.get_config_list("APT_PKGS"), None);
+    }
+
+    #[test]
+    fn test_split_env_string() {
+        assert_eq!(
+            split_env_string("nodejs yarn"),
+            vec!["nodejs".to_string(), "yarn".to_string()]
+        );
+        assert_eq!(
+            split_env_string("nodejs, yarn"),
+            vec!["nodejs".to_string(), "yarn".to_string()]
+        );
+    }
+
     #[test]
     fn test_get_config_variable_strips_newlines() {
         let mut environment = Environment::default();
@@ -0,0 +1,159 @@
+use std::{error::Error, fmt, io};
+
+/// Errors returned by the library functions, so embedders can tell why a build failed.
+///
+/// Failures without a more specific kind are kept as [`NixpacksError::Other`] with their full context.
+#[derive(Debug)]
+pub enum NixpacksError {
+    /// No provider detected the app, so there is no plan to build it with.
+    DetectionFailed,
+    /// The build plan can't be built, such as a plan without a start command.
+    PlanInvalid {
+        reason: String,
+    },
+    /// The container engine failed to build the image, with the exit code of the build when it ran.
+    DockerFailed {
+        exit_code: Option<i32>,
+        message: String,
+    },
+    /// The image build ran for longer than the timeout and was stopped.
+    TimedOut {
+        seconds: u64,
+    },
+    /// Reading or writing a file failed, with what was being done at the time.
+    IoError {
+        context: String,
+        source: io::Error,
+    },
+    Other(anyhow::Error),
+}
+
+impl NixpacksError {
+    pub fn plan_invalid(reason: impl fmt::Display) -> NixpacksError {
+        NixpacksError::PlanInvalid {
+            reason: reason.to_string(),
+        }
+    }
+
+    pub fn docker_failed(exit_code: Option<i32>, message: impl fmt::Display) -> NixpacksError {
+        NixpacksError::DockerFailed {
+            exit_code,
+            message: message.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for NixpacksError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NixpacksError::DetectionFailed => {
+                write!(f, "Unable to generate a build plan for this app")
+            }
+            NixpacksError::PlanInvalid { reason } => write!(f, "{reason}"),
+            NixpacksError::DockerFailed { message, .. } => write!(f, "{message}"),
+            NixpacksError::TimedOut { seconds } => {
+                write!(
+                    f,
+                    "The build was stopped after running for {seconds} seconds"
+                )
+            }
+            NixpacksError::IoError { context, source } if context.is_empty() => {
+                write!(f, "{source}")
+            }
+            NixpacksError::IoError { context, .. } => write!(f, "{context}"),
+            NixpacksError::Other(error) => write!(f, "{error}"),
+        }
+    }
+}
+
+impl Error for NixpacksError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            NixpacksError::IoError { context, source } if !context.is_empty() => Some(source),
+            NixpacksError::Other(error) => error.source(),
+            _ => None,
+        }
+    }
+}
+
+/// Errors raised inside nixpacks keep their kind when they cross the library boundary, even under added context.
+impl From<anyhow::Error> for NixpacksError {
+    fn from(error: anyhow::Error) -> Self {
+        if error.is::<NixpacksError>() {
+            return error.downcast::<NixpacksError>().unwrap();
+        }
+        if error.is::<io::Error>() {
+            let context = error
+                .chain()
+                .take_while(|cause| !cause.is::<io::Error>())
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(": ");
+            return NixpacksError::IoError {
+                context,
+                source: error.downcast::<io::Error>().unwrap(),
+            };
+        }
+        NixpacksError::Other(error)
+    }
+}
+
+impl From<io::Error> for NixpacksError {
+    fn from(error: io::Error) -> Self {
+        NixpacksError::IoError {
+            context: String::new(),
+            source: error,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::{anyhow, Context};
+
+    #[test]
+    fn test_error_kinds() {
+        let error: NixpacksError =
+            Err::<(), _>(NixpacksError::docker_failed(Some(2), "Docker build failed"))
+                .context("Building image")
+                .unwrap_err()
+                .into();
+        assert!(matches!(
+            error,
+            NixpacksError::DockerFailed {
+                exit_code: Some(2),
+                ..
+            }
+        ));
+        assert_eq!(error.to_string(), "Docker build failed");
+
+        let error: NixpacksError = anyhow!("Invalid plan").into();
+        assert!(matches!(error, NixpacksError::Other(_)));
+        assert_eq!(error.to_string(), "Invalid plan");
+    }
+
+    #[test]
+    fn test_io_error_keeps_context() {
+        let error: NixpacksError = Err::<(), _>(io::Error::new(
+            io::ErrorKind::NotFound,
+            "No such file or directory",
+        ))
+        .context("Reading Dockerfile template")
+        .context("Writing app")
+        .unwrap_err()
+        .into();
+
+        match &error {
+            NixpacksError::IoError { context, source } => {
+                assert_eq!(context, "Writing app: Reading Dockerfile template");
+                assert_eq!(source.kind(), io::ErrorKind::NotFound);
+            }
+            _ => panic!("Expected an IoError, got {error:?}"),
+        }
+        assert_eq!(
+            format!("{:#}", anyhow::Error::from(error)),
+            "Writing app: Reading Dockerfile template: No such file or directory"
+        );
+    }
+}
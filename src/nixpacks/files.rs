@@ -1,5 +1,7 @@
-use anyhow::Result;
+use anyhow::{bail, Context, Result};
+use globset::{Glob, GlobSet, GlobSetBuilder};
 use ignore::WalkBuilder;
+use indicatif::ProgressBar;
 use std::{fs, io, path::Path};
 
 #[cfg(unix)]
@@ -17,15 +19,80 @@ fn is_writable<P: AsRef<Path>>(path: P) -> io::Result<bool> {
     Ok(!metadata.file_attributes() & 1 != 0)
 }
 
-/// Copies a directory and all its contents to the destination path, recursively.
-pub fn recursive_copy_dir<T: AsRef<Path>, Q: AsRef<Path>>(source: T, dest: Q) -> Result<()> {
+/// Recreates the symlink at `from` at the `to` path, pointing at the same target.
+#[cfg(unix)]
+fn copy_symlink(from: &Path, to: &Path) -> io::Result<()> {
+    let target = fs::read_link(from)?;
+    std::os::unix::fs::symlink(target, to)
+}
+
+/// Recreates the symlink at `from` at the `to` path, pointing at the same target.
+#[cfg(windows)]
+fn copy_symlink(from: &Path, to: &Path) -> io::Result<()> {
+    let target = fs::read_link(from)?;
+    if from.is_dir() {
+        std::os::windows::fs::symlink_dir(target, to)
+    } else {
+        std::os::windows::fs::symlink_file(target, to)
+    }
+}
+
+/// File listing additional paths to leave out of the build context, using `.gitignore` syntax.
+pub const NIXPACKS_IGNORE_FILE: &str = ".nixpacksignore";
+
+/// Controls which files are skipped by `recursive_copy_dir`.
+#[derive(Clone, Debug, Default)]
+pub struct CopyOptions {
+    /// Glob patterns, relative to the source, of paths to skip.
+    pub exclude: Vec<String>,
+    /// Skip paths matched by `.gitignore` files inside the source.
+    pub git_ignore: bool,
+    /// Fail once more than this many bytes of files have been copied.
+    pub max_size: Option<u64>,
+    /// Spinner that shows how much has been copied so far.
+    pub progress: Option<ProgressBar>,
+}
+
+/// Compiles glob patterns (relative to the copy source) into a matcher.
+fn build_exclude_set(patterns: &[String]) -> Result<GlobSet> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        let pattern = pattern.trim_start_matches("./").trim_end_matches('/');
+        builder.add(Glob::new(pattern).with_context(|| format!("Invalid pattern `{pattern}`"))?);
+    }
+
+    Ok(builder.build()?)
+}
+
+/// Copies a directory and all its contents to the destination path, recursively, returning the number of bytes copied.
+///
+/// Symlinks are recreated rather than followed, and file and directory permissions are preserved.
+/// Paths matched by `.nixpacksignore` files or the `options` are skipped.
+pub fn recursive_copy_dir<T: AsRef<Path>, Q: AsRef<Path>>(
+    source: T,
+    dest: Q,
+    options: &CopyOptions,
+) -> Result<u64> {
+    let exclude = build_exclude_set(&options.exclude)?;
+    let root = source.as_ref().to_path_buf();
     let walker = WalkBuilder::new(&source)
         .follow_links(false)
         // this includes hidden directories & files
         .standard_filters(false)
         .hidden(false)
+        // only ignore files inside the source apply, not ones from parent directories or the host
+        .parents(false)
+        .require_git(false)
+        .git_ignore(options.git_ignore)
+        .add_custom_ignore_filename(NIXPACKS_IGNORE_FILE)
+        // skip excluded paths without descending into them
+        .filter_entry(move |entry| match entry.path().strip_prefix(&root) {
+            Ok(relative) => relative.as_os_str().is_empty() || !exclude.is_match(relative),
+            Err(_) => true,
+        })
         .build();
 
+    let mut size = 0;
     for entry in walker {
         let entry = entry?;
 
@@ -35,16 +102,40 @@ pub fn recursive_copy_dir<T: AsRef<Path>, Q: AsRef<Path>>(source: T, dest: Q) ->
 
             // create directories
             if file_type.is_dir() {
-                if let Err(e) = fs::create_dir(to) {
+                if let Err(e) = fs::create_dir(&to) {
                     match e.kind() {
                         io::ErrorKind::AlreadyExists => {}
                         _ => return Err(e.into()),
                     }
                 }
+                // read-only directories keep the default permissions so their contents can be copied
+                let permissions = fs::metadata(from)?.permissions();
+                if !permissions.readonly() {
+                    fs::set_permissions(&to, permissions)?;
+                }
+            }
+            // recreate symlinks
+            else if file_type.is_symlink() {
+                if fs::symlink_metadata(&to).is_ok() {
+                    fs::remove_file(&to)?;
+                }
+                copy_symlink(from, &to)?;
             }
             // copy files
             else if file_type.is_file() {
-                fs::copy(from, &to)?;
+                size += fs::copy(from, &to)?;
+                if let Some(max_size) = options.max_size {
+                    if size > max_size {
+                        bail!(
+                            "The app is larger than the limit of {}, copying stopped at {}. Add large files and directories to {NIXPACKS_IGNORE_FILE}",
+                            format_size(max_size),
+                            from.strip_prefix(&source)?.display()
+                        );
+                    }
+                }
+                if let Some(progress) = &options.progress {
+                    progress.set_message(format_size(size));
+                }
 
                 if is_writable(&to)? {
                     // replace CRLF with LF
@@ -55,5 +146,152 @@ pub fn recursive_copy_dir<T: AsRef<Path>, Q: AsRef<Path>>(source: T, dest: Q) ->
             }
         }
     }
-    Ok(())
+    Ok(size)
+}
+
+/// Formats a number of bytes for messages, such as `1.5 GB`.
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1000.0 && unit < UNITS.len() - 1 {
+        size /= 1000.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{bytes} B")
+    } else {
+        format!("{size:.1} {}", UNITS[unit])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempdir::TempDir;
+
+    #[test]
+    fn test_recursive_copy_dir_with_exclusions() -> Result<()> {
+        let source = TempDir::new("nixpacks-src")?;
+        fs::create_dir_all(source.path().join("node_modules/pkg"))?;
+        fs::create_dir_all(source.path().join("src"))?;
+        fs::write(source.path().join("node_modules/pkg/index.js"), "")?;
+        fs::write(source.path().join("src/index.js"), "hello\r\n")?;
+        fs::write(source.path().join(".env"), "SECRET=1")?;
+
+        let dest = TempDir::new("nixpacks-dest")?;
+        recursive_copy_dir(
+            source.path(),
+            dest.path(),
+            &CopyOptions {
+                exclude: vec!["node_modules".to_string(), ".env".to_string()],
+                ..Default::default()
+            },
+        )?;
+
+        assert!(!dest.path().join("node_modules").exists());
+        assert!(!dest.path().join(".env").exists());
+        assert_eq!(
+            fs::read_to_string(dest.path().join("src/index.js"))?,
+            "hello\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_recursive_copy_dir_respects_ignore_files() -> Result<()> {
+        let source = TempDir::new("nixpacks-src")?;
+        fs::create_dir_all(source.path().join("dist"))?;
+        fs::write(source.path().join("dist/out.js"), "")?;
+        fs::write(source.path().join("secrets.json"), "{}")?;
+        fs::write(source.path().join("index.js"), "")?;
+        fs::write(source.path().join(".gitignore"), "dist/\n")?;
+        fs::write(source.path().join(NIXPACKS_IGNORE_FILE), "secrets.json\n")?;
+
+        let dest = TempDir::new("nixpacks-dest")?;
+        recursive_copy_dir(
+            source.path(),
+            dest.path(),
+            &CopyOptions {
+                git_ignore: true,
+                ..Default::default()
+            },
+        )?;
+        assert!(!dest.path().join("dist").exists());
+        assert!(!dest.path().join("secrets.json").exists());
+        assert!(dest.path().join("index.js").exists());
+
+        // .gitignore is opt-in, .nixpacksignore always applies
+        let dest = TempDir::new("nixpacks-dest")?;
+        recursive_copy_dir(source.path(), dest.path(), &CopyOptions::default())?;
+        assert!(dest.path().join("dist/out.js").exists());
+        assert!(!dest.path().join("secrets.json").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_recursive_copy_dir_max_size() -> Result<()> {
+        let source = TempDir::new("nixpacks-src")?;
+        fs::write(source.path().join("a.txt"), "a".repeat(600))?;
+        fs::write(source.path().join("b.txt"), "b".repeat(600))?;
+
+        let dest = TempDir::new("nixpacks-dest")?;
+        assert_eq!(
+            recursive_copy_dir(source.path(), dest.path(), &CopyOptions::default())?,
+            1200
+        );
+
+        let dest = TempDir::new("nixpacks-dest")?;
+        let error = recursive_copy_dir(
+            source.path(),
+            dest.path(),
+            &CopyOptions {
+                max_size: Some(1000),
+                ..Default::default()
+            },
+        )
+        .unwrap_err();
+        assert!(error
+            .to_string()
+            .starts_with("The app is larger than the limit of 1.0 KB, copying stopped at"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_format_size() {
+        assert_eq!(format_size(512), "512 B");
+        assert_eq!(format_size(1_500_000), "1.5 MB");
+        assert_eq!(format_size(2_000_000_000), "2.0 GB");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_recursive_copy_dir_preserves_symlinks_and_permissions() -> Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+
+        let source = TempDir::new("nixpacks-src")?;
+        fs::write(source.path().join("run.sh"), "#!/bin/sh")?;
+        fs::set_permissions(
+            source.path().join("run.sh"),
+            fs::Permissions::from_mode(0o755),
+        )?;
+        std::os::unix::fs::symlink("run.sh", source.path().join("start"))?;
+
+        let dest = TempDir::new("nixpacks-dest")?;
+        recursive_copy_dir(source.path(), dest.path(), &CopyOptions::default())?;
+
+        let link = dest.path().join("start");
+        assert!(fs::symlink_metadata(&link)?.file_type().is_symlink());
+        assert_eq!(fs::read_link(&link)?, Path::new("run.sh"));
+        assert_eq!(
+            fs::metadata(dest.path().join("run.sh"))?
+                .permissions()
+                .mode()
+                & 0o777,
+            0o755
+        );
+        Ok(())
+    }
 }
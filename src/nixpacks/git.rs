@@ -0,0 +1,185 @@
+use anyhow::{bail, Context, Result};
+use std::{
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+/// URL schemes that are cloned instead of being read as local paths.
+const GIT_URL_PREFIXES: &[&str] = &["https://", "http://", "ssh://", "git://", "git@", "file://"];
+
+/// A git repository to build, written as `<url>#<ref>:<subdir>` like Docker's remote build contexts.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GitSource {
+    pub url: String,
+    /// The branch, tag, or commit to check out, instead of the remote's default branch.
+    pub git_ref: Option<String>,
+    /// The directory in the repository that contains the app.
+    pub subdir: Option<String>,
+}
+
+impl GitSource {
+    /// Parses a source that is a git URL, returning `None` for local paths.
+    pub fn parse(source: &str) -> Option<GitSource> {
+        if !GIT_URL_PREFIXES
+            .iter()
+            .any(|prefix| source.starts_with(prefix))
+        {
+            return None;
+        }
+
+        let (url, fragment) = source.split_once('#').unwrap_or((source, ""));
+        let (git_ref, subdir) = fragment.split_once(':').unwrap_or((fragment, ""));
+        let non_empty = |s: &str| {
+            let s = s.trim_matches('/');
+            if s.is_empty() {
+                None
+            } else {
+                Some(s.to_string())
+            }
+        };
+
+        Some(GitSource {
+            url: url.to_string(),
+            git_ref: non_empty(git_ref),
+            subdir: non_empty(subdir),
+        })
+    }
+
+    /// Shallow clones the ref into `dir`, returning the path of the app inside it.
+    pub fn checkout(&self, dir: &Path) -> Result<PathBuf> {
+        let git_ref = self.git_ref.clone().unwrap_or_else(|| "HEAD".to_string());
+
+        // Fetching a single ref works for branches, tags, and commit SHAs alike
+        run_git(Command::new("git").arg("init").arg("-q").arg(dir))?;
+        run_git(git_in(dir).args(["remote", "add", "origin", &self.url]))?;
+        run_git(git_in(dir).args(["fetch", "-q", "--depth", "1", "origin", &git_ref]))
+            .with_context(|| format!("Fetching `{git_ref}` from {}", self.url))?;
+        run_git(git_in(dir).args(["checkout", "-q", "FETCH_HEAD"]))?;
+        run_git(git_in(dir).args([
+            "submodule",
+            "update",
+            "-q",
+            "--init",
+            "--recursive",
+            "--depth",
+            "1",
+        ]))?;
+
+        let app_dir = match &self.subdir {
+            Some(subdir) => dir.join(subdir),
+            None => dir.to_path_buf(),
+        };
+        if !app_dir.is_dir() {
+            bail!(
+                "The directory `{}` does not exist in {}",
+                self.subdir.clone().unwrap_or_default(),
+                self.url
+            );
+        }
+
+        Ok(app_dir)
+    }
+}
+
+fn git_in(dir: &Path) -> Command {
+    let mut command = Command::new("git");
+    command.arg("-C").arg(dir);
+    command
+}
+
+fn run_git(command: &mut Command) -> Result<()> {
+    let output = match command.output() {
+        Ok(output) => output,
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            bail!("Please install git to build from a git URL")
+        }
+        Err(e) => return Err(e.into()),
+    };
+
+    if !output.status.success() {
+        bail!("{}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempdir::TempDir;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(GitSource::parse("./examples/node"), None);
+        assert_eq!(GitSource::parse("/home/user/app"), None);
+        assert_eq!(
+            GitSource::parse("https://github.com/railwayapp/nixpacks"),
+            Some(GitSource {
+                url: "https://github.com/railwayapp/nixpacks".to_string(),
+                git_ref: None,
+                subdir: None,
+            })
+        );
+        assert_eq!(
+            GitSource::parse("git@github.com:railwayapp/nixpacks.git#v1.0.0:examples/node/"),
+            Some(GitSource {
+                url: "git@github.com:railwayapp/nixpacks.git".to_string(),
+                git_ref: Some("v1.0.0".to_string()),
+                subdir: Some("examples/node".to_string()),
+            })
+        );
+        assert_eq!(
+            GitSource::parse("https://github.com/railwayapp/nixpacks#:examples/go"),
+            Some(GitSource {
+                url: "https://github.com/railwayapp/nixpacks".to_string(),
+                git_ref: None,
+                subdir: Some("examples/go".to_string()),
+            })
+        );
+    }
+
+    #[test]
+    fn test_checkout() -> Result<()> {
+        let repo = TempDir::new("nixpacks-repo")?;
+        let git = |args: &[&str]| {
+            run_git(
+                git_in(repo.path())
+                    .args(["-c", "user.name=Test", "-c", "user.email=test@example.com"])
+                    .args(args),
+            )
+        };
+        git(&["init", "-q", "-b", "main"])?;
+        fs::create_dir_all(repo.path().join("apps/api"))?;
+        fs::write(repo.path().join("apps/api/main.py"), "print('v1')")?;
+        git(&["add", "."])?;
+        git(&["commit", "-q", "-m", "v1"])?;
+        git(&["checkout", "-q", "-b", "next"])?;
+        fs::write(repo.path().join("apps/api/main.py"), "print('v2')")?;
+        git(&["commit", "-q", "-am", "v2"])?;
+        git(&["checkout", "-q", "main"])?;
+
+        let url = format!("file://{}", repo.path().display());
+
+        let checkout = TempDir::new("nixpacks-checkout")?;
+        let app_dir = GitSource::parse(&url).unwrap().checkout(checkout.path())?;
+        assert_eq!(
+            fs::read_to_string(app_dir.join("apps/api/main.py"))?,
+            "print('v1')"
+        );
+
+        let checkout = TempDir::new("nixpacks-checkout")?;
+        let app_dir = GitSource::parse(&format!("{url}#next:apps/api"))
+            .unwrap()
+            .checkout(checkout.path())?;
+        assert_eq!(fs::read_to_string(app_dir.join("main.py"))?, "print('v2')");
+
+        let checkout = TempDir::new("nixpacks-checkout")?;
+        assert!(GitSource::parse(&format!("{url}#main:apps/web"))
+            .unwrap()
+            .checkout(checkout.path())
+            .is_err());
+        Ok(())
+    }
+}
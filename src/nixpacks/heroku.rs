@@ -0,0 +1,359 @@
+use super::{
+    app::App,
+    environment::{Environment, EnvironmentVariables},
+    plan::BuildPlan,
+    procfile::Procfile,
+};
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+pub const APP_JSON: &str = "app.json";
+pub const HEROKU_YML: &str = "heroku.yml";
+
+/// Heroku's official buildpacks and the providers that build the same languages.
+const BUILDPACK_PROVIDERS: &[(&str, &str)] = &[
+    ("nodejs", "node"),
+    ("python", "python"),
+    ("ruby", "ruby"),
+    ("go", "go"),
+    ("java", "java"),
+    ("gradle", "java"),
+    ("scala", "scala"),
+    ("clojure", "clojure"),
+    ("php", "php"),
+];
+
+/// The parts of an `app.json` manifest that carry over to a build.
+#[derive(Deserialize, Default, Debug)]
+struct AppJson {
+    #[serde(default)]
+    env: BTreeMap<String, AppJsonVariable>,
+    #[serde(default)]
+    buildpacks: Vec<AppJsonBuildpack>,
+}
+
+/// A variable in `app.json`, either a plain value or a definition that may leave the value to the user.
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+enum AppJsonVariable {
+    Value(String),
+    Definition {
+        value: Option<String>,
+        required: Option<bool>,
+        generator: Option<String>,
+    },
+}
+
+#[derive(Deserialize, Debug)]
+struct AppJsonBuildpack {
+    url: String,
+}
+
+/// The parts of a `heroku.yml` manifest that carry over to a build. Its Docker build settings are ignored.
+#[derive(Deserialize, Default, Debug)]
+struct HerokuYml {
+    setup: Option<HerokuYmlConfig>,
+    build: Option<HerokuYmlConfig>,
+    release: Option<HerokuYmlProcess>,
+    #[serde(default)]
+    run: BTreeMap<String, HerokuYmlRun>,
+}
+
+#[derive(Deserialize, Default, Debug)]
+struct HerokuYmlConfig {
+    #[serde(default)]
+    config: BTreeMap<String, serde_yaml::Value>,
+}
+
+#[derive(Deserialize, Debug)]
+struct HerokuYmlProcess {
+    command: HerokuYmlCommand,
+}
+
+/// A process in the `run` section, either just its command or a definition with the command.
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+enum HerokuYmlRun {
+    Command(HerokuYmlCommand),
+    Process(HerokuYmlProcess),
+}
+
+/// A command, which Heroku also accepts as a list of parts that are joined with spaces.
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+enum HerokuYmlCommand {
+    Shell(String),
+    List(Vec<String>),
+}
+
+impl HerokuYmlCommand {
+    fn to_shell(&self) -> String {
+        match self {
+            HerokuYmlCommand::Shell(cmd) => cmd.clone(),
+            HerokuYmlCommand::List(parts) => parts.join(" "),
+        }
+    }
+}
+
+/// Settings from the `app.json` and `heroku.yml` of an app that is moving off Heroku.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct HerokuConfig {
+    /// Variables with a value in `app.json`, and the `setup` config of `heroku.yml`.
+    pub variables: EnvironmentVariables,
+    /// The `build` config of `heroku.yml`.
+    pub build_variables: EnvironmentVariables,
+    /// Variables that `app.json` requires without giving them a value.
+    pub required_variables: Vec<String>,
+    /// Providers for the buildpacks listed in `app.json`.
+    pub providers: Vec<String>,
+    /// Buildpacks without a matching provider.
+    pub unknown_buildpacks: Vec<String>,
+    /// The `run` processes and `release` command of `heroku.yml`.
+    pub processes: Procfile,
+}
+
+impl HerokuConfig {
+    pub fn from_app(app: &App) -> Result<HerokuConfig> {
+        let mut config = HerokuConfig::default();
+
+        if app.includes_file(APP_JSON) {
+            let app_json: AppJson = app.read_json(APP_JSON).context("Reading app.json")?;
+            config.add_app_json(app_json);
+        }
+        if app.includes_file(HEROKU_YML) {
+            let heroku_yml: HerokuYml = app.read_yaml(HEROKU_YML).context("Reading heroku.yml")?;
+            config.add_heroku_yml(heroku_yml);
+        }
+
+        Ok(config)
+    }
+
+    fn add_app_json(&mut self, app_json: AppJson) {
+        for (name, variable) in app_json.env {
+            match variable {
+                AppJsonVariable::Value(value)
+                | AppJsonVariable::Definition {
+                    value: Some(value),
+                    generator: None,
+                    ..
+                } => {
+                    self.variables.insert(name, value);
+                }
+                // Generated secrets are unique to each app, so they are set like any other secret
+                AppJsonVariable::Definition {
+                    generator: Some(_), ..
+                } => {}
+                AppJsonVariable::Definition { required, .. } => {
+                    if required.unwrap_or(true) {
+                        self.required_variables.push(name);
+                    }
+                }
+            }
+        }
+
+        for buildpack in app_json.buildpacks {
+            match buildpack_provider(&buildpack.url) {
+                Some(provider) if !self.providers.iter().any(|p| p == provider) => {
+                    self.providers.push(provider.to_string());
+                }
+                Some(_) => {}
+                None => self.unknown_buildpacks.push(buildpack.url),
+            }
+        }
+    }
+
+    fn add_heroku_yml(&mut self, heroku_yml: HerokuYml) {
+        let config_variables = |section: Option<HerokuYmlConfig>| {
+            section
+                .unwrap_or_default()
+                .config
+                .into_iter()
+                .filter_map(|(name, value)| config_value(&value).map(|value| (name, value)))
+                .collect::<EnvironmentVariables>()
+        };
+        self.variables.extend(config_variables(heroku_yml.setup));
+        self.build_variables
+            .extend(config_variables(heroku_yml.build));
+
+        let mut processes = heroku_yml
+            .run
+            .into_iter()
+            .map(|(name, run)| {
+                let command = match run {
+                    HerokuYmlRun::Command(command)
+                    | HerokuYmlRun::Process(HerokuYmlProcess { command }) => command,
+                };
+                (name, command.to_shell())
+            })
+            .collect::<Vec<_>>();
+        if let Some(release) = heroku_yml.release {
+            processes.push(("release".to_string(), release.command.to_shell()));
+        }
+        self.processes = Procfile::new(processes);
+    }
+
+    /// The providers to build with, when `app.json` lists buildpacks that nixpacks has providers for.
+    pub fn get_providers(&self) -> Option<Vec<String>> {
+        Some(self.providers.clone()).filter(|providers| !providers.is_empty())
+    }
+
+    /// A plan that starts the `heroku.yml` processes.
+    pub fn to_plan(&self, env: &Environment) -> BuildPlan {
+        let mut plan = BuildPlan::default();
+        let processes = self
+            .processes
+            .clone()
+            .expand_variables(&Environment::clone_variables(env));
+        if let Some(start_phase) = processes.start_phase() {
+            plan.set_start_phase(start_phase);
+        }
+        plan
+    }
+
+    /// Warnings about the buildpacks that are ignored, and the required variables that aren't set.
+    pub fn get_warnings(&self, env: &Environment) -> Vec<String> {
+        let buildpacks = self.unknown_buildpacks.iter().map(|buildpack| {
+            format!("Buildpack `{buildpack}` from app.json has no matching provider and is ignored")
+        });
+        let variables = self
+            .required_variables
+            .iter()
+            .filter(|name| env.get_variable(name).is_none())
+            .map(|name| format!("app.json requires `{name}`, which is not set"));
+
+        buildpacks.chain(variables).collect()
+    }
+}
+
+/// The provider for a buildpack such as `heroku/nodejs` or `https://github.com/heroku/heroku-buildpack-python`.
+fn buildpack_provider(url: &str) -> Option<&'static str> {
+    let url = url.trim().trim_end_matches('/').trim_end_matches(".git");
+    let name = url.rsplit('/').next().unwrap_or(url);
+    let language = if url.starts_with("heroku/") {
+        name
+    } else {
+        name.strip_prefix("heroku-buildpack-")?
+    };
+
+    BUILDPACK_PROVIDERS
+        .iter()
+        .find(|(buildpack, _)| *buildpack == language)
+        .map(|(_, provider)| *provider)
+}
+
+/// The value of a `heroku.yml` config variable, which YAML may have parsed as a number or boolean.
+fn config_value(value: &serde_yaml::Value) -> Option<String> {
+    match value {
+        serde_yaml::Value::String(value) => Some(value.clone()),
+        serde_yaml::Value::Number(value) => Some(value.to_string()),
+        serde_yaml::Value::Bool(value) => Some(value.to_string()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempdir::TempDir;
+
+    #[test]
+    fn test_buildpack_provider() {
+        assert_eq!(buildpack_provider("heroku/nodejs"), Some("node"));
+        assert_eq!(
+            buildpack_provider("https://github.com/heroku/heroku-buildpack-python.git"),
+            Some("python")
+        );
+        assert_eq!(buildpack_provider("heroku/gradle"), Some("java"));
+        assert_eq!(buildpack_provider("heroku/heroku-buildpack-ruby"), None);
+        assert_eq!(
+            buildpack_provider("https://github.com/jontewks/puppeteer-heroku-buildpack"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_heroku_config() -> Result<()> {
+        let dir = TempDir::new("nixpacks-heroku")?;
+        fs::write(
+            dir.path().join(APP_JSON),
+            r#"{
+                "env": {
+                    "RAILS_ENV": "production",
+                    "WEB_CONCURRENCY": { "value": "2", "description": "Puma workers" },
+                    "SECRET_KEY_BASE": { "generator": "secret" },
+                    "STRIPE_KEY": { "description": "Stripe API key" },
+                    "SENTRY_DSN": { "required": false }
+                },
+                "buildpacks": [
+                    { "url": "heroku/ruby" },
+                    { "url": "heroku/nodejs" },
+                    { "url": "https://github.com/example/custom-buildpack" }
+                ]
+            }"#,
+        )?;
+        fs::write(
+            dir.path().join(HEROKU_YML),
+            "setup:\n  config:\n    MAX_THREADS: 5\nbuild:\n  config:\n    ASSET_HOST: cdn.example.com\nrelease:\n  command:\n    - ./bin/rails db:migrate\nrun:\n  web: bundle exec puma -C config/puma.rb\n  worker:\n    command:\n      - bundle exec sidekiq\n      - -q default\n    image: web\n",
+        )?;
+
+        let config = HerokuConfig::from_app(&App::new(dir.path())?)?;
+        assert_eq!(
+            config.variables,
+            BTreeMap::from([
+                ("MAX_THREADS".to_string(), "5".to_string()),
+                ("RAILS_ENV".to_string(), "production".to_string()),
+                ("WEB_CONCURRENCY".to_string(), "2".to_string()),
+            ])
+        );
+        assert_eq!(
+            config.build_variables,
+            BTreeMap::from([("ASSET_HOST".to_string(), "cdn.example.com".to_string())])
+        );
+        assert_eq!(config.required_variables, vec!["STRIPE_KEY"]);
+        assert_eq!(
+            config.get_providers(),
+            Some(vec!["ruby".to_string(), "node".to_string()])
+        );
+        assert_eq!(
+            config.unknown_buildpacks,
+            vec!["https://github.com/example/custom-buildpack"]
+        );
+        assert_eq!(
+            config.get_warnings(&Environment::default()),
+            vec![
+                "Buildpack `https://github.com/example/custom-buildpack` from app.json has no matching provider and is ignored",
+                "app.json requires `STRIPE_KEY`, which is not set",
+            ]
+        );
+
+        let start = config.to_plan(&Environment::default()).start_phase.unwrap();
+        assert_eq!(
+            start.cmd,
+            Some("bundle exec puma -C config/puma.rb".to_string())
+        );
+        assert_eq!(
+            start.release_cmd,
+            Some("./bin/rails db:migrate".to_string())
+        );
+        assert_eq!(
+            start.processes.unwrap()["worker"],
+            "bundle exec sidekiq -q default"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_heroku_config_without_files() -> Result<()> {
+        let dir = TempDir::new("nixpacks-heroku")?;
+        let config = HerokuConfig::from_app(&App::new(dir.path())?)?;
+        assert_eq!(config, HerokuConfig::default());
+        assert_eq!(config.get_providers(), None);
+        assert!(config
+            .to_plan(&Environment::default())
+            .start_phase
+            .is_none());
+        Ok(())
+    }
+}
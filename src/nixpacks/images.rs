@@ -3,3 +3,26 @@ pub const UBUNTU_BASE_IMAGE: &str = "ghcr.io/railwayapp/nixpacks:ubuntu-17374178
 pub const DEFAULT_BASE_IMAGE: &str = UBUNTU_BASE_IMAGE;
 
 pub const STANDALONE_IMAGE: &str = "ubuntu:jammy";
+
+/// The empty image, for statically linked binaries that need nothing else to run.
+pub const SCRATCH_IMAGE: &str = "scratch";
+
+/// Prefix of the images published from `base/`, which come with Nix already installed.
+const NIXPACKS_IMAGE_PREFIX: &str = "ghcr.io/railwayapp/nixpacks:";
+
+/// Whether the image is one of the Nixpacks base images.
+/// Any other build image needs Nix installed on top of it before the plan can run.
+pub fn is_nixpacks_base_image(image: &str) -> bool {
+    image.starts_with(NIXPACKS_IMAGE_PREFIX)
+}
+
+/// Distroless images have no shell or package manager, so they can only be used to run an app.
+pub fn is_distroless_image(image: &str) -> bool {
+    image.contains("distroless")
+}
+
+/// Whether a run image has no shell, so the app has to be started without one.
+/// The `debug` tags of distroless images come with a busybox shell.
+pub fn is_shell_less_image(image: &str) -> bool {
+    image == SCRATCH_IMAGE || (is_distroless_image(image) && !image.ends_with(":debug"))
+}
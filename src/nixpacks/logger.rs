@@ -1,21 +1,247 @@
+use crate::nixpacks::environment::REDACTED;
 use colored::Colorize;
+use console::Term;
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
+use serde_json::{json, Map, Value};
+use std::{
+    env,
+    sync::Mutex,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+/// Shorter values are left alone, since redacting them would garble unrelated output.
+const MIN_SECRET_LENGTH: usize = 4;
+
+/// How build progress is written to stdout.
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub enum LogFormat {
+    /// Human readable sections and steps.
+    #[default]
+    Text,
+    /// One JSON event per line, for platforms that parse the build progress.
+    Json,
+}
 
 /// Used for reporting Docker build information to stdout.
-pub struct Logger {}
+pub struct Logger {
+    format: LogFormat,
+    /// The phase that events are logged under, and when it started.
+    phase: Mutex<Option<(String, Instant)>>,
+    /// How long each finished phase took, in the order they started.
+    timings: Mutex<Vec<(String, Duration)>>,
+    started: Instant,
+    /// Values that are replaced with `[redacted]` wherever they would be logged.
+    secrets: Mutex<Vec<String>>,
+}
 
 impl Logger {
     pub fn new() -> Logger {
-        Logger {}
+        Logger::with_format(LogFormat::Text)
+    }
+
+    pub fn with_format(format: LogFormat) -> Logger {
+        Logger {
+            format,
+            phase: Mutex::new(None),
+            timings: Mutex::new(Vec::new()),
+            started: Instant::now(),
+            secrets: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Redact these values, such as the values of secret variables, from everything logged afterwards.
+    pub fn add_secrets(&self, values: Vec<String>) {
+        let mut secrets = self.secrets.lock().unwrap();
+        secrets.extend(
+            values
+                .into_iter()
+                .filter(|value| value.len() >= MIN_SECRET_LENGTH),
+        );
+        // Longer values first, so a secret that contains another is redacted as a whole
+        secrets.sort_by_key(|value| std::cmp::Reverse(value.len()));
+        secrets.dedup();
+    }
+
+    /// Whether any values are redacted, which means output has to pass through the logger.
+    pub fn has_secrets(&self) -> bool {
+        !self.secrets.lock().unwrap().is_empty()
+    }
+
+    fn redact(&self, text: &str) -> String {
+        self.secrets
+            .lock()
+            .unwrap()
+            .iter()
+            .fold(text.to_string(), |text, secret| {
+                text.replace(secret.as_str(), REDACTED)
+            })
+    }
+
+    pub fn format(&self) -> LogFormat {
+        self.format
     }
 
     /// Pretty-print the given log section title.
     pub fn log_section(&self, msg: &str) {
-        println!("=== {} ===", msg.magenta().bold());
+        match self.format {
+            LogFormat::Text => println!("=== {} ===", self.redact(msg).magenta().bold()),
+            LogFormat::Json => self.log_event(msg),
+        }
     }
 
     /// Pretty-print the given log line.
     pub fn log_step(&self, msg: &str) {
-        println!("=> {msg}");
+        match self.format {
+            LogFormat::Text => println!("{} {}", "=>".cyan().bold(), self.redact(msg)),
+            LogFormat::Json => self.log_event(msg),
+        }
+    }
+
+    /// Print plain output, such as the output of the Docker build.
+    pub fn log_output(&self, output: &str) {
+        match self.format {
+            LogFormat::Text => println!("{}", self.redact(output)),
+            LogFormat::Json => self.log_event(output.trim()),
+        }
+    }
+
+    /// Log a section with a summary of the build, such as the image size.
+    ///
+    /// JSON logs get a single event with the `fields` instead of the text.
+    pub fn log_summary(&self, title: &str, text: &str, fields: Map<String, Value>) {
+        match self.format {
+            LogFormat::Text => {
+                self.log_section(title);
+                println!("{}", self.redact(text));
+            }
+            LogFormat::Json => self.log_event_with_fields(title, fields),
+        }
+    }
+
+    /// Warn about something that doesn't stop the build, such as a large image.
+    ///
+    /// Text warnings are written to stderr, and JSON logs get an event with a `"level": "warning"` field.
+    pub fn log_warning(&self, warning: &str) {
+        match self.format {
+            LogFormat::Text => eprintln!("{} {}", "Warning:".yellow().bold(), self.redact(warning)),
+            LogFormat::Json => self.log_event_with_fields(
+                warning,
+                Map::from_iter([("level".to_string(), json!("warning"))]),
+            ),
+        }
+    }
+
+    /// Log a section of aligned key/value pairs, such as the providers and commands of the build plan.
+    ///
+    /// JSON logs get a single event with the pairs as fields.
+    pub fn log_key_values(&self, title: &str, pairs: &[(String, String)]) {
+        let fields = pairs
+            .iter()
+            .map(|(key, value)| (key.clone(), json!(value)))
+            .collect::<Map<_, _>>();
+        self.log_summary(title, &format_key_values(pairs), fields);
+    }
+
+    /// Whether progress is drawn with spinners and bars, which needs text logs on a terminal.
+    pub fn shows_progress(&self) -> bool {
+        self.format == LogFormat::Text && Term::stdout().is_term()
+    }
+
+    /// Show a spinner while a long step runs, with a message such as the amount copied so far.
+    ///
+    /// Without a terminal the step is logged as a plain line instead, and the returned spinner is hidden.
+    pub fn start_progress(&self, step: &str) -> ProgressBar {
+        if !self.shows_progress() {
+            self.log_step(step);
+            return ProgressBar::hidden();
+        }
+
+        spinner(step, ProgressDrawTarget::stdout())
+    }
+
+    /// Bars for the layers of a Docker build, or `None` when progress isn't drawn.
+    pub fn start_layer_progress(&self) -> Option<MultiProgress> {
+        if self.shows_progress() {
+            Some(MultiProgress::with_draw_target(ProgressDrawTarget::stdout()))
+        } else {
+            None
+        }
+    }
+
+    /// Start a phase of the build, such as `plan` or `build`, which finishes the previous phase.
+    pub fn start_phase(&self, phase: &str) {
+        self.end_phase();
+        *self.phase.lock().unwrap() = Some((phase.to_string(), Instant::now()));
+    }
+
+    /// Finish the current phase, logging how long it took in JSON mode.
+    pub fn end_phase(&self) {
+        let finished = self.phase.lock().unwrap().take();
+        if let Some((phase, started)) = finished {
+            let duration = started.elapsed();
+            if self.format == LogFormat::Json {
+                println!(
+                    "{}",
+                    format_event(
+                        get_timestamp(),
+                        Some(&phase),
+                        &format!("Finished {phase}"),
+                        Some(duration)
+                    )
+                );
+            }
+
+            let mut timings = self.timings.lock().unwrap();
+            match timings.iter_mut().find(|(name, _)| *name == phase) {
+                Some((_, total)) => *total += duration,
+                None => timings.push((phase, duration)),
+            }
+        }
+    }
+
+    /// Finish the current phase and log how long each phase of the build took.
+    pub fn finish(&self) {
+        self.end_phase();
+
+        let timings = self.timings.lock().unwrap();
+        let total = self.started.elapsed();
+        match self.format {
+            LogFormat::Text => {
+                self.log_section("Build time");
+                println!("{}", format_timings(&timings, total));
+            }
+            LogFormat::Json => {
+                let mut event = get_event(get_timestamp(), None, "Build time", Some(total));
+                event["timings"] = timings
+                    .iter()
+                    .map(|(phase, duration)| (phase.clone(), json!(duration.as_millis() as u64)))
+                    .collect::<Map<_, _>>()
+                    .into();
+                println!("{event}");
+            }
+        }
+    }
+
+    fn log_event_with_fields(&self, message: &str, fields: Map<String, Value>) {
+        let phase = self.phase.lock().unwrap().as_ref().map(|(p, _)| p.clone());
+        let mut event = get_event(get_timestamp(), phase.as_deref(), message, None);
+        if let Value::Object(event) = &mut event {
+            event.extend(fields);
+        }
+        println!("{}", self.redact(&event.to_string()));
+    }
+
+    fn log_event(&self, message: &str) {
+        let phase = self.phase.lock().unwrap().as_ref().map(|(p, _)| p.clone());
+        println!(
+            "{}",
+            format_event(
+                get_timestamp(),
+                phase.as_deref(),
+                &self.redact(message),
+                None
+            )
+        );
     }
 }
 
@@ -24,3 +250,209 @@ impl Default for Logger {
         Self::new()
     }
 }
+
+/// Whether human output is colored, which is only on a terminal and never when `NO_COLOR` is set.
+pub fn colors_enabled() -> bool {
+    env::var_os("NO_COLOR").map_or(true, |value| value.is_empty()) && Term::stdout().is_term()
+}
+
+/// Turns colors on or off for all human output, including spinners and progress bars.
+pub fn set_colors(enabled: bool) {
+    colored::control::set_override(enabled);
+    console::set_colors_enabled(enabled);
+}
+
+/// Milliseconds since the Unix epoch.
+fn get_timestamp() -> u128 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_millis())
+}
+
+/// A spinner for a step, which draws nothing when the target isn't a terminal.
+pub fn spinner(step: &str, target: ProgressDrawTarget) -> ProgressBar {
+    let spinner = ProgressBar::with_draw_target(None, target);
+    spinner.set_style(
+        ProgressStyle::with_template("{spinner:.magenta} {prefix} {msg:.dim}")
+            .unwrap()
+            .tick_chars("⠋⠙⠹⠸⠼⠴⠦⠧⠇⠏ "),
+    );
+    spinner.set_prefix(step.to_string());
+    spinner.enable_steady_tick(Duration::from_millis(100));
+    spinner
+}
+
+/// A bar for downloading or extracting one layer of an image.
+pub fn layer_progress_bar(total: u64) -> ProgressBar {
+    let bar = ProgressBar::new(total);
+    bar.set_style(
+        ProgressStyle::with_template("{prefix:12} {msg:16} [{bar:30}] {bytes}/{total_bytes}")
+            .unwrap()
+            .progress_chars("=> "),
+    );
+    bar
+}
+
+/// Formats a duration such as `2m 5.3s`.
+fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs_f64();
+    if seconds < 60.0 {
+        format!("{seconds:.1}s")
+    } else {
+        format!("{}m {:.1}s", (seconds / 60.0).floor(), seconds % 60.0)
+    }
+}
+
+/// Formats the time taken by each phase as a table, followed by the total.
+fn format_timings(timings: &[(String, Duration)], total: Duration) -> String {
+    let rows = timings
+        .iter()
+        .map(|(phase, duration)| (phase.clone(), format_duration(*duration)))
+        .chain(std::iter::once((
+            "total".to_string(),
+            format_duration(total),
+        )))
+        .collect::<Vec<_>>();
+    format_key_values(&rows)
+}
+
+/// Formats pairs with their values aligned, continuing multi-line values under the first line.
+fn format_key_values(pairs: &[(String, String)]) -> String {
+    let width = pairs
+        .iter()
+        .map(|(key, _)| console::measure_text_width(key))
+        .max()
+        .unwrap_or(0);
+    let indent = " ".repeat(width + 4);
+
+    pairs
+        .iter()
+        .map(|(key, value)| {
+            let value = value
+                .lines()
+                .collect::<Vec<_>>()
+                .join(&format!("\n{indent}"));
+            format!("  {}  {value}", format!("{key:<width$}").cyan())
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Formats a JSON log event, with the duration in milliseconds.
+fn format_event(
+    timestamp: u128,
+    phase: Option<&str>,
+    message: &str,
+    duration: Option<Duration>,
+) -> String {
+    get_event(timestamp, phase, message, duration).to_string()
+}
+
+fn get_event(
+    timestamp: u128,
+    phase: Option<&str>,
+    message: &str,
+    duration: Option<Duration>,
+) -> Value {
+    let mut event = json!({
+        "timestamp": timestamp as u64,
+        "phase": phase,
+        "message": message,
+    });
+    if let (Some(duration), Value::Object(fields)) = (duration, &mut event) {
+        fields.insert("duration".to_string(), json!(duration.as_millis() as u64));
+    }
+
+    event
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_event() {
+        assert_eq!(
+            format_event(1_700_000_000_000, Some("build"), "#1 DONE 0.1s", None),
+            r##"{"message":"#1 DONE 0.1s","phase":"build","timestamp":1700000000000}"##
+        );
+        assert_eq!(
+            format_event(
+                1_700_000_000_000,
+                Some("plan"),
+                "Finished plan",
+                Some(Duration::from_millis(1500))
+            ),
+            r#"{"duration":1500,"message":"Finished plan","phase":"plan","timestamp":1700000000000}"#
+        );
+        assert_eq!(
+            format_event(0, None, "Successfully Built!", None),
+            r#"{"message":"Successfully Built!","phase":null,"timestamp":0}"#
+        );
+    }
+
+    #[test]
+    fn test_format_timings() {
+        assert_eq!(
+            console::strip_ansi_codes(&format_timings(
+                &[
+                    ("detect".to_string(), Duration::from_millis(40)),
+                    ("build".to_string(), Duration::from_millis(125_300)),
+                ],
+                Duration::from_secs(126),
+            )),
+            "  detect  0.0s\n  build   2m 5.3s\n  total   2m 6.0s"
+        );
+    }
+
+    #[test]
+    fn test_format_key_values() {
+        let pairs = vec![
+            ("providers".to_string(), "node".to_string()),
+            ("build".to_string(), "npm ci\nnpm run build".to_string()),
+        ];
+        assert_eq!(
+            console::strip_ansi_codes(&format_key_values(&pairs)),
+            "  providers  node\n  build      npm ci\n             npm run build"
+        );
+    }
+
+    #[test]
+    fn test_redact() {
+        let logger = Logger::new();
+        assert!(!logger.has_secrets());
+        logger.add_secrets(vec![
+            "abc".to_string(),
+            "npm_abc123".to_string(),
+            "npm_abc123_extra".to_string(),
+        ]);
+        assert!(logger.has_secrets());
+        assert_eq!(
+            logger.redact("abc npm_abc123 and npm_abc123_extra"),
+            "abc [redacted] and [redacted]"
+        );
+    }
+
+    #[test]
+    fn test_phases() {
+        let logger = Logger::with_format(LogFormat::Json);
+        logger.start_phase("plan");
+        logger.start_phase("build");
+        assert_eq!(
+            logger
+                .phase
+                .lock()
+                .unwrap()
+                .as_ref()
+                .map(|(p, _)| p.clone()),
+            Some("build".to_string())
+        );
+        logger.start_phase("plan");
+        logger.end_phase();
+        assert!(logger.phase.lock().unwrap().is_none());
+
+        let timings = logger.timings.lock().unwrap();
+        let phases: Vec<&str> = timings.iter().map(|(phase, _)| phase.as_str()).collect();
+        assert_eq!(phases, vec!["plan", "build"]);
+    }
+}
@@ -1,12 +1,18 @@
 pub mod app;
 pub mod asdf;
 pub mod builder;
+pub mod deploy;
 pub mod environment;
+pub mod error;
 mod files;
+pub mod git;
+pub mod heroku;
 pub mod images;
 pub mod logger;
+pub mod multi;
 pub mod nix;
 pub mod plan;
+pub mod procfile;
 #[macro_use]
 pub mod static_assets;
 
@@ -0,0 +1,100 @@
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use std::{collections::BTreeSet, fs, path::Path};
+
+/// File in the repository root that lists the apps built by `nixpacks build --multi`.
+pub const APPS_MANIFEST: &str = "nixpacks-apps.toml";
+
+#[derive(Deserialize)]
+struct AppsManifest {
+    apps: Vec<String>,
+}
+
+/// The subdirectories listed in the repository's apps manifest.
+pub fn read_apps_manifest(root: &Path) -> Result<Vec<String>> {
+    let path = root.join(APPS_MANIFEST);
+    if !path.is_file() {
+        bail!("List the apps to build after --multi, or in {APPS_MANIFEST} as `apps = [\"apps/web\", \"apps/api\"]`");
+    }
+
+    let contents =
+        fs::read_to_string(&path).with_context(|| format!("Reading {}", path.display()))?;
+    let manifest: AppsManifest =
+        toml::from_str(&contents).with_context(|| format!("Parsing {APPS_MANIFEST}"))?;
+    if manifest.apps.is_empty() {
+        bail!("{APPS_MANIFEST} doesn't list any apps");
+    }
+    Ok(manifest.apps)
+}
+
+/// The image name for an app, from its subdirectory and the `--name` given for the whole build.
+pub fn get_app_image_name(name: Option<&str>, app: &str) -> String {
+    let app = app
+        .trim_start_matches("./")
+        .trim_end_matches('/')
+        .to_lowercase()
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '.' || c == '_' {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect::<String>();
+
+    match name {
+        Some(name) => format!("{name}-{app}"),
+        None => app,
+    }
+}
+
+/// Image names for each app, failing when two apps would be built as the same image.
+pub fn get_app_image_names(name: Option<&str>, apps: &[String]) -> Result<Vec<String>> {
+    let names = apps
+        .iter()
+        .map(|app| get_app_image_name(name, app))
+        .collect::<Vec<_>>();
+
+    let mut seen = BTreeSet::new();
+    if let Some(duplicate) = names.iter().find(|name| !seen.insert(name.as_str())) {
+        bail!("Several apps would be built as the image `{duplicate}`");
+    }
+    Ok(names)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempdir::TempDir;
+
+    #[test]
+    fn test_get_app_image_names() -> Result<()> {
+        assert_eq!(
+            get_app_image_names(None, &["apps/web".to_string(), "./API/".to_string()])?,
+            vec!["apps-web", "api"]
+        );
+        assert_eq!(get_app_image_name(Some("shop"), "web"), "shop-web");
+        assert!(get_app_image_names(None, &["web".to_string(), "./web".to_string()]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_apps_manifest() -> Result<()> {
+        let root = TempDir::new("nixpacks-monorepo")?;
+        assert!(read_apps_manifest(root.path()).is_err());
+
+        fs::write(root.path().join(APPS_MANIFEST), "apps = []")?;
+        assert!(read_apps_manifest(root.path()).is_err());
+
+        fs::write(
+            root.path().join(APPS_MANIFEST),
+            "apps = [\"apps/web\", \"apps/api\"]",
+        )?;
+        assert_eq!(
+            read_apps_manifest(root.path())?,
+            vec!["apps/web", "apps/api"]
+        );
+        Ok(())
+    }
+}
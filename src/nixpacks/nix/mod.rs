@@ -1,9 +1,31 @@
+use anyhow::{bail, Context, Result};
 use indoc::formatdoc;
-use std::collections::{BTreeMap, BTreeSet};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io::ErrorKind,
+    process::Command,
+};
 
 use crate::nixpacks::plan::phase::{Phase, Phases};
 
 pub mod pkg;
+pub mod template;
+
+use template::NixTemplate;
+
+pub const NIX_FLAKE_FILE_NAME: &str = "flake.nix";
+
+/// Builds the environment for a group of Nix packages, given how to import nixpkgs.
+const ENV_TEMPLATE: &str = include_str!("templates/env.nix.in");
+
+/// A flake with an input for each nixpkgs revision and a package for each environment.
+const FLAKE_TEMPLATE: &str = include_str!("templates/flake.nix.in");
+
+/// A `shell.nix` that enters the environments for every group of Nix packages.
+const SHELL_TEMPLATE: &str = include_str!("templates/shell.nix.in");
+
+/// Nix files in the app that can provide the environment instead of a generated one, in order of preference.
+pub const APP_NIX_FILE_NAMES: [&str; 3] = [NIX_FLAKE_FILE_NAME, "default.nix", "shell.nix"];
 
 // This line is automatically updated.
 // Last Modified: 2023-09-17 17:13:39 UTC+0000
@@ -13,6 +35,31 @@ pub const NIXPKGS_ARCHIVE: &str = "5148520bfab61f99fd25fb9ff7bfbb50dad3c9db";
 // Version of the Nix archive that uses OpenSSL 1.1
 pub const NIXPACKS_ARCHIVE_LEGACY_OPENSSL: &str = "a0b7e70db7a55088d3de0cc370a59f9fbcc906c3";
 
+/// Resolves a nixpkgs channel name (`unstable`, `23.05` or `nixos-23.05`) to the branch archive to fetch.
+///
+/// Channels move over time, so builds using them are not reproducible the way a pinned revision is.
+pub fn nixpkgs_archive_for_channel(channel: &str) -> Result<String> {
+    let channel = channel.trim();
+    match channel {
+        "unstable" | "nixos-unstable" => return Ok("nixos-unstable".to_string()),
+        "nixpkgs-unstable" => return Ok("nixpkgs-unstable".to_string()),
+        _ => {}
+    }
+
+    let release = channel.strip_prefix("nixos-").unwrap_or(channel);
+
+    let is_release = matches!(release.split_once('.'), Some((year, month))
+        if year.len() == 2
+            && month.len() == 2
+            && year.chars().all(|c| c.is_ascii_digit())
+            && month.chars().all(|c| c.is_ascii_digit()));
+    if !is_release {
+        bail!("Unknown nixpkgs channel `{channel}`. Expected `unstable` or a release like `23.05`");
+    }
+
+    Ok(format!("nixos-{release}"))
+}
+
 /// Contains all the data needed to generate a Nix expression file for installing Nix dependencies.
 #[derive(Eq, PartialEq, Default, Debug, Clone)]
 struct NixGroup {
@@ -20,6 +67,8 @@ struct NixGroup {
     pkgs: Vec<String>,
     libs: Vec<String>,
     overlays: Vec<String>,
+    snippets: Vec<String>,
+    extra_outputs: Vec<String>,
     files: Vec<String>,
 }
 
@@ -35,6 +84,8 @@ fn group_nix_packages_by_archive(phases: &[Phase]) -> Vec<NixGroup> {
             pkgs: phase.nix_pkgs.clone().unwrap_or_default(),
             libs: phase.nix_libs.clone().unwrap_or_default(),
             overlays: phase.nix_overlays.clone().unwrap_or_default(),
+            snippets: phase.nix_snippets.clone().unwrap_or_default(),
+            extra_outputs: phase.nix_extra_outputs.clone().unwrap_or_default(),
             files: phase.only_include_files.clone().unwrap_or_default(),
         });
 
@@ -44,6 +95,8 @@ fn group_nix_packages_by_archive(phases: &[Phase]) -> Vec<NixGroup> {
                 group.pkgs.extend(g.pkgs);
                 group.libs.extend(g.libs);
                 group.overlays.extend(g.overlays);
+                group.snippets.extend(g.snippets);
+                group.extra_outputs.extend(g.extra_outputs);
                 group.files.extend(g.files);
             }
             None => {
@@ -103,6 +156,44 @@ pub fn setup_files_for_phases(phases: &Phases) -> Vec<String> {
     })
 }
 
+/// Generates a `shell.nix` with the same packages and libraries as the image, for developing the app locally.
+pub fn create_nix_shell_for_phases(phases: &Phases) -> String {
+    let groups = group_nix_packages_by_archive(&phases.values().cloned().collect::<Vec<_>>());
+
+    let envs = groups
+        .iter()
+        .map(|g| {
+            format!(
+                "env-{} = (\n{}\n);",
+                flake_input_name(g),
+                env_expression_for_group(g, &archive_import(g.archive.as_ref()), "").trim_end()
+            )
+        })
+        .collect::<Vec<_>>();
+    let inputs = groups
+        .iter()
+        .map(|g| format!("env-{}", flake_input_name(g)))
+        .collect::<Vec<_>>();
+    // The environments export library paths from a profile script, which the shell sources instead of the login shell
+    let hooks = groups
+        .iter()
+        .map(|g| {
+            let archive = g.archive.as_deref().unwrap_or(NIXPKGS_ARCHIVE);
+            format!(
+                "source ${{env-{}}}/etc/profile.d/{archive}-env.sh",
+                flake_input_name(g)
+            )
+        })
+        .collect::<Vec<_>>();
+
+    NixTemplate::new(SHELL_TEMPLATE)
+        .set_list("envs", envs)
+        .set("import", archive_import(None))
+        .set_list("inputs", inputs)
+        .set_list("hooks", hooks)
+        .render()
+}
+
 /// Generates the filename for each Nix expression file.
 fn nix_file_name(archive: Option<&String>) -> String {
     match archive {
@@ -118,70 +209,283 @@ fn nix_expression_for_group(group: &NixGroup) -> String {
         .clone()
         .unwrap_or_else(|| NIXPKGS_ARCHIVE.to_string());
 
+    formatdoc! {"
+        {{ }}:
+
+        {}",
+        env_expression_for_group(group, &archive_import(Some(&archive)), ""),
+    }
+}
+
+/// Imports nixpkgs from the archive of a revision, or the default revision.
+fn archive_import(archive: Option<&String>) -> String {
+    let archive = archive.map_or(NIXPKGS_ARCHIVE, String::as_str);
+    format!("import (fetchTarball \"https://github.com/NixOS/nixpkgs/archive/{archive}.tar.gz\")")
+}
+
+/// Generates the body of a Nix expression that builds an environment for a group, given how to import nixpkgs.
+fn env_expression_for_group(group: &NixGroup, pkg_import: &str, import_args: &str) -> String {
+    let archive = group
+        .archive
+        .clone()
+        .unwrap_or_else(|| NIXPKGS_ARCHIVE.to_string());
+
     let mut pkgs = group.pkgs.clone();
     pkgs.sort();
-    let pkgs = pkgs.join(" ");
+    pkgs.dedup();
 
     let mut libs = group.libs.clone();
     libs.sort();
-    let libs = libs.join(" ");
+    libs.dedup();
 
-    let overlays_string = group
+    let overlays = group
         .overlays
         .iter()
-        .map(|url| format!("(import (builtins.fetchTarball \"{url}\"))"))
-        .collect::<Vec<String>>()
-        .join("\n");
+        .map(|overlay| overlay_expression(overlay))
+        .collect::<Vec<String>>();
 
-    let pkg_import = format!(
-        "import (fetchTarball \"https://github.com/NixOS/nixpkgs/archive/{archive}.tar.gz\")"
-    );
+    let mut snippets = group
+        .snippets
+        .iter()
+        .map(|snippet| snippet_binding(snippet))
+        .collect::<Vec<_>>();
+    snippets.dedup();
+
+    let mut extra_outputs = group
+        .extra_outputs
+        .iter()
+        .map(|output| format!("\"{}\"", output.trim()))
+        .collect::<Vec<_>>();
+    extra_outputs.sort();
+    extra_outputs.dedup();
+
+    NixTemplate::new(ENV_TEMPLATE)
+        .set("import", pkg_import)
+        .set("import_args", import_args)
+        .set_list("overlays", overlays)
+        .set_list("snippets", snippets)
+        .set_list("extra_outputs", extra_outputs)
+        .set_list("exports", library_exports(&libs))
+        .set_list("libs", libs)
+        .set("name", format!("{archive}-env"))
+        .set_list("pkgs", pkgs)
+        .render()
+}
 
+/// A snippet as a binding of the environment's `let`, which has to end with a semicolon.
+fn snippet_binding(snippet: &str) -> String {
+    let snippet = snippet.trim();
+    if snippet.ends_with(';') {
+        snippet.to_string()
+    } else {
+        format!("{snippet};")
+    }
+}
+
+/// The names bound by a phase's snippets, such as `myPython` for `myPython = python3.withPackages (...);`.
+fn snippet_names(phase: &Phase) -> Vec<&str> {
+    phase
+        .nix_snippets
+        .iter()
+        .flatten()
+        .filter_map(|snippet| snippet.split_once('='))
+        .map(|(name, _)| name.trim())
+        .collect()
+}
+
+/// Variables exported in the environment's profile that point at the location of libraries.
+fn library_exports(libs: &[String]) -> Vec<String> {
     // If the openssl library is added, set the OPENSSL_DIR and OPENSSL_LIB_DIR environment variables
-    // In the future, we will probably want a generic way for providers to set variables based off Nix package locations
-    let openssl_dirs =
-        if let Some(openssl_lib) = group.libs.iter().find(|lib| lib.contains("openssl")) {
-            formatdoc! {"
-          export OPENSSL_DIR=\"${{{openssl_lib}.dev}}\"
-          export OPENSSL_LIB_DIR=\"${{{openssl_lib}.out}}/lib\"
-        "}
+    match libs.iter().find(|lib| lib.contains("openssl")) {
+        Some(openssl_lib) => vec![
+            format!("export OPENSSL_DIR=\"${{{openssl_lib}.dev}}\""),
+            format!("export OPENSSL_LIB_DIR=\"${{{openssl_lib}.out}}/lib\""),
+        ],
+        None => Vec::new(),
+    }
+}
+
+/// Generates an expression that evaluates to the names of the phase's Nix packages and libraries that are missing from its nixpkgs.
+///
+/// Packages with overrides are complete expressions rather than attribute paths, so they aren't checked.
+fn missing_packages_expression(phase: &Phase) -> Option<String> {
+    // Snippets define their packages in the environment rather than in nixpkgs
+    let defined = snippet_names(phase);
+    let names = phase
+        .nix_pkgs
+        .iter()
+        .chain(phase.nix_libs.iter())
+        .flatten()
+        .filter(|name| {
+            name.chars()
+                .all(|c| c.is_ascii_alphanumeric() || "_-'.".contains(c))
+                && !defined.contains(&name.as_str())
+        })
+        .map(|name| format!("\"{name}\""))
+        .collect::<Vec<_>>();
+    if names.is_empty() {
+        return None;
+    }
+
+    let archive = phase
+        .nixpkgs_archive
+        .clone()
+        .unwrap_or_else(|| NIXPKGS_ARCHIVE.to_string());
+    let overlays = phase
+        .nix_overlays
+        .iter()
+        .flatten()
+        .map(|overlay| overlay_expression(overlay))
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    Some(formatdoc! {"
+        let pkgs = import (fetchTarball \"https://github.com/NixOS/nixpkgs/archive/{archive}.tar.gz\") {{ overlays = [ {overlays} ]; }};
+        in builtins.filter (name: !(pkgs.lib.hasAttrByPath (pkgs.lib.splitString \".\" name) pkgs)) [ {} ]",
+        names.join(" "),
+    })
+}
+
+/// Finds the Nix packages and libraries of a phase that don't exist in its nixpkgs archive.
+///
+/// Returns `None` if Nix is not installed on this machine.
+pub fn find_missing_packages(phase: &Phase) -> Result<Option<Vec<String>>> {
+    let expression = match missing_packages_expression(phase) {
+        Some(expression) => expression,
+        None => return Ok(Some(Vec::new())),
+    };
+
+    let output = match Command::new("nix-instantiate")
+        .args(["--eval", "--strict", "--json", "-E"])
+        .arg(expression)
+        .output()
+    {
+        Ok(output) => output,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e).context("Running nix-instantiate"),
+    };
+    if !output.status.success() {
+        bail!(
+            "Evaluating nixpkgs failed:\n{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+
+    let missing = serde_json::from_slice(&output.stdout).context("Parsing missing packages")?;
+    Ok(Some(missing))
+}
+
+/// Whether the overlay is a tarball to fetch, rather than an inline Nix expression.
+fn is_overlay_url(overlay: &str) -> bool {
+    let overlay = overlay.trim();
+    overlay.starts_with("https://")
+        || overlay.starts_with("http://")
+        || overlay.starts_with("file://")
+}
+
+/// Renders an overlay as a Nix expression, fetching it if it is a URL and using it verbatim otherwise.
+///
+/// Inline overlays let plans override package versions or add derivations, e.g. `self: super: { hello = super.cowsay; }`.
+fn overlay_expression(overlay: &str) -> String {
+    if is_overlay_url(overlay) {
+        format!("(import (builtins.fetchTarball \"{}\"))", overlay.trim())
+    } else {
+        format!("({})", overlay.trim())
+    }
+}
+
+/// Name of the flake input that pins the nixpkgs revision for a group.
+fn flake_input_name(group: &NixGroup) -> String {
+    let archive = group
+        .archive
+        .clone()
+        .unwrap_or_else(|| NIXPKGS_ARCHIVE.to_string());
+    // Branch names like `nixos-23.05` are not valid Nix identifiers
+    let archive = archive.replace(|c: char| !c.is_ascii_alphanumeric() && c != '-', "_");
+    format!("nixpkgs-{archive}")
+}
+
+/// Names of the flake packages that provide the environment for each group of Nix dependencies.
+pub fn flake_package_names_for_phases(phases: &Phases) -> Vec<String> {
+    group_nix_packages_by_archive(&phases.values().cloned().collect::<Vec<_>>())
+        .iter()
+        .map(|g| format!("env-{}", flake_input_name(g)))
+        .collect()
+}
+
+/// Whether any of the phases use overlay URLs, which must be fetched impurely when building the flake.
+pub fn flake_requires_impure(phases: &Phases) -> bool {
+    group_nix_packages_by_archive(&phases.values().cloned().collect::<Vec<_>>())
+        .iter()
+        .any(|g| g.overlays.iter().any(|overlay| is_overlay_url(overlay)))
+}
+
+/// Generates a `flake.nix` with an input pinned to each nixpkgs revision and a package per group of Nix dependencies.
+pub fn create_nix_flake_for_phases(phases: &Phases) -> String {
+    let groups = group_nix_packages_by_archive(&phases.values().cloned().collect::<Vec<_>>());
+
+    let inputs = groups
+        .iter()
+        .map(|g| {
+            format!(
+                "{}.url = \"github:NixOS/nixpkgs/{}\";",
+                flake_input_name(g),
+                g.archive
+                    .clone()
+                    .unwrap_or_else(|| NIXPKGS_ARCHIVE.to_string())
+            )
+        })
+        .collect::<Vec<_>>();
+
+    let packages = groups
+        .iter()
+        .map(|g| {
+            let pkg_import = format!("import inputs.{}", flake_input_name(g));
+            format!(
+                "\"env-{}\" = (\n{}\n);",
+                flake_input_name(g),
+                env_expression_for_group(g, &pkg_import, "inherit system; ").trim_end()
+            )
+        })
+        .collect::<Vec<_>>();
+
+    NixTemplate::new(FLAKE_TEMPLATE)
+        .set_list("inputs", inputs)
+        .set_list("packages", packages)
+        .render()
+}
+
+/// Generates the Dockerfile commands that install the environment from a Nix file in the app.
+///
+/// Flakes install their default package, `shell.nix` files install the inputs of the shell, and any other file is installed with `nix-env`.
+/// `<nixpkgs>` is resolved to the given archive, since the image has no channels.
+pub fn app_nix_install_cmds(nix_file: &str, archive: Option<&String>) -> String {
+    let nix_file = nix_file.trim_start_matches("./");
+    let (dir, file_name) = nix_file.rsplit_once('/').unwrap_or((".", nix_file));
+
+    if file_name == NIX_FLAKE_FILE_NAME {
+        let flake_ref = if dir == "." {
+            "path:.".to_string()
         } else {
-            String::new()
+            format!("path:./{dir}")
         };
+        // The lock file is optional, and the glob lets the copy succeed without it
+        return format!(
+            "COPY {dir}/flake.nix {dir}/flake.lock* {dir}/\nRUN nix --extra-experimental-features 'nix-command flakes' build --no-link --print-out-paths '{flake_ref}#' | xargs nix-env -i && nix-collect-garbage -d"
+        );
+    }
 
-    let name = format!("{archive}-env");
-    let nix_expression = formatdoc! {"
-            {{ }}:
-
-            let pkgs = {} {{ overlays = [ {} ]; }};
-            in with pkgs;
-              let
-                APPEND_LIBRARY_PATH = \"${{lib.makeLibraryPath [ {} ] }}\";
-                myLibraries = writeText \"libraries\" ''
-                  export LD_LIBRARY_PATH=\"${{APPEND_LIBRARY_PATH}}:$LD_LIBRARY_PATH\"
-                  {}
-                '';
-              in
-                buildEnv {{
-                  name = \"{name}\";
-                  paths = [
-                    (runCommand \"{name}\" {{ }} ''
-                      mkdir -p $out/etc/profile.d
-                      cp ${{myLibraries}} $out/etc/profile.d/{name}.sh
-                    '')
-                    {}
-                  ];
-                }}
-        ",
-        pkg_import,
-        overlays_string,
-        libs,
-        openssl_dirs,
-        pkgs,
-        name=name,
+    let archive = archive.map_or(NIXPKGS_ARCHIVE, String::as_str);
+    let nixpkgs = format!("nixpkgs=https://github.com/NixOS/nixpkgs/archive/{archive}.tar.gz");
+    let install = if file_name == "shell.nix" {
+        format!(
+            "nix-env -I {nixpkgs} -iE '_: let shell = import ./{nix_file}; env = if builtins.isFunction shell then shell {{}} else shell; in (env.buildInputs or []) ++ (env.nativeBuildInputs or [])'"
+        )
+    } else {
+        format!("nix-env -I {nixpkgs} -if {nix_file}")
     };
 
-    nix_expression
+    format!("COPY {nix_file} {nix_file}\nRUN {install} && nix-collect-garbage -d")
 }
 
 #[cfg(test)]
@@ -199,7 +503,7 @@ mod tests {
 
         let setup3 = Phase::setup(Some(vec![Pkg::new("baz")]));
 
-        let groups = group_nix_packages_by_archive(&vec![setup1, setup2, setup3]);
+        let groups = group_nix_packages_by_archive(&[setup1, setup2, setup3]);
         assert_eq!(groups.len(), 2);
         assert_eq!(
             groups[0],
@@ -208,6 +512,8 @@ mod tests {
                 pkgs: vec!["foo".to_string(), "bar".to_string(), "baz".to_string()],
                 libs: vec!["lib1".to_string()],
                 overlays: vec![],
+                snippets: vec![],
+                extra_outputs: vec![],
                 files: vec!["test-file".to_string()]
             }
         );
@@ -218,8 +524,173 @@ mod tests {
                 pkgs: vec!["hello".to_string(), "world".to_string()],
                 libs: vec![],
                 overlays: vec![],
+                snippets: vec![],
+                extra_outputs: vec![],
                 files: vec![]
             }
         );
     }
+
+    #[test]
+    fn test_inline_overlays() {
+        let mut setup = Phase::setup(Some(vec![Pkg::new("hello")]));
+        setup.nix_overlays = Some(vec![
+            "https://example.com/overlay.tar.gz".to_string(),
+            "self: super: { hello = super.cowsay; }".to_string(),
+        ]);
+        let phases = Phases::from([("setup".to_string(), setup)]);
+
+        let expressions = create_nix_expressions_for_phases(&phases);
+        let expression = expressions.values().next().unwrap();
+        assert!(expression
+            .contains("(import (builtins.fetchTarball \"https://example.com/overlay.tar.gz\"))"));
+        assert!(expression.contains("(self: super: { hello = super.cowsay; })"));
+        assert!(flake_requires_impure(&phases));
+
+        let mut setup = Phase::setup(Some(vec![Pkg::new("hello")]));
+        setup.nix_overlays = Some(vec!["self: super: { }".to_string()]);
+        assert!(!flake_requires_impure(&Phases::from([(
+            "setup".to_string(),
+            setup
+        )])));
+    }
+
+    #[test]
+    fn test_snippets_and_extra_outputs() {
+        let mut setup = Phase::setup(Some(vec![Pkg::new("myPython")]));
+        setup.nix_snippets = Some(vec![
+            "myPython = python311.withPackages (ps: [ ps.numpy ])".to_string()
+        ]);
+        setup.nix_extra_outputs = Some(vec!["lib".to_string(), "dev".to_string()]);
+        let phases = Phases::from([("setup".to_string(), setup.clone())]);
+
+        let expressions = create_nix_expressions_for_phases(&phases);
+        let expression = expressions.values().next().unwrap();
+        assert!(expression
+            .contains("  let\n    myPython = python311.withPackages (ps: [ ps.numpy ]);\n"));
+        assert!(expression.contains("extraOutputsToInstall = [ \"dev\" \"lib\" ];"));
+
+        // Packages defined by snippets aren't looked up in nixpkgs
+        assert_eq!(missing_packages_expression(&setup), None);
+    }
+
+    #[test]
+    fn test_nixpkgs_archive_for_channel() {
+        assert_eq!(
+            nixpkgs_archive_for_channel("unstable").unwrap(),
+            "nixos-unstable"
+        );
+        assert_eq!(
+            nixpkgs_archive_for_channel("nixpkgs-unstable").unwrap(),
+            "nixpkgs-unstable"
+        );
+        assert_eq!(nixpkgs_archive_for_channel("23.05").unwrap(), "nixos-23.05");
+        assert_eq!(
+            nixpkgs_archive_for_channel("nixos-22.11").unwrap(),
+            "nixos-22.11"
+        );
+        assert!(nixpkgs_archive_for_channel("stable").is_err());
+    }
+
+    #[test]
+    fn test_create_nix_flake_for_phases() {
+        let mut setup = Phase::setup(Some(vec![Pkg::new("nodejs")]));
+        setup.nixpkgs_archive = Some("abc123".to_string());
+        let phases = Phases::from([("setup".to_string(), setup)]);
+
+        let flake = create_nix_flake_for_phases(&phases);
+        assert!(flake.contains("nixpkgs-abc123.url = \"github:NixOS/nixpkgs/abc123\";"));
+        assert!(flake.contains("\"env-nixpkgs-abc123\" = ("));
+        assert!(flake.contains("import inputs.nixpkgs-abc123 { inherit system; overlays = [  ]; }"));
+        assert!(!flake.contains("fetchTarball"));
+        assert_eq!(
+            flake_package_names_for_phases(&phases),
+            vec!["env-nixpkgs-abc123".to_string()]
+        );
+        assert!(!flake_requires_impure(&phases));
+    }
+
+    #[test]
+    fn test_create_nix_shell_for_phases() {
+        let mut setup = Phase::setup(Some(vec![Pkg::new("nodejs")]));
+        setup.nixpkgs_archive = Some("abc123".to_string());
+        let phases = Phases::from([("setup".to_string(), setup)]);
+
+        let shell = create_nix_shell_for_phases(&phases);
+        assert!(shell.contains("env-nixpkgs-abc123 = ("));
+        assert!(shell.contains("nixpkgs/archive/abc123.tar.gz"));
+        assert!(shell.contains("buildInputs = [ env-nixpkgs-abc123 ];"));
+        assert!(shell.contains("source ${env-nixpkgs-abc123}/etc/profile.d/abc123-env.sh"));
+    }
+
+    #[test]
+    fn test_missing_packages_expression() {
+        let mut phase = Phase::new("setup");
+        assert_eq!(missing_packages_expression(&phase), None);
+
+        phase.add_nix_pkgs(&[Pkg::new("nodejs_18"), Pkg::new("python311Packages.pip")]);
+        phase.add_nix_pkgs(&[Pkg::new("php").set_override("php", "php81")]);
+        let expression = missing_packages_expression(&phase).unwrap();
+
+        assert!(expression.contains(&format!("nixpkgs/archive/{NIXPKGS_ARCHIVE}.tar.gz")));
+        assert!(expression.ends_with("[ \"nodejs_18\" \"python311Packages.pip\" ]"));
+    }
+
+    #[test]
+    fn test_app_nix_install_cmds() {
+        assert_eq!(
+            app_nix_install_cmds("./default.nix", Some(&"abc123".to_string())),
+            "COPY default.nix default.nix\nRUN nix-env -I nixpkgs=https://github.com/NixOS/nixpkgs/archive/abc123.tar.gz -if default.nix && nix-collect-garbage -d"
+        );
+        assert!(app_nix_install_cmds("shell.nix", None)
+            .contains("let shell = import ./shell.nix; env = if builtins.isFunction shell"));
+        assert_eq!(
+            app_nix_install_cmds("nix/flake.nix", None),
+            "COPY nix/flake.nix nix/flake.lock* nix/\nRUN nix --extra-experimental-features 'nix-command flakes' build --no-link --print-out-paths 'path:./nix#' | xargs nix-env -i && nix-collect-garbage -d"
+        );
+    }
+
+    /// Phases that use the default archive, a pinned archive, libraries, and overlays.
+    fn snapshot_phases() -> Phases {
+        let mut setup = Phase::setup(Some(vec![Pkg::new("nodejs_18"), Pkg::new("npm-9_x")]));
+        setup.add_pkgs_libs(vec!["stdenv.cc.cc.lib".to_string()]);
+
+        let mut pinned = Phase::setup(Some(vec![
+            Pkg::new("python311"),
+            Pkg::new("php").set_override("php", "php81"),
+        ]));
+        pinned.name = Some("pinned".to_string());
+        pinned.add_pkgs_libs(vec!["openssl".to_string()]);
+        pinned.nixpkgs_archive = Some("bf744fe90419885eefced41b3e5ae442d732712d".to_string());
+        pinned.nix_overlays = Some(vec![
+            "https://github.com/oxalica/rust-overlay/archive/master.tar.gz".to_string(),
+            "self: super: { hello = super.cowsay; }".to_string(),
+        ]);
+
+        Phases::from([("setup".to_string(), setup), ("pinned".to_string(), pinned)])
+    }
+
+    #[test]
+    fn test_nix_expressions_snapshot() {
+        let expressions = create_nix_expressions_for_phases(&snapshot_phases())
+            .into_iter()
+            .map(|(name, expression)| format!("# {name}\n{expression}"))
+            .collect::<Vec<_>>()
+            .join("\n");
+        insta::assert_snapshot!(expressions.replace(NIXPKGS_ARCHIVE, "[archive]"));
+    }
+
+    #[test]
+    fn test_nix_shell_snapshot() {
+        insta::assert_snapshot!(
+            create_nix_shell_for_phases(&snapshot_phases()).replace(NIXPKGS_ARCHIVE, "[archive]")
+        );
+    }
+
+    #[test]
+    fn test_nix_flake_snapshot() {
+        insta::assert_snapshot!(
+            create_nix_flake_for_phases(&snapshot_phases()).replace(NIXPKGS_ARCHIVE, "[archive]")
+        );
+    }
 }
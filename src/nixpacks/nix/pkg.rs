@@ -1,13 +1,17 @@
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 
 /// Represents a Nix package, any derivation overrides for it, and the nixpkgs overlay to fetch it from.
 #[serde_with::skip_serializing_none]
 #[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Debug)]
 pub struct Pkg {
+    /// Attribute path of the package in nixpkgs, such as `nodePackages.pnpm`.
     pub name: String,
     pub overlay: Option<String>,
-    pub overrides: Option<HashMap<String, String>>,
+    /// Arguments the package is called with, changed with `override`.
+    pub overrides: Option<BTreeMap<String, String>>,
+    /// Attributes of the derivation, changed with `overrideAttrs`.
+    pub override_attrs: Option<BTreeMap<String, String>>,
 }
 
 impl Pkg {
@@ -16,34 +20,55 @@ impl Pkg {
             name: name.to_string(),
             overrides: None,
             overlay: None,
+            override_attrs: None,
         }
     }
 
+    /// A package at a nested attribute path, such as `["nodePackages", "@angular/cli"]`.
+    ///
+    /// Names that aren't plain Nix identifiers are quoted.
+    pub fn from_attr_path(path: &[&str]) -> Pkg {
+        let name = path
+            .iter()
+            .map(|attr| quote_attr(attr))
+            .collect::<Vec<_>>()
+            .join(".");
+        Pkg::new(&name)
+    }
+
     /// Renders the Pkg as a Nix expression.
     pub fn to_nix_string(&self) -> String {
-        match &self.overrides {
-            Some(overrides) => {
-                let override_string = overrides
-                    .iter()
-                    .map(|(name, value)| format!("{name} = {value};"))
-                    .collect::<Vec<_>>()
-                    .join(" ");
-                format!("({}.override {{ {override_string} }})", self.name)
-            }
-            None => self.name.clone(),
+        let mut expression = self.name.clone();
+        if let Some(overrides) = &self.overrides {
+            expression = format!("({expression}.override {{ {} }})", nix_bindings(overrides));
+        }
+        if let Some(attrs) = &self.override_attrs {
+            expression = format!(
+                "({expression}.overrideAttrs (old: {{ {} }}))",
+                nix_bindings(attrs)
+            );
         }
+
+        expression
     }
 
     /// Add desired overrides on the derivation for the given package.
     #[must_use]
     pub fn set_override(mut self, name: &str, pkg: &str) -> Self {
-        if let Some(mut overrides) = self.overrides {
-            overrides.insert(name.to_string(), pkg.to_string());
-            self.overrides = Some(overrides);
-        } else {
-            self.overrides = Some(HashMap::from([(name.to_string(), pkg.to_string())]));
-        }
+        self.overrides
+            .get_or_insert_with(BTreeMap::new)
+            .insert(name.to_string(), pkg.to_string());
+        self
+    }
 
+    /// Override an attribute of the derivation, such as `doCheck` to `false`.
+    ///
+    /// The value is a Nix expression, which can refer to the original attributes as `old`.
+    #[must_use]
+    pub fn set_override_attr(mut self, name: &str, value: &str) -> Self {
+        self.override_attrs
+            .get_or_insert_with(BTreeMap::new)
+            .insert(name.to_string(), value.to_string());
         self
     }
 
@@ -56,20 +81,47 @@ impl Pkg {
 
     /// Pretty-print the package and any overrides as a Nix expression.
     pub fn to_pretty_string(&self) -> String {
-        match &self.overrides {
-            Some(overrides) => {
-                let override_string = overrides
-                    .iter()
-                    .map(|(name, value)| format!("{name} = {value}"))
-                    .collect::<Vec<_>>()
-                    .join(", ");
-                format!("{} {{ {override_string} }}", self.name)
-            }
-            None => self.name.clone(),
+        let overrides = self
+            .overrides
+            .iter()
+            .chain(self.override_attrs.iter())
+            .flatten()
+            .map(|(name, value)| format!("{name} = {value}"))
+            .collect::<Vec<_>>();
+        if overrides.is_empty() {
+            self.name.clone()
+        } else {
+            format!("{} {{ {} }}", self.name, overrides.join(", "))
         }
     }
 }
 
+/// Renders attributes as Nix bindings, such as `a = 1; b = 2;`.
+fn nix_bindings(attrs: &BTreeMap<String, String>) -> String {
+    attrs
+        .iter()
+        .map(|(name, value)| format!("{} = {value};", quote_attr(name)))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Quotes an attribute name unless it is a plain Nix identifier.
+fn quote_attr(attr: &str) -> String {
+    let mut chars = attr.chars();
+    let is_identifier = matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || "_'-".contains(c));
+    if is_identifier {
+        attr.to_string()
+    } else {
+        format!(
+            "\"{}\"",
+            attr.replace('\\', "\\\\")
+                .replace('"', "\\\"")
+                .replace("${", "\\${")
+        )
+    }
+}
+
 impl<S> From<S> for Pkg
 where
     S: Into<String>,
@@ -96,4 +148,44 @@ mod tests {
             "(cowsay.override { hello = hello_1.1; })".to_string()
         );
     }
+
+    #[test]
+    fn test_pkg_attr_path_to_string() {
+        assert_eq!(
+            Pkg::from_attr_path(&["nodePackages", "pnpm"]).to_nix_string(),
+            "nodePackages.pnpm"
+        );
+        assert_eq!(
+            Pkg::from_attr_path(&["nodePackages", "@angular/cli"]).to_nix_string(),
+            "nodePackages.\"@angular/cli\""
+        );
+        assert_eq!(
+            Pkg::from_attr_path(&["python311Packages", "pip"]).name,
+            "python311Packages.pip"
+        );
+    }
+
+    #[test]
+    fn test_pkg_overrides_to_string() {
+        let pkg = Pkg::new("python311Packages.uvicorn")
+            .set_override("withWebsockets", "true")
+            .set_override("fetchPypi", "super.fetchPypi")
+            .set_override_attr("doCheck", "false")
+            .set_override_attr("pname", "old.pname + \"-custom\"");
+
+        assert_eq!(
+            pkg.to_nix_string(),
+            "((python311Packages.uvicorn.override { fetchPypi = super.fetchPypi; withWebsockets = true; }).overrideAttrs (old: { doCheck = false; pname = old.pname + \"-custom\"; }))"
+        );
+        assert_eq!(
+            pkg.to_pretty_string(),
+            "python311Packages.uvicorn { fetchPypi = super.fetchPypi, withWebsockets = true, doCheck = false, pname = old.pname + \"-custom\" }"
+        );
+        assert_eq!(
+            Pkg::new("hello")
+                .set_override_attr("doCheck", "false")
+                .to_nix_string(),
+            "(hello.overrideAttrs (old: { doCheck = false; }))"
+        );
+    }
 }
@@ -0,0 +1,214 @@
+use std::collections::BTreeMap;
+
+/// Renders the Nix files that nixpacks generates from templates kept alongside this module.
+///
+/// Templates are plain Nix with two additions, in the style of nixpkgs' `substituteAll`:
+///
+/// - `@name@` is replaced by the values of the variable. Values are joined with spaces, unless the
+///   placeholder is alone on its line, in which case each value goes on its own line. Values that
+///   span several lines keep the indentation of the line they are placed on.
+/// - Lines between `@if name@` and `@end@` are only kept when the variable has a non-empty value.
+///   Sections can be nested, and the marker lines themselves are never output.
+///
+/// Variables that are never set render as empty.
+#[derive(Debug, Clone)]
+pub struct NixTemplate<'a> {
+    source: &'a str,
+    vars: BTreeMap<&'a str, Vec<String>>,
+}
+
+impl<'a> NixTemplate<'a> {
+    pub fn new(source: &'a str) -> Self {
+        NixTemplate {
+            source,
+            vars: BTreeMap::new(),
+        }
+    }
+
+    /// Sets a variable to a single value, where an empty value disables its sections.
+    #[must_use]
+    pub fn set<S: Into<String>>(self, name: &'a str, value: S) -> Self {
+        let value = value.into();
+        let values = if value.is_empty() {
+            Vec::new()
+        } else {
+            vec![value]
+        };
+        self.set_list(name, values)
+    }
+
+    /// Sets a variable to a list of values, such as packages or overlays.
+    #[must_use]
+    pub fn set_list(mut self, name: &'a str, values: Vec<String>) -> Self {
+        self.vars.insert(name, values);
+        self
+    }
+
+    pub fn render(&self) -> String {
+        // Whether each enclosing section is kept
+        let mut sections: Vec<bool> = Vec::new();
+        let mut output = Vec::new();
+
+        for line in self.source.lines() {
+            let trimmed = line.trim();
+            if let Some(name) = section_start(trimmed) {
+                let enabled = sections.iter().all(|kept| *kept) && self.is_set(name);
+                sections.push(enabled);
+                continue;
+            }
+            if trimmed == "@end@" {
+                sections.pop();
+                continue;
+            }
+            if sections.iter().all(|kept| *kept) {
+                output.extend(self.render_line(line));
+            }
+        }
+
+        let mut rendered = output.join("\n");
+        if self.source.ends_with('\n') {
+            rendered.push('\n');
+        }
+        rendered
+    }
+
+    fn is_set(&self, name: &str) -> bool {
+        self.vars
+            .get(name)
+            .map_or(false, |values| !values.is_empty())
+    }
+
+    /// Substitutes the placeholders of a line, which can expand to any number of lines.
+    fn render_line(&self, line: &str) -> Vec<String> {
+        let indent = &line[..line.len() - line.trim_start().len()];
+        let trimmed = line.trim();
+
+        // A placeholder on its own line puts each value on a line of its own
+        if let Some(name) = placeholder_name(trimmed) {
+            if trimmed.len() == name.len() + 2 {
+                return self
+                    .vars
+                    .get(name)
+                    .into_iter()
+                    .flatten()
+                    .map(|value| format!("{indent}{}", indent_continuation(value, indent)))
+                    .collect();
+            }
+        }
+
+        let mut rendered = String::new();
+        let mut rest = line;
+        while let Some(start) = rest.find('@') {
+            rendered.push_str(&rest[..start]);
+            let candidate = &rest[start..];
+            if let Some(name) = placeholder_name(candidate) {
+                let value = self
+                    .vars
+                    .get(name)
+                    .map(|values| values.join(" "))
+                    .unwrap_or_default();
+                rendered.push_str(&indent_continuation(&value, indent));
+                rest = &candidate[name.len() + 2..];
+            } else {
+                rendered.push('@');
+                rest = &candidate[1..];
+            }
+        }
+        rendered.push_str(rest);
+        vec![rendered]
+    }
+}
+
+/// The variable of an `@if name@` line.
+fn section_start(line: &str) -> Option<&str> {
+    line.strip_prefix("@if ")
+        .and_then(|rest| rest.strip_suffix('@'))
+        .filter(|name| is_variable_name(name))
+}
+
+/// The variable of a placeholder at the start of `s`, e.g. `name` for `@name@ ...`.
+///
+/// Anything else starting with `@`, such as the `@inputs` of a flake's outputs, is not a placeholder.
+fn placeholder_name(s: &str) -> Option<&str> {
+    let rest = s.strip_prefix('@')?;
+    let end = rest.find('@')?;
+    let name = &rest[..end];
+    if is_variable_name(name) {
+        Some(name)
+    } else {
+        None
+    }
+}
+
+fn is_variable_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_lowercase() || c == '_')
+}
+
+/// Indents the lines of a value after the first, which continues the line it is placed on.
+fn indent_continuation(value: &str, indent: &str) -> String {
+    value
+        .lines()
+        .enumerate()
+        .map(|(i, line)| {
+            if i == 0 || line.is_empty() {
+                line.to_string()
+            } else {
+                format!("{indent}{line}")
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_placeholders() {
+        let template = NixTemplate::new("paths = [ @pkgs@ ];\n  list = [\n    @pkgs@\n  ];\n")
+            .set_list("pkgs", vec!["nodejs".to_string(), "python3".to_string()]);
+        assert_eq!(
+            template.render(),
+            "paths = [ nodejs python3 ];\n  list = [\n    nodejs\n    python3\n  ];\n"
+        );
+
+        // Unset variables are empty, and values on their own lines are left out entirely
+        assert_eq!(
+            NixTemplate::new("[ @pkgs@ ]\n  @pkgs@\n{ self }@inputs: x@y").render(),
+            "[  ]\n{ self }@inputs: x@y"
+        );
+
+        // Multi-line values keep the indentation of their line
+        let template = NixTemplate::new("  overlays = [ @overlays@ ];\n    @overlays@")
+            .set("overlays", "(self: super: {\n  hello = super.cowsay;\n})");
+        assert_eq!(
+            template.render(),
+            "  overlays = [ (self: super: {\n    hello = super.cowsay;\n  }) ];\n    (self: super: {\n      hello = super.cowsay;\n    })"
+        );
+    }
+
+    #[test]
+    fn test_render_sections() {
+        let source = "start\n@if libs@\n  libs = @libs@;\n  @if openssl@\n  openssl = @openssl@;\n  @end@\n@end@\nend";
+
+        assert_eq!(NixTemplate::new(source).render(), "start\nend");
+        assert_eq!(
+            NixTemplate::new(source)
+                .set("openssl", "openssl_3")
+                .render(),
+            "start\nend"
+        );
+        assert_eq!(
+            NixTemplate::new(source).set("libs", "zlib").render(),
+            "start\n  libs = zlib;\nend"
+        );
+        assert_eq!(
+            NixTemplate::new(source)
+                .set("libs", "zlib")
+                .set("openssl", "openssl_3")
+                .render(),
+            "start\n  libs = zlib;\n  openssl = openssl_3;\nend"
+        );
+    }
+}
@@ -0,0 +1,55 @@
+use super::{BuildPlan, NIX_PROFILE};
+
+pub const CACERT_PKG: &str = "cacert";
+
+/// The variables that OpenSSL, and Nix's patched builds of it, read the CA bundle from.
+const CERT_FILE_VARIABLES: &[&str] = &["SSL_CERT_FILE", "NIX_SSL_CERT_FILE"];
+
+/// The CA bundle from the `cacert` package.
+pub fn ca_bundle_path() -> String {
+    format!("{NIX_PROFILE}/etc/ssl/certs/ca-bundle.crt")
+}
+
+impl BuildPlan {
+    /// Install Mozilla's CA certificates and point OpenSSL at them, so HTTPS requests from the app
+    /// don't depend on the certificates of the image it runs on.
+    ///
+    /// Variables that are already set, such as a bundle with a private CA, are kept.
+    pub fn add_ca_certificates(&mut self) {
+        self.add_setup_pkg(CACERT_PKG);
+
+        let variables = self.variables.get_or_insert_with(Default::default);
+        for name in CERT_FILE_VARIABLES {
+            variables
+                .entry((*name).to_string())
+                .or_insert_with(ca_bundle_path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn test_add_ca_certificates() {
+        let mut plan = BuildPlan::default();
+        plan.add_variables(BTreeMap::from([(
+            "SSL_CERT_FILE".to_string(),
+            "/app/certs/internal.pem".to_string(),
+        )]));
+        plan.add_ca_certificates();
+
+        assert_eq!(
+            plan.get_phase("setup").unwrap().nix_pkgs,
+            Some(vec!["...".to_string(), CACERT_PKG.to_string()])
+        );
+        let variables = plan.variables.unwrap();
+        assert_eq!(variables["SSL_CERT_FILE"], "/app/certs/internal.pem");
+        assert_eq!(
+            variables["NIX_SSL_CERT_FILE"],
+            "/nix/var/nix/profiles/default/etc/ssl/certs/ca-bundle.crt"
+        );
+    }
+}
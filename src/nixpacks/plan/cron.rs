@@ -0,0 +1,145 @@
+use super::BuildPlan;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Runs the jobs, and unlike cron it keeps the container's environment and logs to stdout.
+pub const SUPERCRONIC_PKG: &str = "supercronic";
+
+/// Where the crontab is copied to in the image.
+pub const CRONTAB_PATH: &str = "/nixpacks/crontab";
+
+/// The process that only runs the jobs, for running them in a container of their own.
+pub const CRON_PROCESS: &str = "cron";
+
+const SCHEDULE_SHORTCUTS: &[&str] = &[
+    "@yearly",
+    "@annually",
+    "@monthly",
+    "@weekly",
+    "@daily",
+    "@midnight",
+    "@hourly",
+];
+
+/// A command that runs on a schedule alongside the app.
+#[derive(PartialEq, Eq, Serialize, Deserialize, Default, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct CronJob {
+    /// A cron expression such as `*/15 * * * *`, or a shortcut such as `@daily`.
+    pub schedule: String,
+    pub cmd: String,
+}
+
+impl CronJob {
+    pub fn new<S: Into<String>>(schedule: S, cmd: S) -> CronJob {
+        CronJob {
+            schedule: schedule.into(),
+            cmd: cmd.into(),
+        }
+    }
+}
+
+pub type CronJobs = BTreeMap<String, CronJob>;
+
+impl BuildPlan {
+    pub fn has_cron_jobs(&self) -> bool {
+        self.cron.as_ref().map_or(false, |jobs| !jobs.is_empty())
+    }
+
+    /// Installs the scheduler when the plan has jobs for it to run.
+    pub fn add_cron_scheduler(&mut self) {
+        if self.has_cron_jobs() {
+            self.add_setup_pkg(SUPERCRONIC_PKG);
+        }
+    }
+
+    /// The crontab for the scheduler, with a comment naming each job.
+    pub fn get_crontab(&self) -> Option<String> {
+        let jobs = self.cron.as_ref().filter(|jobs| !jobs.is_empty())?;
+        Some(
+            jobs.iter()
+                .map(|(name, job)| {
+                    format!("# {name}\n{} {}\n", job.schedule.trim(), job.cmd.trim())
+                })
+                .collect::<Vec<_>>()
+                .concat(),
+        )
+    }
+}
+
+/// Checks that a schedule is a shortcut, `@every <duration>`, or a cron expression with 5 to 7 fields.
+pub fn check_schedule(schedule: &str) -> Result<(), String> {
+    let schedule = schedule.trim();
+    if SCHEDULE_SHORTCUTS.contains(&schedule) || schedule.starts_with("@every ") {
+        return Ok(());
+    }
+
+    let fields = schedule.split_whitespace().collect::<Vec<_>>();
+    if !(5..=7).contains(&fields.len()) {
+        return Err(format!(
+            "`{schedule}` is not a cron expression, which has 5 fields such as `*/15 * * * *`"
+        ));
+    }
+    let is_valid_field = |field: &&str| {
+        field
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "*/,-?#".contains(c))
+    };
+    if let Some(field) = fields.iter().find(|field| !is_valid_field(field)) {
+        return Err(format!("`{field}` in `{schedule}` is not a valid field"));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cron_from_toml() {
+        let mut plan = BuildPlan::from_toml(
+            r#"
+            [cron.cleanup]
+            schedule = "0 * * * *"
+            cmd = "python manage.py clearsessions"
+
+            [cron.report]
+            schedule = "@daily"
+            cmd = "python manage.py send_report"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            plan.get_crontab().unwrap(),
+            "# cleanup\n0 * * * * python manage.py clearsessions\n# report\n@daily python manage.py send_report\n"
+        );
+
+        plan.add_cron_scheduler();
+        assert_eq!(
+            plan.get_phase("setup").unwrap().nix_pkgs,
+            Some(vec!["...".to_string(), SUPERCRONIC_PKG.to_string()])
+        );
+    }
+
+    #[test]
+    fn test_no_cron_jobs() {
+        let mut plan = BuildPlan::default();
+        plan.add_cron_scheduler();
+        assert_eq!(plan.get_crontab(), None);
+        assert!(plan.get_phase("setup").is_none());
+    }
+
+    #[test]
+    fn test_check_schedule() {
+        assert!(check_schedule("*/15 * * * *").is_ok());
+        assert!(check_schedule("0 9 * * MON-FRI").is_ok());
+        assert!(check_schedule("*/30 * * * * * *").is_ok());
+        assert!(check_schedule("@hourly").is_ok());
+        assert!(check_schedule("@every 5m").is_ok());
+        assert!(check_schedule("* * * *").is_err());
+        assert!(check_schedule("@sometimes").is_err());
+        assert!(check_schedule("0 * * * * ; rm").is_err());
+    }
+}
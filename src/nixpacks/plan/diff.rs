@@ -0,0 +1,281 @@
+use super::BuildPlan;
+use anyhow::Result;
+use colored::Colorize;
+use serde_json::Value;
+use std::fmt;
+
+/// A value in a build plan that differs between two plans, such as `phases.setup.nixPkgs`.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct PlanChange {
+    pub path: String,
+    pub removed: Vec<String>,
+    pub added: Vec<String>,
+}
+
+impl fmt::Display for PlanChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}", self.path.bold())?;
+        for value in &self.removed {
+            writeln!(f, "{}", format!("  - {value}").red())?;
+        }
+        for value in &self.added {
+            writeln!(f, "{}", format!("  + {value}").green())?;
+        }
+        Ok(())
+    }
+}
+
+impl BuildPlan {
+    /// The values that change when going from this plan to `other`, such as added packages or changed commands.
+    pub fn diff(&self, other: &BuildPlan) -> Result<Vec<PlanChange>> {
+        let mut old = self.clone();
+        let mut new = other.clone();
+        old.remove_phase_names();
+        new.remove_phase_names();
+
+        let mut changes = Vec::new();
+        diff_values(
+            "",
+            &serde_json::to_value(old)?,
+            &serde_json::to_value(new)?,
+            false,
+            &mut changes,
+        );
+        Ok(changes)
+    }
+
+    /// The values that this plan, such as a committed config file, pins and that `detected` now generates differently.
+    ///
+    /// Values the plan leaves out, and lists that extend the detected ones with `...`, are left to detection and aren't compared.
+    pub fn drift(&self, detected: &BuildPlan) -> Result<Vec<PlanChange>> {
+        let mut pinned = self.clone();
+        let mut detected = detected.clone();
+        pinned.remove_phase_names();
+        detected.remove_phase_names();
+
+        let mut changes = Vec::new();
+        diff_values(
+            "",
+            &serde_json::to_value(pinned)?,
+            &serde_json::to_value(detected)?,
+            true,
+            &mut changes,
+        );
+        Ok(changes)
+    }
+}
+
+fn diff_values(
+    path: &str,
+    old: &Value,
+    new: &Value,
+    pinned_only: bool,
+    changes: &mut Vec<PlanChange>,
+) {
+    if old == new {
+        return;
+    }
+    if pinned_only && (old.is_null() || is_extended_list(old)) {
+        return;
+    }
+
+    match (old, new) {
+        (Value::Object(_), _) | (_, Value::Object(_)) => {
+            let empty = serde_json::Map::new();
+            let old = old.as_object().unwrap_or(&empty);
+            let new = new.as_object().unwrap_or(&empty);
+
+            let mut keys = old.keys().chain(new.keys()).collect::<Vec<_>>();
+            keys.sort();
+            keys.dedup();
+
+            for key in keys {
+                let key_path = if path.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{path}.{key}")
+                };
+                diff_values(
+                    &key_path,
+                    old.get(key).unwrap_or(&Value::Null),
+                    new.get(key).unwrap_or(&Value::Null),
+                    pinned_only,
+                    changes,
+                );
+            }
+        }
+        (Value::Array(_), _) | (_, Value::Array(_)) => {
+            let old = as_strings(old);
+            let new = as_strings(new);
+            let removed = old
+                .iter()
+                .filter(|value| !new.contains(value))
+                .cloned()
+                .collect::<Vec<_>>();
+            let added = new
+                .iter()
+                .filter(|value| !old.contains(value))
+                .cloned()
+                .collect::<Vec<_>>();
+
+            // Only the order changed, which matters for commands
+            let (removed, added) = if removed.is_empty() && added.is_empty() {
+                (vec![old.join(", ")], vec![new.join(", ")])
+            } else {
+                (removed, added)
+            };
+
+            changes.push(PlanChange {
+                path: path.to_string(),
+                removed,
+                added,
+            });
+        }
+        _ => changes.push(PlanChange {
+            path: path.to_string(),
+            removed: as_strings(old),
+            added: as_strings(new),
+        }),
+    }
+}
+
+/// Whether a list adds to the values from providers, rather than replacing them.
+fn is_extended_list(value: &Value) -> bool {
+    value.as_array().map_or(false, |values| {
+        values.iter().any(|v| v == "..." || v == "@auto")
+    })
+}
+
+fn as_strings(value: &Value) -> Vec<String> {
+    match value {
+        Value::Null => Vec::new(),
+        Value::String(s) => vec![s.clone()],
+        Value::Array(values) => values.iter().flat_map(as_strings).collect(),
+        value => vec![value.to_string()],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diff() {
+        let old = BuildPlan::from_toml(
+            r#"
+            [variables]
+            NODE_ENV = "production"
+
+            [phases.setup]
+            nixPkgs = ["nodejs_18", "npm-9_x"]
+
+            [phases.build]
+            cmds = ["npm run build"]
+
+            [start]
+            cmd = "npm start"
+            "#,
+        )
+        .unwrap();
+        let new = BuildPlan::from_toml(
+            r#"
+            [variables]
+            NODE_ENV = "production"
+            CI = "true"
+
+            [phases.setup]
+            nixPkgs = ["nodejs_20", "npm-9_x"]
+
+            [phases.build]
+            cmds = ["npm run build"]
+
+            [phases.install]
+            cmds = ["npm ci"]
+
+            [start]
+            cmd = "npm start"
+            "#,
+        )
+        .unwrap();
+
+        assert!(old.diff(&old).unwrap().is_empty());
+        assert_eq!(
+            old.diff(&new).unwrap(),
+            vec![
+                PlanChange {
+                    path: "phases.install.cmds".to_string(),
+                    removed: vec![],
+                    added: vec!["npm ci".to_string()],
+                },
+                PlanChange {
+                    path: "phases.setup.nixPkgs".to_string(),
+                    removed: vec!["nodejs_18".to_string()],
+                    added: vec!["nodejs_20".to_string()],
+                },
+                PlanChange {
+                    path: "variables.CI".to_string(),
+                    removed: vec![],
+                    added: vec!["true".to_string()],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_drift() {
+        let committed = BuildPlan::from_toml(
+            r#"
+            [phases.setup]
+            nixPkgs = ["nodejs_18"]
+
+            [phases.build]
+            cmds = ["...", "npm run docs"]
+
+            [start]
+            cmd = "npm start"
+            "#,
+        )
+        .unwrap();
+        let detected = BuildPlan::from_toml(
+            r#"
+            [variables]
+            CI = "true"
+
+            [phases.setup]
+            nixPkgs = ["nodejs_20"]
+
+            [phases.build]
+            cmds = ["npm run build"]
+
+            [start]
+            cmd = "npm start"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            committed.drift(&detected).unwrap(),
+            vec![PlanChange {
+                path: "phases.setup.nixPkgs".to_string(),
+                removed: vec!["nodejs_18".to_string()],
+                added: vec!["nodejs_20".to_string()],
+            }]
+        );
+        assert!(detected.drift(&detected).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_diff_reordered_commands() {
+        let old = BuildPlan::from_toml("[phases.build]\ncmds = ['a', 'b']").unwrap();
+        let new = BuildPlan::from_toml("[phases.build]\ncmds = ['b', 'a']").unwrap();
+
+        assert_eq!(
+            old.diff(&new).unwrap(),
+            vec![PlanChange {
+                path: "phases.build.cmds".to_string(),
+                removed: vec!["a, b".to_string()],
+                added: vec!["b, a".to_string()],
+            }]
+        );
+    }
+}
@@ -1,12 +1,20 @@
-use std::path::Path;
+use rayon::prelude::*;
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
 
 use crate::{
     nixpacks::{
         app::App,
         environment::{Environment, EnvironmentVariables},
-        plan::{BuildPlan, PlanGenerator},
+        heroku::HerokuConfig,
+        logger::Logger,
+        nix::{nixpkgs_archive_for_channel, APP_NIX_FILE_NAMES},
+        plan::{BuildPlan, PlanGenerator, PlanMetadata, ProviderInfo},
     },
-    providers::{procfile::ProcfileProvider, Provider},
+    providers::{procfile::ProcfileProvider, Confidence, Provider},
 };
 use anyhow::{bail, Context, Ok, Result};
 
@@ -17,17 +25,39 @@ use super::{
 
 const NIXPACKS_METADATA: &str = "NIXPACKS_METADATA";
 
+/// The providers that detect an app and how confident they are, from the most confident to the least.
+type DetectedProviders = Vec<(String, Confidence)>;
+
 /// Holds plan options defined in config files or existing build plans.
 #[derive(Clone, Default, Debug)]
+#[allow(clippy::struct_excessive_bools)]
 pub struct GeneratePlanOptions {
     pub plan: Option<BuildPlan>,
     pub config_file: Option<String>,
+    /// nixpkgs revision or branch that overrides the one chosen by providers.
+    pub nixpkgs_archive: Option<String>,
+    /// Use every provider that detects the app instead of only the first one.
+    pub detect_all_providers: bool,
+    /// Use the `flake.nix`, `default.nix`, or `shell.nix` in the app as the environment.
+    pub use_app_nix: bool,
+    /// Directory inside the app source that holds the app, for monorepos.
+    pub subdir: Option<String>,
+    /// Leave out the CA certificates that are installed in every image.
+    pub no_cacert: bool,
+    /// Run every process in one container with a process manager.
+    pub process_manager: bool,
+    /// Leave out the app's config file, to compare what is detected with a plan committed as the config file.
+    pub no_config_file: bool,
 }
 
 /// Holds plan options and providers for a build.
 pub struct NixpacksBuildPlanGenerator<'a> {
-    providers: &'a [&'a (dyn Provider)],
+    providers: &'a [&'a dyn Provider],
     config: GeneratePlanOptions,
+    /// Where warnings are logged during a build. Without one they are written to stderr.
+    logger: Option<&'a Logger>,
+    /// The providers that detect each app with the variables they were run with, so detection only runs once.
+    detected: Mutex<HashMap<(PathBuf, EnvironmentVariables), DetectedProviders>>,
 }
 
 /// NixpacksBuildPlanGenerators produce build plans using the options and providers they contain.
@@ -42,85 +72,290 @@ impl PlanGenerator for NixpacksBuildPlanGenerator<'_> {
     /// Combine detected providers with providers specified in config files, environment variables, and CLI arguments.
     fn get_plan_providers(&self, app: &App, env: &Environment) -> Result<Vec<String>> {
         let plan_before_providers = self.get_plan_before_providers(app, env)?;
-        let providers = self.get_all_providers(app, env, plan_before_providers.providers)?;
+        // Providers are detected with the same variables as when the plan is generated
+        let env = get_plan_environment(&HerokuConfig::from_app(app)?, env, &plan_before_providers);
+        let providers = self.get_all_providers(app, &env, plan_before_providers.providers)?;
 
         Ok(providers)
     }
 }
 
-impl NixpacksBuildPlanGenerator<'_> {
-    pub fn new<'a>(
-        providers: &'a [&'a (dyn Provider)],
+/// Stacks the runtime and build-only variables of a plan.
+///
+/// The defaults set by providers, such as `NODE_ENV=production`, have the lowest precedence. They are overridden by
+/// variables passed with `--env`, which are in turn overridden by the variables of the config file and CLI plan.
+/// Build-only defaults are dropped when the user sets a variable of the same name, so it is also used while building.
+fn get_variables(
+    provider_plan: &BuildPlan,
+    user_plan: &BuildPlan,
+    env: &Environment,
+) -> (EnvironmentVariables, EnvironmentVariables) {
+    let mut user_variables = Environment::clone_variables(env);
+    user_variables.extend(user_plan.variables.clone().unwrap_or_default());
+
+    let mut variables = provider_plan.variables.clone().unwrap_or_default();
+    variables.extend(user_variables.clone());
+
+    let mut build_variables = provider_plan.build_variables.clone().unwrap_or_default();
+    build_variables.retain(|name, _| !user_variables.contains_key(name));
+    build_variables.extend(user_plan.build_variables.clone().unwrap_or_default());
+
+    (variables, build_variables)
+}
+
+/// The variables a plan is generated with.
+///
+/// The app.json variables are defaults for the variables passed with `--env`, and the nixpacks.toml adds to both.
+fn get_plan_environment(
+    heroku: &HerokuConfig,
+    env: &Environment,
+    plan_before_providers: &BuildPlan,
+) -> Environment {
+    let heroku_env = Environment::append_variables(
+        &Environment::new(heroku.variables.clone()),
+        Environment::clone_variables(env),
+    );
+    Environment::append_variables(
+        &heroku_env,
+        plan_before_providers.variables.clone().unwrap_or_default(),
+    )
+}
+
+/// A warning when the used provider was only picked over another by its place in the provider list.
+pub fn get_provider_tie_warning(
+    providers: &[(String, Confidence)],
+    used: &[String],
+) -> Option<String> {
+    match providers {
+        [(first, confidence), (second, tied), ..]
+            if confidence == tied && used.contains(first) && !used.contains(second) =>
+        {
+            Some(format!("{first} and {second} detect the app with {confidence} confidence, and {first} is used because it comes first. Set `providers` in nixpacks.toml to choose"))
+        }
+        _ => None,
+    }
+}
+
+impl<'a> NixpacksBuildPlanGenerator<'a> {
+    pub fn new(
+        providers: &'a [&'a dyn Provider],
         config: GeneratePlanOptions,
     ) -> NixpacksBuildPlanGenerator<'a> {
-        NixpacksBuildPlanGenerator { providers, config }
+        NixpacksBuildPlanGenerator {
+            providers,
+            config,
+            logger: None,
+            detected: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Log warnings with the build's logger, so they are part of JSON logs.
+    #[must_use]
+    pub fn with_logger(mut self, logger: &'a Logger) -> Self {
+        self.logger = Some(logger);
+        self
+    }
+
+    fn warn(&self, warning: &str) {
+        match self.logger {
+            Some(logger) => logger.log_warning(warning),
+            None => eprintln!("Warning: {warning}"),
+        }
     }
 
     /// Get a build plan from the provider and by applying a config from the environment.
     fn get_build_plan(&self, app: &App, env: &Environment) -> Result<(BuildPlan, App)> {
         let plan_before_providers = self.get_plan_before_providers(app, env)?;
+        let heroku = HerokuConfig::from_app(app)?;
+        for warning in heroku.get_warnings(env) {
+            self.warn(&warning);
+        }
 
-        // Add the variables from the nixpacks.toml to environment
-        let new_env = &Environment::append_variables(
-            env,
-            plan_before_providers.variables.clone().unwrap_or_default(),
-        );
+        let new_env = &get_plan_environment(&heroku, env, &plan_before_providers);
 
-        let provider_plan =
+        let mut provider_plan =
             self.get_plan_from_providers(app, new_env, plan_before_providers.providers.clone())?;
+        provider_plan.add_build_variables(heroku.build_variables.clone());
 
         let procfile_plan = (ProcfileProvider {})
             .get_build_plan(app, new_env)?
             .unwrap_or_default();
+        let heroku_plan = heroku.to_plan(new_env);
+
+        let (variables, build_variables) =
+            get_variables(&provider_plan, &plan_before_providers, new_env);
+        let mut plan = BuildPlan::merge_plans(&[
+            provider_plan,
+            procfile_plan,
+            heroku_plan,
+            plan_before_providers,
+        ]);
+        plan.variables = Some(variables).filter(|vars| !vars.is_empty());
+        plan.build_variables = Some(build_variables).filter(|vars| !vars.is_empty());
+
+        plan.interpolate_variables(new_env);
+
+        let variables = plan.variables.clone().unwrap_or_default();
+        if let Some(start) = plan.start_phase.as_mut() {
+            start.detect_port(&variables);
+        }
 
-        let mut plan =
-            BuildPlan::merge_plans(&vec![provider_plan, procfile_plan, plan_before_providers]);
+        if plan.nix_file.is_none() {
+            plan.nix_file = self.get_app_nix_file(app, new_env)?;
+        }
 
-        if !new_env.get_variable_names().is_empty() {
-            plan.add_variables(Environment::clone_variables(new_env));
+        // An app's own Nix file provides its own certificates, and a plan without phases is retried in a subdirectory
+        let has_phases = !plan.phases.clone().unwrap_or_default().is_empty();
+        if has_phases
+            && plan.nix_file.is_none()
+            && !self.config.no_cacert
+            && !new_env.is_config_variable_truthy("NO_CACERT")
+        {
+            plan.add_ca_certificates();
+        }
+        plan.add_cron_scheduler();
+
+        if self.config.process_manager || new_env.is_config_variable_truthy("PROCESS_MANAGER") {
+            let has_run_image = plan
+                .start_phase
+                .as_ref()
+                .map_or(false, |start| start.run_image.is_some());
+            if has_run_image {
+                self.warn("The process manager can't run in a start.runImage, so only the start command is run");
+            } else if !plan.run_all_processes() {
+                self.warn("The process manager is only used for apps with more than one process");
+            }
+        }
+
+        if let Some(archive) = self.get_nixpkgs_archive(new_env)? {
+            plan.set_nix_archive(&archive);
         }
 
         plan.pin(new_env.is_config_variable_truthy("DEBIAN"));
-        if plan.clone().phases.unwrap_or_default().is_empty() {
+        if plan.clone().phases.unwrap_or_default().is_empty() && plan.nix_file.is_none() {
             // try again in a subdir
             let dir_count = app.paths.clone().iter().filter(|p| p.is_dir()).count();
             if dir_count == 1 {
                 // there is 1 sub dir, try and generate a plan from that
                 let paths = app.paths.clone();
                 let new_dir = paths.iter().find(|p| p.is_dir()).unwrap();
-                return self
-                    .get_build_plan(&App::new(new_dir.display().to_string().as_str())?, env);
+                let app =
+                    App::new(new_dir.display().to_string().as_str())?.with_max_search_depth(env)?;
+                return self.get_build_plan(&app, env);
             }
         }
         Ok((plan, app.clone()))
     }
 
+    /// Find the nixpkgs archive the user asked for, either as a revision or a channel name.
+    fn get_nixpkgs_archive(&self, env: &Environment) -> Result<Option<String>> {
+        if let Some(archive) = &self.config.nixpkgs_archive {
+            return Ok(Some(archive.clone()));
+        }
+        if let Some(archive) = env.get_config_variable("NIXPKGS_ARCHIVE") {
+            return Ok(Some(archive));
+        }
+
+        env.get_config_variable("NIXPKGS_CHANNEL")
+            .map(|channel| nixpkgs_archive_for_channel(&channel))
+            .transpose()
+    }
+
+    /// Find the Nix file in the app to use as the environment, if the user asked for one.
+    fn get_app_nix_file(&self, app: &App, env: &Environment) -> Result<Option<String>> {
+        if !self.config.use_app_nix && !env.is_config_variable_truthy("USE_APP_NIX") {
+            return Ok(None);
+        }
+
+        match APP_NIX_FILE_NAMES
+            .iter()
+            .find(|name| app.includes_file(name))
+        {
+            Some(name) => Ok(Some((*name).to_string())),
+            None => bail!(
+                "Unable to use the app's Nix file, since none of {} were found",
+                APP_NIX_FILE_NAMES.join(", ")
+            ),
+        }
+    }
+
     /// Generate a build plan based on config files, environment variables, and CLI arguments.
     fn get_plan_before_providers(&self, app: &App, env: &Environment) -> Result<BuildPlan> {
+        // Providers for the buildpacks in app.json, unless the config picks them
+        let heroku_plan = BuildPlan {
+            providers: HerokuConfig::from_app(app)?.get_providers(),
+            ..Default::default()
+        };
         let file_plan = self.read_file_plan(app, env)?;
         let env_plan = BuildPlan::from_environment(env);
         let cli_plan = self.config.plan.clone().unwrap_or_default();
-        let plan_before_providers = BuildPlan::merge_plans(&vec![file_plan, env_plan, cli_plan]);
+        let plan_before_providers =
+            BuildPlan::merge_plans(&[heroku_plan, file_plan, env_plan, cli_plan]);
 
         Ok(plan_before_providers)
     }
 
-    /// Use each provider's detect method to determine which providers are needed for the build.
-    fn get_detected_providers(&self, app: &App, env: &Environment) -> Result<Vec<String>> {
-        let mut providers = Vec::new();
+    /// Every provider that detects the app and how confident it is, from the most confident to the least.
+    ///
+    /// Providers are checked concurrently. Providers with the same confidence keep their position in the provider list.
+    /// The result is kept, so the plan, its provider reasons, and the tie warning don't run detection again.
+    pub fn get_matching_providers(
+        &self,
+        app: &App,
+        env: &Environment,
+    ) -> Result<Vec<(String, Confidence)>> {
+        let key = (app.source.clone(), Environment::clone_variables(env));
+        if let Some(providers) = self.detected.lock().unwrap().get(&key) {
+            return Ok(providers.clone());
+        }
 
-        for provider in self.providers {
-            if provider.detect(app, env)? {
-                providers.push(provider.name().to_string());
+        let results = self
+            .providers
+            .par_iter()
+            .map(|provider| provider.detect(app, env))
+            .collect::<Vec<_>>();
 
-                // Only match a single provider... for now
-                break;
+        let mut providers = Vec::new();
+        for (provider, confidence) in self.providers.iter().zip(results) {
+            match confidence {
+                std::result::Result::Ok(confidence) if confidence.is_detected() => {
+                    providers.push((provider.name().to_string(), confidence));
+                }
+                std::result::Result::Ok(_) => {}
+                Err(e) => self.warn_detect_failed(*provider, &e),
             }
         }
+        // The sort is stable, so ties are broken by the order of the provider list
+        providers.sort_by(|(_, a), (_, b)| b.cmp(a));
 
+        self.detected.lock().unwrap().insert(key, providers.clone());
         Ok(providers)
     }
 
+    /// The provider that detects the app with the most confidence, or the first in the list on a tie.
+    fn get_best_provider(&self, app: &App, env: &Environment) -> Result<Option<String>> {
+        Ok(self
+            .get_matching_providers(app, env)?
+            .into_iter()
+            .next()
+            .map(|(name, _)| name))
+    }
+
+    /// Use each provider's detect method to determine which providers are needed for the build.
+    fn get_detected_providers(&self, app: &App, env: &Environment) -> Result<Vec<String>> {
+        // Polyglot apps opt in to combining every matching provider
+        if self.config.detect_all_providers || env.is_config_variable_truthy("DETECT_ALL_PROVIDERS")
+        {
+            return Ok(self
+                .get_matching_providers(app, env)?
+                .into_iter()
+                .map(|(name, _)| name)
+                .collect());
+        }
+
+        Ok(self.get_best_provider(app, env)?.into_iter().collect())
+    }
+
     /// Get a list of providers that will be used to create the plan.
     pub fn get_all_providers(
         &self,
@@ -129,7 +364,7 @@ impl NixpacksBuildPlanGenerator<'_> {
         manual_providers: Option<Vec<String>>,
     ) -> Result<Vec<String>> {
         let detected_providers = self.get_detected_providers(app, env)?;
-        let provider_names = remove_autos_from_vec(
+        let mut provider_names = remove_autos_from_vec(
             fill_auto_in_vec(
                 Some(detected_providers),
                 Some(manual_providers.unwrap_or_else(|| vec!["...".to_string()])),
@@ -137,6 +372,10 @@ impl NixpacksBuildPlanGenerator<'_> {
             .unwrap_or_default(),
         );
 
+        // A provider listed before the "..." is moved ahead of the detected providers rather than used twice
+        let mut seen = HashSet::new();
+        provider_names.retain(|name| seen.insert(name.clone()));
+
         Ok(provider_names)
     }
 
@@ -148,25 +387,55 @@ impl NixpacksBuildPlanGenerator<'_> {
         manual_providers: Option<Vec<String>>,
     ) -> Result<BuildPlan> {
         let provider_names = self.get_all_providers(app, env, manual_providers)?;
+        let detected = self.get_matching_providers(app, env)?;
+        if let Some(warning) = get_provider_tie_warning(&detected, &provider_names) {
+            self.warn(&warning);
+        }
 
         let mut plan = BuildPlan::default();
         let mut count = 0;
 
         let mut metadata = Vec::new();
+        let mut provider_infos = Vec::new();
 
         for name in provider_names {
             let provider = self.providers.iter().find(|p| p.name() == name);
             if let Some(provider) = provider {
                 if let Some(mut provider_plan) = provider.get_build_plan(app, env)? {
+                    if let Some(setup) = provider.get_setup_phase(app, env)? {
+                        provider_plan.add_setup_phase(setup);
+                    }
+                    let static_assets = provider.static_assets(app, env)?;
+                    if !static_assets.is_empty() {
+                        provider_plan.add_static_assets(static_assets);
+                    }
+
                     // All but the first provider have their phases prefixed with their name
                     if count > 0 {
                         provider_plan.prefix_phases(provider.name());
                     }
 
-                    let metadata_string = provider
-                        .metadata(app, env)?
-                        .join_as_comma_separated(provider.name().to_owned());
-                    metadata.push(metadata_string);
+                    let provider_metadata = provider.metadata(app, env)?;
+                    metadata.push(
+                        provider_metadata.join_as_comma_separated(provider.name().to_owned()),
+                    );
+
+                    // Providers can also be used because they were listed in the config
+                    let reason = match provider_metadata.reason {
+                        Some(reason) => Some(reason),
+                        None if !detected
+                            .iter()
+                            .any(|(detected, _)| detected == provider.name()) =>
+                        {
+                            Some("Listed in the providers".to_string())
+                        }
+                        None => None,
+                    };
+                    provider_infos.push(ProviderInfo {
+                        name: provider.name().to_string(),
+                        version: provider_metadata.version,
+                        reason,
+                    });
 
                     plan = BuildPlan::merge(&provider_plan, &plan);
                 }
@@ -183,13 +452,20 @@ impl NixpacksBuildPlanGenerator<'_> {
                 metadata.join(","),
             )]));
         }
+        if !provider_infos.is_empty() {
+            plan.metadata = Some(PlanMetadata {
+                providers: provider_infos,
+            });
+        }
 
         Ok(plan)
     }
 
     /// If a supported config file exists, use it to generate a build plan.
-    fn read_file_plan(&self, app: &App, env: &Environment) -> Result<BuildPlan> {
-        let file_path = if let Some(file_path) = &self.config.config_file {
+    pub fn read_file_plan(&self, app: &App, env: &Environment) -> Result<BuildPlan> {
+        let file_path = if self.config.no_config_file {
+            None
+        } else if let Some(file_path) = &self.config.config_file {
             Some(file_path.clone())
         } else if let Some(env_config_file) = env.get_config_variable("CONFIG_FILE") {
             if !app.includes_file(&env_config_file) {
@@ -230,4 +506,128 @@ impl NixpacksBuildPlanGenerator<'_> {
 
         Ok(plan.unwrap_or_default())
     }
+
+    /// A provider failing to detect the app doesn't stop the others from being used.
+    fn warn_detect_failed(&self, provider: &dyn Provider, error: &anyhow::Error) {
+        self.warn(&format!(
+            "The {} provider failed to detect the app: {error:#}",
+            provider.name()
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::get_providers;
+    use std::{
+        fs,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+    use tempdir::TempDir;
+
+    #[test]
+    fn test_jvm_project_outranks_package_json() -> Result<()> {
+        let dir = TempDir::new("nixpacks-java-node")?;
+        fs::write(dir.path().join("pom.xml"), "<project></project>")?;
+        fs::write(dir.path().join("package.json"), "{}")?;
+        fs::write(dir.path().join("package-lock.json"), "{}")?;
+        let app = App::new(dir.path().to_str().unwrap())?;
+
+        let generator =
+            NixpacksBuildPlanGenerator::new(get_providers(), GeneratePlanOptions::default());
+        let providers = generator.get_matching_providers(&app, &Environment::default())?;
+        assert_eq!(
+            providers[..2],
+            [
+                ("java".to_string(), Confidence::High),
+                ("node".to_string(), Confidence::High)
+            ]
+        );
+        assert!(get_provider_tie_warning(&providers, &["java".to_string()])
+            .unwrap()
+            .starts_with("java and node detect the app with high confidence"));
+        assert!(get_provider_tie_warning(&providers[1..], &["node".to_string()]).is_none());
+        Ok(())
+    }
+
+    struct TestProvider(&'static str, Option<Confidence>);
+
+    impl Provider for TestProvider {
+        fn name(&self) -> &str {
+            self.0
+        }
+
+        fn detect(&self, _app: &App, _env: &Environment) -> Result<Confidence> {
+            match self.1 {
+                Some(confidence) => Ok(confidence),
+                None => bail!("Could not read the app"),
+            }
+        }
+
+        fn get_build_plan(&self, _app: &App, _env: &Environment) -> Result<Option<BuildPlan>> {
+            Ok(None)
+        }
+    }
+
+    #[test]
+    fn test_best_provider() -> Result<()> {
+        let app = App::new("./examples/node-npm")?;
+        let env = Environment::default();
+        let get_best = |providers: &[&dyn Provider]| {
+            NixpacksBuildPlanGenerator::new(providers, GeneratePlanOptions::default())
+                .get_best_provider(&app, &env)
+                .unwrap()
+        };
+
+        let failing = TestProvider("failing", None);
+        let low = TestProvider("low", Some(Confidence::Low));
+        let medium = TestProvider("medium", Some(Confidence::Medium));
+        let high = TestProvider("high", Some(Confidence::High));
+        assert_eq!(
+            get_best(&[&failing, &low, &medium, &high]),
+            Some("high".to_string())
+        );
+        assert_eq!(
+            get_best(&[&low, &failing, &medium]),
+            Some("medium".to_string())
+        );
+        assert_eq!(get_best(&[&failing]), None);
+        Ok(())
+    }
+
+    struct CountingProvider(AtomicUsize);
+
+    impl Provider for CountingProvider {
+        fn name(&self) -> &'static str {
+            "counting"
+        }
+
+        fn detect(&self, _app: &App, _env: &Environment) -> Result<Confidence> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            Ok(Confidence::Medium)
+        }
+
+        fn get_build_plan(&self, _app: &App, _env: &Environment) -> Result<Option<BuildPlan>> {
+            Ok(Some(BuildPlan::default()))
+        }
+    }
+
+    #[test]
+    fn test_providers_detect_once() -> Result<()> {
+        let dir = TempDir::new("nixpacks-detect-once")?;
+        fs::write(dir.path().join("index.txt"), "")?;
+        let app = App::new(dir.path().to_str().unwrap())?;
+        let env = Environment::default();
+
+        let provider = CountingProvider(AtomicUsize::new(0));
+        let providers: &[&dyn Provider] = &[&provider];
+        let mut generator =
+            NixpacksBuildPlanGenerator::new(providers, GeneratePlanOptions::default());
+        let (plan, _) = generator.generate_plan(&app, &env)?;
+        assert_eq!(generator.get_plan_providers(&app, &env)?, vec!["counting"]);
+        assert_eq!(plan.metadata.unwrap().providers[0].reason, None);
+        assert_eq!(provider.0.load(Ordering::SeqCst), 1);
+        Ok(())
+    }
 }
@@ -0,0 +1,104 @@
+use super::{BuildPlan, NIX_PROFILE};
+use crate::nixpacks::environment::{Environment, EnvironmentVariables};
+
+pub const TZDATA_PKG: &str = "tzdata";
+pub const LOCALES_PKG: &str = "glibcLocales";
+
+pub const DEFAULT_TIMEZONE: &str = "Etc/UTC";
+pub const DEFAULT_LOCALE: &str = "en_US.UTF-8";
+
+/// Whether `NIXPACKS_TZDATA` turns off the timezone database that providers add for frameworks that need it.
+pub fn is_tzdata_disabled(env: &Environment) -> bool {
+    matches!(
+        env.get_config_variable("TZDATA").as_deref(),
+        Some("" | "0" | "false")
+    )
+}
+
+/// Points the apps' libc at the Nix timezone database and sets the timezone.
+pub fn timezone_variables(timezone: &str) -> EnvironmentVariables {
+    EnvironmentVariables::from([
+        ("TZ".to_string(), timezone.to_string()),
+        ("TZDIR".to_string(), format!("{NIX_PROFILE}/share/zoneinfo")),
+    ])
+}
+
+/// Points the apps' libc at the Nix locale archive and sets the locale.
+pub fn locale_variables(locale: &str) -> EnvironmentVariables {
+    EnvironmentVariables::from([
+        ("LANG".to_string(), locale.to_string()),
+        (
+            "LOCALE_ARCHIVE".to_string(),
+            format!("{NIX_PROFILE}/lib/locale/locale-archive"),
+        ),
+    ])
+}
+
+impl BuildPlan {
+    /// Install the timezone database, which the base image doesn't have for Nix packages, and set `TZ`.
+    pub fn add_timezone(&mut self, timezone: &str) {
+        self.add_setup_pkg(TZDATA_PKG);
+        self.add_variables(timezone_variables(timezone));
+    }
+
+    /// Install glibc's locales and set `LANG`, for apps that crash or mangle text without a UTF-8 locale.
+    pub fn add_locale(&mut self, locale: &str) {
+        self.add_setup_pkg(LOCALES_PKG);
+        self.add_variables(locale_variables(locale));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_add_timezone_and_locale() {
+        let mut plan = BuildPlan::default();
+        plan.add_timezone("Europe/Berlin");
+        plan.add_locale(DEFAULT_LOCALE);
+
+        assert_eq!(
+            plan.get_phase("setup").unwrap().nix_pkgs,
+            Some(vec![
+                "...".to_string(),
+                TZDATA_PKG.to_string(),
+                LOCALES_PKG.to_string()
+            ])
+        );
+        let variables = plan.variables.unwrap();
+        assert_eq!(variables["TZ"], "Europe/Berlin");
+        assert_eq!(
+            variables["TZDIR"],
+            "/nix/var/nix/profiles/default/share/zoneinfo"
+        );
+        assert_eq!(variables["LANG"], "en_US.UTF-8");
+    }
+
+    #[test]
+    fn test_timezone_from_environment() {
+        let env = Environment::from_envs(vec![
+            "NIXPACKS_PKGS=cowsay",
+            "NIXPACKS_TZDATA=1",
+            "NIXPACKS_LOCALES=0",
+        ])
+        .unwrap();
+        let plan = BuildPlan::from_environment(&env);
+
+        assert_eq!(
+            plan.get_phase("setup").unwrap().nix_pkgs,
+            Some(vec![
+                "cowsay".to_string(),
+                "...".to_string(),
+                TZDATA_PKG.to_string()
+            ])
+        );
+        let variables = plan.variables.unwrap();
+        assert_eq!(variables["TZ"], DEFAULT_TIMEZONE);
+        assert!(!variables.contains_key("LANG"));
+        assert!(!is_tzdata_disabled(&env));
+        assert!(is_tzdata_disabled(
+            &Environment::from_envs(vec!["NIXPACKS_TZDATA=false"]).unwrap()
+        ));
+    }
+}
@@ -1,7 +1,7 @@
 use super::{
     phase::{Phase, StartPhase},
     utils::fill_auto_in_vec,
-    BuildPlan,
+    BuildPlan, Hooks,
 };
 
 /// Types that impl this trait can be pairwise combined.
@@ -17,6 +17,7 @@ impl Mergeable for BuildPlan {
 
         new_plan.providers = fill_auto_in_vec(new_plan.providers.clone(), plan2.providers.clone());
         new_plan.build_image = plan2.build_image.or(new_plan.build_image);
+        new_plan.nix_file = plan2.nix_file.or(new_plan.nix_file);
 
         new_plan.static_assets = match (new_plan.static_assets, plan2.static_assets) {
             (None, assets) | (assets, None) => assets,
@@ -27,6 +28,9 @@ impl Mergeable for BuildPlan {
             }
         };
 
+        new_plan.cache_directories =
+            fill_auto_in_vec(new_plan.cache_directories, plan2.cache_directories);
+
         new_plan.variables = match (new_plan.variables, plan2.variables) {
             (None, vars) | (vars, None) => vars,
             (Some(vars1), Some(vars2)) => {
@@ -36,6 +40,15 @@ impl Mergeable for BuildPlan {
             }
         };
 
+        new_plan.build_variables = match (new_plan.build_variables, plan2.build_variables) {
+            (None, vars) | (vars, None) => vars,
+            (Some(vars1), Some(vars2)) => {
+                let mut vars = vars1;
+                vars.extend(vars2);
+                Some(vars)
+            }
+        };
+
         if new_plan.phases.is_none() {
             new_plan.phases = plan2.phases;
         } else {
@@ -47,7 +60,7 @@ impl Mergeable for BuildPlan {
                         phase.depends_on_phase("setup");
                     } else if name == "build" {
                         phase.depends_on_phase("install");
-                    };
+                    }
 
                     phase
                 });
@@ -55,13 +68,33 @@ impl Mergeable for BuildPlan {
                 let merged_phase = Phase::merge(&phase, &c2_phase);
                 new_plan.add_phase(merged_phase);
             }
-        };
+        }
 
         new_plan.start_phase = match (new_plan.start_phase, plan2.start_phase) {
             (None, s) | (s, None) => s,
             (Some(s1), Some(s2)) => Some(StartPhase::merge(&s1, &s2)),
         };
 
+        new_plan.hooks = match (new_plan.hooks, plan2.hooks) {
+            (None, hooks) | (hooks, None) => hooks,
+            (Some(hooks1), Some(hooks2)) => Some(Hooks {
+                pre_build: fill_auto_in_vec(hooks1.pre_build, hooks2.pre_build),
+                post_build: fill_auto_in_vec(hooks1.post_build, hooks2.post_build),
+            }),
+        };
+
+        new_plan.cron = match (new_plan.cron, plan2.cron) {
+            (None, jobs) | (jobs, None) => jobs,
+            (Some(jobs1), Some(jobs2)) => {
+                let mut jobs = jobs1;
+                jobs.extend(jobs2);
+                Some(jobs)
+            }
+        };
+
+        // The first plan is the one generated by the providers, so its metadata describes the build
+        new_plan.metadata = new_plan.metadata.or(plan2.metadata);
+
         new_plan.resolve_phase_names();
         new_plan
     }
@@ -80,11 +113,15 @@ impl Mergeable for Phase {
         phase.nix_libs = fill_auto_in_vec(phase.nix_libs.clone(), c2.nix_libs);
         phase.apt_pkgs = fill_auto_in_vec(phase.apt_pkgs.clone(), c2.apt_pkgs);
         phase.nix_overlays = fill_auto_in_vec(phase.nix_overlays.clone(), c2.nix_overlays);
+        phase.nix_snippets = fill_auto_in_vec(phase.nix_snippets.clone(), c2.nix_snippets);
+        phase.nix_extra_outputs =
+            fill_auto_in_vec(phase.nix_extra_outputs.clone(), c2.nix_extra_outputs);
         phase.only_include_files =
             fill_auto_in_vec(phase.only_include_files.clone(), c2.only_include_files);
         phase.cache_directories =
             fill_auto_in_vec(phase.cache_directories.clone(), c2.cache_directories);
         phase.paths = fill_auto_in_vec(phase.paths.clone(), c2.paths);
+        phase.rebuild = c2.rebuild.or(phase.rebuild);
 
         phase
     }
@@ -102,6 +139,11 @@ impl Mergeable for StartPhase {
             c2.only_include_files,
         );
         start_phase.user = c2.user.or_else(|| start_phase.user.clone());
+        start_phase.workdir = c2.workdir.or_else(|| start_phase.workdir.clone());
+        start_phase.port = c2.port.or(start_phase.port);
+        start_phase.healthcheck = c2.healthcheck.or_else(|| start_phase.healthcheck.clone());
+        start_phase.processes = c2.processes.or_else(|| start_phase.processes.clone());
+        start_phase.release_cmd = c2.release_cmd.or_else(|| start_phase.release_cmd.clone());
         start_phase
     }
 }
@@ -208,5 +250,35 @@ mod test {
             .unwrap(),
             merged
         );
+
+        let merged = BuildPlan::merge(
+            &BuildPlan::from_toml(
+                r#"
+                [buildVariables]
+                TOKEN = "provider"
+                SENTRY_RELEASE = "1"
+                "#,
+            )
+            .unwrap(),
+            &BuildPlan::from_toml(
+                r#"
+                [buildVariables]
+                TOKEN = "file"
+                "#,
+            )
+            .unwrap(),
+        );
+
+        assert_eq!(
+            BuildPlan::from_toml(
+                r#"
+                [buildVariables]
+                TOKEN = "file"
+                SENTRY_RELEASE = "1"
+                "#,
+            )
+            .unwrap(),
+            merged
+        );
     }
 }
@@ -1,24 +1,41 @@
 use self::{
+    cron::CronJobs,
     merge::Mergeable,
-    phase::{Phase, Phases, StartPhase},
+    phase::{Phase, Phases, Rebuild, StartPhase},
     topological_sort::topological_sort,
 };
 use super::images::{DEBIAN_BASE_IMAGE, UBUNTU_BASE_IMAGE};
 use crate::nixpacks::{
     app::{App, StaticAssets},
-    environment::{Environment, EnvironmentVariables},
+    environment::{get_secret_variable_names, Environment, EnvironmentVariables, REDACTED},
 };
-use anyhow::Result;
+use anyhow::{bail, Result};
 use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
+use utils::remove_autos_from_vec;
 
 // pub mod config;
+pub mod certs;
+pub mod cron;
+pub mod diff;
 pub mod generator;
+pub mod locale;
 pub mod merge;
 pub mod phase;
 pub mod pretty_print;
+pub mod process_manager;
 mod topological_sort;
 pub mod utils;
+pub mod validate;
+
+/// Version of the serialized plan format, which is bumped whenever a change needs older plans to be migrated.
+pub const PLAN_VERSION: u32 = 1;
+
+/// The profile that packages installed with `nix-env` are linked into, which every user of the image can read.
+pub const NIX_PROFILE: &str = "/nix/var/nix/profiles/default";
+
+/// Upgrades a plan from the version at the same index plus one to the next version.
+const MIGRATIONS: &[fn(&mut BuildPlan)] = &[];
 
 /// Types that impl this trait can generate build plans.
 pub trait PlanGenerator {
@@ -34,20 +51,74 @@ pub trait PlanGenerator {
 /// Nixpacks is essentially a compiler from {the closure of the direct and indirect dependencies of an application} to a Docker image reifying that closure.
 /// BuildPlans are an intermediate representation of this compiler, and are either compiled to a Dockerfile and then built into an image or are serialized as json or toml to a config file.
 pub struct BuildPlan {
+    /// The plan format version, which is only set on plans that have been serialized.
+    pub version: Option<u32>,
+
     pub providers: Option<Vec<String>>,
 
     #[serde(rename = "buildImage")]
     pub build_image: Option<String>,
 
+    /// A Nix file in the app, such as `shell.nix`, that provides the environment instead of the generated Nix expressions.
+    #[serde(rename = "nixFile")]
+    pub nix_file: Option<String>,
+
     pub variables: Option<EnvironmentVariables>,
 
+    /// Variables that are only available while building, such as tokens for build tooling.
+    #[serde(rename = "buildVariables")]
+    pub build_variables: Option<EnvironmentVariables>,
+
     #[serde(rename = "staticAssets")]
     pub static_assets: Option<StaticAssets>,
 
+    /// Directories cached for every phase, such as a compiler cache used by both install and build.
+    #[serde(rename = "cacheDirectories")]
+    pub cache_directories: Option<Vec<String>>,
+
     pub phases: Option<Phases>,
 
     #[serde(rename = "start")]
     pub start_phase: Option<StartPhase>,
+
+    pub hooks: Option<Hooks>,
+
+    /// Jobs that run on a schedule in the same container as the app.
+    pub cron: Option<CronJobs>,
+
+    pub metadata: Option<PlanMetadata>,
+}
+
+/// Information about how the plan was generated, for tools to display.
+#[serde_with::skip_serializing_none]
+#[derive(PartialEq, Eq, Default, Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PlanMetadata {
+    pub providers: Vec<ProviderInfo>,
+}
+
+/// A provider that contributed to the plan, such as Node 18 detected from a `package.json`.
+#[serde_with::skip_serializing_none]
+#[derive(PartialEq, Eq, Default, Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ProviderInfo {
+    pub name: String,
+    pub version: Option<String>,
+    pub reason: Option<String>,
+}
+
+/// Commands that run on the host machine around the Docker build, such as code generation or image scanning.
+#[serde_with::skip_serializing_none]
+#[derive(PartialEq, Eq, Default, Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Hooks {
+    /// Run in the app directory before it is copied into the build context.
+    #[serde(alias = "pre_build")]
+    pub pre_build: Option<Vec<String>>,
+
+    /// Run once the image has been built.
+    #[serde(alias = "post_build")]
+    pub post_build: Option<Vec<String>>,
 }
 
 impl BuildPlan {
@@ -63,6 +134,7 @@ impl BuildPlan {
     /// Create a BuildPlan from a toml config file.
     pub fn from_toml<S: Into<String>>(toml: S) -> Result<Self> {
         let mut plan: BuildPlan = toml::from_str(&toml.into())?;
+        plan.migrate()?;
         plan.resolve_phase_names();
         Ok(plan)
     }
@@ -70,6 +142,7 @@ impl BuildPlan {
     /// Create a BuildPlan from a json config file.
     pub fn from_json<S: Into<String>>(json: S) -> Result<Self> {
         let mut plan: BuildPlan = serde_json::from_str(&json.into())?;
+        plan.migrate()?;
         plan.resolve_phase_names();
         Ok(plan)
     }
@@ -77,6 +150,7 @@ impl BuildPlan {
     /// Render a BuildPlan to a toml-formatted string.
     pub fn to_toml(&self) -> Result<String> {
         let mut plan = self.clone();
+        plan.version = Some(PLAN_VERSION);
         plan.remove_phase_names();
         Ok(toml::to_string_pretty(&plan)?)
     }
@@ -84,27 +158,131 @@ impl BuildPlan {
     /// Render a BuildPlan to a json-formatted string.
     pub fn to_json(&self) -> Result<String> {
         let mut plan = self.clone();
+        plan.version = Some(PLAN_VERSION);
         plan.remove_phase_names();
         Ok(serde_json::to_string_pretty(&plan)?)
     }
 
+    /// Upgrade a deserialized plan to the current version. Plans without a version are treated as the first version.
+    fn migrate(&mut self) -> Result<()> {
+        let version = self.version.take().unwrap_or(1);
+        if version == 0 {
+            bail!("Invalid plan version 0");
+        }
+        if version > PLAN_VERSION {
+            bail!(
+                "The plan has version {version}, but this version of Nixpacks only supports plans up to version {PLAN_VERSION}. Please upgrade Nixpacks to use it"
+            );
+        }
+
+        for migration in &MIGRATIONS[(version - 1) as usize..] {
+            migration(self);
+        }
+
+        Ok(())
+    }
+
     /// Add the given phase to this BuildPlan.
     pub fn add_phase(&mut self, phase: Phase) {
         let phases = self.phases.get_or_insert(BTreeMap::default());
         phases.insert(phase.get_name(), phase);
     }
 
+    /// Add the given phase so that it runs directly after another, such as a codegen step between install and build.
+    /// Phases that depended on `after` depend on the new phase instead.
+    pub fn add_phase_after(&mut self, mut phase: Phase, after: &str) {
+        let name = phase.get_name();
+        for existing in self.phases.get_or_insert(BTreeMap::default()).values_mut() {
+            for dep in existing.depends_on.iter_mut().flatten() {
+                if dep == after {
+                    dep.clone_from(&name);
+                }
+            }
+        }
+
+        phase.depends_on_phase(after);
+        self.add_phase(phase);
+    }
+
     /// Stores the app entrypoint command in this BuildPlan.
     pub fn set_start_phase(&mut self, start_phase: StartPhase) {
         self.start_phase = Some(start_phase);
     }
 
+    /// Adds a package to the setup phase, keeping the packages of the plans this one is merged with.
+    fn add_setup_pkg(&mut self, pkg: &str) {
+        if self.get_phase("setup").is_none() {
+            self.add_phase(Phase::setup(None));
+        }
+        let setup = self.get_phase_mut("setup").unwrap();
+        let pkgs = setup
+            .nix_pkgs
+            .get_or_insert_with(|| vec!["...".to_string()]);
+        if !pkgs.iter().any(|existing| existing == pkg) {
+            pkgs.push(pkg.to_string());
+        }
+    }
+
+    /// Adds the packages and commands of a provider's setup phase to the plan's setup phase.
+    ///
+    /// The setup phase is then run without the app's files, so its commands are only run again when the setup changes.
+    pub fn add_setup_phase(&mut self, setup: Phase) {
+        let extend = |existing: Option<Vec<String>>, new: Option<Vec<String>>| match (existing, new)
+        {
+            (Some(existing), Some(new)) => Some([existing, new].concat()),
+            (existing, new) => existing.or(new),
+        };
+
+        let mut phase = self
+            .get_phase("setup")
+            .cloned()
+            .unwrap_or_else(|| Phase::setup(None));
+        phase.nix_pkgs = extend(phase.nix_pkgs, setup.nix_pkgs);
+        phase.nix_libs = extend(phase.nix_libs, setup.nix_libs);
+        phase.nix_overlays = extend(phase.nix_overlays, setup.nix_overlays);
+        phase.nix_snippets = extend(phase.nix_snippets, setup.nix_snippets);
+        phase.nix_extra_outputs = extend(phase.nix_extra_outputs, setup.nix_extra_outputs);
+        phase.apt_pkgs = extend(phase.apt_pkgs, setup.apt_pkgs);
+        phase.cmds = extend(phase.cmds, setup.cmds);
+        phase.paths = extend(phase.paths, setup.paths);
+        phase.nixpkgs_archive = setup.nixpkgs_archive.or(phase.nixpkgs_archive);
+        phase.only_include_files = Some(Vec::new());
+        self.add_phase(phase);
+    }
+
+    /// The values of the variables that hold secrets, which are redacted from the build output.
+    pub fn get_secret_values(&self) -> Vec<String> {
+        let variables = self.get_build_args();
+        get_secret_variable_names(&variables)
+            .into_iter()
+            .filter_map(|name| variables.get(&name).cloned())
+            .collect()
+    }
+
+    /// A copy of the plan with the values of the variables that hold secrets redacted, for printing.
+    #[must_use]
+    pub fn redacted(&self) -> BuildPlan {
+        let names = get_secret_variable_names(&self.get_build_args());
+        let mut plan = self.clone();
+        for variables in [plan.variables.as_mut(), plan.build_variables.as_mut()]
+            .into_iter()
+            .flatten()
+        {
+            for name in &names {
+                if let Some(value) = variables.get_mut(name) {
+                    *value = REDACTED.to_string();
+                }
+            }
+        }
+        plan
+    }
+
     /// Stores environment variables passed to the `nixpacks` command, set in project files, or from ProviderMetadata.
     pub fn add_variables(&mut self, variables: EnvironmentVariables) {
         match self.variables.as_mut() {
             Some(vars) => {
                 for (key, value) in &variables {
-                    vars.insert(key.to_string(), value.to_string());
+                    vars.insert(key.clone(), value.clone());
                 }
             }
             None => {
@@ -113,12 +291,65 @@ impl BuildPlan {
         }
     }
 
+    /// Stores variables that are passed to the build as `ARG`s but are not set in the final image.
+    pub fn add_build_variables(&mut self, variables: EnvironmentVariables) {
+        self.build_variables
+            .get_or_insert_with(EnvironmentVariables::default)
+            .extend(variables);
+    }
+
+    /// All values passed to the build as `--build-arg`s. Runtime variables take precedence over build variables.
+    pub fn get_build_args(&self) -> EnvironmentVariables {
+        self.build_variables
+            .clone()
+            .unwrap_or_default()
+            .into_iter()
+            .chain(self.variables.clone().unwrap_or_default())
+            .map(|(name, value)| (name.trim().to_string(), value))
+            .collect()
+    }
+
+    /// Whether any phase is rebuilt on every build, which needs a new cache busting argument each time.
+    pub fn has_always_rebuilt_phase(&self) -> bool {
+        self.phases
+            .clone()
+            .unwrap_or_default()
+            .values()
+            .any(|phase| phase.rebuild == Some(Rebuild::Always))
+    }
+
+    /// Cache the given directory in every phase that runs commands.
+    pub fn add_cache_directory<S: Into<String>>(&mut self, dir: S) {
+        let dir = dir.into();
+        let dirs = self.cache_directories.get_or_insert_with(Vec::new);
+        if !dirs.contains(&dir) {
+            dirs.push(dir);
+        }
+    }
+
+    /// The directories cached while running a phase, from both the plan and the phase.
+    pub fn get_phase_cache_directories(&self, phase: &Phase) -> Option<Vec<String>> {
+        match (&self.cache_directories, &phase.cache_directories) {
+            (None, dirs) | (dirs, None) => dirs.clone(),
+            (Some(plan_dirs), Some(phase_dirs)) => {
+                let mut dirs = phase_dirs.clone();
+                dirs.extend(
+                    plan_dirs
+                        .iter()
+                        .filter(|dir| !phase_dirs.contains(dir))
+                        .cloned(),
+                );
+                Some(dirs)
+            }
+        }
+    }
+
     /// Providers use this to define which files get copied into the container image.
     pub fn add_static_assets(&mut self, static_assets: StaticAssets) {
         match self.static_assets.as_mut() {
             Some(assets) => {
                 for (key, value) in &static_assets {
-                    assets.insert(key.to_string(), value.to_string());
+                    assets.insert(key.clone(), value.clone());
                 }
             }
             None => {
@@ -233,23 +464,17 @@ impl BuildPlan {
         let mut setup = Phase::setup(None);
         let mut uses_setup = false;
 
-        if let Some(pkg_string) = env.get_config_variable("PKGS") {
-            let mut pkgs = split_env_string(pkg_string.as_str());
+        if let Some(mut pkgs) = env.get_config_list("PKGS") {
             pkgs.push("...".to_string());
             setup.nix_pkgs = Some(pkgs);
             uses_setup = true;
         }
-        if let Some(apt_string) = env.get_config_variable("APT_PKGS") {
-            let mut apts = split_env_string(apt_string.as_str());
+        if let Some(mut apts) = env.get_config_list("APT_PKGS") {
             apts.push("...".to_string());
             setup.apt_pkgs = Some(apts);
             uses_setup = true;
         }
-        if let Some(nix_lib_string) = env.get_config_variable("LIBS") {
-            let mut libs = nix_lib_string
-                .split(' ')
-                .map(std::string::ToString::to_string)
-                .collect::<Vec<_>>();
+        if let Some(mut libs) = env.get_config_list("LIBS") {
             libs.push("...".to_string());
             setup.nix_libs = Some(libs);
             uses_setup = true;
@@ -261,12 +486,13 @@ impl BuildPlan {
 
         // Install
         if let Some(cmd_string) = env.get_config_variable("INSTALL_CMD") {
-            let mut install = Phase::install(Some(cmd_string));
+            let mut install = Phase::install(None);
+            install.cmds = Some(split_env_cmds(&cmd_string));
 
-            if let Some(cache_dirs) = env.get_config_variable("INSTALL_CACHE_DIRS") {
-                split_env_string(cache_dirs.as_str())
-                    .iter()
-                    .for_each(|dir| install.add_cache_directory(dir));
+            if let Some(cache_dirs) = env.get_config_list("INSTALL_CACHE_DIRS") {
+                for dir in cache_dirs {
+                    install.add_cache_directory(dir);
+                }
             }
 
             phases.push(install);
@@ -274,21 +500,79 @@ impl BuildPlan {
 
         // Build
         if let Some(cmd_string) = env.get_config_variable("BUILD_CMD") {
-            let mut build = Phase::build(Some(cmd_string));
+            let mut build = Phase::build(None);
+            build.cmds = Some(split_env_cmds(&cmd_string));
 
-            if let Some(cache_dirs) = env.get_config_variable("BUILD_CACHE_DIRS") {
-                split_env_string(cache_dirs.as_str())
-                    .iter()
-                    .for_each(|dir| build.add_cache_directory(dir));
+            if let Some(cache_dirs) = env.get_config_list("BUILD_CACHE_DIRS") {
+                for dir in cache_dirs {
+                    build.add_cache_directory(dir);
+                }
             }
 
             phases.push(build);
         }
 
         // Start
-        let start = env.get_config_variable("START_CMD").map(StartPhase::new);
+        let mut start = env.get_config_variable("START_CMD").map(StartPhase::new);
+        if let Some(release_cmd) = env.get_config_variable("RELEASE_CMD") {
+            start.get_or_insert_with(StartPhase::default).release_cmd = Some(release_cmd);
+        }
+        if let Some(workdir) = env.get_config_variable("START_WORKDIR") {
+            start.get_or_insert_with(StartPhase::default).workdir = Some(workdir);
+        }
+
+        let mut plan = BuildPlan::new(&phases, start);
+        plan.providers = env.get_config_list("PROVIDERS");
+
+        // Set to a timezone or locale, or to `1` for the default one
+        let get_setting = |name: &str, default: &str| {
+            env.get_config_variable(name)
+                .and_then(|value| match value.as_str() {
+                    "1" | "true" => Some(default.to_string()),
+                    "" | "0" | "false" => None,
+                    _ => Some(value),
+                })
+        };
+        if let Some(timezone) = get_setting("TZDATA", locale::DEFAULT_TIMEZONE) {
+            plan.add_timezone(&timezone);
+        }
+        if let Some(locale) = get_setting("LOCALES", locale::DEFAULT_LOCALE) {
+            plan.add_locale(&locale);
+        }
 
-        BuildPlan::new(&phases, start)
+        plan
+    }
+
+    /// Use the given nixpkgs revision for every phase that installs Nix packages, overriding the provider's choice.
+    pub fn set_nix_archive(&mut self, archive: &str) {
+        let phases = self.phases.get_or_insert(Phases::default());
+        for phase in (*phases).values_mut() {
+            if phase.uses_nix() {
+                phase.set_nix_archive(archive.to_string());
+            }
+        }
+    }
+
+    /// Resolve `${NAME}` references in the phase, start, and release commands from the environment.
+    pub fn interpolate_variables(&mut self, env: &Environment) {
+        let variables = Environment::clone_variables(env);
+        let interpolate = |cmd: &mut String| *cmd = utils::interpolate_variables(cmd, &variables);
+
+        if let Some(phases) = self.phases.as_mut() {
+            for phase in phases.values_mut() {
+                phase.cmds.iter_mut().flatten().for_each(interpolate);
+            }
+        }
+
+        if let Some(start) = self.start_phase.as_mut() {
+            start.cmd.iter_mut().for_each(interpolate);
+            start.release_cmd.iter_mut().for_each(interpolate);
+            start
+                .processes
+                .iter_mut()
+                .flat_map(BTreeMap::values_mut)
+                .for_each(interpolate);
+        }
     }
 
     /// Store the base image and phase dependencies in this BuildPlan, for later reproducibility.
@@ -312,6 +596,13 @@ impl BuildPlan {
         if let Some(start) = &mut self.start_phase {
             start.pin();
         }
+
+        self.cache_directories = self.cache_directories.take().map(remove_autos_from_vec);
+
+        if let Some(hooks) = &mut self.hooks {
+            hooks.pre_build = hooks.pre_build.take().map(remove_autos_from_vec);
+            hooks.post_build = hooks.post_build.take().map(remove_autos_from_vec);
+        }
     }
 
     /// Prefix each phase name with the name of the provider that generated the phase, in the case of multiple providers.
@@ -354,17 +645,19 @@ impl topological_sort::TopItem for (String, Phase) {
     }
 }
 
-/// Splits a string taken from an environment variable into a vector of packages, libraries, or directories.
-fn split_env_string(s: &str) -> Vec<String> {
-    s.split([' ', ','])
+/// Splits a command variable into one command per line, so that each one runs in its own layer.
+fn split_env_cmds(s: &str) -> Vec<String> {
+    s.lines()
+        .map(str::trim)
+        .filter(|cmd| !cmd.is_empty())
         .map(std::string::ToString::to_string)
-        .filter(|s| !s.is_empty())
         .collect::<Vec<_>>()
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::nixpacks::nix::pkg::Pkg;
 
     #[test]
     fn get_plan_from_environment() {
@@ -377,12 +670,15 @@ mod test {
             "NIXPACKS_BUILD_CMD=yarn build",
             "NIXPACKS_BUILD_CACHE_DIRS=build/cache/dir",
             "NIXPACKS_START_CMD=yarn start",
+            "NIXPACKS_PROVIDERS=python,...",
         ])
         .unwrap();
         let env_plan = BuildPlan::from_environment(&env);
 
         let result = BuildPlan::from_toml(
             r#"
+            providers = ["python", "..."]
+
             [phases.setup]
             nixPkgs = ["cowsay", "sl", "..."]
             aptPkgs = ["foo", "bar", "..."]
@@ -407,6 +703,22 @@ mod test {
         assert_eq!(result, env_plan);
     }
 
+    #[test]
+    fn test_plan_version() {
+        let plan =
+            BuildPlan::from_json(format!(r#"{{"version": {PLAN_VERSION}, "providers": []}}"#))
+                .unwrap();
+        assert_eq!(plan.version, None);
+        assert_eq!(plan.providers, Some(Vec::new()));
+
+        let plan = BuildPlan::from_toml("providers = []").unwrap();
+        assert_eq!(plan.version, None);
+
+        let error = BuildPlan::from_toml(format!("version = {}", PLAN_VERSION + 1)).unwrap_err();
+        assert!(error.to_string().contains("Please upgrade Nixpacks"));
+        assert!(BuildPlan::from_json(r#"{"version": 0}"#).is_err());
+    }
+
     #[test]
     fn test_to_json_and_from_json() {
         let original_plan = BuildPlan::from_toml(
@@ -431,6 +743,7 @@ mod test {
         .unwrap();
 
         let json_str = original_plan.to_json().unwrap();
+        assert!(json_str.contains(&format!("\"version\": {PLAN_VERSION}")));
         let deserialized_plan = BuildPlan::from_json(json_str).unwrap();
 
         assert_eq!(original_plan, deserialized_plan);
@@ -461,7 +774,7 @@ mod test {
         let mut another = Phase::new("another");
         another.depends_on_phase("setup");
 
-        let plan = BuildPlan::new(&vec![setup, install, build, another], None);
+        let plan = BuildPlan::new(&[setup, install, build, another], None);
 
         let build_phase = plan.get_phases_with_dependencies("build");
         let phases = build_phase.values();
@@ -469,6 +782,87 @@ mod test {
         assert_eq!(phases.len(), 3);
     }
 
+    #[test]
+    fn test_phase_cache_directories() {
+        let mut plan = BuildPlan::from_toml(
+            r#"
+            cacheDirectories = ["/root/.cache/go-build"]
+
+            [phases.install]
+            cmds = ["go mod download"]
+
+            [phases.build]
+            cmds = ["go build"]
+            cacheDirectories = ["target", "/root/.cache/go-build"]
+            "#,
+        )
+        .unwrap();
+        plan.add_cache_directory("/root/.cache/go-build");
+        assert_eq!(
+            plan.cache_directories,
+            Some(vec!["/root/.cache/go-build".to_string()])
+        );
+
+        assert_eq!(
+            plan.get_phase_cache_directories(plan.get_phase("install").unwrap()),
+            Some(vec!["/root/.cache/go-build".to_string()])
+        );
+        assert_eq!(
+            plan.get_phase_cache_directories(plan.get_phase("build").unwrap()),
+            Some(vec![
+                "target".to_string(),
+                "/root/.cache/go-build".to_string()
+            ])
+        );
+    }
+
+    #[test]
+    fn test_phase_pkgs_are_deduplicated() {
+        let mut setup = Phase::setup(Some(vec![Pkg::new("nodejs"), Pkg::new("python3")]));
+        setup.add_nix_pkgs(&[Pkg::new("python3"), Pkg::new("gcc")]);
+        setup.add_pkgs_libs(vec!["zlib".to_string(), "zlib".to_string()]);
+        setup.add_apt_pkgs(vec!["git".to_string(), "curl".to_string()]);
+        setup.add_apt_pkgs(vec!["git".to_string()]);
+
+        assert_eq!(
+            setup.nix_pkgs,
+            Some(vec![
+                "nodejs".to_string(),
+                "python3".to_string(),
+                "gcc".to_string()
+            ])
+        );
+        assert_eq!(setup.nix_libs, Some(vec!["zlib".to_string()]));
+        assert_eq!(
+            setup.apt_pkgs,
+            Some(vec!["git".to_string(), "curl".to_string()])
+        );
+    }
+
+    #[test]
+    fn test_add_phase_after() {
+        let mut plan = BuildPlan::new(
+            &[
+                Phase::setup(None),
+                Phase::install(Some("npm ci".to_string())),
+                Phase::build(Some("npm run build".to_string())),
+            ],
+            None,
+        );
+
+        let mut codegen = Phase::new("codegen");
+        codegen.add_cmd("npx prisma generate");
+        plan.add_phase_after(codegen, "install");
+
+        let names = plan
+            .get_sorted_phases()
+            .unwrap()
+            .iter()
+            .map(Phase::get_name)
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["setup", "install", "codegen", "build"]);
+    }
+
     #[test]
     fn test_pin_build_plan() {
         let mut plan = BuildPlan::from_toml(
@@ -494,14 +888,155 @@ mod test {
     }
 
     #[test]
-    fn test_split_env_string() {
+    fn test_redacted() {
+        let mut plan = BuildPlan::from_toml(
+            r#"
+            [variables]
+            NODE_ENV = "production"
+            DATABASE_PASSWORD = "hunter22"
+            "#,
+        )
+        .unwrap();
+        plan.add_build_variables(EnvironmentVariables::from([(
+            "NPM_TOKEN".to_string(),
+            "npm_abc123".to_string(),
+        )]));
+
+        let mut secrets = plan.get_secret_values();
+        secrets.sort();
+        assert_eq!(secrets, vec!["hunter22", "npm_abc123"]);
+
+        let redacted = plan.redacted();
+        let variables = redacted.variables.unwrap();
+        assert_eq!(variables["NODE_ENV"], "production");
+        assert_eq!(variables["DATABASE_PASSWORD"], REDACTED);
+        assert_eq!(redacted.build_variables.unwrap()["NPM_TOKEN"], REDACTED);
+    }
+
+    #[test]
+    fn test_split_env_cmds() {
+        assert_eq!(split_env_cmds("npm ci"), vec!["npm ci".to_string()]);
         assert_eq!(
-            split_env_string("nodejs yarn"),
-            vec!["nodejs".to_string(), "yarn".to_string()]
+            split_env_cmds("npm ci\nnpx prisma generate\n"),
+            vec!["npm ci".to_string(), "npx prisma generate".to_string()]
         );
+    }
+
+    #[test]
+    fn test_detect_port() {
+        let detect = |cmd: &str, variables: &[(&str, &str)]| {
+            let mut start = StartPhase::new(cmd);
+            start.detect_port(
+                &variables
+                    .iter()
+                    .map(|(k, v)| ((*k).to_string(), (*v).to_string()))
+                    .collect(),
+            );
+            start.port
+        };
+
+        assert_eq!(detect("npm start", &[]), None);
+        assert_eq!(detect("npm start", &[("PORT", "4000")]), Some(4000));
+        assert_eq!(
+            detect("gunicorn --bind 0.0.0.0:8000 app:app", &[]),
+            Some(8000)
+        );
+        assert_eq!(detect("rails server -p 5000", &[]), Some(5000));
+        assert_eq!(detect("rails server -p ${PORT:-4000}", &[]), Some(4000));
+        assert_eq!(
+            detect("java -Dserver.port=$PORT -jar app.jar", &[]),
+            Some(phase::DEFAULT_PORT)
+        );
+    }
+
+    #[test]
+    fn test_healthcheck_from_toml() {
+        let plan = BuildPlan::from_toml(
+            r#"
+            [start.healthcheck]
+            path = "/health"
+            startPeriod = "10s"
+            "#,
+        )
+        .unwrap();
+
+        let healthcheck = plan.start_phase.unwrap().healthcheck.unwrap();
+        assert_eq!(healthcheck.path, Some("/health".to_string()));
+        assert_eq!(healthcheck.start_period, Some("10s".to_string()));
+    }
+
+    #[test]
+    fn test_hooks_from_toml() {
+        let plan = BuildPlan::from_toml(
+            r#"
+            [hooks]
+            preBuild = ["npm run codegen"]
+            post_build = ["trivy image $NIXPACKS_IMAGE_NAME"]
+            "#,
+        )
+        .unwrap();
+
+        let hooks = plan.hooks.clone().unwrap();
+        assert_eq!(hooks.pre_build, Some(vec!["npm run codegen".to_string()]));
+        assert_eq!(
+            hooks.post_build,
+            Some(vec!["trivy image $NIXPACKS_IMAGE_NAME".to_string()])
+        );
+
+        let mut merged = BuildPlan::merge_plans(&[
+            plan,
+            BuildPlan::from_toml(
+                r#"
+                [hooks]
+                preBuild = ["...", "echo done"]
+                "#,
+            )
+            .unwrap(),
+        ]);
+        merged.pin(false);
         assert_eq!(
-            split_env_string("nodejs, yarn"),
-            vec!["nodejs".to_string(), "yarn".to_string()]
+            merged.hooks.unwrap().pre_build,
+            Some(vec!["npm run codegen".to_string(), "echo done".to_string()])
         );
     }
+
+    #[test]
+    fn test_add_setup_phase() {
+        let mut plan = BuildPlan::new(&[Phase::setup(Some(vec![Pkg::new("nodejs_18")]))], None);
+        let mut setup = Phase::setup(None);
+        setup.add_cmd("corepack enable");
+        plan.add_setup_phase(setup);
+
+        let setup = plan.get_phase("setup").unwrap();
+        assert_eq!(setup.nix_pkgs, Some(vec!["nodejs_18".to_string()]));
+        assert_eq!(setup.cmds, Some(vec!["corepack enable".to_string()]));
+        assert_eq!(setup.only_include_files, Some(Vec::new()));
+    }
+
+    #[test]
+    fn test_phase_rebuild() {
+        let plan = BuildPlan::from_toml(
+            r#"
+            [phases.install]
+            rebuild = "never"
+
+            [phases.build]
+            cmds = ["date > built-at"]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            plan.get_phase("install").unwrap().rebuild,
+            Some(Rebuild::Never)
+        );
+        assert!(!plan.has_always_rebuilt_phase());
+
+        let plan = BuildPlan::merge_plans(&[
+            plan,
+            BuildPlan::from_toml("[phases.build]\nrebuild = 'always'").unwrap(),
+        ]);
+        assert!(plan.has_always_rebuilt_phase());
+
+        assert!(BuildPlan::from_toml("[phases.build]\nrebuild = 'sometimes'").is_err());
+    }
 }
@@ -1,15 +1,19 @@
 use crate::nixpacks::{
-    images::{DEFAULT_BASE_IMAGE, STANDALONE_IMAGE},
+    environment::EnvironmentVariables,
+    images::{DEFAULT_BASE_IMAGE, SCRATCH_IMAGE, STANDALONE_IMAGE},
     nix::{pkg::Pkg, NIXPACKS_ARCHIVE_LEGACY_OPENSSL, NIXPKGS_ARCHIVE},
 };
+use regex::Regex;
 use serde::{Deserialize, Serialize};
-use std::collections::{BTreeMap, HashSet};
-use std::hash::Hash;
+use std::collections::BTreeMap;
 
 use super::utils::remove_autos_from_vec;
 
 pub type Phases = BTreeMap<String, Phase>;
 
+/// Port assumed for apps that listen on `$PORT` without saying what it should be.
+pub const DEFAULT_PORT: u16 = 3000;
+
 /// Holds the packages, commands, and directories needed for part of a build.
 #[serde_with::skip_serializing_none]
 #[derive(PartialEq, Eq, Serialize, Deserialize, Default, Clone, Debug)]
@@ -28,6 +32,12 @@ pub struct Phase {
 
     pub nix_overlays: Option<Vec<String>>,
 
+    /// Nix bindings, like `name = expression;`, that the phase's Nix packages can refer to.
+    pub nix_snippets: Option<Vec<String>>,
+
+    /// Outputs such as `dev` or `lib` to install for every Nix package, in addition to the default ones.
+    pub nix_extra_outputs: Option<Vec<String>>,
+
     pub nixpkgs_archive: Option<String>,
 
     #[serde(alias = "aptPackages")]
@@ -44,6 +54,19 @@ pub struct Phase {
 
     #[serde(alias = "envPaths")]
     pub paths: Option<Vec<String>>,
+
+    /// Override Docker's layer cache for the phase.
+    pub rebuild: Option<Rebuild>,
+}
+
+/// When a phase's Docker layers are rebuilt, instead of whenever its files or commands change.
+#[derive(PartialEq, Eq, Serialize, Deserialize, Clone, Copy, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum Rebuild {
+    /// Run the phase on every build, e.g. for a build that embeds a timestamp.
+    Always,
+    /// Only copy the phase's `only_include_files`, which must be set, so other changes to the app don't rebuild it.
+    Never,
 }
 
 /// Represents the final step of a container image, contains the startup command, any necessary files, and the final image that gets run by Docker.
@@ -55,6 +78,35 @@ pub struct StartPhase {
     pub run_image: Option<String>,
     pub only_include_files: Option<Vec<String>>,
     pub user: Option<String>,
+    /// Directory the container starts in, relative to the app directory unless it is absolute.
+    pub workdir: Option<String>,
+    pub port: Option<u16>,
+    pub healthcheck: Option<Healthcheck>,
+    pub processes: Option<BTreeMap<String, String>>,
+    pub release_cmd: Option<String>,
+}
+
+/// How to check that a running container is healthy, either by requesting a path on the app's port or running a command.
+#[serde_with::skip_serializing_none]
+#[derive(PartialEq, Eq, Serialize, Deserialize, Default, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Healthcheck {
+    pub path: Option<String>,
+    pub cmd: Option<String>,
+    pub interval: Option<String>,
+    pub timeout: Option<String>,
+    pub start_period: Option<String>,
+    pub retries: Option<u32>,
+}
+
+impl Healthcheck {
+    /// Check the app by requesting the given HTTP path.
+    pub fn http<S: Into<String>>(path: S) -> Self {
+        Self {
+            path: Some(path.into()),
+            ..Default::default()
+        }
+    }
 }
 
 impl Phase {
@@ -149,30 +201,33 @@ impl Phase {
 
     /// Add a collection of packages to install with Nix in this phase.
     pub fn add_nix_pkgs(&mut self, new_pkgs: &[Pkg]) {
-        self.nix_overlays = Some(add_multiple_to_option_vec(
+        self.nix_overlays = Some(prevent_duplicates_vec(add_multiple_to_option_vec(
             self.nix_overlays.clone(),
             new_pkgs
                 .iter()
                 .filter_map(|pkg| pkg.overlay.clone())
                 .collect::<Vec<_>>(),
-        ));
-        self.nix_pkgs = Some(add_multiple_to_option_vec(
+        )));
+        self.nix_pkgs = Some(prevent_duplicates_vec(add_multiple_to_option_vec(
             self.nix_pkgs.clone(),
             new_pkgs.iter().map(Pkg::to_nix_string).collect(),
-        ));
+        )));
     }
 
     /// Add a collection of libraries to install with Nix in this phase.
     pub fn add_pkgs_libs(&mut self, new_libraries: Vec<String>) {
-        self.nix_libs = Some(add_multiple_to_option_vec(
+        self.nix_libs = Some(prevent_duplicates_vec(add_multiple_to_option_vec(
             self.nix_libs.clone(),
             new_libraries,
-        ));
+        )));
     }
 
     /// Add a collection of packages to install with apt in this phase.
     pub fn add_apt_pkgs(&mut self, new_pkgs: Vec<String>) {
-        self.apt_pkgs = Some(add_multiple_to_option_vec(self.apt_pkgs.clone(), new_pkgs));
+        self.apt_pkgs = Some(prevent_duplicates_vec(add_multiple_to_option_vec(
+            self.apt_pkgs.clone(),
+            new_pkgs,
+        )));
     }
 
     /// Add a command to execute in this phase.
@@ -224,6 +279,8 @@ impl Phase {
         self.nix_libs = pin_option_vec(self.nix_libs.as_ref());
         self.apt_pkgs = pin_option_vec(self.apt_pkgs.as_ref());
         self.nix_overlays = pin_option_vec(self.nix_overlays.as_ref());
+        self.nix_snippets = pin_option_vec(self.nix_snippets.as_ref());
+        self.nix_extra_outputs = pin_option_vec(self.nix_extra_outputs.as_ref());
         self.only_include_files = pin_option_vec(self.only_include_files.as_ref());
         self.cache_directories = pin_option_vec(self.cache_directories.as_ref());
         self.paths = pin_option_vec(self.paths.as_ref());
@@ -253,6 +310,11 @@ impl StartPhase {
         self.run_image = Some(STANDALONE_IMAGE.to_string());
     }
 
+    /// Run the StartPhase in an empty image, which only works for statically linked binaries.
+    pub fn run_in_scratch_image(&mut self) {
+        self.run_image = Some(SCRATCH_IMAGE.to_string());
+    }
+
     /// Add a file to the set of files to copy into the container image.
     pub fn add_file_dependency<S: Into<String>>(&mut self, file: S) {
         self.only_include_files = Some(add_to_option_vec(
@@ -261,6 +323,36 @@ impl StartPhase {
         ));
     }
 
+    /// Work out which port the app listens on, if one wasn't set.
+    ///
+    /// A `PORT` variable wins, then a port written in the start command (including `${PORT:-n}` defaults), then `DEFAULT_PORT` if the command reads `$PORT`.
+    pub fn detect_port(&mut self, variables: &EnvironmentVariables) {
+        if self.port.is_some() {
+            return;
+        }
+
+        if let Some(port) = variables.get("PORT").and_then(|port| port.parse().ok()) {
+            self.port = Some(port);
+            return;
+        }
+
+        let cmd = self.cmd.clone().unwrap_or_default();
+        let port_regex = Regex::new(
+            r"(?:--port[= ]|-p |\$\{PORT:-|(?:0\.0\.0\.0|localhost|127\.0\.0\.1|\[::\]):)(\d{2,5})\b",
+        )
+        .unwrap();
+        self.port = port_regex
+            .captures(&cmd)
+            .and_then(|captures| captures[1].parse().ok())
+            .or_else(|| {
+                if cmd.contains("$PORT") || cmd.contains("${PORT") {
+                    Some(DEFAULT_PORT)
+                } else {
+                    None
+                }
+            });
+    }
+
     /// Store the list of files to include in this phase for later reproducibility.
     pub fn pin(&mut self) {
         self.only_include_files = pin_option_vec(self.only_include_files.as_ref());
@@ -291,9 +383,13 @@ fn add_multiple_to_option_vec<T: Clone>(values: Option<Vec<T>>, new_values: Vec<
     }
 }
 
-/// Ensure that files aren't copied into the image twice.
-#[allow(clippy::needless_pass_by_value)]
-fn prevent_duplicates_vec<T: Clone + Eq + Hash>(values: Vec<T>) -> Vec<T> {
-    let set: HashSet<T> = values.iter().cloned().collect::<HashSet<_>>();
-    set.into_iter().collect()
+/// Remove repeated values, keeping the first of each so the order is the same on every run.
+fn prevent_duplicates_vec<T: PartialEq>(values: Vec<T>) -> Vec<T> {
+    let mut unique = Vec::with_capacity(values.len());
+    for value in values {
+        if !unique.contains(&value) {
+            unique.push(value);
+        }
+    }
+    unique
 }
@@ -30,6 +30,7 @@ impl BuildPlan {
             .unwrap_or_default();
 
         let env_contents = self
+            .redacted()
             .variables
             .clone()
             .unwrap_or_default()
@@ -171,6 +172,55 @@ impl BuildPlan {
         })
     }
 
+    /// The providers, packages, commands, and variables of the plan, as pairs for an aligned summary of the build.
+    ///
+    /// The values of variables that look like secrets are redacted.
+    pub fn get_summary(&self, providers: &[String]) -> Result<Vec<(String, String)>> {
+        let phases = self.get_sorted_phases()?;
+        let mut summary = Vec::new();
+
+        if !providers.is_empty() {
+            summary.push(("providers".to_string(), providers.join(", ")));
+        }
+
+        let mut pkgs: Vec<String> = Vec::new();
+        for phase in &phases {
+            for pkg in phase.nix_pkgs.iter().chain(phase.apt_pkgs.iter()).flatten() {
+                if !pkgs.contains(pkg) {
+                    pkgs.push(pkg.clone());
+                }
+            }
+        }
+        if !pkgs.is_empty() {
+            summary.push(("packages".to_string(), pkgs.join(", ")));
+        }
+
+        for phase in &phases {
+            let cmds = phase.cmds.clone().unwrap_or_default();
+            if !cmds.is_empty() {
+                summary.push((phase.get_name(), cmds.join("\n")));
+            }
+        }
+        if let Some(cmd) = self
+            .start_phase
+            .as_ref()
+            .and_then(|start| start.cmd.clone())
+        {
+            summary.push(("start".to_string(), cmd));
+        }
+
+        let variables = self.redacted().variables.unwrap_or_default();
+        if !variables.is_empty() {
+            let variables = variables
+                .iter()
+                .map(|(name, value)| format!("{name}={value}"))
+                .collect::<Vec<_>>();
+            summary.push(("variables".to_string(), variables.join("\n")));
+        }
+
+        Ok(summary)
+    }
+
     /// Produces a string of the packages to install and/or commands to run in the given phase.
     fn get_phase_content(&self, phase: &Phase) -> Result<String> {
         let mut c = String::new();
@@ -255,3 +305,44 @@ fn print_row(
 
     output
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nixpacks::{nix::pkg::Pkg, plan::phase::StartPhase};
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn test_get_summary() -> Result<()> {
+        let mut setup = Phase::setup(Some(vec![Pkg::new("nodejs_18")]));
+        setup.add_apt_pkgs(vec!["libvips".to_string()]);
+        let mut plan = BuildPlan::new(
+            &[
+                setup,
+                Phase::install(Some("npm ci".to_string())),
+                Phase::build(Some("npm run build".to_string())),
+            ],
+            Some(StartPhase::new("npm run start")),
+        );
+        plan.add_variables(BTreeMap::from([
+            ("NODE_ENV".to_string(), "production".to_string()),
+            ("NPM_TOKEN".to_string(), "npm_abc123".to_string()),
+        ]));
+
+        assert_eq!(
+            plan.get_summary(&["node".to_string()])?,
+            vec![
+                ("providers".to_string(), "node".to_string()),
+                ("packages".to_string(), "nodejs_18, libvips".to_string()),
+                ("install".to_string(), "npm ci".to_string()),
+                ("build".to_string(), "npm run build".to_string()),
+                ("start".to_string(), "npm run start".to_string()),
+                (
+                    "variables".to_string(),
+                    "NODE_ENV=production\nNPM_TOKEN=[redacted]".to_string()
+                ),
+            ]
+        );
+        Ok(())
+    }
+}
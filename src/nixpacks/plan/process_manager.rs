@@ -0,0 +1,87 @@
+use super::{phase::StartPhase, BuildPlan};
+use crate::nixpacks::app::ASSETS_DIR;
+use std::collections::BTreeMap;
+
+/// Runs every process of a Procfile and stops the others when one exits, so the container is restarted as a whole.
+pub const OVERMIND_PKG: &str = "overmind";
+
+/// The asset with the processes for the process manager to run.
+pub const PROCESS_MANAGER_PROCFILE: &str = "Procfile.nixpacks";
+
+impl BuildPlan {
+    /// Starts every process in the one container under a process manager, for hosts that only run a single container.
+    ///
+    /// Returns whether there were processes to run, since a single process is started as usual.
+    pub fn run_all_processes(&mut self) -> bool {
+        let processes = self
+            .start_phase
+            .as_ref()
+            .and_then(|start| start.processes.clone())
+            .unwrap_or_default();
+        if processes.len() < 2 {
+            return false;
+        }
+
+        self.add_setup_pkg(OVERMIND_PKG);
+        self.add_static_assets(BTreeMap::from([(
+            PROCESS_MANAGER_PROCFILE.to_string(),
+            processes
+                .iter()
+                .map(|(name, cmd)| format!("{name}: {}\n", cmd.trim()))
+                .collect::<Vec<_>>()
+                .concat(),
+        )]));
+
+        // `-N` keeps the app's PORT, rather than giving each process a port of its own
+        let start = self.start_phase.get_or_insert_with(StartPhase::default);
+        start.cmd = Some(format!(
+            "{OVERMIND_PKG} start -N -f {ASSETS_DIR}{PROCESS_MANAGER_PROCFILE}"
+        ));
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run_all_processes() {
+        let mut start = StartPhase::new("npm start");
+        start.processes = Some(BTreeMap::from([
+            ("web".to_string(), "npm start".to_string()),
+            ("worker".to_string(), "node worker.js".to_string()),
+        ]));
+        start.release_cmd = Some("npm run migrate".to_string());
+        let mut plan = BuildPlan::new(&[], Some(start));
+
+        assert!(plan.run_all_processes());
+        let start = plan.start_phase.clone().unwrap();
+        assert_eq!(
+            start.cmd,
+            Some("overmind start -N -f /assets/Procfile.nixpacks".to_string())
+        );
+        assert_eq!(start.release_cmd, Some("npm run migrate".to_string()));
+        assert_eq!(
+            plan.static_assets.clone().unwrap()[PROCESS_MANAGER_PROCFILE],
+            "web: npm start\nworker: node worker.js\n"
+        );
+        assert_eq!(
+            plan.get_phase("setup").unwrap().nix_pkgs,
+            Some(vec!["...".to_string(), OVERMIND_PKG.to_string()])
+        );
+    }
+
+    #[test]
+    fn test_run_all_processes_with_one_process() {
+        let mut start = StartPhase::new("npm start");
+        start.processes = Some(BTreeMap::from([(
+            "web".to_string(),
+            "npm start".to_string(),
+        )]));
+        let mut plan = BuildPlan::new(&[], Some(start.clone()));
+
+        assert!(!plan.run_all_processes());
+        assert_eq!(plan.start_phase, Some(start));
+    }
+}
@@ -1,3 +1,10 @@
+use crate::nixpacks::environment::{get_secret_variable_names, EnvironmentVariables};
+use once_cell::sync::Lazy;
+use regex::{Captures, Regex};
+
+static VARIABLE_REFERENCE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"\$\{([A-Za-z_][A-Za-z0-9_]*)\}").unwrap());
+
 /// Removes all the `"..."`'s or `"@auto"`'s from the `original`
 pub fn remove_autos_from_vec(original: Vec<String>) -> Vec<String> {
     original
@@ -43,6 +50,23 @@ pub fn fill_auto_in_vec(
     }
 }
 
+/// Replaces each `${NAME}` in `value` with the variable of that name.
+///
+/// Unknown variables, as well as `$NAME` and `${NAME:-default}`, are left for the shell to expand.
+/// So are secrets, which are read from their mounts when the command runs instead of being written into the image.
+pub fn interpolate_variables(value: &str, variables: &EnvironmentVariables) -> String {
+    let secrets = get_secret_variable_names(variables);
+    VARIABLE_REFERENCE
+        .replace_all(value, |caps: &Captures| {
+            variables
+                .get(&caps[1])
+                .filter(|_| !secrets.iter().any(|name| *name == caps[1]))
+                .cloned()
+                .unwrap_or_else(|| caps[0].to_string())
+        })
+        .to_string()
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -88,4 +112,29 @@ mod test {
             .unwrap()
         );
     }
+
+    #[test]
+    fn test_interpolate_variables() {
+        let variables = EnvironmentVariables::from([
+            ("PORT".to_string(), "8080".to_string()),
+            ("APP_MODULE".to_string(), "app:app".to_string()),
+            ("NPM_TOKEN".to_string(), "npm_abc123".to_string()),
+        ]);
+
+        assert_eq!(
+            "uvicorn app:app --port 8080",
+            interpolate_variables("uvicorn ${APP_MODULE} --port ${PORT}", &variables)
+        );
+        assert_eq!(
+            "echo ${MISSING} $PORT ${PORT:-3000}",
+            interpolate_variables("echo ${MISSING} $PORT ${PORT:-3000}", &variables)
+        );
+        assert_eq!(
+            "npm config set //registry.npmjs.org/:_authToken ${NPM_TOKEN}",
+            interpolate_variables(
+                "npm config set //registry.npmjs.org/:_authToken ${NPM_TOKEN}",
+                &variables
+            )
+        );
+    }
 }
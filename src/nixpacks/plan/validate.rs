@@ -0,0 +1,293 @@
+use super::{
+    cron::check_schedule,
+    phase::{Healthcheck, Rebuild},
+    BuildPlan,
+};
+use crate::nixpacks::{
+    environment::{is_valid_env_name, EnvironmentVariables},
+    nix,
+};
+use anyhow::{bail, Result};
+
+impl BuildPlan {
+    /// Problems that would make the build fail, each prefixed with the field they were found in.
+    pub fn get_validation_errors(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+
+        check_variables("variables", self.variables.as_ref(), &mut errors);
+        check_variables("buildVariables", self.build_variables.as_ref(), &mut errors);
+
+        for (name, phase) in self.phases.clone().unwrap_or_default() {
+            check_cmds(
+                &format!("phases.{name}.cmds"),
+                phase.cmds.as_ref(),
+                &mut errors,
+            );
+            for (field, pkgs) in [
+                ("nixPkgs", &phase.nix_pkgs),
+                ("nixLibs", &phase.nix_libs),
+                ("aptPkgs", &phase.apt_pkgs),
+            ] {
+                if pkgs.iter().flatten().any(|pkg| pkg.trim().is_empty()) {
+                    errors.push(format!(
+                        "phases.{name}.{field}: package names cannot be empty"
+                    ));
+                }
+            }
+            // Snippets are placed in a `let`, where anything but a binding is a syntax error
+            if phase
+                .nix_snippets
+                .iter()
+                .flatten()
+                .any(|snippet| !snippet.contains('='))
+            {
+                errors.push(format!(
+                    "phases.{name}.nixSnippets: snippets must bind a name, like `myPython = python3.withPackages (ps: [ ps.numpy ]);`"
+                ));
+            }
+            // Without the list, the phase would either copy the whole app or run without the files it needs
+            if phase.rebuild == Some(Rebuild::Never) && phase.only_include_files.is_none() {
+                errors.push(format!(
+                    "phases.{name}.rebuild: `never` needs onlyIncludeFiles to list the files the phase uses, or [] for none"
+                ));
+            }
+        }
+
+        if let Some(start) = &self.start_phase {
+            check_cmd("start.cmd", start.cmd.as_ref(), &mut errors);
+            check_cmd("start.releaseCmd", start.release_cmd.as_ref(), &mut errors);
+            for (name, cmd) in start.processes.iter().flatten() {
+                check_cmd(&format!("start.processes.{name}"), Some(cmd), &mut errors);
+            }
+            if start
+                .workdir
+                .as_ref()
+                .map_or(false, |dir| dir.trim().is_empty())
+            {
+                errors.push("start.workdir: the directory cannot be empty".to_string());
+            }
+            if let Some(healthcheck) = &start.healthcheck {
+                check_healthcheck(healthcheck, &mut errors);
+            }
+        }
+
+        for (name, job) in self.cron.iter().flatten() {
+            if let Err(error) = check_schedule(&job.schedule) {
+                errors.push(format!("cron.{name}.schedule: {error}"));
+            }
+            check_cmd(&format!("cron.{name}.cmd"), Some(&job.cmd), &mut errors);
+            if job.cmd.contains('\n') {
+                errors.push(format!("cron.{name}.cmd: commands cannot span lines"));
+            }
+        }
+        if self.has_cron_jobs()
+            && self
+                .start_phase
+                .as_ref()
+                .map_or(false, |start| start.run_image.is_some())
+        {
+            errors.push(
+                "cron: jobs can't run in a start.runImage, which doesn't have the scheduler"
+                    .to_string(),
+            );
+        }
+
+        if let Some(hooks) = &self.hooks {
+            check_cmds("hooks.preBuild", hooks.pre_build.as_ref(), &mut errors);
+            check_cmds("hooks.postBuild", hooks.post_build.as_ref(), &mut errors);
+        }
+
+        errors
+    }
+
+    /// Fails with every validation error, so problems are reported before anything is built.
+    pub fn validate(&self) -> Result<()> {
+        let errors = self.get_validation_errors();
+        if !errors.is_empty() {
+            bail!("Invalid build plan:\n  {}", errors.join("\n  "));
+        }
+
+        Ok(())
+    }
+
+    /// Nix packages that don't exist in the nixpkgs archive of their phase.
+    ///
+    /// This evaluates nixpkgs with the local Nix installation, and returns `None` if Nix is not installed.
+    pub fn get_package_errors(&self) -> Result<Option<Vec<String>>> {
+        let mut errors = Vec::new();
+        for (name, phase) in self.phases.clone().unwrap_or_default() {
+            let missing = match nix::find_missing_packages(&phase)? {
+                Some(missing) => missing,
+                None => return Ok(None),
+            };
+
+            let archive = phase
+                .nixpkgs_archive
+                .clone()
+                .unwrap_or_else(|| nix::NIXPKGS_ARCHIVE.to_string());
+            for (field, pkgs) in [("nixPkgs", &phase.nix_pkgs), ("nixLibs", &phase.nix_libs)] {
+                for pkg in pkgs.iter().flatten().filter(|pkg| missing.contains(pkg)) {
+                    errors.push(format!(
+                        "phases.{name}.{field}: `{pkg}` was not found in nixpkgs archive {archive}"
+                    ));
+                }
+            }
+        }
+
+        Ok(Some(errors))
+    }
+}
+
+fn check_variables(
+    field: &str,
+    variables: Option<&EnvironmentVariables>,
+    errors: &mut Vec<String>,
+) {
+    for name in variables.into_iter().flat_map(EnvironmentVariables::keys) {
+        if !is_valid_env_name(name) {
+            errors.push(format!(
+                "{field}: `{name}` is not a valid variable name, since it is empty or contains whitespace or `=`"
+            ));
+        }
+    }
+}
+
+fn check_cmds(field: &str, cmds: Option<&Vec<String>>, errors: &mut Vec<String>) {
+    for (i, cmd) in cmds.into_iter().flatten().enumerate() {
+        if cmd.trim().is_empty() {
+            errors.push(format!("{field}[{i}]: commands cannot be empty"));
+        }
+    }
+}
+
+/// The healthcheck is written into a `HEALTHCHECK` instruction, which whitespace, quotes, or new lines would break.
+fn check_healthcheck(healthcheck: &Healthcheck, errors: &mut Vec<String>) {
+    if let Some(path) = &healthcheck.path {
+        if path.is_empty()
+            || path.contains(|c: char| c.is_whitespace() || c.is_control() || "\"'`$\\".contains(c))
+        {
+            errors.push(
+                "start.healthcheck.path: the path cannot be empty or contain whitespace, quotes, `$`, or backslashes"
+                    .to_string(),
+            );
+        }
+    }
+    if let Some(cmd) = &healthcheck.cmd {
+        check_cmd("start.healthcheck.cmd", Some(cmd), errors);
+        if cmd.contains(['\n', '\r']) {
+            errors.push("start.healthcheck.cmd: commands cannot span lines".to_string());
+        }
+    }
+    for (field, value) in [
+        ("interval", &healthcheck.interval),
+        ("timeout", &healthcheck.timeout),
+        ("startPeriod", &healthcheck.start_period),
+    ] {
+        if let Some(value) = value.as_ref().filter(|value| !is_duration(value)) {
+            errors.push(format!(
+                "start.healthcheck.{field}: `{value}` is not a duration, such as `30s` or `1m30s`"
+            ));
+        }
+    }
+}
+
+/// Whether a value is a duration like `30s` or `1m30s`, as Docker's `HEALTHCHECK` options take them.
+fn is_duration(value: &str) -> bool {
+    const UNITS: &[&str] = &["ns", "us", "µs", "ms", "s", "m", "h"];
+
+    let mut rest = value;
+    while !rest.is_empty() {
+        let number_len = rest
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .unwrap_or(rest.len());
+        let number = &rest[..number_len];
+        if !number.contains(|c: char| c.is_ascii_digit()) || number.matches('.').count() > 1 {
+            return false;
+        }
+        rest = &rest[number_len..];
+        match UNITS.iter().find(|unit| rest.starts_with(*unit)) {
+            Some(unit) => rest = &rest[unit.len()..],
+            None => return false,
+        }
+    }
+    !value.is_empty()
+}
+
+fn check_cmd(field: &str, cmd: Option<&String>, errors: &mut Vec<String>) {
+    if cmd.map_or(false, |cmd| cmd.trim().is_empty()) {
+        errors.push(format!("{field}: commands cannot be empty"));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate() {
+        let plan = BuildPlan::from_toml(
+            r#"
+            [variables]
+            NODE_ENV = "production"
+            "MY VAR" = "1"
+
+            [phases.setup]
+            nixPkgs = ["nodejs", ""]
+            nixSnippets = ["python3"]
+
+            [phases.build]
+            cmds = ["npm run build", " "]
+            rebuild = "never"
+
+            [start]
+            cmd = ""
+            workdir = ""
+            runImage = "debian:bookworm-slim"
+
+            [start.healthcheck]
+            path = "/health check"
+            interval = "30"
+            timeout = "1m30s"
+
+            [cron.cleanup]
+            schedule = "hourly"
+            cmd = "rm -rf tmp/*"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            plan.get_validation_errors(),
+            vec![
+                "variables: `MY VAR` is not a valid variable name, since it is empty or contains whitespace or `=`",
+                "phases.build.cmds[1]: commands cannot be empty",
+                "phases.build.rebuild: `never` needs onlyIncludeFiles to list the files the phase uses, or [] for none",
+                "phases.setup.nixPkgs: package names cannot be empty",
+                "phases.setup.nixSnippets: snippets must bind a name, like `myPython = python3.withPackages (ps: [ ps.numpy ]);`",
+                "start.cmd: commands cannot be empty",
+                "start.workdir: the directory cannot be empty",
+                "start.healthcheck.path: the path cannot be empty or contain whitespace, quotes, `$`, or backslashes",
+                "start.healthcheck.interval: `30` is not a duration, such as `30s` or `1m30s`",
+                "cron.cleanup.schedule: `hourly` is not a cron expression, which has 5 fields such as `*/15 * * * *`",
+                "cron: jobs can't run in a start.runImage, which doesn't have the scheduler",
+            ]
+        );
+        assert!(plan
+            .validate()
+            .unwrap_err()
+            .to_string()
+            .starts_with("Invalid build plan:\n  variables: `MY VAR`"));
+
+        let plan = BuildPlan::from_toml(
+            r#"
+            [phases.build]
+            cmds = ["npm run build"]
+
+            [start]
+            cmd = "npm start"
+            "#,
+        )
+        .unwrap();
+        assert!(plan.validate().is_ok());
+    }
+}
@@ -0,0 +1,274 @@
+use super::{app::App, environment::EnvironmentVariables, plan::phase::StartPhase};
+use anyhow::{bail, Context, Result};
+use regex::{Captures, Regex};
+use std::collections::BTreeMap;
+
+pub const PROCFILE: &str = "Procfile";
+
+/// The process that runs once before each deploy, rather than alongside the others.
+const RELEASE_PROCESS: &str = "release";
+
+/// The process types an app declares in its `Procfile`, with a `<name>: <command>` line for each.
+///
+/// Blank lines and lines starting with `#` are ignored. Commands can be wrapped in single or double quotes,
+/// which are removed, and everything else on the line is kept as is for the shell that runs it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Procfile {
+    /// Processes in the order they are declared.
+    processes: Vec<(String, String)>,
+}
+
+impl Procfile {
+    /// Processes declared elsewhere, such as the `run` section of a `heroku.yml`.
+    pub fn new(processes: Vec<(String, String)>) -> Procfile {
+        Procfile { processes }
+    }
+
+    /// Reads the app's `Procfile`, if it has one.
+    pub fn from_app(app: &App) -> Result<Option<Procfile>> {
+        if !app.includes_file(PROCFILE) {
+            return Ok(None);
+        }
+
+        let contents = app.read_file(PROCFILE)?;
+        Procfile::parse(&contents)
+            .context("Reading Procfile")
+            .map(Some)
+    }
+
+    pub fn parse(contents: &str) -> Result<Procfile> {
+        let mut processes: Vec<(String, String)> = Vec::new();
+
+        for (i, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (name, command) = match line.split_once(':') {
+                Some((name, command)) => (name.trim(), unquote(command.trim())),
+                None => bail!("Line {} is not `<process>: <command>`: `{line}`", i + 1),
+            };
+            if name.is_empty() || name.contains(char::is_whitespace) {
+                bail!("Line {} has an invalid process name `{name}`", i + 1);
+            }
+            if command.is_empty() {
+                bail!("Process `{name}` on line {} has no command", i + 1);
+            }
+            if processes.iter().any(|(existing, _)| existing == name) {
+                bail!("Process `{name}` is declared more than once");
+            }
+
+            processes.push((name.to_string(), command));
+        }
+
+        Ok(Procfile { processes })
+    }
+
+    /// Expands `$NAME` and `${NAME}` in the commands to the value of each variable that is set.
+    ///
+    /// Other variables, such as `$PORT`, and anything in single quotes are left for the shell to expand.
+    #[must_use]
+    pub fn expand_variables(mut self, variables: &EnvironmentVariables) -> Procfile {
+        for (_, command) in &mut self.processes {
+            *command = expand_variables(command, variables);
+        }
+        self
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.processes
+            .iter()
+            .find(|(process, _)| process == name)
+            .map(|(_, command)| command.as_str())
+    }
+
+    /// Every process other than `release`, which runs at deploy time instead.
+    pub fn processes(&self) -> BTreeMap<String, String> {
+        self.processes
+            .iter()
+            .filter(|(name, _)| name != RELEASE_PROCESS)
+            .cloned()
+            .collect()
+    }
+
+    /// The command the image starts with: `web`, then `worker`, then the first process by name.
+    pub fn start_cmd(&self) -> Option<&str> {
+        self.get("web").or_else(|| self.get("worker")).or_else(|| {
+            self.processes
+                .iter()
+                .filter(|(name, _)| name != RELEASE_PROCESS)
+                .min_by_key(|(name, _)| name)
+                .map(|(_, command)| command.as_str())
+        })
+    }
+
+    pub fn release_cmd(&self) -> Option<&str> {
+        self.get(RELEASE_PROCESS)
+    }
+
+    /// The start phase that runs these processes, or `None` when there aren't any.
+    pub fn start_phase(&self) -> Option<StartPhase> {
+        let mut start_phase = match self.start_cmd() {
+            Some(start_cmd) => {
+                let mut start_phase = StartPhase::new(start_cmd);
+                start_phase.processes = Some(self.processes());
+                start_phase
+            }
+            None => StartPhase::default(),
+        };
+
+        // The release command runs before each deploy of the built image, not while building it
+        start_phase.release_cmd = self.release_cmd().map(ToString::to_string);
+
+        Some(start_phase).filter(|start_phase| *start_phase != StartPhase::default())
+    }
+}
+
+/// Removes the quotes around a command that is quoted as a whole, like a YAML string.
+fn unquote(command: &str) -> String {
+    let quote = match command.chars().next() {
+        Some(quote @ ('"' | '\'')) if command.len() > 1 && command.ends_with(quote) => quote,
+        _ => return command.to_string(),
+    };
+    let inner = &command[1..command.len() - 1];
+
+    if quote == '\'' {
+        // `''` is an escaped quote, so a lone quote means the command isn't quoted as a whole
+        if inner.replace("''", "").contains('\'') {
+            return command.to_string();
+        }
+        return inner.replace("''", "'");
+    }
+
+    let mut unquoted = String::new();
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some(escaped @ ('"' | '\\')) => unquoted.push(escaped),
+                Some(other) => {
+                    unquoted.push('\\');
+                    unquoted.push(other);
+                }
+                None => unquoted.push('\\'),
+            },
+            '"' => return command.to_string(),
+            c => unquoted.push(c),
+        }
+    }
+    unquoted
+}
+
+fn expand_variables(command: &str, variables: &EnvironmentVariables) -> String {
+    let re =
+        Regex::new(r"(\\)?\$(?:\{([A-Za-z_][A-Za-z0-9_]*)\}|([A-Za-z_][A-Za-z0-9_]*))").unwrap();
+
+    // Every other part is inside single quotes, where the shell doesn't expand variables either
+    command
+        .split('\'')
+        .enumerate()
+        .map(|(i, part)| {
+            if i % 2 == 1 {
+                return part.to_string();
+            }
+            re.replace_all(part, |caps: &Captures| {
+                let name = caps.get(2).or_else(|| caps.get(3)).unwrap().as_str();
+                match variables.get(name) {
+                    Some(value) if caps.get(1).is_none() => value.clone(),
+                    _ => caps[0].to_string(),
+                }
+            })
+            .to_string()
+        })
+        .collect::<Vec<_>>()
+        .join("'")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() -> Result<()> {
+        let procfile = Procfile::parse(
+            "# Processes\n\
+             web: bundle exec puma -p $PORT\n\
+             \n\
+             worker-high_priority: bundle exec sidekiq -q high\n\
+             release: \"rake db:migrate\"\n\
+             clock:'echo ''tick'''\n",
+        )?;
+
+        assert_eq!(procfile.get("web"), Some("bundle exec puma -p $PORT"));
+        assert_eq!(
+            procfile.get("worker-high_priority"),
+            Some("bundle exec sidekiq -q high")
+        );
+        assert_eq!(procfile.release_cmd(), Some("rake db:migrate"));
+        assert_eq!(procfile.get("clock"), Some("echo 'tick'"));
+        assert_eq!(
+            procfile.processes().keys().collect::<Vec<_>>(),
+            vec!["clock", "web", "worker-high_priority"]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(Procfile::parse("web npm start").is_err());
+        assert!(Procfile::parse("web:").is_err());
+        assert!(Procfile::parse("my web: npm start").is_err());
+        assert!(Procfile::parse("web: npm start\nweb: node index.js").is_err());
+        assert_eq!(
+            Procfile::parse("# only comments\n\n").unwrap(),
+            Procfile::default()
+        );
+    }
+
+    #[test]
+    fn test_unquote() {
+        assert_eq!(unquote(r#""echo \"hi\"""#), r#"echo "hi""#);
+        assert_eq!(unquote(r#""a\nb""#), r"a\nb");
+        assert_eq!(unquote("'it''s'"), "it's");
+        // Only commands quoted as a whole are unquoted
+        assert_eq!(unquote(r#""a" && "b""#), r#""a" && "b""#);
+        assert_eq!(unquote("'a' 'b'"), "'a' 'b'");
+        assert_eq!(unquote("\""), "\"");
+    }
+
+    #[test]
+    fn test_start_cmd() -> Result<()> {
+        let start_cmd =
+            |contents| Procfile::parse(contents).map(|p| p.start_cmd().map(String::from));
+
+        assert_eq!(start_cmd("worker: b\nweb: a")?, Some("a".to_string()));
+        assert_eq!(start_cmd("other: c\nworker: b")?, Some("b".to_string()));
+        assert_eq!(start_cmd("zeta: z\nalpha: a")?, Some("a".to_string()));
+        assert_eq!(start_cmd("release: r")?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_expand_variables() -> Result<()> {
+        let variables = BTreeMap::from([
+            ("WORKERS".to_string(), "4".to_string()),
+            ("QUEUE".to_string(), "default".to_string()),
+        ]);
+        let procfile = Procfile::parse(
+            "web: gunicorn -w $WORKERS -b 0.0.0.0:$PORT app:app\n\
+             worker: rq worker ${QUEUE} '$QUEUE' \\$QUEUE",
+        )?
+        .expand_variables(&variables);
+
+        assert_eq!(
+            procfile.get("web"),
+            Some("gunicorn -w 4 -b 0.0.0.0:$PORT app:app")
+        );
+        assert_eq!(
+            procfile.get("worker"),
+            Some("rq worker default '$QUEUE' \\$QUEUE")
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,100 @@
+use crate::nixpacks::{
+    environment::EnvironmentVariables,
+    nix::pkg::Pkg,
+    plan::{phase::Phase, NIX_PROFILE},
+};
+
+pub const CHROMIUM_PKG: &str = "chromium";
+
+/// Node packages that drive a headless browser, including their `-core` variants.
+pub const NODE_BROWSER_DEPENDENCIES: &[&str] = &["puppeteer", "playwright"];
+
+/// Python packages that drive a headless browser.
+pub const PYTHON_BROWSER_DEPENDENCIES: &[&str] = &["playwright", "pyppeteer"];
+
+/// Libraries that the browsers downloaded by Puppeteer and Playwright load, for apps that use one of those instead.
+/// https://gist.github.com/winuxue/cfef08e2f5fe9dfc16a1d67a4ad38a01
+const BROWSER_APT_PKGS: &[&str] = &[
+    "libnss3",
+    "libatk1.0-0",
+    "libatk-bridge2.0-0",
+    "libcups2",
+    "libgbm1",
+    "libasound2",
+    "libpangocairo-1.0-0",
+    "libxss1",
+    "libgtk-3-0",
+    "libxshmfence1",
+    "libglu1",
+];
+
+/// Installs Chromium and the libraries headless browsers need, for the first browser dependency the app uses.
+///
+/// Returns whether one was found, in which case the plan also needs `chromium_variables`.
+pub fn add_chromium(
+    setup: &mut Phase,
+    dependencies: &[&str],
+    uses_dependency: impl Fn(&str) -> bool,
+) -> bool {
+    let dependency = match dependencies.iter().find(|dep| uses_dependency(dep)) {
+        Some(dependency) => dependency,
+        None => return false,
+    };
+
+    eprintln!("Adding `{CHROMIUM_PKG}` because the app depends on `{dependency}`");
+    setup.add_nix_pkgs(&[Pkg::new(CHROMIUM_PKG)]);
+    setup.add_apt_pkgs(BROWSER_APT_PKGS.iter().map(ToString::to_string).collect());
+    true
+}
+
+/// Points Puppeteer at the installed Chromium instead of downloading its own while installing.
+pub fn chromium_variables() -> EnvironmentVariables {
+    let skip_download = [
+        "PUPPETEER_SKIP_DOWNLOAD",
+        // Versions of Puppeteer before 19
+        "PUPPETEER_SKIP_CHROMIUM_DOWNLOAD",
+    ];
+
+    skip_download
+        .iter()
+        .map(|name| ((*name).to_string(), "true".to_string()))
+        .chain([(
+            "PUPPETEER_EXECUTABLE_PATH".to_string(),
+            format!("{NIX_PROFILE}/bin/{CHROMIUM_PKG}"),
+        )])
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_add_chromium() {
+        let mut setup = Phase::setup(Some(vec![Pkg::new("nodejs_18")]));
+        assert!(add_chromium(&mut setup, NODE_BROWSER_DEPENDENCIES, |dep| {
+            dep == "playwright"
+        }));
+        assert_eq!(
+            setup.nix_pkgs,
+            Some(vec!["nodejs_18".to_string(), CHROMIUM_PKG.to_string()])
+        );
+        assert!(setup.apt_pkgs.unwrap().contains(&"libnss3".to_string()));
+
+        let mut setup = Phase::setup(None);
+        assert!(!add_chromium(
+            &mut setup,
+            PYTHON_BROWSER_DEPENDENCIES,
+            |_| false
+        ));
+        assert_eq!(setup, Phase::setup(None));
+    }
+
+    #[test]
+    fn test_chromium_variables() {
+        assert_eq!(
+            chromium_variables()["PUPPETEER_EXECUTABLE_PATH"],
+            "/nix/var/nix/profiles/default/bin/chromium"
+        );
+    }
+}
@@ -1,4 +1,7 @@
-use super::Provider;
+use super::{
+    jlink::{self, JLINK_JAR, JLINK_RUNTIME_DIR},
+    Confidence, Provider,
+};
 use crate::nixpacks::{
     app::App,
     environment::Environment,
@@ -20,12 +23,16 @@ impl Provider for ClojureProvider {
         "clojure"
     }
 
-    fn detect(&self, app: &App, _env: &Environment) -> Result<bool> {
-        Ok(self.is_using_lein(app) || self.is_using_tools_build(app))
+    fn detect(&self, app: &App, _env: &Environment) -> Result<Confidence> {
+        // Leiningen and deps.edn projects only build as Clojure, so they outrank a package.json for shadow-cljs
+        Ok(Confidence::first_of(&[(
+            self.is_using_lein(app) || self.is_using_tools_build(app),
+            Confidence::High,
+        )]))
     }
 
     fn get_build_plan(&self, app: &App, env: &Environment) -> Result<Option<BuildPlan>> {
-        let setup = Phase::setup(Some(vec![
+        let mut setup = Phase::setup(Some(vec![
             if self.is_using_tools_build(app) {
                 Pkg::new("clojure")
             } else {
@@ -53,11 +60,24 @@ impl Provider for ClojureProvider {
         /*
           Looks for a file ending with standalone.jar or .jar but not *-SNAPSHOT.jar and starts the app
         */
-        let start = StartPhase::new(
-            r#"JAR_FILE=$(find /app/target -name "*-standalone.jar" -o -name "*.jar" ! -name "*-SNAPSHOT.jar" | head -n 1) && bash -c "java $JAVA_OPTS -jar $JAR_FILE""#,
-        );
+        let find_jar = r#"find /app/target -name "*-standalone.jar" -o -name "*.jar" ! -name "*-SNAPSHOT.jar" | head -n 1"#;
+        let start = if jlink::is_enabled(env) {
+            build.add_cmd(format!("cp \"$({find_jar})\" {JLINK_JAR}"));
+            let jdk_version = ClojureProvider::get_jlink_jdk_version(app, env)?;
+            jlink::link_runtime(env, jdk_version, JLINK_JAR, &mut setup, &mut build)?;
+
+            let mut start = StartPhase::new(format!(
+                "./{JLINK_RUNTIME_DIR}/bin/java $JAVA_OPTS -jar {JLINK_JAR}"
+            ));
+            jlink::run_with_runtime(env, &mut start, &[JLINK_JAR]);
+            start
+        } else {
+            StartPhase::new(format!(
+                r#"JAR_FILE=$({find_jar}) && bash -c "java $JAVA_OPTS -jar $JAR_FILE""#
+            ))
+        };
 
-        let plan = BuildPlan::new(&vec![setup, build], Some(start));
+        let plan = BuildPlan::new(&[setup, build], Some(start));
         Ok(Some(plan))
     }
 }
@@ -118,6 +138,18 @@ impl ClojureProvider {
         Ok(value.to_string())
     }
 
+    /// The JDK to link a runtime with, which runs the jars built with the Nix JDK since it is the same or newer.
+    fn get_jlink_jdk_version(app: &App, env: &Environment) -> Result<u32> {
+        let custom_version = ClojureProvider::get_custom_version(app, env)?;
+        Ok(
+            match ClojureProvider::parse_custom_version(&custom_version)?.as_str() {
+                "latest" => 21,
+                "11" => 11,
+                _ => 17,
+            },
+        )
+    }
+
     pub fn get_nix_jdk_package(app: &App, env: &Environment) -> Result<Pkg> {
         let custom_version = ClojureProvider::get_custom_version(app, env)?;
         let parsed_version = ClojureProvider::parse_custom_version(&custom_version)?;
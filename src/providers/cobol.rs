@@ -1,6 +1,6 @@
 use std::{path::PathBuf, str::FromStr};
 
-use super::Provider;
+use super::{Confidence, Provider};
 use crate::nixpacks::{
     app::App,
     environment::Environment,
@@ -10,7 +10,7 @@ use crate::nixpacks::{
         BuildPlan,
     },
 };
-use anyhow::Result;
+use anyhow::{Context, Result};
 use path_slash::PathBufExt;
 
 const COBOL_COMPILE_ARGS: &str = "COBOL_COMPILE_ARGS";
@@ -24,8 +24,11 @@ impl Provider for CobolProvider {
         "cobol"
     }
 
-    fn detect(&self, app: &App, _env: &Environment) -> Result<bool> {
-        Ok(app.has_match("*.cbl"))
+    fn detect(&self, app: &App, _env: &Environment) -> Result<Confidence> {
+        Ok(Confidence::first_of(&[(
+            app.has_match("*.cbl"),
+            Confidence::Low,
+        )]))
     }
 
     fn get_build_plan(
@@ -50,13 +53,15 @@ impl Provider for CobolProvider {
             "cobc {} {} {}",
             compile_args,
             file_name,
-            app_path.as_os_str().to_str().unwrap()
+            app_path
+                .to_str()
+                .context("The COBOL source path must be valid UTF-8")?
         )));
         build.depends_on_phase("setup");
 
         let start = StartPhase::new(format!("./{file_name}"));
 
-        let plan = BuildPlan::new(&vec![setup, build], Some(start));
+        let plan = BuildPlan::new(&[setup, build], Some(start));
         Ok(Some(plan))
     }
 }
@@ -90,10 +95,7 @@ impl CobolProvider {
     }
 
     fn normalized_path(path: &PathBuf) -> Option<PathBuf> {
-        path.to_slash().and_then(|normalized_path| {
-            PathBuf::from_str(normalized_path.as_ref())
-                .map(Some)
-                .unwrap_or(None)
-        })
+        path.to_slash()
+            .and_then(|normalized_path| PathBuf::from_str(normalized_path.as_ref()).ok())
     }
 }
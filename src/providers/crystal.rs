@@ -1,6 +1,6 @@
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 
-use super::Provider;
+use super::{Confidence, Provider};
 use crate::nixpacks::{
     app::App,
     environment::Environment,
@@ -17,7 +17,7 @@ use serde::Deserialize;
 #[derive(Deserialize, Debug)]
 pub struct ShardYaml {
     pub name: String,
-    pub targets: HashMap<String, HashMap<String, String>>,
+    pub targets: BTreeMap<String, BTreeMap<String, String>>,
 }
 
 pub struct CrystalProvider {}
@@ -27,8 +27,11 @@ impl Provider for CrystalProvider {
         "crystal"
     }
 
-    fn detect(&self, app: &App, _env: &Environment) -> Result<bool> {
-        Ok(app.includes_file("shard.yml"))
+    fn detect(&self, app: &App, _env: &Environment) -> Result<Confidence> {
+        Ok(Confidence::from_files(
+            app.includes_file("shard.yml"),
+            app.includes_file("shard.lock"),
+        ))
     }
 
     fn get_build_plan(&self, app: &App, _env: &Environment) -> Result<Option<BuildPlan>> {
@@ -45,7 +48,7 @@ impl Provider for CrystalProvider {
                 .ok_or_else(|| anyhow::anyhow!("Unable to get executable name"))?
         ));
 
-        let plan = BuildPlan::new(&vec![setup, install, build], Some(start));
+        let plan = BuildPlan::new(&[setup, install, build], Some(start));
         Ok(Some(plan))
     }
 }
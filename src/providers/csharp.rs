@@ -1,4 +1,4 @@
-use super::Provider;
+use super::{Confidence, Provider};
 use crate::nixpacks::{
     app::App,
     environment::{Environment, EnvironmentVariables},
@@ -9,7 +9,9 @@ use crate::nixpacks::{
     },
 };
 use anyhow::{Context, Result};
+use path_slash::PathExt;
 use serde::{Deserialize, Serialize};
+use std::{ffi::OsStr, path::PathBuf};
 
 #[derive(Serialize, Deserialize, Default, Debug)]
 pub struct CSharpSdk {
@@ -30,27 +32,37 @@ impl Provider for CSharpProvider {
         "c#"
     }
 
-    fn detect(&self, app: &App, _env: &Environment) -> Result<bool> {
-        Ok(!app.find_files("*.csproj")?.is_empty())
+    fn detect(&self, app: &App, _env: &Environment) -> Result<Confidence> {
+        Ok(Confidence::from_files(
+            app.includes_glob("**/*.csproj"),
+            app.includes_glob("**/packages.lock.json"),
+        ))
     }
 
     fn get_build_plan(&self, app: &App, env: &Environment) -> Result<Option<BuildPlan>> {
+        let csproj = CSharpProvider::get_project_file(app)?;
+        // dotnet only finds projects and solutions in the current directory
+        let project_arg = if CSharpProvider::has_root_project(app) {
+            String::new()
+        } else {
+            format!(" {}", csproj.to_slash_lossy())
+        };
+
         let sdk = CSharpProvider::get_sdk_version(app, env);
         let setup = Phase::setup(Some(vec![Pkg::new(sdk?.as_str())]));
-        let install = Phase::install(Some("dotnet restore".to_string()));
+        let install = Phase::install(Some(format!("dotnet restore{project_arg}")));
         let build = Phase::build(Some(format!(
-            "dotnet publish --no-restore -c Release -o {ARTIFACT_DIR}"
+            "dotnet publish{project_arg} --no-restore -c Release -o {ARTIFACT_DIR}"
         )));
 
-        let csproj = &app.find_files("*.csproj")?[0].with_extension("");
         let project_name = csproj
-            .file_name()
+            .file_stem()
             .context("Invalid file_name")?
             .to_str()
             .context("Invalid project_name")?;
         let start = StartPhase::new(format!("./{ARTIFACT_DIR}/{project_name}"));
 
-        let mut plan = BuildPlan::new(&vec![setup, install, build], Some(start));
+        let mut plan = BuildPlan::new(&[setup, install, build], Some(start));
         plan.add_variables(EnvironmentVariables::from([
             (
                 "ASPNETCORE_ENVIRONMENT".to_string(),
@@ -71,6 +83,35 @@ impl Provider for CSharpProvider {
 }
 
 impl CSharpProvider {
+    /// The project to build, preferring one in the root over projects in nested directories.
+    fn get_project_file(app: &App) -> Result<PathBuf> {
+        let root_project = app
+            .paths
+            .iter()
+            .find(|path| path.is_file() && path.extension() == Some(OsStr::new("csproj")));
+        let project = match root_project {
+            Some(project) => project.clone(),
+            None => app
+                .find_files("**/*.csproj")?
+                .into_iter()
+                .next()
+                .context("No .csproj file found")?,
+        };
+
+        app.strip_source_path(&project)
+    }
+
+    /// Whether the root has a project or solution that `dotnet` commands build without being given a path.
+    fn has_root_project(app: &App) -> bool {
+        app.paths.iter().any(|path| {
+            path.is_file()
+                && matches!(
+                    path.extension().and_then(OsStr::to_str),
+                    Some("csproj" | "sln")
+                )
+        })
+    }
+
     fn get_sdk_version(app: &App, env: &Environment) -> Result<String> {
         // Check if a version is specified in global.json
         let global_json = if app.includes_file("global.json") {
@@ -1,4 +1,4 @@
-use super::Provider;
+use super::{Confidence, Provider};
 use crate::nixpacks::{
     app::App,
     environment::Environment,
@@ -26,8 +26,11 @@ impl Provider for DartProvider {
         "dart"
     }
 
-    fn detect(&self, app: &App, _env: &Environment) -> Result<bool> {
-        Ok(app.includes_file("pubspec.yaml"))
+    fn detect(&self, app: &App, _env: &Environment) -> Result<Confidence> {
+        Ok(Confidence::from_files(
+            app.includes_file("pubspec.yaml"),
+            app.includes_file("pubspec.lock"),
+        ))
     }
 
     fn get_build_plan(&self, app: &App, _env: &Environment) -> Result<Option<BuildPlan>> {
@@ -42,7 +45,7 @@ impl Provider for DartProvider {
         let pubspec = DartProvider::get_pubspec(app)?;
         let start = StartPhase::new(format!("./bin/{}.exe", pubspec.name));
 
-        let plan = BuildPlan::new(&vec![setup, install, build], Some(start));
+        let plan = BuildPlan::new(&[setup, install, build], Some(start));
         Ok(Some(plan))
     }
 }
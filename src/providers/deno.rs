@@ -1,6 +1,6 @@
 use std::path::PathBuf;
 
-use super::Provider;
+use super::{Confidence, Provider};
 use crate::nixpacks::{
     app::App,
     environment::Environment,
@@ -32,14 +32,23 @@ impl Provider for DenoProvider {
         "deno"
     }
 
-    fn detect(&self, app: &App, _env: &Environment) -> Result<bool> {
+    fn detect(&self, app: &App, _env: &Environment) -> Result<Confidence> {
+        if app.includes_file("deno.json") || app.includes_file("deno.jsonc") {
+            return Ok(Confidence::from_files(true, app.includes_file("deno.lock")));
+        }
+        // The Node provider detects apps with a package.json more confidently, so they aren't searched
+        if app.includes_file("package.json") {
+            return Ok(Confidence::None);
+        }
+
         let re = Regex::new(
             r#"import .+ from (?:"|'|`)https://deno.land/[^"`']+\.(?:ts|js|tsx|jsx)(?:"|'|`);?"#,
         )
         .unwrap();
-        Ok(app.includes_file("deno.json")
-            || app.includes_file("deno.jsonc")
-            || app.find_match(&re, "**/*.{ts,tsx,js,jsx}")?)
+        Ok(Confidence::first_of(&[(
+            app.find_match(&re, "**/*.{ts,tsx,js,jsx}")?,
+            Confidence::Low,
+        )]))
     }
 
     fn get_build_plan(&self, app: &App, _env: &Environment) -> Result<Option<BuildPlan>> {
@@ -52,7 +61,7 @@ impl Provider for DenoProvider {
             let mut build = Phase::build(Some(build_cmd));
             build.depends_on_phase("setup");
             plan.add_phase(build);
-        };
+        }
 
         if let Some(start_cmd) = DenoProvider::get_start_cmd(app)? {
             let start = StartPhase::new(start_cmd);
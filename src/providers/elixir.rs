@@ -1,4 +1,4 @@
-use super::Provider;
+use super::{Confidence, Provider};
 use crate::nixpacks::{
     app::App,
     environment::{Environment, EnvironmentVariables},
@@ -20,8 +20,11 @@ impl Provider for ElixirProvider {
         "elixir"
     }
 
-    fn detect(&self, app: &App, _env: &Environment) -> Result<bool> {
-        Ok(app.includes_file("mix.exs"))
+    fn detect(&self, app: &App, _env: &Environment) -> Result<Confidence> {
+        Ok(Confidence::from_files(
+            app.includes_file("mix.exs"),
+            app.includes_file("mix.lock"),
+        ))
     }
 
     fn get_build_plan(&self, app: &App, env: &Environment) -> Result<Option<BuildPlan>> {
@@ -91,7 +94,7 @@ impl ElixirProvider {
     }
 
     fn get_nix_elixir_package(app: &App, env: &Environment) -> Result<Pkg> {
-        fn as_default(v: Option<Match>) -> &str {
+        fn as_default(v: Option<Match<'_>>) -> &str {
             match v {
                 Some(m) => m.as_str(),
                 None => "_",
@@ -1,4 +1,4 @@
-use super::Provider;
+use super::{Confidence, Provider};
 use crate::nixpacks::{
     app::App,
     environment::{Environment, EnvironmentVariables},
@@ -9,6 +9,8 @@ use crate::nixpacks::{
     },
 };
 use anyhow::{Context, Result};
+use path_slash::PathExt;
+use std::{ffi::OsStr, path::PathBuf};
 
 pub struct FSharpProvider {}
 
@@ -19,26 +21,36 @@ impl Provider for FSharpProvider {
         "f#"
     }
 
-    fn detect(&self, app: &App, _env: &Environment) -> Result<bool> {
-        Ok(!app.find_files("*.fsproj")?.is_empty())
+    fn detect(&self, app: &App, _env: &Environment) -> Result<Confidence> {
+        Ok(Confidence::from_files(
+            app.includes_glob("**/*.fsproj"),
+            app.includes_glob("**/packages.lock.json"),
+        ))
     }
 
     fn get_build_plan(&self, app: &App, _env: &Environment) -> Result<Option<BuildPlan>> {
+        let fsproj = FSharpProvider::get_project_file(app)?;
+        // dotnet only finds projects and solutions in the current directory
+        let project_arg = if FSharpProvider::has_root_project(app) {
+            String::new()
+        } else {
+            format!(" {}", fsproj.to_slash_lossy())
+        };
+
         let setup = Phase::setup(Some(vec![Pkg::new("dotnet-sdk")]));
-        let install = Phase::install(Some("dotnet restore".to_string()));
+        let install = Phase::install(Some(format!("dotnet restore{project_arg}")));
         let build = Phase::build(Some(format!(
-            "dotnet publish --no-restore -c Release -o {ARTIFACT_DIR}"
+            "dotnet publish{project_arg} --no-restore -c Release -o {ARTIFACT_DIR}"
         )));
 
-        let fsproj = &app.find_files("*.fsproj")?[0].with_extension("");
         let project_name = fsproj
-            .file_name()
+            .file_stem()
             .context("Invalid file_name")?
             .to_str()
             .context("Invalid project_name")?;
         let start = StartPhase::new(format!("./{ARTIFACT_DIR}/{project_name}"));
 
-        let mut plan = BuildPlan::new(&vec![setup, install, build], Some(start));
+        let mut plan = BuildPlan::new(&[setup, install, build], Some(start));
         plan.add_variables(EnvironmentVariables::from([
             (
                 "ASPNETCORE_ENVIRONMENT".to_string(),
@@ -57,3 +69,34 @@ impl Provider for FSharpProvider {
         Ok(Some(plan))
     }
 }
+
+impl FSharpProvider {
+    /// The project to build, preferring one in the root over projects in nested directories.
+    fn get_project_file(app: &App) -> Result<PathBuf> {
+        let root_project = app
+            .paths
+            .iter()
+            .find(|path| path.is_file() && path.extension() == Some(OsStr::new("fsproj")));
+        let project = match root_project {
+            Some(project) => project.clone(),
+            None => app
+                .find_files("**/*.fsproj")?
+                .into_iter()
+                .next()
+                .context("No .fsproj file found")?,
+        };
+
+        app.strip_source_path(&project)
+    }
+
+    /// Whether the root has a project or solution that `dotnet` commands build without being given a path.
+    fn has_root_project(app: &App) -> bool {
+        app.paths.iter().any(|path| {
+            path.is_file()
+                && matches!(
+                    path.extension().and_then(OsStr::to_str),
+                    Some("fsproj" | "sln")
+                )
+        })
+    }
+}
@@ -10,7 +10,7 @@ use crate::nixpacks::{
     },
 };
 
-use super::Provider;
+use super::{Confidence, Provider};
 
 #[derive(Serialize, Deserialize, Debug)]
 struct GleamPackageSpec {
@@ -42,8 +42,15 @@ impl Provider for GleamProvider {
         "gleam"
     }
 
-    fn detect(&self, app: &App, _env: &Environment) -> Result<bool> {
-        Ok(app.has_match("gleam.toml") && app.has_match("manifest.toml"))
+    fn detect(&self, app: &App, _env: &Environment) -> Result<Confidence> {
+        // The manifest.toml is Gleam's lock file
+        Ok(
+            if app.has_match("gleam.toml") && app.has_match("manifest.toml") {
+                Confidence::High
+            } else {
+                Confidence::None
+            },
+        )
     }
 
     fn get_build_plan(&self, app: &App, env: &Environment) -> Result<Option<BuildPlan>> {
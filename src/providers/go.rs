@@ -1,4 +1,4 @@
-use super::Provider;
+use super::{Confidence, Provider, ProviderMetadata};
 use crate::nixpacks::{
     app::App,
     environment::{Environment, EnvironmentVariables},
@@ -51,8 +51,27 @@ impl Provider for GolangProvider {
         "go"
     }
 
-    fn detect(&self, app: &App, _env: &Environment) -> Result<bool> {
-        Ok(app.includes_file("main.go") || app.includes_file("go.mod"))
+    fn detect(&self, app: &App, _env: &Environment) -> Result<Confidence> {
+        Ok(Confidence::first_of(&[
+            (
+                app.includes_file("go.mod"),
+                Confidence::from_files(true, app.includes_file("go.sum")),
+            ),
+            (app.includes_file("main.go"), Confidence::Low),
+        ]))
+    }
+
+    fn metadata(&self, app: &App, _env: &Environment) -> Result<ProviderMetadata> {
+        let go_mod = self.read_go_mod_if_exists(app)?;
+        let (go_pkg, _) = GolangProvider::get_nix_golang_pkg(go_mod.as_ref())?;
+        let version = AVAILABLE_GO_VERSIONS
+            .iter()
+            .find(|(_, pkg, _)| *pkg == go_pkg)
+            .map(|(version, _, _)| (*version).to_string());
+
+        Ok(ProviderMetadata::default()
+            .set_version(version)
+            .set_reason(ProviderMetadata::found_file(app, &["go.mod", "main.go"])))
     }
 
     fn get_build_plan(&self, app: &App, env: &Environment) -> Result<Option<BuildPlan>> {
@@ -68,9 +87,7 @@ impl Provider for GolangProvider {
         let is_go_module = app.includes_file("go.mod");
 
         if is_go_module {
-            let mut install = Phase::install(Some("go mod download".to_string()));
-            install.add_cache_directory(GO_BUILD_CACHE_DIR.to_string());
-            plan.add_phase(install);
+            plan.add_phase(Phase::install(Some("go mod download".to_string())));
         }
 
         let has_root_go_files = app.find_files("*.go").ok().map_or(false, |files| {
@@ -105,19 +122,25 @@ impl Provider for GolangProvider {
         };
 
         let mut build = Phase::build(build_command);
-        build.add_cache_directory(GO_BUILD_CACHE_DIR.to_string());
         build.depends_on_phase("setup");
         plan.add_phase(build);
+        plan.add_cache_directory(GO_BUILD_CACHE_DIR);
 
         let has_go_files = app.has_match("**/*.go");
 
         if has_go_files {
             let mut start = StartPhase::new(format!("./{BINARY_NAME}"));
-            let cgo = env.get_variable("CGO_ENABLED").unwrap_or("0");
-
-            // Only run in a new image if CGO_ENABLED=0 (default)
-            if cgo != "1" {
-                start.run_in_slim_image();
+            let cgo = env.get_config_or_variable("CGO_ENABLED");
+
+            // Only run in a new image if CGO_ENABLED=0 (default), since the binary is then statically linked
+            match cgo.as_deref() {
+                Some("1") => {}
+                // Only the binary is copied to the empty image, so apps that read other files at runtime opt in
+                _ if env.is_config_variable_truthy("SCRATCH") => {
+                    start.run_in_scratch_image();
+                    start.add_file_dependency(BINARY_NAME);
+                }
+                _ => start.run_in_slim_image(),
             }
             plan.set_start_phase(start);
         }
@@ -141,8 +164,8 @@ impl GolangProvider {
     }
 
     pub fn get_nix_golang_pkg(go_mod_contents: Option<&String>) -> Result<(String, String)> {
-        if go_mod_contents.is_some() {
-            let mut lines = go_mod_contents.as_ref().unwrap().lines();
+        if let Some(go_mod_contents) = go_mod_contents {
+            let mut lines = go_mod_contents.lines();
             let go_version_line = lines.find(|line| line.trim().starts_with("go"));
 
             if let Some(go_version_line) = go_version_line {
@@ -1,4 +1,4 @@
-use super::Provider;
+use super::{Confidence, Provider};
 use crate::nixpacks::{
     app::App,
     environment::Environment,
@@ -23,8 +23,11 @@ impl Provider for HaskellStackProvider {
         "haskell"
     }
 
-    fn detect(&self, app: &App, _env: &Environment) -> Result<bool> {
-        Ok(app.includes_file("package.yaml") && app.has_match("**/*.hs"))
+    fn detect(&self, app: &App, _env: &Environment) -> Result<Confidence> {
+        Ok(Confidence::from_files(
+            app.includes_file("package.yaml") && app.has_match("**/*.hs"),
+            app.includes_file("stack.yaml.lock"),
+        ))
     }
 
     fn get_build_plan(&self, app: &App, _env: &Environment) -> Result<Option<BuildPlan>> {
@@ -51,11 +54,9 @@ impl Provider for HaskellStackProvider {
             ]);
         }
 
-        let mut install = Phase::install(Some("stack setup".to_string()));
-        install.add_cache_directory(STACK_CACHE_DIR.to_string());
+        let install = Phase::install(Some("stack setup".to_string()));
 
         let mut build = Phase::build(Some("stack install".to_string()));
-        build.add_cache_directory(STACK_CACHE_DIR.to_string());
         build.add_cache_directory(STACK_WORK_CACHE_DIR.to_string());
 
         let package: HaskellStackPackageYaml = app.read_yaml("package.yaml")?;
@@ -67,7 +68,8 @@ impl Provider for HaskellStackProvider {
 
         let start = StartPhase::new(format!("/root/.local/bin/{name}"));
 
-        let plan = BuildPlan::new(&vec![setup, install, build], Some(start));
+        let mut plan = BuildPlan::new(&[setup, install, build], Some(start));
+        plan.add_cache_directory(STACK_CACHE_DIR);
 
         Ok(Some(plan))
     }
@@ -1,4 +1,7 @@
-use super::Provider;
+use super::{
+    jlink::{self, JLINK_JAR, JLINK_RUNTIME_DIR},
+    Confidence, Provider, ProviderMetadata,
+};
 use crate::nixpacks::{
     app::App,
     environment::Environment,
@@ -22,16 +25,25 @@ impl Provider for JavaProvider {
         "java"
     }
 
-    fn detect(&self, app: &App, _env: &Environment) -> Result<bool> {
-        Ok(app.includes_file("pom.xml")
-            || app.includes_directory("pom.atom")
-            || app.includes_directory("pom.clj")
-            || app.includes_directory("pom.groovy")
-            || app.includes_file("pom.rb")
-            || app.includes_file("pom.scala")
-            || app.includes_file("pom.yaml")
-            || app.includes_file("pom.yml")
-            || app.includes_file("gradlew"))
+    fn detect(&self, app: &App, _env: &Environment) -> Result<Confidence> {
+        // Maven and Gradle projects only build as Java, so they outrank a package.json for frontend assets
+        Ok(Confidence::first_of(&[(
+            app.includes_file("pom.xml")
+                || app.includes_directory("pom.atom")
+                || app.includes_directory("pom.clj")
+                || app.includes_directory("pom.groovy")
+                || app.includes_file("pom.rb")
+                || app.includes_file("pom.scala")
+                || app.includes_file("pom.yaml")
+                || app.includes_file("pom.yml")
+                || app.includes_file("gradlew"),
+            Confidence::High,
+        )]))
+    }
+
+    fn metadata(&self, app: &App, _env: &Environment) -> Result<ProviderMetadata> {
+        Ok(ProviderMetadata::default()
+            .set_reason(ProviderMetadata::found_file(app, &["pom.xml", "gradlew"])))
     }
 
     fn get_build_plan(&self, app: &App, env: &Environment) -> Result<Option<BuildPlan>> {
@@ -69,9 +81,13 @@ impl Provider for JavaProvider {
             (setup, build)
         };
 
-        let start = StartPhase::new(self.get_start_cmd(app)?);
+        let (setup, build, start) = if jlink::is_enabled(env) {
+            self.with_jlink_runtime(app, env, setup, build)?
+        } else {
+            (setup, build, StartPhase::new(self.get_start_cmd(app)?))
+        };
 
-        let plan = BuildPlan::new(&vec![setup, build], Some(start));
+        let plan = BuildPlan::new(&[setup, build], Some(start));
         Ok(Some(plan))
     }
 }
@@ -96,25 +112,65 @@ impl JavaProvider {
         }
     }
 
+    /// The jar that the build creates.
+    fn get_jar(&self, app: &App) -> &'static str {
+        if self.is_using_gradle(app) {
+            "$(ls -1 build/libs/*jar | grep -v plain)"
+        } else {
+            "target/*jar"
+        }
+    }
+
+    /// The largest jar that the build creates, which is the one with the dependencies when a fat jar is built.
+    fn get_main_jar(&self, app: &App) -> String {
+        let dir = if self.is_using_gradle(app) {
+            "build/libs"
+        } else {
+            "target"
+        };
+        format!("$(ls -1S {dir}/*.jar | grep -v -e -plain.jar -e -sources.jar -e -javadoc.jar | head -n 1)")
+    }
+
     fn get_start_cmd(&self, app: &App) -> Result<String> {
+        self.get_java_start_cmd(app, "java", self.get_jar(app))
+    }
+
+    /// The command that starts the app's jar with a `java` binary, which is in the runtime linked with `jlink` when it is used.
+    fn get_java_start_cmd(&self, app: &App, java: &str, jar: &str) -> Result<String> {
         let build_gradle_content = self.read_build_gradle(app)?;
         let cmd = if self.is_using_gradle(app) {
             format!(
-                "java $JAVA_OPTS -jar {} $(ls -1 build/libs/*jar | grep -v plain)",
+                "{java} $JAVA_OPTS -jar {} {jar}",
                 self.get_gradle_port_config(&build_gradle_content)
             )
         } else if app.includes_file("pom.xml") {
-            format!(
-                "java {} $JAVA_OPTS -jar target/*jar",
-                self.get_port_config(app)
-            )
+            format!("{java} {} $JAVA_OPTS -jar {jar}", self.get_port_config(app))
         } else {
-            "java $JAVA_OPTS -jar target/*jar".to_string()
+            format!("{java} $JAVA_OPTS -jar {jar}")
         };
 
         Ok(cmd)
     }
 
+    /// Links a runtime with only the modules the app uses, and runs the app with it in a slim image without the JDK.
+    fn with_jlink_runtime(
+        &self,
+        app: &App,
+        env: &Environment,
+        mut setup: Phase,
+        mut build: Phase,
+    ) -> Result<(Phase, Phase, StartPhase)> {
+        let jdk_version = self.get_jdk_version(app, env)?;
+        build.add_cmd(format!("cp \"{}\" {JLINK_JAR}", self.get_main_jar(app)));
+        jlink::link_runtime(env, jdk_version, JLINK_JAR, &mut setup, &mut build)?;
+
+        let java = format!("./{JLINK_RUNTIME_DIR}/bin/java");
+        let mut start = StartPhase::new(self.get_java_start_cmd(app, &java, JLINK_JAR)?);
+        jlink::run_with_runtime(env, &mut start, &[JLINK_JAR]);
+
+        Ok((setup, build, start))
+    }
+
     fn is_using_gradle(&self, app: &App) -> bool {
         app.includes_file("gradlew")
     }
@@ -0,0 +1,101 @@
+use crate::nixpacks::{
+    environment::Environment,
+    plan::phase::{Phase, StartPhase},
+};
+use anyhow::{bail, Result};
+
+/// JDK versions with an Ubuntu package to link runtimes with. The Nix JDKs link runtimes that only run with Nix.
+const UBUNTU_JDK_VERSIONS: &[u32] = &[11, 17, 21];
+/// JDK versions with a package in Debian bullseye, the distribution of the Debian base image.
+const DEBIAN_JDK_VERSIONS: &[u32] = &[11, 17];
+/// Modules that are always linked, since `jdeps` can't see the dependencies packed inside fat jars like Spring Boot's.
+const BASE_MODULES: &[&str] = &[
+    "java.base",
+    "java.desktop",
+    "java.instrument",
+    "java.logging",
+    "java.management",
+    "java.naming",
+    "java.net.http",
+    "java.security.jgss",
+    "java.sql",
+    "java.xml",
+    "jdk.crypto.ec",
+    "jdk.unsupported",
+    "jdk.zipfs",
+];
+/// Where single jar apps are copied to, so the start command doesn't have to find them in the runtime image.
+pub const JLINK_JAR: &str = "app.jar";
+/// Directory of the runtime linked with `jlink`.
+pub const JLINK_RUNTIME_DIR: &str = "jre";
+const DEBIAN_RUN_IMAGE: &str = "debian:bullseye-slim";
+
+/// Whether the app is run with a runtime linked with `jlink`, from `NIXPACKS_JLINK`.
+pub fn is_enabled(env: &Environment) -> bool {
+    env.is_config_variable_truthy("JLINK")
+}
+
+/// Links a runtime with only the modules that the `jars` use, after the build phase has built them.
+///
+/// The `jars` are shell words, so they can be globs such as `lib/*.jar`.
+pub fn link_runtime(
+    env: &Environment,
+    jdk_version: u32,
+    jars: &str,
+    setup: &mut Phase,
+    build: &mut Phase,
+) -> Result<()> {
+    let (distro, versions) = if env.is_config_variable_truthy("DEBIAN") {
+        ("the Debian base image", DEBIAN_JDK_VERSIONS)
+    } else {
+        ("the Ubuntu base image", UBUNTU_JDK_VERSIONS)
+    };
+    if !versions.contains(&jdk_version) {
+        bail!(
+            "jlink runtimes can only be linked for JDK {} with {distro}, and not for JDK {jdk_version}",
+            versions
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+    }
+    // The headless JDK doesn't include the module files that jlink links from
+    setup.add_apt_pkgs(vec![
+        format!("openjdk-{jdk_version}-jdk-headless"),
+        format!("openjdk-{jdk_version}-jmods"),
+    ]);
+
+    let modules = BASE_MODULES
+        .iter()
+        .map(ToString::to_string)
+        .chain(
+            env.get_config_variable("JLINK_MODULES")
+                .unwrap_or_default()
+                .split([',', ' '])
+                .filter(|module| !module.is_empty())
+                .map(ToString::to_string),
+        )
+        .collect::<Vec<_>>()
+        .join(",");
+    build.add_cmd(format!(
+        "modules=$(/usr/bin/jdeps --ignore-missing-deps -q --multi-release {jdk_version} --print-module-deps {jars}) \
+         && /usr/bin/jlink --add-modules ${{modules:+$modules,}}{modules} --strip-debug --no-man-pages --no-header-files --output {JLINK_RUNTIME_DIR}"
+    ));
+
+    Ok(())
+}
+
+/// Runs the start command in a slim image with the linked runtime and the given files of the app.
+pub fn run_with_runtime(env: &Environment, start: &mut StartPhase, files: &[&str]) {
+    // The runtime is linked against the libc of the build image, so it runs in the slim image of the same distro
+    if env.is_config_variable_truthy("DEBIAN") {
+        start.run_in_image(DEBIAN_RUN_IMAGE.to_string());
+    } else {
+        start.run_in_slim_image();
+    }
+    start.add_file_dependency(JLINK_RUNTIME_DIR);
+    for file in files {
+        start.add_file_dependency(*file);
+    }
+}
@@ -1,4 +1,4 @@
-use super::Provider;
+use super::{Confidence, Provider};
 use crate::nixpacks::{
     app::App,
     environment::Environment,
@@ -18,13 +18,17 @@ impl Provider for LunaticProvider {
         "lunatic"
     }
 
-    fn detect(&self, app: &App, _env: &Environment) -> Result<bool> {
+    fn detect(&self, app: &App, _env: &Environment) -> Result<Confidence> {
         if !app.includes_file("Cargo.toml") {
-            return Ok(false);
+            return Ok(Confidence::None);
         }
 
+        // Lunatic apps are also Rust apps, so the runner makes them more certain than any Cargo.toml
         let re_runner = Regex::new(r#"runner\s*=\s*"lunatic""#).expect("BUG: Broken regex");
-        app.find_match(&re_runner, ".cargo/config.toml")
+        Ok(Confidence::first_of(&[(
+            app.find_match(&re_runner, ".cargo/config.toml")?,
+            Confidence::High,
+        )]))
     }
 
     fn get_build_plan(&self, app: &App, env: &Environment) -> Result<Option<BuildPlan>> {
@@ -32,7 +36,7 @@ impl Provider for LunaticProvider {
         let build = LunaticProvider::get_build(app, env)?;
         let start = LunaticProvider::get_start(app, env)?;
 
-        let plan = BuildPlan::new(&vec![setup, build], start);
+        let plan = BuildPlan::new(&[setup, build], start);
 
         Ok(Some(plan))
     }
@@ -1,6 +1,12 @@
-use crate::nixpacks::{app::App, environment::Environment, plan::BuildPlan};
+use crate::nixpacks::{
+    app::{App, StaticAssets},
+    environment::Environment,
+    plan::{phase::Phase, BuildPlan},
+};
 use anyhow::Result;
+use std::fmt;
 
+pub mod chromium;
 pub mod clojure;
 pub mod cobol;
 pub mod crystal;
@@ -13,6 +19,7 @@ pub mod gleam;
 pub mod go;
 pub mod haskell;
 pub mod java;
+pub mod jlink;
 pub mod lunatic;
 pub mod node;
 pub mod php;
@@ -24,14 +31,76 @@ pub mod scala;
 pub mod scheme;
 pub mod staticfile;
 pub mod swift;
+pub mod system_packages;
 pub mod zig;
 
+/// How sure a provider is that it should build an app.
+///
+/// The provider that detects the app with the most confidence is used, and providers with the same
+/// confidence are picked in the order of the provider list.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Confidence {
+    #[default]
+    None,
+    /// Files that apps in other languages often have too, such as a `public` directory, a `main.py`, or source files.
+    Low,
+    /// The language's project file, such as `package.json` or `Cargo.toml`.
+    Medium,
+    /// A project file with the lock file of its dependencies, or a project file that only one provider builds.
+    High,
+}
+
+impl Confidence {
+    pub fn is_detected(self) -> bool {
+        self != Confidence::None
+    }
+
+    /// `High` when there is a lock file, otherwise `Medium` when there is a project file.
+    pub fn from_files(has_project_file: bool, has_lock_file: bool) -> Confidence {
+        match (has_project_file, has_lock_file) {
+            (true, true) => Confidence::High,
+            (true, false) => Confidence::Medium,
+            (false, _) => Confidence::None,
+        }
+    }
+
+    /// The confidence of the first check that passes, or `None` if none of them do.
+    pub fn first_of(checks: &[(bool, Confidence)]) -> Confidence {
+        checks
+            .iter()
+            .find(|(passes, _)| *passes)
+            .map_or(Confidence::None, |(_, confidence)| *confidence)
+    }
+}
+
+impl fmt::Display for Confidence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Confidence::None => "none",
+            Confidence::Low => "low",
+            Confidence::Medium => "medium",
+            Confidence::High => "high",
+        };
+        write!(f, "{name}")
+    }
+}
+
 pub trait Provider: Send + Sync {
     fn name(&self) -> &str;
-    fn detect(&self, _app: &App, _env: &Environment) -> Result<bool> {
-        Ok(false)
+    fn detect(&self, _app: &App, _env: &Environment) -> Result<Confidence> {
+        Ok(Confidence::None)
     }
     fn get_build_plan(&self, _app: &App, _environment: &Environment) -> Result<Option<BuildPlan>>;
+    /// Packages and commands that prepare the image before the app's files are copied, such as enabling corepack.
+    /// They are added to the setup phase of the build plan, which is cached until the setup itself changes.
+    fn get_setup_phase(&self, _app: &App, _env: &Environment) -> Result<Option<Phase>> {
+        Ok(None)
+    }
+    /// Generated files, such as config files or wrapper scripts, that are written to the `/assets/` directory of the image.
+    /// Assets starting with a `#!` are made executable.
+    fn static_assets(&self, _app: &App, _env: &Environment) -> Result<StaticAssets> {
+        Ok(StaticAssets::new())
+    }
     fn metadata(&self, _app: &App, _env: &Environment) -> Result<ProviderMetadata> {
         Ok(ProviderMetadata::default())
     }
@@ -40,6 +109,10 @@ pub trait Provider: Send + Sync {
 #[derive(Default)]
 pub struct ProviderMetadata {
     pub values: Option<Vec<String>>,
+    /// Version of the language the app is built with, such as `18` for Node.
+    pub version: Option<String>,
+    /// Why the provider matched the app, such as the file it was detected by.
+    pub reason: Option<String>,
 }
 
 impl ProviderMetadata {
@@ -52,9 +125,30 @@ impl ProviderMetadata {
 
         ProviderMetadata {
             values: Some(values),
+            ..Default::default()
         }
     }
 
+    #[must_use]
+    pub fn set_version(mut self, version: Option<String>) -> Self {
+        self.version = version;
+        self
+    }
+
+    #[must_use]
+    pub fn set_reason(mut self, reason: Option<String>) -> Self {
+        self.reason = reason;
+        self
+    }
+
+    /// Describes the first of the given files that exists in the app, as the reason for detecting it.
+    pub fn found_file(app: &App, files: &[&str]) -> Option<String> {
+        files
+            .iter()
+            .find(|file| app.includes_file(file))
+            .map(|file| format!("Found {file}"))
+    }
+
     pub fn join_as_comma_separated(&self, provider_name: String) -> String {
         let mut arr = vec![provider_name];
         let mut labels_arr = match &self.values {
@@ -78,3 +172,29 @@ fn test_join_as_comma_separated() {
     let tags_str = &metadata.join_as_comma_separated("my_provider".to_string());
     assert_eq!(tags_str, "my_provider,test_tag,test_tag_3");
 }
+
+#[test]
+fn test_confidence() {
+    assert!(Confidence::High > Confidence::Medium);
+    assert!(Confidence::Low > Confidence::None);
+    assert_eq!(Confidence::from_files(true, true), Confidence::High);
+    assert_eq!(Confidence::from_files(true, false), Confidence::Medium);
+    assert_eq!(Confidence::from_files(false, true), Confidence::None);
+    assert_eq!(
+        Confidence::first_of(&[(false, Confidence::High), (true, Confidence::Low)]),
+        Confidence::Low
+    );
+    assert!(!Confidence::first_of(&[]).is_detected());
+}
+
+#[test]
+fn test_found_file() -> Result<()> {
+    let app = App::new("./examples/node")?;
+
+    assert_eq!(
+        ProviderMetadata::found_file(&app, &["yarn.lock", "package.json"]),
+        Some("Found package.json".to_string())
+    );
+    assert_eq!(ProviderMetadata::found_file(&app, &["Gemfile"]), None);
+    Ok(())
+}
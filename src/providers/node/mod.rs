@@ -1,5 +1,9 @@
 use self::{moon::Moon, nx::Nx, spa::SpaProvider, turborepo::Turborepo};
-use super::Provider;
+use super::{
+    chromium::{add_chromium, chromium_variables, NODE_BROWSER_DEPENDENCIES},
+    system_packages::{add_system_packages, NODE_DATABASE_DEPENDENCIES, NODE_MEDIA_DEPENDENCIES},
+    Confidence, Provider, ProviderMetadata,
+};
 use crate::nixpacks::plan::merge::Mergeable;
 use crate::nixpacks::{
     app::App,
@@ -10,7 +14,7 @@ use crate::nixpacks::{
         BuildPlan,
     },
 };
-use anyhow::Result;
+use anyhow::{bail, Context, Result};
 use node_semver::Range;
 use path_slash::PathExt;
 use serde::{Deserialize, Serialize};
@@ -39,6 +43,19 @@ const BUN_CACHE_DIR: &str = "/root/.bun";
 const CYPRESS_CACHE_DIR: &str = "/root/.cache/Cypress";
 const NODE_MODULES_CACHE_DIR: &str = "node_modules/.cache";
 
+const ESBUILD_VERSION: &str = "0.24.0";
+/// A `.cjs` file is run as CommonJS even when the app's package.json sets `"type": "module"`.
+const BUNDLE_FILE: &str = "dist/nixpacks-bundle.cjs";
+const BUNDLE_ENTRY_EXECUTORS: &[&str] = &["node", "ts-node", "tsx"];
+
+const NODE_LOCK_FILES: &[&str] = &[
+    "package-lock.json",
+    "yarn.lock",
+    "pnpm-lock.yaml",
+    "bun.lockb",
+    "bun.lock",
+];
+
 #[derive(Serialize, Deserialize, Default, Debug, Clone)]
 struct TsConfigJson {
     #[serde(rename = "compilerOptions")]
@@ -125,8 +142,36 @@ impl Provider for NodeProvider {
         "node"
     }
 
-    fn detect(&self, app: &App, _env: &Environment) -> Result<bool> {
-        Ok(app.includes_file("package.json"))
+    fn detect(&self, app: &App, _env: &Environment) -> Result<Confidence> {
+        Ok(Confidence::from_files(
+            app.includes_file("package.json"),
+            NODE_LOCK_FILES.iter().any(|file| app.includes_file(file)),
+        ))
+    }
+
+    fn get_setup_phase(&self, app: &App, env: &Environment) -> Result<Option<Phase>> {
+        if !NodeProvider::uses_corepack(app, env)? {
+            return Ok(None);
+        }
+
+        // Corepack only needs Node, so it isn't installed again whenever the app changes
+        let mut setup = Phase::setup(None);
+        setup.add_cmd("npm install -g corepack@0.24.1");
+        setup.add_cmd("corepack enable");
+        Ok(Some(setup))
+    }
+
+    fn metadata(&self, app: &App, env: &Environment) -> Result<ProviderMetadata> {
+        let package_json: PackageJson = if app.includes_file("package.json") {
+            app.read_json("package.json")?
+        } else {
+            PackageJson::default()
+        };
+        let node_pkg = NodeProvider::get_nix_node_pkg(&package_json, app, env)?;
+
+        Ok(ProviderMetadata::default()
+            .set_version(node_pkg.name.strip_prefix("nodejs_").map(String::from))
+            .set_reason(ProviderMetadata::found_file(app, &["package.json"])))
     }
 
     fn get_build_plan(&self, app: &App, env: &Environment) -> Result<Option<BuildPlan>> {
@@ -141,54 +186,40 @@ impl Provider for NodeProvider {
             setup.add_pkgs_libs(vec!["gcc-unwrapped".to_string()]);
         }
 
-        if NodeProvider::uses_node_dependency(app, "puppeteer") {
-            // https://gist.github.com/winuxue/cfef08e2f5fe9dfc16a1d67a4ad38a01
-            setup.add_apt_pkgs(vec![
-                "libnss3".to_string(),
-                "libatk1.0-0".to_string(),
-                "libatk-bridge2.0-0".to_string(),
-                "libcups2".to_string(),
-                "libgbm1".to_string(),
-                "libasound2".to_string(),
-                "libpangocairo-1.0-0".to_string(),
-                "libxss1".to_string(),
-                "libgtk-3-0".to_string(),
-                "libxshmfence1".to_string(),
-                "libglu1".to_string(),
-            ]);
-        }
+        let uses_chromium = add_chromium(&mut setup, NODE_BROWSER_DEPENDENCIES, |dep| {
+            NodeProvider::uses_node_dependency(app, dep)
+        });
 
         if NodeProvider::uses_node_dependency(app, "canvas") {
             setup.add_pkgs_libs(vec!["libuuid".to_string(), "libGL".to_string()]);
         }
 
-        // Install
-        let corepack = NodeProvider::uses_corepack(app, env)?;
-        let mut install = Phase::install(if corepack {
-            Some("npm install -g corepack@0.24.1 && corepack enable".to_string())
-        } else {
-            NodeProvider::get_install_command(app)
+        add_system_packages(&mut setup, NODE_MEDIA_DEPENDENCIES, |dep| {
+            NodeProvider::uses_node_dependency(app, dep)
+        });
+        // Matched by the exact name, since the names of drivers such as `libpq` are part of other packages' names
+        let all_deps = NodeProvider::get_all_deps(app)?;
+        add_system_packages(&mut setup, NODE_DATABASE_DEPENDENCIES, |dep| {
+            all_deps.contains(dep)
         });
 
-        if corepack {
-            let install_cmd = NodeProvider::get_install_command(app);
-
-            if install_cmd.is_some() {
-                install.add_cmd(install_cmd.unwrap_or_default());
-            }
+        // Install
+        let mut install = Phase::install(None);
+        if let Some(install_cmd) = NodeProvider::get_install_command(app) {
+            install.add_cmd(install_cmd);
         }
 
         install.add_cache_directory(NodeProvider::get_package_manager_cache_dir(app));
         install.add_path("/app/node_modules/.bin".to_string());
 
         // Cypress cache directory
-        let all_deps = NodeProvider::get_all_deps(app)?;
         if all_deps.contains("cypress") {
             install.add_cache_directory((*CYPRESS_CACHE_DIR).to_string());
         }
 
         // Build
         let mut build = Phase::build(NodeProvider::get_build_cmd(app, env)?);
+        let package_json: PackageJson = app.read_json("package.json").unwrap_or_default();
 
         // Next build cache directories
         let next_cache_dirs = NodeProvider::find_next_packages(app)?;
@@ -203,10 +234,9 @@ impl Provider for NodeProvider {
 
         // Node modules cache directory
         build.add_cache_directory((*NODE_MODULES_CACHE_DIR).to_string());
-        let package_json: PackageJson = app.read_json("package.json").unwrap_or_default();
-        if let Some(cache_directories) = package_json.cache_directories {
+        if let Some(cache_directories) = &package_json.cache_directories {
             for dir in cache_directories {
-                build.add_cache_directory(dir);
+                build.add_cache_directory(dir.clone());
             }
         }
 
@@ -217,7 +247,21 @@ impl Provider for NodeProvider {
         }
 
         // Start
-        let start = NodeProvider::get_start_cmd(app, env)?.map(StartPhase::new);
+        let mut start = NodeProvider::get_start_cmd(app, env)?.map(StartPhase::new);
+        if let Some(start) = start.as_mut() {
+            if !Moon::is_moon_repo(app, env) {
+                start.workdir = Nx::get_nx_start_workdir(app, env)?;
+            }
+        }
+
+        if env.is_config_variable_truthy("BUNDLE") {
+            start = Some(NodeProvider::with_bundle(
+                app,
+                env,
+                &package_json,
+                &mut build,
+            )?);
+        }
 
         let mut phases = vec![setup, install, build];
         if let Some(caddy) = SpaProvider::caddy_phase(app, env) {
@@ -230,6 +274,10 @@ impl Provider for NodeProvider {
             plan.add_static_assets(SpaProvider::static_assets());
         }
         plan.add_variables(NodeProvider::get_node_environment_variables());
+        plan.add_build_variables(NodeProvider::get_node_build_variables());
+        if uses_chromium {
+            plan.add_variables(chromium_variables());
+        }
         if is_spa {
             plan.add_variables(EnvironmentVariables::from([(
                 "NIXPACKS_SPA_OUTPUT_DIR".to_string(),
@@ -242,15 +290,85 @@ impl Provider for NodeProvider {
 }
 
 impl NodeProvider {
+    /// Bundles the app and its dependencies into one file with esbuild, which runs in the official Node image without `node_modules`.
+    fn with_bundle(
+        app: &App,
+        env: &Environment,
+        package_json: &PackageJson,
+        build: &mut Phase,
+    ) -> Result<StartPhase> {
+        if SpaProvider::is_spa(app) {
+            bail!("Static sites are served by Caddy and can't be bundled");
+        }
+        if NodeProvider::get_executor(app) == "bun" {
+            bail!("Bun apps can't be bundled to run with Node");
+        }
+        let entry = NodeProvider::get_bundle_entry(app, env, package_json).context(
+            "Unable to find the entry point of the app to bundle. Set it with NIXPACKS_BUNDLE_ENTRY",
+        )?;
+
+        let node_pkg = NodeProvider::get_nix_node_pkg(package_json, app, env)?;
+        let node_version = node_pkg
+            .name
+            .trim_start_matches(|c: char| !c.is_ascii_digit());
+        build.add_cmd(format!(
+            "npx --yes esbuild@{ESBUILD_VERSION} {entry} --bundle --platform=node --target=node{node_version} \
+             --define:process.env.NODE_ENV=\\\"production\\\" --outfile={BUNDLE_FILE}"
+        ));
+
+        let mut start = StartPhase::new(format!("node {BUNDLE_FILE}"));
+        start.run_in_image(format!("node:{node_version}-slim"));
+        start.add_file_dependency(BUNDLE_FILE);
+        Ok(start)
+    }
+
+    /// The file to bundle from `NIXPACKS_BUNDLE_ENTRY`, the `start` script, or the `main` file of the package.
+    fn get_bundle_entry(
+        app: &App,
+        env: &Environment,
+        package_json: &PackageJson,
+    ) -> Option<String> {
+        if let Some(entry) = env.get_config_variable("BUNDLE_ENTRY") {
+            return Some(entry);
+        }
+
+        let start_script_entry = package_json
+            .scripts
+            .as_ref()
+            .and_then(|scripts| scripts.get("start"))
+            .and_then(|start| {
+                let mut words = start
+                    .split_whitespace()
+                    .skip_while(|word| word.contains('='));
+                if !BUNDLE_ENTRY_EXECUTORS.contains(&words.next()?) {
+                    return None;
+                }
+                words
+                    .find(|word| !word.starts_with('-'))
+                    .map(ToString::to_string)
+            });
+        start_script_entry
+            .into_iter()
+            .chain(package_json.main.clone())
+            .chain(["index.js".to_string(), "index.ts".to_string()])
+            .map(|entry| entry.trim_start_matches("./").to_string())
+            .find(|entry| app.includes_file(entry))
+    }
+
     pub fn get_node_environment_variables() -> EnvironmentVariables {
         EnvironmentVariables::from([
             ("NODE_ENV".to_string(), "production".to_string()),
-            ("NPM_CONFIG_PRODUCTION".to_string(), "false".to_string()),
             // CI required for various node tooling
             ("CI".to_string(), "true".to_string()),
         ])
     }
 
+    /// Variables that are only set while building, unless they are set by the user.
+    pub fn get_node_build_variables() -> EnvironmentVariables {
+        // Install dev dependencies, which are usually needed to build, even though NODE_ENV is production
+        EnvironmentVariables::from([("NPM_CONFIG_PRODUCTION".to_string(), "false".to_string())])
+    }
+
     pub fn has_script(app: &App, script: &str) -> Result<bool> {
         let package_json: PackageJson = app.read_json("package.json").unwrap_or_default();
         if let Some(scripts) = package_json.scripts {
@@ -361,7 +479,7 @@ impl NodeProvider {
         environment: &Environment,
     ) -> Result<Pkg> {
         let default_node_pkg_name = version_number_to_pkg(DEFAULT_NODE_VERSION);
-        let env_node_version = environment.get_config_variable("NODE_VERSION");
+        let env_node_version = environment.get_config_or_variable("NODE_VERSION");
 
         let pkg_node_version = package_json
             .engines
@@ -548,7 +666,7 @@ impl NodeProvider {
                 // npm v9 uses lockfile v3 as default
                 pm_pkg = Pkg::new("npm-9_x");
             }
-        };
+        }
         pkgs.push(pm_pkg.from_overlay(NODE_OVERLAY));
 
         Ok(pkgs)
@@ -583,7 +701,11 @@ impl NodeProvider {
                 continue;
             }
 
-            let json: PackageJson = app.read_json(file.to_str().unwrap())?;
+            // Files are read by name, so a package.json in a directory that isn't UTF-8 is skipped
+            let json: PackageJson = match file.to_str() {
+                Some(file) => app.read_json(file)?,
+                None => continue,
+            };
             let deps = NodeProvider::get_deps_from_package_json(&json);
             if deps.contains("next") {
                 let relative = app.strip_source_path(file.as_path())?;
@@ -611,7 +733,10 @@ impl NodeProvider {
                 continue;
             }
 
-            let json: PackageJson = app.read_json(file.to_str().unwrap())?;
+            let json: PackageJson = match file.to_str() {
+                Some(file) => app.read_json(file)?,
+                None => continue,
+            };
 
             all_deps.extend(NodeProvider::get_deps_from_package_json(&json));
         }
@@ -671,7 +796,7 @@ impl NodeProvider {
                     }
                 }
             }
-        };
+        }
     }
 }
 
@@ -99,6 +99,30 @@ impl Nx {
         })
     }
 
+    /// Next.js apps start from their build output, which has its own `package.json`.
+    pub fn get_nx_start_workdir(app: &App, env: &Environment) -> Result<Option<String>> {
+        if !Nx::is_nx_monorepo(app, env) {
+            return Ok(None);
+        }
+
+        match Nx::get_nx_app_name(app, env) {
+            Some(nx_app_name) => {
+                let project_json = Nx::get_nx_project_json_for_app(app, &nx_app_name)?;
+                if project_json.targets.start.is_none() && Nx::is_next_build(&project_json) {
+                    Ok(Some(Nx::get_nx_output_path(app, &nx_app_name)?))
+                } else {
+                    Ok(None)
+                }
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn is_next_build(project_json: &ProjectJson) -> bool {
+        project_json.targets.build.executor == "@nx/next:build"
+            || project_json.targets.build.executor == "@nrwl/next:build"
+    }
+
     pub fn get_nx_start_cmd(app: &App, env: &Environment) -> Result<Option<String>> {
         if !Nx::is_nx_monorepo(app, env) {
             return Ok(None);
@@ -123,16 +147,18 @@ impl Nx {
                 )));
             }
 
-            if project_json.targets.build.executor == "@nx/next:build"
-                || project_json.targets.build.executor == "@nrwl/next:build"
-            {
-                return Ok(Some(format!("cd {output_path} && npm run start")));
+            // Started from the build output, which is the start phase's workdir
+            if Nx::is_next_build(&project_json) {
+                return Ok(Some("npm run start".to_string()));
             }
 
             if let Some(options) = project_json.targets.build.options {
                 if let Some(main_path) = options.main {
                     let current_path = PathBuf::from(main_path);
-                    let file_name = current_path.file_stem().unwrap().to_str().unwrap();
+                    let file_name = current_path
+                        .file_stem()
+                        .and_then(std::ffi::OsStr::to_str)
+                        .unwrap_or("index");
 
                     return Ok(Some(format!("node {output_path}/{file_name}.js")));
                 }
@@ -12,7 +12,7 @@ use crate::nixpacks::{
     },
 };
 
-use super::{node::NodeProvider, Provider};
+use super::{node::NodeProvider, Confidence, Provider, ProviderMetadata};
 use anyhow::Result;
 
 const LEGACY_ARCHIVE_VERSION: &str = "5148520bfab61f99fd25fb9ff7bfbb50dad3c9db";
@@ -38,8 +38,23 @@ impl Provider for PhpProvider {
         "php"
     }
 
-    fn detect(&self, app: &App, _env: &Environment) -> Result<bool> {
-        Ok(app.includes_file("composer.json") || app.includes_file("index.php"))
+    fn detect(&self, app: &App, _env: &Environment) -> Result<Confidence> {
+        Ok(Confidence::first_of(&[
+            (
+                app.includes_file("composer.json"),
+                Confidence::from_files(true, app.includes_file("composer.lock")),
+            ),
+            (app.includes_file("index.php"), Confidence::Low),
+        ]))
+    }
+
+    fn metadata(&self, app: &App, _env: &Environment) -> Result<ProviderMetadata> {
+        Ok(
+            ProviderMetadata::default().set_reason(ProviderMetadata::found_file(
+                app,
+                &["composer.json", "index.php"],
+            )),
+        )
     }
 
     fn get_build_plan(&self, app: &App, env: &Environment) -> Result<Option<BuildPlan>> {
@@ -56,11 +71,24 @@ impl Provider for PhpProvider {
             Some(start),
         );
 
-        plan.add_static_assets(PhpProvider::static_assets());
         plan.add_variables(PhpProvider::environment_variables(app));
 
         Ok(Some(plan))
     }
+
+    fn static_assets(&self, _app: &App, _env: &Environment) -> Result<StaticAssets> {
+        Ok(static_asset_list! {
+            "nginx.template.conf" => include_str!("nginx.template.conf"),
+            "scripts/prestart.mjs" => include_str!("scripts/prestart.mjs"),
+            "php-fpm.conf" => include_str!("php-fpm.conf"),
+            "scripts/util/cmd.mjs" => include_str!("scripts/util/cmd.mjs"),
+            "scripts/util/nix.mjs" => include_str!("scripts/util/nix.mjs"),
+            "scripts/config/template.mjs" => include_str!("scripts/config/template.mjs"),
+            "scripts/util/laravel.mjs" => include_str!("scripts/util/laravel.mjs"),
+            "scripts/util/logger.mjs" => include_str!("scripts/util/logger.mjs"),
+            "scripts/supervise.sh" => include_str!("scripts/supervise.sh")
+        })
+    }
 }
 
 impl PhpProvider {
@@ -111,7 +139,7 @@ impl PhpProvider {
         ));
         if app.includes_file("composer.json") {
             install.add_cmd("composer install --ignore-platform-reqs".to_string());
-        };
+        }
         if app.includes_file("package.json") {
             if let Some(install_cmd) = NodeProvider::get_install_command(app) {
                 install.add_cmd(install_cmd);
@@ -137,37 +165,30 @@ impl PhpProvider {
 
     fn get_start(app: &App) -> StartPhase {
         if app.includes_file("nginx.conf") {
-            StartPhase::new(format!(
-                "php-fpm -y {} & nginx -c /app/nginx.conf",
-                app.asset_path("php-fpm.conf")
-            ))
+            StartPhase::new(PhpProvider::get_serve_cmd(app, "/app/nginx.conf"))
         } else if app.includes_file("nginx.template.conf") {
             StartPhase::new(format!(
-                "node {} /app/nginx.template.conf /nginx.conf && (php-fpm -y {} & nginx -c /nginx.conf)",
+                "node {} /app/nginx.template.conf /nginx.conf && {}",
                 app.asset_path("scripts/prestart.mjs"),
-                app.asset_path("php-fpm.conf"),
+                PhpProvider::get_serve_cmd(app, "/nginx.conf"),
             ))
         } else {
             StartPhase::new(format!(
-                "node {} {} /nginx.conf && (php-fpm -y {} & nginx -c /nginx.conf)",
+                "node {} {} /nginx.conf && {}",
                 app.asset_path("scripts/prestart.mjs"),
                 app.asset_path("nginx.template.conf"),
-                app.asset_path("php-fpm.conf"),
+                PhpProvider::get_serve_cmd(app, "/nginx.conf"),
             ))
         }
     }
 
-    fn static_assets() -> StaticAssets {
-        static_asset_list! {
-            "nginx.template.conf" => include_str!("nginx.template.conf"),
-            "scripts/prestart.mjs" => include_str!("scripts/prestart.mjs"),
-            "php-fpm.conf" => include_str!("php-fpm.conf"),
-            "scripts/util/cmd.mjs" => include_str!("scripts/util/cmd.mjs"),
-            "scripts/util/nix.mjs" => include_str!("scripts/util/nix.mjs"),
-            "scripts/config/template.mjs" => include_str!("scripts/config/template.mjs"),
-            "scripts/util/laravel.mjs" => include_str!("scripts/util/laravel.mjs"),
-            "scripts/util/logger.mjs" => include_str!("scripts/util/logger.mjs")
-        }
+    /// Runs PHP-FPM and Nginx in the foreground under a supervisor, which stops the container when either of them exits.
+    fn get_serve_cmd(app: &App, nginx_conf: &str) -> String {
+        format!(
+            "exec bash {} 'php-fpm -y {} -F' 'nginx -c {nginx_conf}'",
+            app.asset_path("scripts/supervise.sh"),
+            app.asset_path("php-fpm.conf"),
+        )
     }
 
     fn environment_variables(app: &App) -> EnvironmentVariables {
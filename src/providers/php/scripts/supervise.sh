@@ -0,0 +1,19 @@
+#!/usr/bin/env bash
+# Runs each argument as a command and stops all of them as soon as one exits, so the container
+# stops instead of serving requests without PHP-FPM or Nginx. Termination signals reach every command.
+pids=()
+for cmd in "$@"; do
+    bash -c "exec $cmd" &
+    pids+=($!)
+done
+
+stop() {
+    kill -TERM "${pids[@]}" 2>/dev/null
+}
+trap stop TERM INT
+
+wait -n
+status=$?
+stop
+wait
+exit $status
@@ -5,6 +5,7 @@ use crate::{
         asdf::parse_tool_versions_content,
         environment::{Environment, EnvironmentVariables},
         plan::{
+            locale::{is_tzdata_disabled, DEFAULT_TIMEZONE},
             phase::{Phase, StartPhase},
             BuildPlan,
         },
@@ -15,9 +16,15 @@ use anyhow::{bail, Context, Ok, Result};
 use regex::{Match, Regex};
 use serde::Deserialize;
 use std::result::Result::Ok as OkResult;
-use std::{collections::HashMap, fs};
+use std::{collections::BTreeMap, fs};
 
-use super::{Provider, ProviderMetadata};
+use super::{
+    chromium::{add_chromium, chromium_variables, PYTHON_BROWSER_DEPENDENCIES},
+    system_packages::{
+        add_system_packages, PYTHON_DATABASE_DEPENDENCIES, PYTHON_MEDIA_DEPENDENCIES,
+    },
+    Confidence, Provider, ProviderMetadata,
+};
 
 const DEFAULT_PYTHON_PKG_NAME: &str = "python3";
 const POETRY_VERSION: &str = "1.3.1";
@@ -28,6 +35,8 @@ const VENV_LOCATION: &str = "/opt/venv";
 const UV_CACHE_DIR: &str = "/root/.cache/uv";
 const PIP_CACHE_DIR: &str = "/root/.cache/pip";
 const PDM_CACHE_DIR: &str = "/root/.cache/pdm";
+/// Default of `WEB_CONCURRENCY`, the number of Gunicorn workers. Containers see the CPUs of the host, so they aren't counted.
+const DEFAULT_GUNICORN_WORKERS: u32 = 2;
 const DEFAULT_POETRY_PYTHON_PKG_NAME: &str = "python3";
 
 const PYTHON_NIXPKGS_ARCHIVE: &str = "bc8f8d1be58e8c8383e683a06e1e1e57893fff87";
@@ -40,12 +49,20 @@ impl Provider for PythonProvider {
         "python"
     }
 
-    fn detect(&self, app: &App, _env: &Environment) -> Result<bool> {
-        let has_python = app.includes_file("main.py")
-            || app.includes_file("requirements.txt")
+    fn detect(&self, app: &App, _env: &Environment) -> Result<Confidence> {
+        let has_project_file = app.includes_file("requirements.txt")
             || app.includes_file("pyproject.toml")
             || app.includes_file("Pipfile");
-        Ok(has_python)
+        let has_lock_file = ["poetry.lock", "pdm.lock", "Pipfile.lock", "uv.lock"]
+            .iter()
+            .any(|file| app.includes_file(file));
+        Ok(Confidence::first_of(&[
+            (
+                has_project_file,
+                Confidence::from_files(true, has_lock_file),
+            ),
+            (app.includes_file("main.py"), Confidence::Low),
+        ]))
     }
 
     fn metadata(&self, app: &App, env: &Environment) -> Result<ProviderMetadata> {
@@ -54,18 +71,36 @@ impl Provider for PythonProvider {
         let is_poetry = app.includes_file("poetry.lock");
         let is_pdm = app.includes_file("pdm.lock");
 
+        // Packages are named like `python311`, except for the default `python3`
+        let (python_pkg, _) = PythonProvider::get_nix_python_package(app, env)?;
+        let version = python_pkg
+            .name
+            .strip_prefix("python")
+            .filter(|version| version.len() > 1)
+            .map(|version| format!("{}.{}", &version[..1], &version[1..]));
+
         Ok(ProviderMetadata::from(vec![
             (is_django, "django"),
             (is_using_postgres, "postgres"),
             (is_poetry, "poetry"),
             (is_pdm, "pdm"),
-        ]))
+        ])
+        .set_version(version)
+        .set_reason(ProviderMetadata::found_file(
+            app,
+            &["requirements.txt", "pyproject.toml", "Pipfile", "main.py"],
+        )))
     }
 
     fn get_build_plan(&self, app: &App, env: &Environment) -> Result<Option<BuildPlan>> {
         let mut plan = BuildPlan::default();
 
-        let setup = self.setup(app, env)?.unwrap_or_default();
+        let mut setup = self.setup(app, env)?.unwrap_or_default();
+        if add_chromium(&mut setup, PYTHON_BROWSER_DEPENDENCIES, |dep| {
+            PythonProvider::uses_dep(app, dep).unwrap_or_default()
+        }) {
+            plan.add_variables(chromium_variables());
+        }
         plan.add_phase(setup);
 
         let install = self.install(app, env)?.unwrap_or_default();
@@ -126,6 +161,19 @@ impl Provider for PythonProvider {
             ]));
         }
 
+        if PythonProvider::is_django(app, env)? {
+            // Gunicorn reads its default worker count from WEB_CONCURRENCY, so gunicorn.conf.py still takes precedence
+            plan.add_variables(EnvironmentVariables::from([(
+                "WEB_CONCURRENCY".to_string(),
+                DEFAULT_GUNICORN_WORKERS.to_string(),
+            )]));
+
+            // Django's timezone support looks up zones with zoneinfo, which needs a timezone database
+            if !is_tzdata_disabled(env) {
+                plan.add_timezone(DEFAULT_TIMEZONE);
+            }
+        }
+
         Ok(Some(plan))
     }
 }
@@ -142,7 +190,7 @@ struct ProjectDecl {
     pub name: Option<String>,
     pub packages: Option<Vec<String>>,
     pub py_modules: Option<Vec<String>>,
-    pub entry_points: Option<HashMap<String, String>>,
+    pub entry_points: Option<BTreeMap<String, String>>,
 }
 
 #[allow(dead_code)]
@@ -187,6 +235,13 @@ impl PythonProvider {
             setup.add_pkgs_libs(vec!["cairo".to_string()]);
         }
 
+        add_system_packages(&mut setup, PYTHON_MEDIA_DEPENDENCIES, |dep| {
+            PythonProvider::uses_dep(app, dep).unwrap_or_default()
+        });
+        add_system_packages(&mut setup, PYTHON_DATABASE_DEPENDENCIES, |dep| {
+            PythonProvider::uses_dep(app, dep).unwrap_or_default()
+        });
+
         // Many Python packages need some C headers to be available
         // stdenv.cc.cc.lib -> https://discourse.nixos.org/t/nixos-with-poetry-installed-pandas-libstdc-so-6-cannot-open-shared-object-file/8442/3
         setup.add_pkgs_libs(vec!["zlib".to_string(), "stdenv.cc.cc.lib".to_string()]);
@@ -200,13 +255,20 @@ impl PythonProvider {
         let activate_env = format!(". {VENV_LOCATION}/bin/activate");
 
         if app.includes_file("requirements.txt") {
+            // The requirements are installed with the pip cache, which is turned off for everything else with
+            // PIP_NO_CACHE_DIR, so the wheels of packages that are compiled are reused until their version changes
             let mut install_phase = Phase::install(Some(format!(
-                "{create_env} && {activate_env} && pip install -r requirements.txt"
+                "{create_env} && {activate_env} && env -u PIP_NO_CACHE_DIR pip install -r requirements.txt"
             )));
 
             install_phase.add_path(format!("{VENV_LOCATION}/bin"));
             install_phase.add_cache_directory(PIP_CACHE_DIR.to_string());
 
+            // The layer is reused until the requirements change, unless they refer to other files in the app
+            if !PythonProvider::requirements_use_app_files(app)? {
+                install_phase.add_file_dependency("requirements.txt".to_string());
+            }
+
             return Ok(Some(install_phase));
         } else if app.includes_file("pyproject.toml") {
             if app.includes_file("poetry.lock") {
@@ -288,7 +350,8 @@ impl PythonProvider {
             let app_name = PythonProvider::get_django_app_name(app, env)?;
 
             return Ok(Some(StartPhase::new(format!(
-                "python manage.py migrate && gunicorn {app_name}"
+                "python manage.py migrate && gunicorn {app_name} {}",
+                PythonProvider::get_gunicorn_args()
             ))));
         }
 
@@ -312,6 +375,38 @@ impl PythonProvider {
         Ok(None)
     }
 
+    /// The thread count, which is only passed when `GUNICORN_THREADS` is set so it doesn't override the Gunicorn config.
+    fn get_gunicorn_args() -> &'static str {
+        "${GUNICORN_THREADS:+--threads $GUNICORN_THREADS}"
+    }
+
+    /// Whether requirements.txt includes other files or installs packages from the app, such as `-r dev.txt` or `-e .`.
+    fn requirements_use_app_files(app: &App) -> Result<bool> {
+        Ok(app.read_file("requirements.txt")?.lines().any(|line| {
+            let line = line.trim();
+            let requirement = line.split(';').next().unwrap_or_default().trim();
+            let is_archive = [".whl", ".tar.gz", ".zip"]
+                .iter()
+                .any(|extension| requirement.ends_with(extension));
+            [
+                "-r",
+                "-c",
+                "-e",
+                "-f",
+                "--requirement",
+                "--constraint",
+                "--editable",
+                "--find-links",
+                ".",
+                "/",
+                "file:",
+            ]
+            .iter()
+            .any(|prefix| line.starts_with(prefix))
+                || (is_archive && !requirement.contains("://"))
+        }))
+    }
+
     fn is_django(app: &App, _env: &Environment) -> Result<bool> {
         let has_manage = app.includes_file("manage.py");
         let imports_django = PythonProvider::uses_dep(app, "django")?;
@@ -417,7 +512,7 @@ impl PythonProvider {
 
     fn get_nix_python_package(app: &App, env: &Environment) -> Result<(Pkg, String)> {
         // Fetch python versions into tuples with defaults
-        fn as_default(v: Option<Match>) -> &str {
+        fn as_default(v: Option<Match<'_>>) -> &str {
             match v {
                 Some(m) => m.as_str(),
                 None => "_",
@@ -425,7 +520,7 @@ impl PythonProvider {
         }
 
         // Fetch version from configs
-        let mut custom_version = env.get_config_variable("PYTHON_VERSION");
+        let mut custom_version = env.get_config_or_variable("PYTHON_VERSION");
 
         // If not from configs, get it from the .python-version file
         if custom_version.is_none() && app.includes_file(".python-version") {
@@ -572,6 +667,33 @@ mod test {
     use super::*;
     use crate::nixpacks::{app::App, environment::Environment, nix::pkg::Pkg};
     use std::collections::BTreeMap;
+    use tempdir::TempDir;
+
+    #[test]
+    fn test_requirements_use_app_files() -> Result<()> {
+        assert!(!PythonProvider::requirements_use_app_files(&App::new(
+            "./examples/python"
+        )?)?);
+
+        let dir = TempDir::new("nixpacks-python")?;
+        fs::write(
+            dir.path().join("requirements.txt"),
+            "flask==3.0.0\n-r requirements/base.txt\n",
+        )?;
+        let app = App::new(dir.path().to_str().unwrap())?;
+        assert!(PythonProvider::requirements_use_app_files(&app)?);
+
+        for requirements in [
+            "--find-links vendor/\nflask\n",
+            "vendor/pkg-1.0-py3-none-any.whl\n",
+        ] {
+            fs::write(dir.path().join("requirements.txt"), requirements)?;
+            assert!(PythonProvider::requirements_use_app_files(&App::new(
+                dir.path().to_str().unwrap()
+            )?)?);
+        }
+        Ok(())
+    }
 
     #[test]
     fn test_no_version() -> Result<()> {
@@ -1,9 +1,14 @@
-use super::{node::NodeProvider, Provider};
+use super::{
+    node::NodeProvider,
+    system_packages::{add_system_packages, RUBY_MEDIA_DEPENDENCIES},
+    Confidence, Provider, ProviderMetadata,
+};
 use crate::nixpacks::{
     app::App,
     environment::{Environment, EnvironmentVariables},
     nix::pkg::Pkg,
     plan::{
+        locale::{is_tzdata_disabled, DEFAULT_TIMEZONE},
         phase::{Phase, StartPhase},
         BuildPlan,
     },
@@ -28,14 +33,30 @@ impl RubyVersion {
 pub struct RubyProvider {}
 
 const BUNDLE_CACHE_DIR: &str = "/root/.bundle/cache";
+const BUNDLE_WITHOUT_GROUPS: &[&str] = &["development", "test"];
 
 impl Provider for RubyProvider {
     fn name(&self) -> &'static str {
         "ruby"
     }
 
-    fn detect(&self, app: &App, _env: &Environment) -> Result<bool> {
-        Ok(app.includes_file("Gemfile"))
+    fn detect(&self, app: &App, _env: &Environment) -> Result<Confidence> {
+        Ok(Confidence::from_files(
+            app.includes_file("Gemfile"),
+            app.includes_file("Gemfile.lock"),
+        ))
+    }
+
+    fn metadata(&self, app: &App, env: &Environment) -> Result<ProviderMetadata> {
+        // The version is only required once the app is built
+        let version = self
+            .get_ruby_version(app, env)
+            .ok()
+            .map(|version| version.trim_start_matches("ruby-").to_string());
+
+        Ok(ProviderMetadata::default()
+            .set_version(version)
+            .set_reason(ProviderMetadata::found_file(app, &["Gemfile"])))
     }
 
     fn get_build_plan(&self, app: &App, env: &Environment) -> Result<Option<BuildPlan>> {
@@ -53,9 +74,12 @@ impl Provider for RubyProvider {
         );
 
         let node = NodeProvider::default();
-        if node.detect(app, env)? || self.uses_gem_dep(app, "execjs") {
+        if node.detect(app, env)?.is_detected() || self.uses_gem_dep(app, "execjs") {
             let node_build_plan = node.get_build_plan(app, env)?;
-            if let Some(node_build_plan) = node_build_plan {
+            if let Some(mut node_build_plan) = node_build_plan {
+                if let Some(setup) = node.get_setup_phase(app, env)? {
+                    node_build_plan.add_setup_phase(setup);
+                }
                 // Include the install phase from the node provider
                 let root_phase_name =
                     plan.add_phases_from_another_plan(&node_build_plan, node.name(), "install");
@@ -65,6 +89,11 @@ impl Provider for RubyProvider {
 
         plan.add_variables(self.get_environment_variables(app, env)?);
 
+        // Rails resolves `config.time_zone` with tzinfo, which fails without a timezone database
+        if self.is_rails_app(app) && !is_tzdata_disabled(env) {
+            plan.add_timezone(DEFAULT_TIMEZONE);
+        }
+
         Ok(Some(plan))
     }
 }
@@ -85,10 +114,16 @@ impl RubyProvider {
             setup.add_apt_pkgs(vec!["default-libmysqlclient-dev".to_string()]);
         }
 
+        if self.uses_gem_dep(app, "sqlite3") {
+            setup.add_apt_pkgs(vec!["libsqlite3-dev".to_string()]);
+        }
+
         if self.uses_gem_dep(app, "magick") {
             setup.add_apt_pkgs(vec![String::from("libmagickwand-dev")]);
-            setup.add_nix_pkgs(&[Pkg::new("imagemagick")]);
         }
+        add_system_packages(&mut setup, RUBY_MEDIA_DEPENDENCIES, |dep| {
+            self.uses_gem_dep(app, dep)
+        });
 
         if self.uses_gem_dep(app, "vips") {
             setup.add_apt_pkgs(vec![String::from("libvips-dev")]);
@@ -218,8 +253,18 @@ impl RubyProvider {
                 format!("/usr/local/rvm/gems/{ruby_version}"),
             ),
             ("MALLOC_ARENA_MAX".to_string(), "2".to_string()),
+            // Set as variables rather than bundler config, so they also apply to whoever runs the app
+            (
+                "BUNDLE_WITHOUT".to_string(),
+                BUNDLE_WITHOUT_GROUPS.join(":"),
+            ),
         ]);
 
+        // Deployment mode installs the exact gems in the lockfile, and fails when they aren't locked for Linux
+        if self.is_locked_for_linux(app) {
+            env_vars.insert("BUNDLE_DEPLOYMENT".to_string(), "1".to_string());
+        }
+
         if self.is_rails_app(app) {
             env_vars.insert("RAILS_LOG_TO_STDOUT".to_string(), "enabled".to_string());
             env_vars.insert("RAILS_SERVE_STATIC_FILES".to_string(), "1".to_string());
@@ -250,7 +295,7 @@ impl RubyProvider {
     }
 
     fn get_ruby_version(&self, app: &App, env: &Environment) -> Result<String> {
-        if let Some(version) = env.get_config_variable("RUBY_VERSION") {
+        if let Some(version) = env.get_config_or_variable("RUBY_VERSION") {
             return Ok(version);
         }
         if app.includes_file(".ruby-version") {
@@ -316,6 +361,20 @@ impl RubyProvider {
         Ok(false)
     }
 
+    /// Whether the `PLATFORMS` of Gemfile.lock include Linux, or `ruby` for gems that build on any platform.
+    fn is_locked_for_linux(&self, app: &App) -> bool {
+        let lockfile = app.read_file("Gemfile.lock").unwrap_or_default();
+        lockfile
+            .lines()
+            .skip_while(|line| line.trim() != "PLATFORMS")
+            .skip(1)
+            .take_while(|line| !line.trim().is_empty())
+            .any(|platform| {
+                let platform = platform.trim();
+                platform == "ruby" || platform.contains("linux")
+            })
+    }
+
     fn uses_gem_dep(&self, app: &App, dependency: &str) -> bool {
         ["Gemfile", "Gemfile.lock"]
             .iter()
@@ -329,6 +388,14 @@ mod test {
 
     use super::*;
 
+    #[test]
+    fn test_is_locked_for_linux() -> Result<()> {
+        let ruby = RubyProvider {};
+        assert!(ruby.is_locked_for_linux(&App::new("./examples/ruby-rails-postgres")?));
+        assert!(!ruby.is_locked_for_linux(&App::new("./examples/ruby-rails-api-app")?));
+        Ok(())
+    }
+
     #[test]
     fn test_gemfile_lock_version() -> Result<()> {
         assert_eq!(
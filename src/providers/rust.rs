@@ -1,7 +1,7 @@
 use std::env::consts::ARCH;
 use std::fmt::Write as _;
 
-use super::Provider;
+use super::{Confidence, Provider, ProviderMetadata};
 use crate::nixpacks::{
     app::App,
     environment::{Environment, EnvironmentVariables},
@@ -11,7 +11,7 @@ use crate::nixpacks::{
         BuildPlan,
     },
 };
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use cargo_toml::{Manifest, Workspace};
 use regex::Regex;
 
@@ -31,8 +31,16 @@ impl Provider for RustProvider {
         "rust"
     }
 
-    fn detect(&self, app: &App, _env: &Environment) -> Result<bool> {
-        Ok(app.includes_file("Cargo.toml"))
+    fn detect(&self, app: &App, _env: &Environment) -> Result<Confidence> {
+        Ok(Confidence::from_files(
+            app.includes_file("Cargo.toml"),
+            app.includes_file("Cargo.lock"),
+        ))
+    }
+
+    fn metadata(&self, app: &App, _env: &Environment) -> Result<ProviderMetadata> {
+        Ok(ProviderMetadata::default()
+            .set_reason(ProviderMetadata::found_file(app, &["Cargo.toml"])))
     }
 
     fn get_build_plan(&self, app: &App, env: &Environment) -> Result<Option<BuildPlan>> {
@@ -40,7 +48,7 @@ impl Provider for RustProvider {
         let build = RustProvider::get_build(app, env)?;
         let start = RustProvider::get_start(app, env)?;
 
-        let mut plan = BuildPlan::new(&vec![setup, build], start);
+        let mut plan = BuildPlan::new(&[setup, build], start);
         plan.add_variables(EnvironmentVariables::from([(
             "ROCKET_ADDRESS".to_string(),
             "0.0.0.0".to_string(),
@@ -186,13 +194,13 @@ impl RustProvider {
         if (RustProvider::get_target(app, env)?).is_some() {
             if let Some(workspace) = RustProvider::resolve_cargo_workspace(app, env)? {
                 let mut start = StartPhase::new(format!("./bin/{workspace}"));
-                start.run_in_slim_image();
+                RustProvider::set_run_image(&mut start, app, env)?;
                 start.add_file_dependency(format!("./bin/{workspace}"));
 
                 Ok(Some(start))
             } else if let Some(bin) = RustProvider::get_start_bin(app, env)? {
                 let mut start = StartPhase::new(bin.clone());
-                start.run_in_slim_image();
+                RustProvider::set_run_image(&mut start, app, env)?;
                 start.add_file_dependency(bin);
                 Ok(Some(start))
             } else {
@@ -207,6 +215,35 @@ impl RustProvider {
         }
     }
 
+    /// Binaries built for a target run in a slim image, or in an empty one when they are statically linked.
+    fn set_run_image(start: &mut StartPhase, app: &App, env: &Environment) -> Result<()> {
+        if RustProvider::is_static(app, env)? {
+            start.run_in_scratch_image();
+        } else {
+            start.run_in_slim_image();
+        }
+        Ok(())
+    }
+
+    /// Whether to build a statically linked binary with `--static` or `NIXPACKS_STATIC`.
+    fn is_static(app: &App, env: &Environment) -> Result<bool> {
+        if !env.is_config_variable_truthy("STATIC")
+            || RustProvider::should_make_wasm32_wasi(app, env)
+        {
+            return Ok(false);
+        }
+
+        if env.is_config_variable_truthy("NO_MUSL") {
+            bail!(
+                "Static builds use the musl target, so NIXPACKS_NO_MUSL can't be set with --static"
+            );
+        }
+        if RustProvider::uses_openssl(app)? {
+            bail!("OpenSSL can't be linked into a static build. Use rustls instead of openssl to build with --static");
+        }
+        Ok(true)
+    }
+
     fn get_app_name(app: &App) -> Result<Option<String>> {
         if let Some(toml_file) = RustProvider::parse_cargo_toml(app)? {
             if let Some(package) = toml_file.package {
@@ -325,6 +362,10 @@ impl RustProvider {
             return Ok(false);
         }
 
+        if RustProvider::is_static(app, env)? {
+            return Ok(true);
+        }
+
         if env.is_config_variable_truthy("NO_MUSL") {
             return Ok(false);
         }
@@ -1,4 +1,7 @@
-use super::Provider;
+use super::{
+    jlink::{self, JLINK_RUNTIME_DIR},
+    Confidence, Provider,
+};
 use crate::nixpacks::{
     app::App,
     environment::Environment,
@@ -28,8 +31,12 @@ impl Provider for ScalaProvider {
         "scala"
     }
 
-    fn detect(&self, app: &App, _env: &Environment) -> Result<bool> {
-        Ok(app.includes_file("build.sbt"))
+    fn detect(&self, app: &App, _env: &Environment) -> Result<Confidence> {
+        // sbt projects only build as Scala, so they outrank a package.json for frontend assets
+        Ok(Confidence::first_of(&[(
+            app.includes_file("build.sbt"),
+            Confidence::High,
+        )]))
     }
 
     fn get_build_plan(&self, app: &App, env: &Environment) -> Result<Option<BuildPlan>> {
@@ -37,7 +44,7 @@ impl Provider for ScalaProvider {
             let jdk_version: u32 = self.get_jdk_version(env);
 
             let pkgs = self.get_sbt_dep_pkgs(jdk_version);
-            let setup = Phase::setup(Some(pkgs));
+            let mut setup = Phase::setup(Some(pkgs));
 
             let mut build = Phase::build(None);
             let sbt_exe = self.get_sbt_exe();
@@ -48,14 +55,31 @@ impl Provider for ScalaProvider {
             build.add_cache_directory("/root/.cache/coursier");
             build.depends_on_phase("setup");
 
-            let start_phase = self.get_start_cmd(app).map(StartPhase::new).map(|phase| {
-                let mut updated_phase = phase;
-                updated_phase.run_in_image(self.get_jdk_run_image(jdk_version).to_string());
-                updated_phase.add_file_dependency("./target/universal");
-                updated_phase
-            });
-
-            let plan = BuildPlan::new(&vec![setup, build], start_phase);
+            let start_phase = if jlink::is_enabled(env) {
+                jlink::link_runtime(
+                    env,
+                    jdk_version,
+                    "target/universal/stage/lib/*.jar",
+                    &mut setup,
+                    &mut build,
+                )?;
+                // The start script runs the java binary of JAVA_HOME
+                self.get_start_cmd(app).map(|cmd| {
+                    let mut phase =
+                        StartPhase::new(format!("JAVA_HOME=/app/{JLINK_RUNTIME_DIR} {cmd}"));
+                    jlink::run_with_runtime(env, &mut phase, &["./target/universal"]);
+                    phase
+                })
+            } else {
+                self.get_start_cmd(app).map(StartPhase::new).map(|phase| {
+                    let mut updated_phase = phase;
+                    updated_phase.run_in_image(self.get_jdk_run_image(jdk_version).to_string());
+                    updated_phase.add_file_dependency("./target/universal");
+                    updated_phase
+                })
+            };
+
+            let plan = BuildPlan::new(&[setup, build], start_phase);
             Ok(Some(plan))
         } else {
             Ok(None)
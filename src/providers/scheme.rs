@@ -1,4 +1,4 @@
-use super::Provider;
+use super::{Confidence, Provider};
 use crate::nixpacks::{
     app::App,
     environment::Environment,
@@ -17,8 +17,11 @@ impl Provider for HauntProvider {
         "scheme"
     }
 
-    fn detect(&self, app: &App, _env: &Environment) -> Result<bool> {
-        Ok(app.includes_file("haunt.scm"))
+    fn detect(&self, app: &App, _env: &Environment) -> Result<Confidence> {
+        Ok(Confidence::first_of(&[(
+            app.includes_file("haunt.scm"),
+            Confidence::High,
+        )]))
     }
 
     fn get_build_plan(&self, _app: &App, _env: &Environment) -> Result<Option<BuildPlan>> {
@@ -30,7 +33,7 @@ impl Provider for HauntProvider {
         // So for the example, init.scm simply logs to the console
         let start = StartPhase::new("guile init.scm --auto-compile".to_string());
 
-        let plan = BuildPlan::new(&vec![setup, build], Some(start));
+        let plan = BuildPlan::new(&[setup, build], Some(start));
         Ok(Some(plan))
     }
 }
@@ -1,4 +1,4 @@
-use super::Provider;
+use super::{Confidence, Provider};
 use crate::nixpacks::{
     app::{App, StaticAssets},
     environment::Environment,
@@ -11,7 +11,7 @@ use crate::nixpacks::{
 use anyhow::Result;
 use indoc::formatdoc;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 use std::fmt::Write as _;
 
 #[derive(Serialize, Deserialize, Default, Debug)]
@@ -19,7 +19,7 @@ pub struct Staticfile {
     pub root: Option<String>,
     pub directory: Option<String>,
     pub gzip: Option<String>,
-    pub status_code: Option<HashMap<u32, String>>,
+    pub status_code: Option<BTreeMap<u32, String>>,
 }
 
 pub struct StaticfileProvider {}
@@ -29,12 +29,17 @@ impl Provider for StaticfileProvider {
         "staticfile"
     }
 
-    fn detect(&self, app: &App, _env: &Environment) -> Result<bool> {
-        Ok(app.includes_file("Staticfile")
-            || app.includes_directory("public")
-            || app.includes_directory("index")
-            || app.includes_directory("dist")
-            || app.includes_file("index.html"))
+    fn detect(&self, app: &App, _env: &Environment) -> Result<Confidence> {
+        Ok(Confidence::first_of(&[
+            (app.includes_file("Staticfile"), Confidence::Medium),
+            (
+                app.includes_directory("public")
+                    || app.includes_directory("index")
+                    || app.includes_directory("dist")
+                    || app.includes_file("index.html"),
+                Confidence::Low,
+            ),
+        ]))
     }
 
     fn get_build_plan(&self, app: &App, env: &Environment) -> Result<Option<BuildPlan>> {
@@ -51,7 +56,7 @@ impl Provider for StaticfileProvider {
 
         let static_assets = StaticfileProvider::get_static_assets(app, env)?;
 
-        let mut plan = BuildPlan::new(&vec![setup], Some(start));
+        let mut plan = BuildPlan::new(&[setup], Some(start));
         plan.add_static_assets(static_assets);
 
         Ok(Some(plan))
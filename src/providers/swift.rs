@@ -1,4 +1,4 @@
-use super::Provider;
+use super::{Confidence, Provider};
 use crate::nixpacks::{
     app::App,
     environment::Environment,
@@ -41,8 +41,11 @@ impl Provider for SwiftProvider {
         "swift"
     }
 
-    fn detect(&self, app: &App, _env: &Environment) -> Result<bool> {
-        Ok(app.includes_file("Package.swift"))
+    fn detect(&self, app: &App, _env: &Environment) -> Result<Confidence> {
+        Ok(Confidence::from_files(
+            app.includes_file("Package.swift"),
+            app.includes_file("Package.resolved"),
+        ))
     }
 
     fn get_build_plan(&self, app: &App, _env: &Environment) -> Result<Option<BuildPlan>> {
@@ -90,7 +93,7 @@ impl Provider for SwiftProvider {
         let name = SwiftProvider::get_executable_name(app)?;
         let start = StartPhase::new(format!("./{name}"));
 
-        let plan = BuildPlan::new(&vec![setup, install, build], Some(start));
+        let plan = BuildPlan::new(&[setup, install, build], Some(start));
 
         Ok(Some(plan))
     }
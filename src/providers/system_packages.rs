@@ -0,0 +1,211 @@
+use crate::nixpacks::{nix::pkg::Pkg, plan::phase::Phase};
+
+/// System packages that a library runs, loads, or compiles against, which its own package doesn't include.
+pub struct SystemDependency {
+    /// The name the dependency is found by in the app's manifests and lockfiles.
+    pub name: &'static str,
+    /// Programs the library runs, such as `ffmpeg`, or headers and tools it is compiled with.
+    pub nix_pkgs: &'static [&'static str],
+    /// Shared libraries the library loads, such as libvips.
+    pub nix_libs: &'static [&'static str],
+}
+
+pub const NODE_MEDIA_DEPENDENCIES: &[SystemDependency] = &[
+    SystemDependency {
+        name: "fluent-ffmpeg",
+        nix_pkgs: &["ffmpeg"],
+        nix_libs: &[],
+    },
+    SystemDependency {
+        name: "imagemagick",
+        nix_pkgs: &["imagemagick"],
+        nix_libs: &[],
+    },
+    SystemDependency {
+        name: "sharp",
+        nix_pkgs: &[],
+        nix_libs: &["vips"],
+    },
+];
+
+pub const PYTHON_MEDIA_DEPENDENCIES: &[SystemDependency] = &[
+    SystemDependency {
+        name: "ffmpeg-python",
+        nix_pkgs: &["ffmpeg"],
+        nix_libs: &[],
+    },
+    SystemDependency {
+        name: "moviepy",
+        nix_pkgs: &["ffmpeg"],
+        nix_libs: &[],
+    },
+    SystemDependency {
+        name: "pydub",
+        nix_pkgs: &["ffmpeg"],
+        nix_libs: &[],
+    },
+    SystemDependency {
+        name: "pillow",
+        nix_pkgs: &[],
+        nix_libs: &["libjpeg", "freetype"],
+    },
+    SystemDependency {
+        name: "pyvips",
+        nix_pkgs: &[],
+        nix_libs: &["vips"],
+    },
+];
+
+pub const RUBY_MEDIA_DEPENDENCIES: &[SystemDependency] = &[
+    SystemDependency {
+        name: "streamio-ffmpeg",
+        nix_pkgs: &["ffmpeg"],
+        nix_libs: &[],
+    },
+    // Both `rmagick` and `mini_magick`
+    SystemDependency {
+        name: "magick",
+        nix_pkgs: &["imagemagick"],
+        nix_libs: &[],
+    },
+];
+
+/// Native database drivers, which are compiled against the database's client library while installing.
+pub const NODE_DATABASE_DEPENDENCIES: &[SystemDependency] = &[
+    SystemDependency {
+        name: "pg-native",
+        nix_pkgs: &["postgresql"],
+        nix_libs: &[],
+    },
+    SystemDependency {
+        name: "libpq",
+        nix_pkgs: &["postgresql"],
+        nix_libs: &[],
+    },
+    SystemDependency {
+        name: "sqlite3",
+        nix_pkgs: &["sqlite.dev"],
+        nix_libs: &["sqlite"],
+    },
+    SystemDependency {
+        name: "better-sqlite3",
+        nix_pkgs: &["sqlite.dev"],
+        nix_libs: &["sqlite"],
+    },
+];
+
+pub const PYTHON_DATABASE_DEPENDENCIES: &[SystemDependency] = &[
+    // psycopg 3, which loads libpq at runtime unless the `binary` extra is installed
+    SystemDependency {
+        name: "psycopg",
+        nix_pkgs: &[],
+        nix_libs: &["postgresql.lib"],
+    },
+    SystemDependency {
+        name: "mysqlclient",
+        nix_pkgs: &["libmysqlclient.dev"],
+        nix_libs: &[],
+    },
+];
+
+/// Adds the system packages for each of the dependencies the app uses to the setup phase, saying why.
+pub fn add_system_packages(
+    setup: &mut Phase,
+    dependencies: &[SystemDependency],
+    uses_dependency: impl Fn(&str) -> bool,
+) {
+    for dependency in dependencies {
+        if !uses_dependency(dependency.name) {
+            continue;
+        }
+
+        let has_pkg = |pkg: &&str| {
+            setup
+                .nix_pkgs
+                .iter()
+                .chain(&setup.nix_libs)
+                .flatten()
+                .any(|existing| existing == pkg)
+        };
+        let new_pkgs = dependency
+            .nix_pkgs
+            .iter()
+            .copied()
+            .filter(|pkg| !has_pkg(pkg))
+            .collect::<Vec<_>>();
+        let new_libs = dependency
+            .nix_libs
+            .iter()
+            .copied()
+            .filter(|lib| !has_pkg(lib))
+            .collect::<Vec<_>>();
+        if new_pkgs.is_empty() && new_libs.is_empty() {
+            continue;
+        }
+
+        let names = new_pkgs
+            .iter()
+            .chain(&new_libs)
+            .map(|pkg| format!("`{pkg}`"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        eprintln!(
+            "Adding {names} because the app depends on `{}`",
+            dependency.name
+        );
+
+        if !new_pkgs.is_empty() {
+            setup.add_nix_pkgs(&new_pkgs.iter().map(|pkg| Pkg::new(pkg)).collect::<Vec<_>>());
+        }
+        if !new_libs.is_empty() {
+            setup.add_pkgs_libs(new_libs.iter().map(|lib| (*lib).to_string()).collect());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_add_system_packages() {
+        let mut setup = Phase::setup(Some(vec![Pkg::new("python3")]));
+        add_system_packages(&mut setup, PYTHON_MEDIA_DEPENDENCIES, |dep| {
+            ["pydub", "moviepy", "pillow"].contains(&dep)
+        });
+
+        assert_eq!(
+            setup.nix_pkgs,
+            Some(vec!["python3".to_string(), "ffmpeg".to_string()])
+        );
+        assert_eq!(
+            setup.nix_libs,
+            Some(vec!["libjpeg".to_string(), "freetype".to_string()])
+        );
+    }
+
+    #[test]
+    fn test_add_database_packages() {
+        // Packages the provider already installs are not added again
+        let mut setup = Phase::setup(Some(vec![Pkg::new("libmysqlclient.dev")]));
+        add_system_packages(&mut setup, PYTHON_DATABASE_DEPENDENCIES, |dep| {
+            dep == "mysqlclient"
+        });
+        assert_eq!(setup.nix_pkgs, Some(vec!["libmysqlclient.dev".to_string()]));
+        assert_eq!(setup.nix_libs, None);
+
+        let mut setup = Phase::setup(None);
+        add_system_packages(&mut setup, NODE_DATABASE_DEPENDENCIES, |dep| {
+            ["sqlite3", "better-sqlite3"].contains(&dep)
+        });
+        assert_eq!(setup.nix_pkgs, Some(vec!["sqlite.dev".to_string()]));
+        assert_eq!(setup.nix_libs, Some(vec!["sqlite".to_string()]));
+    }
+
+    #[test]
+    fn test_add_system_packages_without_dependencies() {
+        let mut setup = Phase::setup(None);
+        add_system_packages(&mut setup, NODE_MEDIA_DEPENDENCIES, |_| false);
+        assert_eq!(setup, Phase::setup(None));
+    }
+}
@@ -1,4 +1,4 @@
-use super::Provider;
+use super::{Confidence, Provider};
 use crate::nixpacks::{
     app::App,
     environment::Environment,
@@ -18,8 +18,14 @@ impl Provider for ZigProvider {
         "zig"
     }
 
-    fn detect(&self, app: &App, _env: &Environment) -> Result<bool> {
-        Ok(app.has_match("*.zig") || app.has_match("**/*.zig"))
+    fn detect(&self, app: &App, _env: &Environment) -> Result<Confidence> {
+        Ok(Confidence::first_of(&[
+            (app.includes_file("build.zig"), Confidence::Medium),
+            (
+                app.has_match("*.zig") || app.has_match("**/*.zig"),
+                Confidence::Low,
+            ),
+        ]))
     }
 
     fn get_build_plan(&self, app: &App, _env: &Environment) -> Result<Option<BuildPlan>> {
@@ -40,7 +46,7 @@ impl Provider for ZigProvider {
                 .map_or("*", Option::unwrap)
         ));
 
-        let plan = BuildPlan::new(&vec![setup, install, build], Some(start));
+        let plan = BuildPlan::new(&[setup, install, build], Some(start));
         Ok(Some(plan))
     }
 }
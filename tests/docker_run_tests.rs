@@ -161,7 +161,9 @@ async fn build_with_env(path: &str, env: Vec<&str>) -> anyhow::Result<()> {
             ..Default::default()
         },
     )
-    .await
+    .await?;
+
+    Ok(())
 }
 
 /// Builds a directory with default options
@@ -896,6 +898,13 @@ async fn test_csharp() {
     assert!(output.contains("Hello world from C#"));
 }
 
+#[tokio::test]
+async fn test_csharp_nested() {
+    let name = simple_build("./examples/csharp-nested").await.unwrap();
+    let output = run_image(&name, None).await;
+    assert!(output.contains("Hello world from a nested C# project"));
+}
+
 #[tokio::test]
 async fn test_fsharp() {
     let name = simple_build("./examples/fsharp-cli").await.unwrap();
@@ -1,4 +1,8 @@
-use nixpacks::{generate_build_plan, nixpacks::plan::generator::GeneratePlanOptions};
+use nixpacks::{
+    generate_build_plan, get_matching_providers, get_plan_providers,
+    nixpacks::plan::{generator::GeneratePlanOptions, BuildPlan},
+    providers::Confidence,
+};
 use std::env::consts::ARCH;
 
 test_helper::generate_plan_tests!();
@@ -18,6 +22,416 @@ fn test_custom_plan_path() {
     assert_plan_snapshot!(plan);
 }
 
+#[test]
+fn test_detect_all_providers() {
+    let providers = get_plan_providers(
+        "./examples/python-with-js-frontend",
+        Vec::new(),
+        &GeneratePlanOptions::default(),
+    )
+    .unwrap();
+    assert_eq!(providers, vec!["node"]);
+
+    let providers = get_plan_providers(
+        "./examples/python-with-js-frontend",
+        vec!["NIXPACKS_DETECT_ALL_PROVIDERS=1"],
+        &GeneratePlanOptions::default(),
+    )
+    .unwrap();
+    assert_eq!(providers, vec!["node", "python"]);
+
+    let providers = get_matching_providers(
+        "./examples/python-with-js-frontend",
+        Vec::new(),
+        &GeneratePlanOptions::default(),
+    )
+    .unwrap();
+    assert_eq!(
+        providers,
+        vec![
+            ("node".to_string(), Confidence::Medium),
+            ("python".to_string(), Confidence::Medium)
+        ]
+    );
+
+    let plan = generate_build_plan(
+        "./examples/python-with-js-frontend",
+        Vec::new(),
+        &GeneratePlanOptions {
+            detect_all_providers: true,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    assert!(plan.get_phase("build").is_some());
+    assert!(plan.get_phase("python:install").is_some());
+    assert_eq!(
+        plan.start_phase.unwrap().cmd,
+        Some("python main.py".to_string())
+    );
+}
+
+#[test]
+fn test_detection_confidence() {
+    // Python has a lock file and Node doesn't, so Python is used even though Node comes first
+    let providers = get_matching_providers(
+        "./examples/python-poetry-with-package-json",
+        Vec::new(),
+        &GeneratePlanOptions::default(),
+    )
+    .unwrap();
+    assert_eq!(
+        providers,
+        vec![
+            ("python".to_string(), Confidence::High),
+            ("node".to_string(), Confidence::Medium)
+        ]
+    );
+
+    let plan = generate_build_plan(
+        "./examples/python-poetry-with-package-json",
+        Vec::new(),
+        &GeneratePlanOptions::default(),
+    )
+    .unwrap();
+    assert_eq!(
+        plan.start_phase.unwrap().cmd,
+        Some("python main.py".to_string())
+    );
+}
+
+#[test]
+fn test_use_app_nix() {
+    let plan = generate_build_plan(
+        "./examples/shell-nix",
+        Vec::new(),
+        &GeneratePlanOptions {
+            use_app_nix: true,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    assert_eq!(plan.nix_file, Some("shell.nix".to_string()));
+
+    let plan =
+        generate_build_plan("./examples/shell-nix", Vec::new(), &Default::default()).unwrap();
+    assert_eq!(plan.nix_file, None);
+
+    assert!(generate_build_plan(
+        "./examples/shell-hello",
+        vec!["NIXPACKS_USE_APP_NIX=1"],
+        &GeneratePlanOptions::default(),
+    )
+    .is_err());
+}
+
+#[test]
+fn test_no_cacert() {
+    let plan = generate_build_plan(
+        "./examples/node-npm",
+        vec!["NIXPACKS_NO_CACERT=1"],
+        &GeneratePlanOptions::default(),
+    )
+    .unwrap();
+    let setup = plan.get_phase("setup").unwrap();
+    assert!(!setup
+        .nix_pkgs
+        .clone()
+        .unwrap()
+        .contains(&"cacert".to_string()));
+    assert!(!plan.variables.unwrap().contains_key("SSL_CERT_FILE"));
+
+    let plan = generate_build_plan(
+        "./examples/node-npm",
+        vec!["SSL_CERT_FILE=/app/ca.pem"],
+        &GeneratePlanOptions::default(),
+    )
+    .unwrap();
+    let variables = plan.variables.unwrap();
+    assert_eq!(variables["SSL_CERT_FILE"], "/app/ca.pem");
+    assert_eq!(
+        variables["NIX_SSL_CERT_FILE"],
+        "/nix/var/nix/profiles/default/etc/ssl/certs/ca-bundle.crt"
+    );
+}
+
+#[test]
+fn test_process_manager() {
+    let plan = generate_build_plan(
+        "./examples/python-procfile",
+        Vec::new(),
+        &GeneratePlanOptions {
+            process_manager: true,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    assert_eq!(
+        plan.start_phase.clone().unwrap().cmd,
+        Some("overmind start -N -f /assets/Procfile.nixpacks".to_string())
+    );
+    assert_eq!(
+        plan.static_assets.clone().unwrap()["Procfile.nixpacks"],
+        "web: python src/main.py\nworker: echo \"another process\"\n"
+    );
+    assert!(plan
+        .get_phase("setup")
+        .unwrap()
+        .nix_pkgs
+        .clone()
+        .unwrap()
+        .contains(&"overmind".to_string()));
+
+    // A single process is started as usual
+    let plan = generate_build_plan(
+        "./examples/procfile",
+        vec!["NIXPACKS_PROCESS_MANAGER=1"],
+        &GeneratePlanOptions::default(),
+    )
+    .unwrap();
+    assert_eq!(
+        plan.start_phase.unwrap().cmd,
+        Some("echo start from procfile".to_string())
+    );
+}
+
+#[test]
+fn test_subdir() {
+    let plan = generate_build_plan(
+        "./examples/node-turborepo",
+        Vec::new(),
+        &GeneratePlanOptions {
+            subdir: Some("apps/web".to_string()),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    // Turborepo apps are built from the root of the workspace
+    assert_eq!(
+        plan.get_phase("install").unwrap().cmds,
+        Some(vec!["npm ci".to_string()])
+    );
+    assert_eq!(
+        plan.start_phase.unwrap().cmd,
+        Some("npm --workspace web run start".to_string())
+    );
+
+    let providers = get_plan_providers(
+        "./examples",
+        vec!["NIXPACKS_SUBDIR=go"],
+        &GeneratePlanOptions::default(),
+    )
+    .unwrap();
+    assert_eq!(providers, vec!["go"]);
+}
+
+#[test]
+fn test_default_variables() {
+    let plan = generate_build_plan("./examples/node", Vec::new(), &Default::default()).unwrap();
+    let variables = plan.variables.unwrap();
+    let build_variables = plan.build_variables.unwrap();
+    assert_eq!(variables.get("NODE_ENV"), Some(&"production".to_string()));
+    assert_eq!(
+        build_variables.get("NPM_CONFIG_PRODUCTION"),
+        Some(&"false".to_string())
+    );
+
+    let plan = generate_build_plan(
+        "./examples/node",
+        vec!["NODE_ENV=staging", "NPM_CONFIG_PRODUCTION=true"],
+        &GeneratePlanOptions {
+            plan: Some(
+                BuildPlan::from_toml(
+                    r#"
+                    [variables]
+                    NODE_ENV = "development"
+                    CI = "false"
+                    "#,
+                )
+                .unwrap(),
+            ),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    let variables = plan.variables.unwrap();
+    assert_eq!(variables.get("NODE_ENV"), Some(&"development".to_string()));
+    assert_eq!(variables.get("CI"), Some(&"false".to_string()));
+    assert_eq!(
+        variables.get("NPM_CONFIG_PRODUCTION"),
+        Some(&"true".to_string())
+    );
+    assert_eq!(plan.build_variables, None);
+}
+
+#[test]
+fn test_variable_interpolation() {
+    let plan = generate_build_plan(
+        "./examples/python",
+        vec!["APP_PORT=8080"],
+        &GeneratePlanOptions {
+            plan: Some(
+                BuildPlan::from_toml(
+                    r#"
+                [phases.build]
+                cmds = ["echo ${APP_PORT} ${UNKNOWN}"]
+
+                [start]
+                cmd = "python main.py --port ${APP_PORT}"
+                "#,
+                )
+                .unwrap(),
+            ),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+
+    assert_eq!(
+        plan.get_phase("build").unwrap().cmds,
+        Some(vec!["echo 8080 ${UNKNOWN}".to_string()])
+    );
+    assert_eq!(
+        plan.start_phase.unwrap().cmd,
+        Some("python main.py --port 8080".to_string())
+    );
+}
+
+#[test]
+fn test_provider_override() {
+    let providers = get_plan_providers(
+        "./examples/python-with-js-frontend",
+        vec!["NIXPACKS_PROVIDERS=python"],
+        &GeneratePlanOptions::default(),
+    )
+    .unwrap();
+    assert_eq!(providers, vec!["python"]);
+
+    let providers = get_plan_providers(
+        "./examples/python-with-js-frontend",
+        vec![
+            "NIXPACKS_PROVIDERS=python,...",
+            "NIXPACKS_DETECT_ALL_PROVIDERS=1",
+        ],
+        &GeneratePlanOptions::default(),
+    )
+    .unwrap();
+    assert_eq!(providers, vec!["python", "node"]);
+
+    let plan = generate_build_plan(
+        "./examples/python-with-js-frontend",
+        Vec::new(),
+        &GeneratePlanOptions {
+            plan: Some(BuildPlan {
+                providers: Some(vec!["python".to_string(), "...".to_string()]),
+                ..Default::default()
+            }),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    assert!(plan.get_phase("install").unwrap().cmds.clone().unwrap()[0].contains("pip"));
+    assert!(plan.get_phase("node:build").is_some());
+}
+
+#[test]
+fn test_nixpkgs_archive_override() {
+    let plan = generate_build_plan(
+        "./examples/node",
+        Vec::new(),
+        &GeneratePlanOptions {
+            nixpkgs_archive: Some("nixos-unstable".to_string()),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    assert_eq!(
+        plan.get_phase("setup").unwrap().nixpkgs_archive,
+        Some("nixos-unstable".to_string())
+    );
+
+    let plan = generate_build_plan(
+        "./examples/node",
+        vec!["NIXPACKS_NIXPKGS_CHANNEL=23.05"],
+        &GeneratePlanOptions::default(),
+    )
+    .unwrap();
+    assert_eq!(
+        plan.get_phase("setup").unwrap().nixpkgs_archive,
+        Some("nixos-23.05".to_string())
+    );
+}
+
+#[test]
+fn test_nx_next_start_workdir() {
+    let plan = generate_build_plan(
+        "./examples/node-nx",
+        vec!["NIXPACKS_NX_APP_NAME=next-app"],
+        &GeneratePlanOptions::default(),
+    )
+    .unwrap();
+    let start = plan.start_phase.unwrap();
+    assert_eq!(start.cmd, Some("npm run start".to_string()));
+    assert_eq!(start.workdir, Some("dist/apps/next-app".to_string()));
+
+    let plan = generate_build_plan(
+        "./examples/node-nx",
+        vec![
+            "NIXPACKS_NX_APP_NAME=next-app",
+            "NIXPACKS_START_WORKDIR=/srv",
+        ],
+        &GeneratePlanOptions::default(),
+    )
+    .unwrap();
+    assert_eq!(plan.start_phase.unwrap().workdir, Some("/srv".to_string()));
+}
+
+#[test]
+fn test_heroku_config() {
+    let plan = simple_gen_plan("./examples/heroku");
+    assert_eq!(
+        plan.variables.clone().unwrap()["GREETING"],
+        "app.json".to_string()
+    );
+    assert_eq!(
+        plan.build_variables.unwrap()["NODE_OPTIONS"],
+        "--max-old-space-size=2048".to_string()
+    );
+    let start = plan.start_phase.unwrap();
+    assert_eq!(start.cmd, Some("node index.js".to_string()));
+    assert_eq!(start.release_cmd, Some("echo release".to_string()));
+
+    // Variables passed to the build take precedence over app.json
+    let plan = generate_build_plan(
+        "./examples/heroku",
+        vec!["GREETING=env"],
+        &GeneratePlanOptions::default(),
+    )
+    .unwrap();
+    assert_eq!(plan.variables.unwrap()["GREETING"], "env".to_string());
+}
+
+#[test]
+fn test_procfile_release_cmd() {
+    let plan = simple_gen_plan("./examples/procfile");
+    assert!(plan.get_phase("release").is_none());
+    assert_eq!(
+        plan.start_phase.unwrap().release_cmd,
+        Some("echo release".to_string())
+    );
+
+    let plan = generate_build_plan(
+        "./examples/node",
+        vec!["NIXPACKS_RELEASE_CMD=npm run migrate"],
+        &GeneratePlanOptions::default(),
+    )
+    .unwrap();
+    assert_eq!(
+        plan.start_phase.unwrap().release_cmd,
+        Some("npm run migrate".to_string())
+    );
+}
+
 #[test]
 fn test_custom_rust_version() {
     let plan = simple_gen_plan("./examples/rust-custom-version");
@@ -62,6 +476,116 @@ fn test_rust_rocket() {
     assert!(start.run_image.is_some());
 }
 
+#[test]
+fn test_java_maven_jlink() {
+    let plan = generate_build_plan(
+        "./examples/java-maven",
+        vec!["NIXPACKS_JLINK=1", "NIXPACKS_JLINK_MODULES=java.scripting"],
+        &GeneratePlanOptions::default(),
+    )
+    .unwrap();
+    assert_eq!(
+        plan.get_phase("setup").unwrap().apt_pkgs,
+        Some(vec![
+            "openjdk-17-jdk-headless".to_string(),
+            "openjdk-17-jmods".to_string()
+        ])
+    );
+    let build_cmds = plan.get_phase("build").unwrap().cmds.clone().unwrap();
+    // The largest jar is the one with the dependencies when there are several
+    assert_eq!(
+        build_cmds[1],
+        "cp \"$(ls -1S target/*.jar | grep -v -e -plain.jar -e -sources.jar -e -javadoc.jar | head -n 1)\" app.jar"
+    );
+    assert!(build_cmds[2].contains("jdk.zipfs,java.scripting --strip-debug"));
+
+    let start = plan.start_phase.unwrap();
+    assert_eq!(
+        start.cmd,
+        Some("./jre/bin/java -Dserver.port=$PORT $JAVA_OPTS -jar app.jar".to_string())
+    );
+    assert_eq!(start.run_image, Some("ubuntu:jammy".to_string()));
+    assert_eq!(
+        start.only_include_files,
+        Some(vec!["jre".to_string(), "app.jar".to_string()])
+    );
+
+    assert!(generate_build_plan(
+        "./examples/java-maven",
+        vec!["NIXPACKS_JLINK=1", "NIXPACKS_JDK_VERSION=8"],
+        &GeneratePlanOptions::default(),
+    )
+    .is_err());
+    // Debian bullseye doesn't package JDK 21
+    assert!(generate_build_plan(
+        "./examples/java-maven",
+        vec![
+            "NIXPACKS_JLINK=1",
+            "NIXPACKS_JDK_VERSION=21",
+            "NIXPACKS_DEBIAN=1"
+        ],
+        &GeneratePlanOptions::default(),
+    )
+    .is_err());
+}
+
+#[test]
+fn test_scala_and_clojure_jlink() {
+    let plan = generate_build_plan(
+        "./examples/scala-sbt",
+        vec!["NIXPACKS_JLINK=1"],
+        &GeneratePlanOptions::default(),
+    )
+    .unwrap();
+    let build_cmds = plan.get_phase("build").unwrap().cmds.clone().unwrap();
+    assert!(build_cmds
+        .last()
+        .unwrap()
+        .contains("--print-module-deps target/universal/stage/lib/*.jar"));
+    let start = plan.start_phase.unwrap();
+    assert_eq!(
+        start.cmd,
+        Some("JAVA_HOME=/app/jre ./target/universal/stage/bin/main".to_string())
+    );
+    assert_eq!(start.run_image, Some("ubuntu:jammy".to_string()));
+
+    let plan = generate_build_plan(
+        "./examples/clojure-jdk11",
+        vec!["NIXPACKS_JLINK=1"],
+        &GeneratePlanOptions::default(),
+    )
+    .unwrap();
+    assert_eq!(
+        plan.get_phase("setup").unwrap().apt_pkgs,
+        Some(vec![
+            "openjdk-11-jdk-headless".to_string(),
+            "openjdk-11-jmods".to_string()
+        ])
+    );
+    let start = plan.start_phase.unwrap();
+    assert_eq!(
+        start.cmd,
+        Some("./jre/bin/java $JAVA_OPTS -jar app.jar".to_string())
+    );
+    assert_eq!(
+        start.only_include_files,
+        Some(vec!["jre".to_string(), "app.jar".to_string()])
+    );
+}
+
+#[test]
+fn test_go_scratch() {
+    let plan = generate_build_plan(
+        "./examples/go-gin",
+        vec!["NIXPACKS_SCRATCH=1"],
+        &GeneratePlanOptions::default(),
+    )
+    .unwrap();
+    let start = plan.start_phase.unwrap();
+    assert_eq!(start.run_image, Some("scratch".to_string()));
+    assert_eq!(start.only_include_files, Some(vec!["out".to_string()]));
+}
+
 #[test]
 fn test_rust_rocket_no_musl() {
     let plan = generate_build_plan(
@@ -73,6 +597,33 @@ fn test_rust_rocket_no_musl() {
     assert_plan_snapshot!(plan);
 }
 
+#[test]
+fn test_rust_rocket_static() {
+    let plan = generate_build_plan(
+        "./examples/rust-rocket",
+        vec!["NIXPACKS_STATIC=1"],
+        &GeneratePlanOptions::default(),
+    )
+    .unwrap();
+    assert_eq!(
+        plan.get_phase("build").unwrap().cmds.clone().unwrap()[1],
+        format!("cargo build --release --target {ARCH}-unknown-linux-musl")
+    );
+    let start = plan.start_phase.unwrap();
+    assert_eq!(start.run_image, Some("scratch".to_string()));
+    assert_eq!(
+        start.only_include_files,
+        Some(vec!["./bin/rocket".to_string()])
+    );
+
+    assert!(generate_build_plan(
+        "./examples/rust-openssl",
+        vec!["NIXPACKS_STATIC=1"],
+        &GeneratePlanOptions::default(),
+    )
+    .is_err());
+}
+
 #[test]
 fn test_rust_cargo_workspaces() {
     let plan = simple_gen_plan("./examples/rust-cargo-workspaces");
@@ -117,3 +668,45 @@ fn test_node_turborepo_custom_app() {
     .unwrap();
     assert!(plan.start_phase.unwrap().cmd.unwrap().contains("docs"));
 }
+
+#[test]
+fn test_node_bundle() {
+    let plan = generate_build_plan(
+        "./examples/node-custom-version",
+        vec!["NIXPACKS_BUNDLE=1"],
+        &GeneratePlanOptions::default(),
+    )
+    .unwrap();
+    let build_cmds = plan.get_phase("build").unwrap().cmds.clone().unwrap();
+    assert!(build_cmds
+        .last()
+        .unwrap()
+        .starts_with("npx --yes esbuild@0.24.0 index.js --bundle --platform=node --target=node22"));
+
+    let start = plan.start_phase.unwrap();
+    assert_eq!(start.cmd, Some("node dist/nixpacks-bundle.cjs".to_string()));
+    assert_eq!(start.run_image, Some("node:22-slim".to_string()));
+    assert_eq!(
+        start.only_include_files,
+        Some(vec!["dist/nixpacks-bundle.cjs".to_string()])
+    );
+
+    let plan = generate_build_plan(
+        "./examples/node-main-file",
+        vec!["NIXPACKS_BUNDLE=1"],
+        &GeneratePlanOptions::default(),
+    )
+    .unwrap();
+    assert!(plan.get_phase("build").unwrap().cmds.clone().unwrap()[0]
+        .contains(" src/index.js --bundle"));
+
+    let plan = generate_build_plan(
+        "./examples/node-main-file-not-exist",
+        vec!["NIXPACKS_BUNDLE=1"],
+        &GeneratePlanOptions::default(),
+    )
+    .unwrap();
+    assert!(
+        plan.get_phase("build").unwrap().cmds.clone().unwrap()[0].contains(" index.js --bundle")
+    );
+}
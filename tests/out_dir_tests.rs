@@ -0,0 +1,68 @@
+use nixpacks::{
+    create_docker_image,
+    nixpacks::{builder::docker::DockerBuilderOptions, plan::generator::GeneratePlanOptions},
+};
+use std::fs;
+use uuid::Uuid;
+
+// Images written to an output directory aren't built, so these run without Docker
+
+#[tokio::test]
+async fn test_image_info_for_out_dir() {
+    let out_dir = tempdir::TempDir::new("nixpacks-out").unwrap();
+    let out_path = out_dir.path().to_str().unwrap().to_string();
+    let info = create_docker_image(
+        "./examples/node",
+        Vec::new(),
+        &GeneratePlanOptions::default(),
+        &DockerBuilderOptions {
+            out_dir: Some(out_path.clone()),
+            tags: vec!["node:latest".to_string()],
+            quiet: true,
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+
+    assert!(Uuid::parse_str(&info.name).is_ok());
+    assert_eq!(info.tags, vec!["node:latest".to_string()]);
+    assert_eq!(info.providers, vec!["node".to_string()]);
+    assert_eq!(info.out_dir, Some(out_path));
+    assert!(info.plan.phases.is_some());
+    assert!(out_dir.path().join(".nixpacks/Dockerfile").exists());
+}
+
+#[tokio::test]
+async fn test_use_dockerfile() {
+    let app_dir = tempdir::TempDir::new("nixpacks-app").unwrap();
+    let dockerfile = "FROM alpine\nCOPY . /app\nCMD [\"cat\", \"/app/hello.txt\"]\n";
+    fs::write(app_dir.path().join("Dockerfile"), dockerfile).unwrap();
+    fs::write(app_dir.path().join("hello.txt"), "Hello from a Dockerfile").unwrap();
+
+    let out_dir = tempdir::TempDir::new("nixpacks-out").unwrap();
+    let info = create_docker_image(
+        app_dir.path().to_str().unwrap(),
+        vec!["GREETING=hello"],
+        &GeneratePlanOptions::default(),
+        &DockerBuilderOptions {
+            out_dir: Some(out_dir.path().to_str().unwrap().to_string()),
+            use_dockerfile: true,
+            quiet: true,
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+
+    assert!(info.providers.is_empty());
+    assert_eq!(
+        fs::read_to_string(out_dir.path().join(".nixpacks/Dockerfile")).unwrap(),
+        dockerfile
+    );
+    assert!(
+        fs::read_to_string(out_dir.path().join(".nixpacks/build.sh"))
+            .unwrap()
+            .contains("--build-arg GREETING=hello")
+    );
+}